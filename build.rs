@@ -2,6 +2,34 @@
 use std::path::PathBuf;
 
 fn main() {
+    // The pure-Rust Oodle backend needs none of the C++ sources.
+    if std::env::var_os("CARGO_FEATURE_RUST_OODLE").is_none() {
+        build_ooz();
+    } else {
+        link_cpp_runtime();
+    }
+
+    #[cfg(windows)]
+    {
+        let mut res = winres::WindowsResource::new();
+        res.set_icon("assets/icon.ico");
+        res.compile().unwrap();
+    }
+}
+
+/// `intel_tex_2` (via `image_dds`) is C++ but leaves linking its runtime to
+/// whoever else builds C++; without ooz that is nobody.
+fn link_cpp_runtime() {
+    let target_os = std::env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    let target_env = std::env::var("CARGO_CFG_TARGET_ENV").unwrap_or_default();
+    match (target_os.as_str(), target_env.as_str()) {
+        ("macos" | "ios", _) => println!("cargo:rustc-link-lib=c++"),
+        ("windows", "msvc") => {}
+        _ => println!("cargo:rustc-link-lib=stdc++"),
+    }
+}
+
+fn build_ooz() {
     // 1. Try env var
     // 2. Try local 'ooz' subdirectory (Git Submodule)
     // 3. Try sibling directory
@@ -75,11 +103,4 @@ fn main() {
     build.compile("ooz");
 
     println!("cargo:rustc-link-lib=static=ooz");
-
-    #[cfg(windows)]
-    {
-        let mut res = winres::WindowsResource::new();
-        res.set_icon("assets/icon.ico");
-        res.compile().unwrap();
-    }
 }
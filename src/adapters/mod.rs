@@ -1,3 +1,4 @@
+use crate::error::Result;
 use crate::parsers::{parse, FileFormat, ParsedContent};
 
 /// Main adapter for parsing files
@@ -5,13 +6,13 @@ pub struct FileAdapter;
 
 impl FileAdapter {
     /// Detect format from file extension and parse
-    pub fn parse_file(extension: &str, bytes: &[u8]) -> Result<ParsedContent, String> {
+    pub fn parse_file(extension: &str, bytes: &[u8]) -> Result<ParsedContent> {
         let format = FileFormat::from_extension(extension);
         parse(format, bytes)
     }
 
     /// Parse with explicit format
-    pub fn parse_with_format(format: FileFormat, bytes: &[u8]) -> Result<ParsedContent, String> {
+    pub fn parse_with_format(format: FileFormat, bytes: &[u8]) -> Result<ParsedContent> {
         parse(format, bytes)
     }
 
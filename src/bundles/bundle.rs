@@ -1,7 +1,23 @@
-use crate::ooz::sys::Ooz_Decompress;
+use crate::error::{Error, Result};
 use byteorder::{ByteOrder, LittleEndian};
-use std::io::{self, Read, Seek, SeekFrom};
-use std::ptr;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::ops::Range;
+
+/// Oodle codecs the vendored `ooz` can encode. The discriminant is the
+/// OodleLZ_Compressor id stored in the bundle header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compressor {
+    Kraken = 8,
+    Mermaid = 9,
+    Leviathan = 13,
+}
+
+/// Block size used by the game's bundles.
+pub const DEFAULT_CHUNK_SIZE: u32 = 256 * 1024;
+/// Oodle's "Normal" level, what the game's patcher uses.
+pub const DEFAULT_LEVEL: i32 = 4;
 
 pub struct Bundle {
     pub uncompressed_size: u32,
@@ -17,9 +33,12 @@ pub struct Bundle {
 }
 
 impl Bundle {
-    pub fn read_header<R: Read + Seek>(mut reader: R) -> io::Result<Self> {
+    pub fn read_header<R: Read + Seek>(mut reader: R) -> Result<Self> {
+        let start = reader.stream_position()?;
         let mut header = [0u8; 60];
-        reader.read_exact(&mut header)?;
+        reader
+            .read_exact(&mut header)
+            .map_err(Error::truncated("bundle header", start))?;
 
         let uncompressed_size = LittleEndian::read_u32(&header[0..4]);
         let total_payload_size = LittleEndian::read_u32(&header[4..8]);
@@ -34,7 +53,9 @@ impl Bundle {
 
         let mut block_sizes = Vec::with_capacity(block_count as usize);
         let mut block_sizes_buf = vec![0u8; (block_count * 4) as usize];
-        reader.read_exact(&mut block_sizes_buf)?;
+        reader
+            .read_exact(&mut block_sizes_buf)
+            .map_err(Error::truncated("bundle block table", start + 60))?;
 
         for i in 0..block_count {
             let size =
@@ -58,61 +79,23 @@ impl Bundle {
         })
     }
 
-    pub fn decompress<R: Read + Seek>(&self, mut reader: R) -> io::Result<Vec<u8>> {
-        // ooz decoders may write up to 64 bytes past the destination end
-        // ("The buffer supplied must have an additional 64 bytes of scratch
-        // space at the end" — ooz/bun.h). Without this slack the final chunk
-        // corrupts the heap, crashing the app with no trace during bulk
-        // exports.
-        const SAFE_SPACE: usize = 64;
+    pub fn decompress<R: Read + Seek>(&self, mut reader: R) -> Result<Vec<u8>> {
         let size = self.uncompressed_size as usize;
         let mut output = vec![0u8; size + SAFE_SPACE];
-        let output_ptr = output.as_mut_ptr();
         let mut output_offset = 0;
 
         reader.seek(SeekFrom::Start(self.data_offset))?;
 
-        for &block_size in &self.block_sizes {
+        let mut input_offset = self.data_offset;
+        for (block, &block_size) in self.block_sizes.iter().enumerate() {
             let mut compressed_data = vec![0u8; block_size as usize];
-            reader.read_exact(&mut compressed_data)?;
-
-            // Determine decompressed size for this block
-            // Usually 256KB, except last one.
-            let remaining = self.uncompressed_size as usize - output_offset;
-            let dst_len = std::cmp::min(remaining, self.chunk_size as usize);
-
-            let ret = unsafe {
-                Ooz_Decompress(
-                    compressed_data.as_ptr(),
-                    block_size as i32,
-                    output_ptr.add(output_offset),
-                    dst_len,
-                    0,
-                    0,
-                    0,
-                    ptr::null_mut(),
-                    0,
-                    ptr::null_mut(),
-                    ptr::null_mut(),
-                    ptr::null_mut(),
-                    0,
-                    0,
-                )
-            };
-
-            if ret != dst_len as i32 {
-                println!("Ooz_Decompress FAILED: ret={}, dst_len={}", ret, dst_len);
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!(
-                        "Ooz_Decompress failed: returned {}, expected {}",
-                        ret, dst_len
-                    ),
-                ));
-            } else {
-                // println!("Ooz_Decompress success: {}", ret);
-            }
+            reader
+                .read_exact(&mut compressed_data)
+                .map_err(Error::truncated("bundle block", input_offset))?;
+            input_offset += block_size as u64;
 
+            let dst_len = self.block_len(block);
+            decode_block(block, &compressed_data, &mut output[output_offset..], dst_len)?;
             output_offset += dst_len;
         }
 
@@ -120,69 +103,349 @@ impl Bundle {
         Ok(output)
     }
 
-    pub fn decompress_from_slice(&self, data: &[u8]) -> io::Result<Vec<u8>> {
-        const SAFE_SPACE: usize = 64;
-        let size = self.uncompressed_size as usize;
-        let mut output = vec![0u8; size + SAFE_SPACE];
-        let output_ptr = output.as_mut_ptr();
+    /// Decompressed size of `block`: `chunk_size`, except for the last one.
+    fn block_len(&self, block: usize) -> usize {
+        let start = block * self.chunk_size as usize;
+        (self.uncompressed_size as usize).saturating_sub(start).min(self.chunk_size as usize)
+    }
+
+    /// Decodes `blocks` of the raw bundle `data` back to back into `output`.
+    /// Blocks are decoded in place while `output` has room for the decoder's
+    /// scratch bytes after them; the rest go through a temporary buffer so
+    /// nothing past `output` (possibly another thread's run) is touched.
+    fn decode_run(&self, data: &[u8], blocks: Range<usize>, output: &mut [u8]) -> Result<()> {
+        let mut input_offset = self.data_offset as usize
+            + self.block_sizes[..blocks.start].iter().map(|&s| s as usize).sum::<usize>();
         let mut output_offset = 0;
-        let mut input_offset = self.data_offset as usize;
-
-        for &block_size in &self.block_sizes {
-            let block_size = block_size as usize;
-            let input_end = input_offset.checked_add(block_size).ok_or_else(|| {
-                io::Error::new(io::ErrorKind::InvalidData, "Block offset overflow")
-            })?;
-            if input_end > data.len() {
-                return Err(io::Error::new(
-                    io::ErrorKind::UnexpectedEof,
-                    "Compressed block out of bounds",
-                ));
-            }
-            let compressed_data = &data[input_offset..input_end];
-
-            let remaining = self.uncompressed_size as usize - output_offset;
-            let dst_len = std::cmp::min(remaining, self.chunk_size as usize);
-
-            let ret = unsafe {
-                Ooz_Decompress(
-                    compressed_data.as_ptr(),
-                    block_size as i32,
-                    output_ptr.add(output_offset),
-                    dst_len,
-                    0,
-                    0,
-                    0,
-                    ptr::null_mut(),
-                    0,
-                    ptr::null_mut(),
-                    ptr::null_mut(),
-                    ptr::null_mut(),
-                    0,
-                    0,
-                )
-            };
-
-            if ret != dst_len as i32 {
-                println!("Ooz_Decompress FAILED: ret={}, dst_len={}", ret, dst_len);
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!(
-                        "Ooz_Decompress failed: returned {}, expected {}",
-                        ret, dst_len
-                    ),
-                ));
+        for block in blocks {
+            let block_size = self.block_sizes[block] as usize;
+            let input_end = input_offset
+                .checked_add(block_size)
+                .filter(|&end| end <= data.len())
+                .ok_or(Error::TruncatedRecord {
+                    what: "bundle block",
+                    offset: input_offset as u64,
+                })?;
+            let compressed = &data[input_offset..input_end];
+            let dst_len = self.block_len(block);
+
+            if output.len() - output_offset >= dst_len + SAFE_SPACE {
+                decode_block(block, compressed, &mut output[output_offset..], dst_len)?;
+            } else {
+                let mut scratch = vec![0u8; dst_len + SAFE_SPACE];
+                decode_block(block, compressed, &mut scratch, dst_len)?;
+                output[output_offset..output_offset + dst_len].copy_from_slice(&scratch[..dst_len]);
             }
 
             input_offset = input_end;
             output_offset += dst_len;
         }
+        Ok(())
+    }
+
+    /// Like [`Bundle::decode_run`], but splits `blocks` into one contiguous
+    /// run per thread. Each thread writes only its own part of `output`.
+    fn decode_blocks(&self, data: &[u8], blocks: Range<usize>, output: &mut [u8], threads: usize) -> Result<()> {
+        let threads = threads.clamp(1, blocks.len().max(1));
+        if threads == 1 {
+            return self.decode_run(data, blocks, output);
+        }
+        let per_thread = blocks.len().div_ceil(threads);
+
+        std::thread::scope(|scope| {
+            let mut rest = output;
+            let mut handles = Vec::with_capacity(threads);
+            let mut start = blocks.start;
+            while start < blocks.end {
+                let end = (start + per_thread).min(blocks.end);
+                // The last run keeps the trailing slack.
+                let len = if end == blocks.end {
+                    rest.len()
+                } else {
+                    (start..end).map(|b| self.block_len(b)).sum()
+                };
+                let (run, tail) = std::mem::take(&mut rest).split_at_mut(len);
+                rest = tail;
+                handles.push(scope.spawn(move || self.decode_run(data, start..end, run)));
+                start = end;
+            }
+            // Joined in block order, so the first error reported is the
+            // earliest failing block.
+            handles
+                .into_iter()
+                .try_for_each(|h| h.join().expect("bundle block decoder panicked"))
+        })
+    }
+
+    /// Indices of the blocks holding bytes `[offset, offset + size)`.
+    pub fn blocks_for_range(&self, offset: u64, size: u64) -> Range<usize> {
+        let chunk = self.chunk_size.max(1) as u64;
+        if size == 0 {
+            let block = (offset / chunk) as usize;
+            return block..block;
+        }
+        (offset / chunk) as usize..(offset + size).div_ceil(chunk) as usize
+    }
+
+    fn check_range(&self, offset: u64, size: u64) -> Result<()> {
+        if offset.checked_add(size).is_none_or(|end| end > self.uncompressed_size as u64) {
+            return Err(Error::TruncatedRecord { what: "bundled file", offset });
+        }
+        Ok(())
+    }
+
+    /// Decompresses a single block of the raw bundle `data`.
+    pub fn decompress_block(&self, data: &[u8], block: usize) -> Result<Vec<u8>> {
+        if block >= self.block_sizes.len() {
+            return Err(Error::InvalidData(format!(
+                "block {} out of range ({} blocks)",
+                block,
+                self.block_sizes.len()
+            )));
+        }
+        let dst_len = self.block_len(block);
+        let mut output = vec![0u8; dst_len + SAFE_SPACE];
+        self.decode_run(data, block..block + 1, &mut output)?;
+        output.truncate(dst_len);
+        Ok(output)
+    }
+
+    /// Returns bytes `[offset, offset + size)` of the decompressed bundle,
+    /// decompressing only the blocks that cover them. `data` is the whole
+    /// raw bundle, as for [`Bundle::decompress_from_slice`]. Ranges spanning
+    /// many blocks (large textures) are decoded on several threads.
+    pub fn decompress_range(&self, data: &[u8], offset: u64, size: u64) -> Result<Vec<u8>> {
+        self.check_range(offset, size)?;
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+        let blocks = self.blocks_for_range(offset, size);
+        let span_start = blocks.start * self.chunk_size as usize;
+        let span_len: usize = blocks.clone().map(|b| self.block_len(b)).sum();
+        let threads = if blocks.len() >= PARALLEL_MIN_BLOCKS { default_threads() } else { 1 };
+        let mut output = vec![0u8; span_len + SAFE_SPACE];
+        self.decode_blocks(data, blocks, &mut output, threads)?;
+
+        let start = offset as usize - span_start;
+        output.truncate(start + size as usize);
+        output.drain(..start);
+        Ok(output)
+    }
+
+    /// Same result as [`Bundle::decompress_from_slice`], with the blocks
+    /// spread over `threads` threads.
+    pub fn decompress_parallel(&self, data: &[u8], threads: usize) -> Result<Vec<u8>> {
+        let size = self.uncompressed_size as usize;
+        let mut output = vec![0u8; size + SAFE_SPACE];
+        self.decode_blocks(data, 0..self.block_sizes.len(), &mut output, threads)?;
+        output.truncate(size);
+        Ok(output)
+    }
+
+    /// Builds a complete bundle (header, block-size table and blocks) from
+    /// `data`, compressing each `chunk_size` block on its own.
+    pub fn compress(data: &[u8], compressor: Compressor, level: i32, chunk_size: u32) -> Result<Vec<u8>> {
+        let chunk_size = chunk_size.max(1);
+        let mut blocks = Vec::with_capacity(data.len().div_ceil(chunk_size as usize));
+        for (block, chunk) in data.chunks(chunk_size as usize).enumerate() {
+            // OodleLZ_GetCompressedBufferSizeNeeded, plus the same 64 bytes of
+            // slack the decoder gets.
+            let capacity = chunk.len() + 274 * chunk.len().div_ceil(0x40000).max(1) + 64;
+            let mut out = vec![0u8; capacity];
+            let ret = crate::ooz::compress(compressor as i32, chunk, &mut out, level)?;
+            if ret <= 0 || ret as usize > capacity {
+                log::error!("Ooz_Compress FAILED: ret={}, src_len={}", ret, chunk.len());
+                return Err(Error::OodleCompress { block, returned: ret });
+            }
+            out.truncate(ret as usize);
+            blocks.push(out);
+        }
+
+        let block_count = blocks.len() as u32;
+        let payload_size: u64 = blocks.iter().map(|b| b.len() as u64).sum();
+        let mut out = Vec::with_capacity(60 + blocks.len() * 4 + payload_size as usize);
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(payload_size as u32).to_le_bytes());
+        // Everything after the first 12 bytes: the rest of the header and
+        // the block-size table.
+        out.extend_from_slice(&(48 + block_count * 4).to_le_bytes());
+        out.extend_from_slice(&(compressor as u32).to_le_bytes());
+        out.extend_from_slice(&1u32.to_le_bytes());
+        out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        out.extend_from_slice(&payload_size.to_le_bytes());
+        out.extend_from_slice(&block_count.to_le_bytes());
+        out.extend_from_slice(&chunk_size.to_le_bytes());
+        out.extend_from_slice(&[0u8; 16]);
+        for block in &blocks {
+            out.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        }
+        for block in &blocks {
+            out.extend_from_slice(block);
+        }
+        Ok(out)
+    }
 
+    pub fn decompress_from_slice(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let size = self.uncompressed_size as usize;
+        let mut output = vec![0u8; size + SAFE_SPACE];
+        self.decode_run(data, 0..self.block_sizes.len(), &mut output)?;
         output.truncate(size);
         Ok(output)
     }
 }
 
+// ooz decoders may write up to 64 bytes past the destination end ("The
+// buffer supplied must have an additional 64 bytes of scratch space at the
+// end" — ooz/bun.h). Without this slack the final chunk corrupts the heap,
+// crashing the app with no trace during bulk exports.
+const SAFE_SPACE: usize = 64;
+
+/// Below this many blocks (2 MiB at the default chunk size) spawning
+/// threads costs more than it saves.
+const PARALLEL_MIN_BLOCKS: usize = 8;
+
+/// One decoder thread per core.
+pub fn default_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Decodes one block into `output[..dst_len]`. `output` must extend at least
+/// `SAFE_SPACE` bytes further.
+fn decode_block(block: usize, compressed: &[u8], output: &mut [u8], dst_len: usize) -> Result<()> {
+    assert!(output.len() >= dst_len + SAFE_SPACE, "decode_block: output lacks scratch space");
+    let ret = crate::ooz::decompress(compressed, output, dst_len);
+
+    if ret != dst_len as i32 {
+        log::error!("Ooz_Decompress FAILED: ret={}, dst_len={}", ret, dst_len);
+        return Err(Error::Oodle {
+            block,
+            returned: ret,
+            expected: dst_len,
+        });
+    }
+    Ok(())
+}
+
+/// A raw bundle plus the blocks decoded from it so far. Reading many files
+/// out of one bundle this way decodes each touched block once and never
+/// inflates blocks nobody asked for.
+pub struct BlockCache<'a> {
+    data: Cow<'a, [u8]>,
+    header: Bundle,
+    blocks: HashMap<usize, Vec<u8>>,
+}
+
+impl<'a> BlockCache<'a> {
+    pub fn new(data: Cow<'a, [u8]>) -> Result<Self> {
+        let header = Bundle::read_header(Cursor::new(&*data))?;
+        Ok(Self { data, header, blocks: HashMap::new() })
+    }
+
+    pub fn header(&self) -> &Bundle {
+        &self.header
+    }
+
+    /// Same result as [`Bundle::decompress_range`].
+    pub fn read(&mut self, offset: u64, size: u64) -> Result<Vec<u8>> {
+        self.header.check_range(offset, size)?;
+        let blocks = self.header.blocks_for_range(offset, size);
+        for block in blocks.clone() {
+            if !self.blocks.contains_key(&block) {
+                let decoded = self.header.decompress_block(&self.data, block)?;
+                self.blocks.insert(block, decoded);
+            }
+        }
+
+        let chunk = self.header.chunk_size as u64;
+        let mut out = Vec::with_capacity(size as usize);
+        for block in blocks {
+            let block_start = block as u64 * chunk;
+            let decoded = &self.blocks[&block];
+            let from = offset.saturating_sub(block_start) as usize;
+            let to = ((offset + size - block_start) as usize).min(decoded.len());
+            out.extend_from_slice(&decoded[from..to]);
+        }
+        Ok(out)
+    }
+}
+
+/// Where a file ended up inside a bundle built by [`BundleBuilder`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BundledFile {
+    pub path: String,
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// Packs files back to back into one bundle, the way the game lays them out.
+pub struct BundleBuilder {
+    data: Vec<u8>,
+    files: Vec<BundledFile>,
+    compressor: Compressor,
+    level: i32,
+    chunk_size: u32,
+}
+
+impl Default for BundleBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BundleBuilder {
+    pub fn new() -> Self {
+        Self {
+            data: Vec::new(),
+            files: Vec::new(),
+            compressor: Compressor::Leviathan,
+            level: DEFAULT_LEVEL,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    pub fn compressor(mut self, compressor: Compressor) -> Self {
+        self.compressor = compressor;
+        self
+    }
+
+    pub fn level(mut self, level: i32) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn chunk_size(mut self, chunk_size: u32) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    pub fn add_file(&mut self, path: &str, data: &[u8]) -> Result<&BundledFile> {
+        // Sizes and offsets in the index are u32.
+        if self.data.len() + data.len() > u32::MAX as usize {
+            return Err(Error::InvalidData(format!("bundle would exceed 4 GiB when adding {}", path)));
+        }
+        let offset = self.data.len() as u32;
+        self.data.extend_from_slice(data);
+        self.files.push(BundledFile { path: path.to_string(), offset, size: data.len() as u32 });
+        Ok(self.files.last().unwrap())
+    }
+
+    /// Uncompressed size so far; what the index records for the bundle.
+    pub fn uncompressed_size(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    pub fn files(&self) -> &[BundledFile] {
+        &self.files
+    }
+
+    /// Compresses the bundle. Returns the `.bundle.bin` bytes and the file
+    /// placements to record in the index.
+    pub fn build(self) -> Result<(Vec<u8>, Vec<BundledFile>)> {
+        let bytes = Bundle::compress(&self.data, self.compressor, self.level, self.chunk_size)?;
+        Ok((bytes, self.files))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -203,6 +466,170 @@ mod tests {
         };
 
         let err = bundle.decompress_from_slice(&[0, 0, 1]).unwrap_err();
-        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(matches!(
+            err,
+            Error::TruncatedRecord { what: "bundle block", offset: 2 }
+        ));
+    }
+
+    // The tests that build bundles need an encoder, which only the native
+    // backend has.
+    #[cfg(not(feature = "rust-oodle"))]
+    fn round_trip(compressor: Compressor, data: &[u8], chunk_size: u32) {
+        let bytes = Bundle::compress(data, compressor, DEFAULT_LEVEL, chunk_size).unwrap();
+        let mut cursor = std::io::Cursor::new(&bytes[..]);
+        let header = Bundle::read_header(&mut cursor).unwrap();
+        assert_eq!(header.uncompressed_size as usize, data.len());
+        assert_eq!(header.first_file_encode, compressor as u32);
+        assert_eq!(header.head_payload_size, 48 + header.block_count * 4);
+        assert_eq!(header.total_payload_size as usize, bytes.len() - header.data_offset as usize);
+        assert_eq!(header.block_count as usize, data.len().div_ceil(chunk_size as usize));
+
+        assert_eq!(header.decompress_from_slice(&bytes).unwrap(), data);
+        assert_eq!(header.decompress(std::io::Cursor::new(&bytes[..])).unwrap(), data);
+    }
+
+    #[cfg(not(feature = "rust-oodle"))]
+    #[test]
+    fn compress_round_trips_through_decompress() {
+        // Compressible text with a non-block-aligned tail.
+        let data: Vec<u8> = (0..300_000u32)
+            .flat_map(|i| format!("row {} value {}\n", i % 977, i % 13).into_bytes())
+            .take(600_001)
+            .collect();
+        for compressor in [Compressor::Kraken, Compressor::Mermaid, Compressor::Leviathan] {
+            round_trip(compressor, &data, DEFAULT_CHUNK_SIZE);
+        }
+        round_trip(Compressor::Leviathan, &data[..1000], 256);
+        round_trip(Compressor::Kraken, b"", DEFAULT_CHUNK_SIZE);
+    }
+
+    // Stored blocks would pass the round trip too, so this checks the
+    // encoder actually shrinks the data.
+    #[cfg(not(feature = "rust-oodle"))]
+    #[test]
+    fn native_compression_shrinks_blocks() {
+        let data: Vec<u8> = (0..100_000u32)
+            .flat_map(|i| format!("Metadata/Items/Rings/Ring{}\n", i % 211).into_bytes())
+            .take(DEFAULT_CHUNK_SIZE as usize * 2 + 999)
+            .collect();
+        for compressor in [Compressor::Kraken, Compressor::Mermaid, Compressor::Leviathan] {
+            let bytes = Bundle::compress(&data, compressor, DEFAULT_LEVEL, DEFAULT_CHUNK_SIZE).unwrap();
+            let header = Bundle::read_header(std::io::Cursor::new(&bytes[..])).unwrap();
+            assert_eq!(header.block_count, 3);
+            for (block, &size) in header.block_sizes.iter().enumerate() {
+                let raw = header.block_len(block);
+                assert!((size as usize) < raw / 4, "{:?} block {}: {} of {} bytes", compressor, block, size, raw);
+            }
+            assert_eq!(header.decompress_from_slice(&bytes).unwrap(), data);
+            assert_eq!(header.decompress_parallel(&bytes, 3).unwrap(), data);
+        }
+    }
+
+    #[cfg(feature = "rust-oodle")]
+    #[test]
+    fn rust_backend_refuses_to_compress() {
+        let err = Bundle::compress(b"data", Compressor::Kraken, DEFAULT_LEVEL, DEFAULT_CHUNK_SIZE).unwrap_err();
+        assert!(matches!(err, Error::Unsupported(_)), "{:?}", err);
+    }
+
+    #[cfg(not(feature = "rust-oodle"))]
+    #[test]
+    fn builder_places_files_back_to_back() {
+        let mut builder = BundleBuilder::new().compressor(Compressor::Kraken).chunk_size(64);
+        builder.add_file("Data/A.datc64", b"first").unwrap();
+        builder.add_file("Data/B.datc64", &[7u8; 100]).unwrap();
+        assert_eq!(builder.uncompressed_size(), 105);
+
+        let (bytes, files) = builder.build().unwrap();
+        assert_eq!(files[1], BundledFile { path: "Data/B.datc64".to_string(), offset: 5, size: 100 });
+
+        let header = Bundle::read_header(std::io::Cursor::new(&bytes[..])).unwrap();
+        let data = header.decompress_from_slice(&bytes).unwrap();
+        assert_eq!(&data[..5], b"first");
+        assert_eq!(&data[5..], &[7u8; 100][..]);
+    }
+
+    #[cfg(not(feature = "rust-oodle"))]
+    #[test]
+    fn decompress_range_matches_full_decompress() {
+        let data: Vec<u8> = (0..5000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let bytes = Bundle::compress(&data, Compressor::Kraken, DEFAULT_LEVEL, 1024).unwrap();
+        let header = Bundle::read_header(std::io::Cursor::new(&bytes[..])).unwrap();
+        let mut cache = BlockCache::new(Cow::Borrowed(&bytes[..])).unwrap();
+
+        // Inside one block, across a boundary, across several, the short
+        // tail block, and empty ranges.
+        for (offset, size) in [(10, 20), (1000, 48), (500, 3000), (4090, 910), (0, 5000), (4096, 0), (5000, 0)] {
+            let expected = &data[offset..offset + size];
+            assert_eq!(header.decompress_range(&bytes, offset as u64, size as u64).unwrap(), expected);
+            assert_eq!(cache.read(offset as u64, size as u64).unwrap(), expected);
+        }
+        assert_eq!(header.blocks_for_range(1000, 48), 0..2);
+        assert!(matches!(
+            header.decompress_range(&bytes, 4990, 11),
+            Err(Error::TruncatedRecord { what: "bundled file", offset: 4990 })
+        ));
+        assert_eq!(header.decompress_block(&bytes, 4).unwrap(), &data[4096..]);
+    }
+
+    #[cfg(not(feature = "rust-oodle"))]
+    #[test]
+    fn decompress_parallel_matches_sequential() {
+        let data: Vec<u8> = (0..20_000u32).map(|i| (i * 31 % 239) as u8).collect();
+        let bytes = Bundle::compress(&data, Compressor::Mermaid, DEFAULT_LEVEL, 1024).unwrap();
+        let header = Bundle::read_header(std::io::Cursor::new(&bytes[..])).unwrap();
+        assert_eq!(header.block_count, 20);
+
+        // Thread counts that do and don't divide the block count evenly,
+        // and more threads than blocks.
+        for threads in [1, 3, 4, 64] {
+            assert_eq!(header.decompress_parallel(&bytes, threads).unwrap(), data);
+        }
+        // A range wide enough to take the threaded path.
+        assert_eq!(header.decompress_range(&bytes, 100, 15_000).unwrap(), &data[100..15_100]);
+
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(matches!(
+            header.decompress_parallel(truncated, 4),
+            Err(Error::TruncatedRecord { what: "bundle block", .. })
+        ));
+    }
+
+    #[cfg(not(feature = "rust-oodle"))]
+    // Compares the single-threaded and threaded paths on a 64 MiB bundle.
+    // Run with: cargo test --release bench_parallel_decompress -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_parallel_decompress() {
+        let mut state = 0x2545F491u32;
+        let data: Vec<u8> = (0..64 << 20)
+            .map(|i| {
+                // Mostly repetitive with some noise, roughly like texture data.
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                if state.is_multiple_of(4) { state as u8 } else { (i / 64) as u8 }
+            })
+            .collect();
+        let bytes = Bundle::compress(&data, Compressor::Leviathan, DEFAULT_LEVEL, DEFAULT_CHUNK_SIZE).unwrap();
+        let header = Bundle::read_header(std::io::Cursor::new(&bytes[..])).unwrap();
+        println!("{} blocks, {} -> {} bytes", header.block_count, data.len(), bytes.len());
+
+        let t = std::time::Instant::now();
+        let sequential = header.decompress_from_slice(&bytes).unwrap();
+        let sequential_time = t.elapsed();
+        let t = std::time::Instant::now();
+        let parallel = header.decompress_parallel(&bytes, default_threads()).unwrap();
+        let parallel_time = t.elapsed();
+
+        assert!(sequential == data && parallel == data);
+        println!(
+            "sequential {:?}, parallel ({} threads) {:?}, {:.1}x",
+            sequential_time,
+            default_threads(),
+            parallel_time,
+            sequential_time.as_secs_f64() / parallel_time.as_secs_f64()
+        );
     }
 }
@@ -2,7 +2,7 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::io::Write;
 use reqwest::blocking::Client;
-use std::error::Error;
+use crate::error::{Error, Result};
 
 #[derive(Clone)]
 pub struct CdnBundleLoader {
@@ -28,15 +28,15 @@ impl CdnBundleLoader {
         &self.patch_ver
     }
 
-    pub fn fetch_bundle(&self, bundle_name: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    pub fn fetch_bundle(&self, bundle_name: &str) -> Result<Vec<u8>> {
         // Reject bundle names that are clearly index-internal paths, not real CDN bundles.
         // The index uses "Folders/" prefix for directory-structure metadata bundles that
         // don't exist as individual files on the CDN.
         if bundle_name.starts_with("Folders/") || bundle_name.starts_with("folders/") {
-            return Err(format!(
+            return Err(Error::InvalidData(format!(
                 "Bundle name '{}' starts with 'Folders/' which is an index-internal path, not a valid CDN bundle",
                 bundle_name
-            ).into());
+            )));
         }
 
         // 1. Check Local Cache
@@ -55,21 +55,19 @@ impl CdnBundleLoader {
              format!("https://patch.poecdn.com/{}/Bundles2/{}", self.patch_ver, bundle_name)
         };
 
-        println!("[CDN] Downloading: {}", url);
+        log::info!("[CDN] Downloading: {}", url);
         let resp = self.client.get(&url).send()?;
 
         let status = resp.status();
         if !status.is_success() {
-            if status.as_u16() == 404 {
-                return Err(format!(
-                    "CDN 404 Not Found: {} — patch version '{}' may be incorrect or the bundle doesn't exist on CDN",
-                    url, self.patch_ver
-                ).into());
+            if status == reqwest::StatusCode::NOT_FOUND {
+                return Err(Error::CdnNotFound { url, patch_version: self.patch_ver.clone() });
             }
-            return Err(format!(
-                "CDN Request Failed: {} ({}) — using patch version '{}'",
-                url, status, self.patch_ver
-            ).into());
+            return Err(Error::CdnStatus {
+                url,
+                status: status.as_u16(),
+                patch_version: self.patch_ver.clone(),
+            });
         }
 
         let bytes = resp.bytes()?;
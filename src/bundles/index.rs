@@ -1,3 +1,6 @@
+use crate::bundles::bundle::{Bundle, BundledFile, Compressor, DEFAULT_CHUNK_SIZE, DEFAULT_LEVEL};
+use crate::error::{Error, Result};
+use std::collections::BTreeMap;
 use std::io::{self, Cursor, Read};
 use byteorder::{ByteOrder, LittleEndian};
 use std::collections::HashMap;
@@ -26,52 +29,79 @@ pub struct DirectoryInfo {
     pub recursive_size: u32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Index {
     pub bundles: Vec<BundleInfo>,
     pub files: HashMap<u64, FileInfo>,
+    /// How the source index hashed paths; [`Index::write`] keeps it.
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
 }
 
+/// `murmur_hash64a("")`: the root directory's hash, which is what marks an
+/// index as using Murmur64A paths.
+const MURMUR_ROOT_HASH: u64 = 0xF42A94E69CFF42FE;
+
 /// Sentinel bundle_index value meaning "read this file as a loose FILE record
 /// from the GGPK itself" (e.g. FMOD/*.bank, Media/*.bk2). Distinct from
 /// `crate::bundles::steam::LOOSE_FILE_SENTINEL` (loose file on disk).
 pub const GGPK_LOOSE_FILE_SENTINEL: u32 = u32::MAX - 1;
 
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum HashAlgorithm {
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum HashAlgorithm {
+    /// Lowercased paths, Murmur64A. Used since patch 3.21.2.
+    #[default]
     Murmur64A,
+    /// Lowercased paths with `++` appended, FNV1a-64.
     Fnv1a,
+    /// The root directory hash matched neither; treated as Murmur64A when
+    /// hashing.
     Unknown,
 }
 
+impl HashAlgorithm {
+    /// The hash of a file or directory path (no trailing slash; the root is
+    /// the empty string).
+    pub fn hash_path(self, path: &str) -> u64 {
+        let lower = path.to_ascii_lowercase();
+        match self {
+            HashAlgorithm::Fnv1a => fnv1a64(format!("{}++", lower).as_bytes()),
+            HashAlgorithm::Murmur64A | HashAlgorithm::Unknown => murmur_hash64a(lower.as_bytes()),
+        }
+    }
+}
+
 impl Index {
-    pub fn read(data: &[u8]) -> io::Result<Self> {
+    pub fn read(data: &[u8]) -> Result<Self> {
         let mut cursor = Cursor::new(data);
-        
+        Self::parse(&mut cursor, data)
+            .map_err(|e| Error::truncated("bundle index", cursor.position())(e))
+    }
 
-        let bundle_count = read_i32(&mut cursor)?;
+    fn parse(cursor: &mut Cursor<&[u8]>, data: &[u8]) -> io::Result<Self> {
+        let bundle_count = read_i32(cursor)?;
         let mut bundles = Vec::with_capacity(bundle_count as usize);
         
         for _ in 0..bundle_count {
-            let name_len = read_i32(&mut cursor)?;
+            let name_len = read_i32(cursor)?;
             let mut name_buf = vec![0u8; name_len as usize];
             cursor.read_exact(&mut name_buf)?;
             let name = String::from_utf8_lossy(&name_buf).to_string();
             
-            let uncompressed_size = read_u32(&mut cursor)?;
+            let uncompressed_size = read_u32(cursor)?;
             bundles.push(BundleInfo { name, uncompressed_size });
         }
         
-        let file_count = read_i32(&mut cursor)?;
-        println!("Index::read: Found {} files", file_count);
+        let file_count = read_i32(cursor)?;
+        log::debug!("Index::read: Found {} files", file_count);
         let mut files_map = HashMap::with_capacity(file_count as usize);
         
         for _ in 0..file_count {
-            let path_hash = read_u64(&mut cursor)?;
-            let bundle_index = read_u32(&mut cursor)?;
-            let file_offset = read_u32(&mut cursor)?;
-            let file_size = read_u32(&mut cursor)?;
+            let path_hash = read_u64(cursor)?;
+            let bundle_index = read_u32(cursor)?;
+            let file_offset = read_u32(cursor)?;
+            let file_size = read_u32(cursor)?;
             
             files_map.insert(path_hash, FileInfo { 
                 path_hash, 
@@ -82,14 +112,14 @@ impl Index {
             });
         }
         
-        let directory_count = read_i32(&mut cursor)?;
+        let directory_count = read_i32(cursor)?;
         let mut directories = Vec::with_capacity(directory_count as usize);
         
         for _ in 0..directory_count {
-            let path_hash = read_u64(&mut cursor)?;
-            let offset = read_u32(&mut cursor)?;
-            let size = read_u32(&mut cursor)?;
-            let recursive_size = read_u32(&mut cursor)?;
+            let path_hash = read_u64(cursor)?;
+            let offset = read_u32(cursor)?;
+            let size = read_u32(cursor)?;
+            let recursive_size = read_u32(cursor)?;
             
             directories.push(DirectoryInfo { path_hash, offset, size, recursive_size });
         }
@@ -100,16 +130,16 @@ impl Index {
 
         let hash_algo = if let Some(first_dir) = directories.first() {
              match first_dir.path_hash {
-                 0xF42A94E69CFF42FE => {
-                     println!("Index::read: Detected Hash Algorithm: Murmur64A");
+                 MURMUR_ROOT_HASH => {
+                     log::debug!("Index::read: Detected Hash Algorithm: Murmur64A");
                      HashAlgorithm::Murmur64A
                  },
                  0x07E47507B4A92E53 => {
-                     println!("Index::read: Detected Hash Algorithm: FNV1a");
+                     log::debug!("Index::read: Detected Hash Algorithm: FNV1a");
                      HashAlgorithm::Fnv1a
                  },
                  other => {
-                     println!("Index::read: Unknown Hash Algorithm root hash: {:X}. Defaulting to fallback.", other);
+                     log::warn!("Index::read: Unknown Hash Algorithm root hash: {:X}. Defaulting to fallback.", other);
                      HashAlgorithm::Unknown
                  },
              }
@@ -122,16 +152,16 @@ impl Index {
              if let Ok(dir_data) = bundle.decompress(&mut dir_cursor) {
                  Self::parse_paths(&directories, &dir_data, &mut files_map, hash_algo);
              } else {
-                 println!("Failed to decompress directory bundle");
+                 log::warn!("Failed to decompress directory bundle");
              }
         } else {
-            println!("Failed to read directory bundle header");
+            log::warn!("Failed to read directory bundle header");
         }
 
         let populated_count = files_map.values().filter(|f| !f.path.is_empty()).count();
-        println!("Index::read: {}/{} files have paths", populated_count, files_map.len());
+        log::debug!("Index::read: {}/{} files have paths", populated_count, files_map.len());
         
-        Ok(Self { bundles, files: files_map })
+        Ok(Self { bundles, files: files_map, hash_algorithm: hash_algo })
     }
 
     /// Injects loose GGPK FILE records (FMOD/, Media/, root files, ...) into
@@ -161,18 +191,103 @@ impl Index {
         added
     }
 
-    pub fn save_to_cache<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+    pub fn save_to_cache<P: AsRef<std::path::Path>>(&self, path: P) -> Result<()> {
         let file = std::fs::File::create(path)?;
         let mut writer = std::io::BufWriter::new(file);
-        bincode::serialize_into(&mut writer, self)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+        bincode::serialize_into(&mut writer, self).map_err(|e| cache_error(*e))
     }
 
-    pub fn load_from_cache<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Self> {
+    pub fn load_from_cache<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
         let file = std::fs::File::open(path)?;
         let mut reader = std::io::BufReader::new(file);
-        bincode::deserialize_from(&mut reader)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+        bincode::deserialize_from(&mut reader).map_err(|e| cache_error(*e))
+    }
+
+    /// Registers a bundle written with [`BundleBuilder`](crate::bundles::bundle::BundleBuilder)
+    /// and points its files at it, replacing any existing records for the
+    /// same paths. `name` is the bundle path without `.bundle.bin`.
+    pub fn add_bundle(&mut self, name: &str, uncompressed_size: u32, files: &[BundledFile]) -> u32 {
+        let bundle_index = match self.bundles.iter().position(|b| b.name == name) {
+            Some(i) => {
+                self.bundles[i].uncompressed_size = uncompressed_size;
+                i as u32
+            }
+            None => {
+                self.bundles.push(BundleInfo { name: name.to_string(), uncompressed_size });
+                (self.bundles.len() - 1) as u32
+            }
+        };
+        for file in files {
+            let path_hash = self.hash_algorithm.hash_path(&file.path);
+            self.files.insert(path_hash, FileInfo {
+                path_hash,
+                bundle_index,
+                file_offset: file.offset,
+                file_size: file.size,
+                path: file.path.clone(),
+            });
+        }
+        bundle_index
+    }
+
+    /// Serializes the index to a complete `_.index.bin`: the bundle, file
+    /// and directory tables plus the path-rep bundle, all wrapped in a
+    /// bundle of their own.
+    ///
+    /// Paths are written lowercased and hashed with the source index's
+    /// [`HashAlgorithm`], so files whose path was never resolved can keep
+    /// their old hash; they can't appear in the path-rep. Loose files injected after
+    /// loading (see [`Index::add_ggpk_loose_files`]) are not part of the
+    /// index and are dropped.
+    pub fn write(&self, compressor: Compressor) -> Result<Vec<u8>> {
+        let body = self.encode(compressor)?;
+        Bundle::compress(&body, compressor, DEFAULT_LEVEL, DEFAULT_CHUNK_SIZE)
+    }
+
+    /// The uncompressed index body, i.e. what [`Index::read`] parses.
+    pub fn encode(&self, compressor: Compressor) -> Result<Vec<u8>> {
+        let bundle_count = self.bundles.len() as u32;
+        let mut files: Vec<(u64, &FileInfo)> = self
+            .files
+            .values()
+            .filter(|f| f.bundle_index < bundle_count)
+            .map(|f| {
+                let hash = if f.path.is_empty() {
+                    f.path_hash
+                } else {
+                    self.hash_algorithm.hash_path(&f.path)
+                };
+                (hash, f)
+            })
+            .collect();
+        files.sort_by_key(|&(hash, _)| hash);
+
+        let (directories, path_rep) = build_path_rep(files.iter().map(|(_, f)| f.path.as_str()), self.hash_algorithm);
+        let path_rep = Bundle::compress(&path_rep, compressor, DEFAULT_LEVEL, DEFAULT_CHUNK_SIZE)?;
+
+        let mut out = Vec::new();
+        out.extend_from_slice(&bundle_count.to_le_bytes());
+        for bundle in &self.bundles {
+            out.extend_from_slice(&(bundle.name.len() as u32).to_le_bytes());
+            out.extend_from_slice(bundle.name.as_bytes());
+            out.extend_from_slice(&bundle.uncompressed_size.to_le_bytes());
+        }
+        out.extend_from_slice(&(files.len() as u32).to_le_bytes());
+        for (hash, f) in &files {
+            out.extend_from_slice(&hash.to_le_bytes());
+            out.extend_from_slice(&f.bundle_index.to_le_bytes());
+            out.extend_from_slice(&f.file_offset.to_le_bytes());
+            out.extend_from_slice(&f.file_size.to_le_bytes());
+        }
+        out.extend_from_slice(&(directories.len() as u32).to_le_bytes());
+        for d in &directories {
+            out.extend_from_slice(&d.path_hash.to_le_bytes());
+            out.extend_from_slice(&d.offset.to_le_bytes());
+            out.extend_from_slice(&d.size.to_le_bytes());
+            out.extend_from_slice(&d.recursive_size.to_le_bytes());
+        }
+        out.extend_from_slice(&path_rep);
+        Ok(out)
     }
 
     fn parse_paths(directories: &[DirectoryInfo], dir_data: &[u8], files: &mut HashMap<u64, FileInfo>, hash_algo: HashAlgorithm) {
@@ -271,8 +386,15 @@ impl Index {
                              // Let's try Original first, then Lower?
                              // Optimization: Only compute FNV.
                              
+                             // Current writers (and ours) hash the lowercased
+                             // path with "++" appended, which is also what
+                             // gives the root its 0x07E47507B4A92E53.
+                             let mut suffixed = full_path_bytes.to_ascii_lowercase();
+                             suffixed.extend_from_slice(b"++");
                              let hash = fnv1a64(&full_path_bytes);
-                             if let Some(f) = files.get_mut(&hash) {
+                             if let Some(f) = files.get_mut(&fnv1a64(&suffixed)) {
+                                 f.path = path_str.clone();
+                             } else if let Some(f) = files.get_mut(&hash) {
                                  f.path = path_str.clone();
                              } else {
                                  // Fallback to lower?
@@ -303,6 +425,74 @@ impl Index {
     }
 }
 
+/// Lays out the path-rep data for `paths`: one chunk per directory, root
+/// first and the rest in depth-first order so a directory's descendants
+/// follow it. Each chunk is the simplest form `parse_paths` accepts: the
+/// directory as the only base string, then every file name appended to it.
+/// Directories holding only subdirectories get an empty chunk.
+fn build_path_rep<'a>(paths: impl Iterator<Item = &'a str>, hash_algorithm: HashAlgorithm) -> (Vec<DirectoryInfo>, Vec<u8>) {
+    // Keyed by path components so that sorting yields depth-first order.
+    let mut dirs: BTreeMap<Vec<String>, Vec<String>> = BTreeMap::new();
+    dirs.insert(Vec::new(), Vec::new());
+    for path in paths.filter(|p| !p.is_empty()) {
+        let lower = path.to_ascii_lowercase();
+        let (dir, name): (Vec<String>, String) = match lower.rsplit_once('/') {
+            Some((dir, name)) => (dir.split('/').map(str::to_string).collect(), name.to_string()),
+            None => (Vec::new(), lower),
+        };
+        for depth in 1..dir.len() {
+            dirs.entry(dir[..depth].to_vec()).or_default();
+        }
+        dirs.entry(dir).or_default().push(name);
+    }
+
+    let mut data = Vec::new();
+    let mut directories = Vec::with_capacity(dirs.len());
+    let mut keys = Vec::with_capacity(dirs.len());
+    for (dir, names) in &dirs {
+        let offset = data.len() as u32;
+        let joined = dir.join("/");
+        let base = if joined.is_empty() { String::new() } else { format!("{}/", joined) };
+        if !names.is_empty() {
+            // 0 toggles base mode; an index past the base list means "no prefix".
+            data.extend_from_slice(&0u32.to_le_bytes());
+            data.extend_from_slice(&1u32.to_le_bytes());
+            data.extend_from_slice(base.as_bytes());
+            data.push(0);
+            data.extend_from_slice(&0u32.to_le_bytes());
+            for name in names {
+                data.extend_from_slice(&1u32.to_le_bytes());
+                data.extend_from_slice(name.as_bytes());
+                data.push(0);
+            }
+        }
+        directories.push(DirectoryInfo {
+            path_hash: hash_algorithm.hash_path(&joined),
+            offset,
+            size: data.len() as u32 - offset,
+            recursive_size: 0,
+        });
+        keys.push(dir);
+    }
+    // Descendants are contiguous, so the recursive size runs to the end of
+    // the last one.
+    for i in 0..directories.len() {
+        let end = (i + 1..keys.len())
+            .take_while(|&j| keys[j].starts_with(keys[i]))
+            .last()
+            .map_or(i, |j| j);
+        directories[i].recursive_size = directories[end].offset + directories[end].size - directories[i].offset;
+    }
+    (directories, data)
+}
+
+fn cache_error(e: bincode::ErrorKind) -> Error {
+    match e {
+        bincode::ErrorKind::Io(e) => Error::Io(e),
+        e => Error::parse("index cache", e),
+    }
+}
+
 pub fn murmur_hash64a(key: &[u8]) -> u64 {
     let seed: u64 = 0x1337B33F;
     let m: u64 = 0xc6a4a7935bd1e995;
@@ -386,5 +576,86 @@ fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
     Ok(LittleEndian::read_u64(&buf))
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    fn file(path: &str, bundle_index: u32, file_offset: u32, file_size: u32) -> FileInfo {
+        FileInfo { path_hash: 0, bundle_index, file_offset, file_size, path: path.to_string() }
+    }
 
+    #[test]
+    fn path_rep_round_trips_through_parse_paths() {
+        let paths = ["Data/Mods.datc64", "data/balance/stats.datc64", "art/2dart/ui/a.dds", "root.txt"];
+        let (directories, data) = build_path_rep(paths.iter().copied(), HashAlgorithm::Murmur64A);
+        assert_eq!(directories[0].path_hash, MURMUR_ROOT_HASH);
+        // art/ and art/2dart/ hold only a subdirectory but are listed.
+        let art = directories.iter().find(|d| d.path_hash == murmur_hash64a(b"art/2dart")).unwrap();
+        let ui = directories.iter().find(|d| d.path_hash == murmur_hash64a(b"art/2dart/ui")).unwrap();
+        assert_eq!((art.size, art.recursive_size), (0, ui.size));
+        assert!(directories.iter().any(|d| d.path_hash == murmur_hash64a(b"art")));
+        // Root recurses over everything; data/ also covers data/balance/.
+        assert_eq!(directories[0].recursive_size as usize, data.len());
+        let data_dir = directories.iter().find(|d| d.path_hash == murmur_hash64a(b"data")).unwrap();
+        let balance = directories.iter().find(|d| d.path_hash == murmur_hash64a(b"data/balance")).unwrap();
+        assert_eq!(data_dir.recursive_size, data_dir.size + balance.size);
+
+        let mut files: HashMap<u64, FileInfo> = paths
+            .iter()
+            .map(|p| {
+                let hash = murmur_hash64a(p.to_ascii_lowercase().as_bytes());
+                (hash, FileInfo { path_hash: hash, ..file("", 0, 0, 0) })
+            })
+            .collect();
+        Index::parse_paths(&directories, &data, &mut files, HashAlgorithm::Murmur64A);
+        for p in paths {
+            let f = &files[&murmur_hash64a(p.to_ascii_lowercase().as_bytes())];
+            assert_eq!(f.path, p.to_ascii_lowercase());
+        }
+    }
+
+    #[cfg(not(feature = "rust-oodle"))]
+    #[test]
+    fn write_round_trips_through_read() {
+        let mut index = Index::default();
+        index.add_bundle("Data/A", 30, &[
+            BundledFile { path: "Data/Mods.datc64".to_string(), offset: 0, size: 10 },
+            BundledFile { path: "Data/Balance/Stats.datc64".to_string(), offset: 10, size: 20 },
+        ]);
+        index.add_bundle("Art/B", 5, &[BundledFile { path: "art/a.dds".to_string(), offset: 0, size: 5 }]);
+        index.files.insert(1, FileInfo { path_hash: 1, ..file("FMOD/x.bank", GGPK_LOOSE_FILE_SENTINEL, 0, 9) });
+
+        let bytes = index.write(Compressor::Leviathan).unwrap();
+        let header = Bundle::read_header(Cursor::new(&bytes[..])).unwrap();
+        let read = Index::read(&header.decompress_from_slice(&bytes).unwrap()).unwrap();
+
+        assert_eq!(read.bundles.iter().map(|b| (b.name.as_str(), b.uncompressed_size)).collect::<Vec<_>>(),
+            vec![("Data/A", 30), ("Art/B", 5)]);
+        assert_eq!(read.files.len(), 3);
+        let stats = &read.files[&murmur_hash64a(b"data/balance/stats.datc64")];
+        assert_eq!((stats.bundle_index, stats.file_offset, stats.file_size), (0, 10, 20));
+        assert_eq!(stats.path, "data/balance/stats.datc64");
+        assert_eq!(read.files[&murmur_hash64a(b"art/a.dds")].bundle_index, 1);
+    }
+
+    #[cfg(not(feature = "rust-oodle"))]
+    #[test]
+    fn write_keeps_fnv_hashes() {
+        let fnv = |path: &str| fnv1a64(format!("{}++", path).as_bytes());
+        let mut index = Index { hash_algorithm: HashAlgorithm::Fnv1a, ..Index::default() };
+        index.add_bundle("Data/A", 30, &[BundledFile { path: "Data/Balance/Stats.datc64".to_string(), offset: 0, size: 30 }]);
+        // A file the source index never named keeps its hash.
+        index.files.insert(42, FileInfo { path_hash: 42, ..file("", 0, 0, 1) });
+        assert!(index.files.contains_key(&fnv("data/balance/stats.datc64")));
+
+        let body = index.encode(Compressor::Kraken).unwrap();
+        let read = Index::read(&body).unwrap();
+        assert_eq!(read.hash_algorithm, HashAlgorithm::Fnv1a);
+        let mut hashes: Vec<u64> = read.files.keys().copied().collect();
+        hashes.sort();
+        let mut expected = vec![42, fnv("data/balance/stats.datc64")];
+        expected.sort();
+        assert_eq!(hashes, expected);
+        assert_eq!(read.files[&fnv("data/balance/stats.datc64")].path, "data/balance/stats.datc64");
+    }
+}
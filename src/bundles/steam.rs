@@ -1,7 +1,6 @@
 use std::path::PathBuf;
-use std::io;
+use crate::error::{Error, Result};
 use crate::bundles::index::{Index, FileInfo, murmur_hash64a};
-use crate::bundles::bundle::Bundle;
 
 /// Sentinel bundle_index value meaning "read this file from disk, not from a bundle".
 pub const LOOSE_FILE_SENTINEL: u32 = u32::MAX;
@@ -50,7 +49,7 @@ impl SteamBundleLoader {
             }
             self.scan_loose_dir(&dir, &root, index);
         }
-        println!("Steam loose file scan complete");
+        log::debug!("Steam loose file scan complete");
     }
 
     fn scan_loose_dir(&self, dir: &std::path::Path, root: &std::path::Path, index: &mut Index) {
@@ -92,36 +91,29 @@ impl SteamBundleLoader {
         }
     }
 
-    pub fn load_index_bytes(&self) -> io::Result<Vec<u8>> {
+    pub fn load_index_bytes(&self) -> Result<Vec<u8>> {
         let path = self.bundles2_dir.join("_.index.bin");
-        std::fs::read(&path)
+        Ok(std::fs::read(&path)?)
     }
 
     /// Reads a raw (compressed) bundle file from the Bundles2 directory.
-    pub fn fetch_bundle(&self, bundle_name: &str) -> io::Result<Vec<u8>> {
+    pub fn fetch_bundle(&self, bundle_name: &str) -> Result<Vec<u8>> {
         let name = if bundle_name.ends_with(".bundle.bin") {
             bundle_name.to_string()
         } else {
             format!("{}.bundle.bin", bundle_name)
         };
         let path = self.bundles2_dir.join(&name);
-        std::fs::read(&path)
+        std::fs::read(&path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => Error::BundleMissing(name),
+            _ => Error::Io(e),
+        })
     }
 
     /// Convenience: decompresses a bundle and extracts one file by hash.
     pub fn load_file(&self, index: &Index, hash: u64) -> Option<Vec<u8>> {
         let file_info = index.files.get(&hash)?;
-        let bundle_info = index.bundles.get(file_info.bundle_index as usize)?;
-        let raw = self.fetch_bundle(&bundle_info.name).ok()?;
-        let mut cursor = std::io::Cursor::new(raw);
-        let header = Bundle::read_header(&mut cursor).ok()?;
-        let data = header.decompress(&mut cursor).ok()?;
-        let start = file_info.file_offset as usize;
-        let end = start + file_info.file_size as usize;
-        if end <= data.len() {
-            Some(data[start..end].to_vec())
-        } else {
-            None
-        }
+        let sources = crate::vfs::BundleSources { steam: Some(self), ..Default::default() };
+        sources.read_file(index, file_info).ok()
     }
 }
@@ -1,73 +1,1180 @@
-use crate::ggpk::reader::GgpkReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::bundles::cdn::CdnBundleLoader;
+use crate::dat::schema::Schema;
+use crate::error::Error;
+use crate::export::{AudioFormat, DataFormat, ExportSettings, ExportStatus, PsgFormat, TextureFormat};
 use crate::settings::AppSettings;
+use crate::vfs::{EntryKind, Vfs, VfsEntry};
+
+const EXTRACT_USAGE: &str = "\
+Usage: ggpk-explorer extract <source> <path-or-glob>... -o <dir> [options]
+
+  <source>            Content.ggpk, a Steam Bundles2 directory, the game
+                      install directory that contains Bundles2, or
+                      cdn:<patch-version> to download from the patch CDN
+  <path-or-glob>      Virtual path (a file or a whole directory) or a glob
+                      such as 'art/2dart/**/*.dds'. Matching is case-insensitive.
+
+Options:
+  -o, --output <dir>  Directory to write extracted files to (required)
+  --texture <fmt>     dds | png | webp          (default: dds)
+  --audio <fmt>       original | wav            (default: original)
+  --data <fmt>        original | json | csv | arrow | sqlite  (default: original);
+                      csv and arrow write one file per table, sqlite writes
+                      every table to <dir>/tables.sqlite
+  --psg <fmt>         original | json           (default: original)
+  --schema <file>     schema.min.json used for dat conversion and path
+                      enrichment (default: the one the GUI uses, with the
+                      local schema overlay merged in)";
 
-fn murmur_hash64a(key: &[u8], seed: u64) -> u64 {
-    let m: u64 = 0xc6a4a7935bd1e995;
-    let r: u8 = 47;
-    let len = key.len() as u64;
-    let mut h: u64 = seed ^ (len.wrapping_mul(m));
-    let n_blocks = len / 8;
-    let md = key;
-    for i in 0..n_blocks {
-        let idx = (i * 8) as usize;
-        let mut k: u64 = u64::from_le_bytes(md[idx..idx+8].try_into().unwrap());
-        k = k.wrapping_mul(m);
-        k ^= k >> r;
-        k = k.wrapping_mul(m);
-        h ^= k;
-        h = h.wrapping_mul(m);
-    }
-    let remainder_idx = (n_blocks * 8) as usize;
-    let remaining_len = (len & 7) as usize;
-    if remaining_len > 0 {
-        let mut k: u64 = 0;
-        for i in 0..remaining_len {
-             k ^= (md[remainder_idx + i] as u64) << (8 * i);
-        }
-        h ^= k;
-        h = h.wrapping_mul(m);
-    }
-    h ^= h >> r;
-    h = h.wrapping_mul(m);
-    h ^= h >> r;
-    h
-}
-
-pub fn run_inspect() -> Result<(), Box<dyn std::error::Error>> {
+pub fn run_inspect() -> crate::error::Result<()> {
     let settings = AppSettings::load();
-    let ggpk_path = settings.ggpk_path.ok_or("No GGPK Path")?;
-    
+    let ggpk_path = settings
+        .ggpk_path
+        .ok_or_else(|| Error::NotFound("GGPK path in settings".to_string()))?;
+
     println!("Opening GGPK at: {}", ggpk_path);
-    let reader = GgpkReader::open(&ggpk_path)?;
+    let vfs = Vfs::open_ggpk(&ggpk_path)?;
     
     println!("--- GGPK INSPECTOR ---");
-    
+    println!("Index Loaded: {} files", vfs.index().files.len());
+
+    let target = "data/balance/activeskills.datc64";
+    let (hash, file) = vfs
+        .lookup(target)
+        .ok_or_else(|| Error::NotFound(target.to_string()))?;
+    println!("Verified Hash for '{}': {:016x}", target, hash);
+    println!("  Bundle Index: {}", file.bundle_index);
+    let data = vfs.read_hash(hash)?;
+    println!("  Read {} bytes", data.len());
 
-    if let Ok(Some(index_file_record)) = reader.read_file_by_path("Bundles2/_.index.bin") {
-        println!("Found Bundles2/_.index.bin");
-        let data = reader.get_data_slice(index_file_record.data_offset, index_file_record.data_length)?;
-        let mut cursor = std::io::Cursor::new(data);
-        
-        if let Ok(bundle) = crate::bundles::bundle::Bundle::read_header(&mut cursor) {
-             if let Ok(decomp) = bundle.decompress(&mut cursor) {
-                 if let Ok(index) = crate::bundles::index::Index::read(&decomp) {
-                     println!("Index Loaded: {} files", index.files.len());
-                     
-                     let target = "data/balance/activeskills.datc64";
-                     let hash = murmur_hash64a(target.to_lowercase().as_bytes(), 0x1337b33f);
-                     if let Some(file) = index.files.get(&hash) {
-                         println!("Verified Hash for '{}': {:016x}", target, hash);
-                         println!("  Bundle Index: {}", file.bundle_index);
-                     }
-                 }
-             }
+    if let Some(reader) = vfs.reader() {
+        let entries = reader.list_files_in_directory("Bundles2")?;
+        println!("Bundles2 Children: {:?}", entries);
+    }
+
+    Ok(())
+}
+
+/// Opens `spec` as a GGPK file, a `Bundles2` directory, a game root that
+/// contains `Bundles2`, or `cdn:<patch-version>` to stream everything from
+/// the patch CDN, then resolves unnamed paths with `schema`.
+/// The GUI's on-disk index cache is deliberately bypassed: it belongs to
+/// whichever install the GUI opened last, not necessarily this one.
+pub fn open_source(spec: &str, schema: Option<&Schema>) -> Result<Vfs, Box<dyn std::error::Error>> {
+    let settings = AppSettings::load();
+    let cache_root = AppSettings::get_app_data_dir().join("cache");
+    let _ = std::fs::create_dir_all(&cache_root);
+
+    let path = Path::new(spec);
+    let mut vfs = if let Some(version) = spec.strip_prefix("cdn:") {
+        // Bundle files are cached by name only, so keep each version's
+        // `_.index.bin` (and bundles) apart from the GUI's shared cache.
+        Vfs::open_cdn(&cache_root.join("cdn").join(version), version)?
+    } else if path.is_file() {
+        let cdn = CdnBundleLoader::new(&cache_root, Some(settings.poe2_patch_version.as_str()));
+        Vfs::open_ggpk(path)?.with_cdn(cdn)
+    } else if path.join("_.index.bin").is_file() {
+        Vfs::open_steam(path)?
+    } else if path.join("Bundles2").join("_.index.bin").is_file() {
+        Vfs::open_steam(path.join("Bundles2"))?
+    } else {
+        return Err(format!(
+            "{} is neither a GGPK file nor a directory containing Bundles2/_.index.bin",
+            spec
+        )
+        .into());
+    };
+
+    if let Some(schema) = schema {
+        vfs.enrich_paths(schema);
+    }
+    Ok(vfs)
+}
+
+/// Loads the schema from `explicit`, or from the same locations the GUI
+/// reads on startup with the local schema overlay merged in. An explicit
+/// schema is used as given. A missing schema is not an error: dat files
+/// are then exported as-is.
+pub fn load_schema(explicit: Option<&Path>) -> Result<Option<Schema>, Box<dyn std::error::Error>> {
+    let path = match explicit {
+        Some(p) => p.to_path_buf(),
+        None => {
+            let settings = AppSettings::load();
+            let default = AppSettings::get_app_data_dir().join("schema.min.json");
+            let p = settings.schema_local_path.map(PathBuf::from).unwrap_or(default);
+            if !p.exists() {
+                return Ok(None);
+            }
+            p
         }
+    };
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read schema {}: {}", path.display(), e))?;
+    let mut schema: Schema = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse schema {}: {}", path.display(), e))?;
+    if explicit.is_none() {
+        crate::dat::overlay::apply_default(&mut schema);
     }
-    
+    Ok(Some(schema))
+}
 
-    if let Ok(entries) = reader.list_files_in_directory("Bundles2") {
-        println!("Bundles2 Children: {:?}", entries);
+/// A virtual-path pattern given on the command line.
+///
+/// Patterns without glob characters match that exact file or everything
+/// below it when it names a directory. Globs use `*` (within one path
+/// segment), `**` (across segments) and `?`.
+pub enum PathMatcher {
+    Exact(String),
+    Glob(regex::Regex),
+}
+
+impl PathMatcher {
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        let pattern = pattern.replace('\\', "/").trim_matches('/').to_lowercase();
+        if !pattern.contains(['*', '?']) {
+            return Ok(PathMatcher::Exact(pattern));
+        }
+
+        let mut re = String::from("^");
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    // `**/` also matches zero directories.
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        re.push_str("(?:.*/)?");
+                    } else {
+                        re.push_str(".*");
+                    }
+                }
+                '*' => re.push_str("[^/]*"),
+                '?' => re.push_str("[^/]"),
+                c => re.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        re.push('$');
+        Ok(PathMatcher::Glob(regex::Regex::new(&re)?))
+    }
+
+    /// `path` must already be lowercased.
+    pub fn is_match(&self, path: &str) -> bool {
+        match self {
+            PathMatcher::Exact(p) => {
+                p.is_empty()
+                    || path == p
+                    || (path.len() > p.len() && path.starts_with(p.as_str()) && path.as_bytes()[p.len()] == b'/')
+            }
+            PathMatcher::Glob(re) => re.is_match(path),
+        }
+    }
+}
+
+fn parse_texture_format(s: &str) -> Result<TextureFormat, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "dds" | "original" => Ok(TextureFormat::OriginalDds),
+        "png" => Ok(TextureFormat::Png),
+        "webp" => Ok(TextureFormat::WebP),
+        _ => Err(format!("Unknown texture format '{}' (expected dds, png or webp)", s)),
+    }
+}
+
+fn parse_audio_format(s: &str) -> Result<AudioFormat, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "original" => Ok(AudioFormat::Original),
+        "wav" => Ok(AudioFormat::Wav),
+        _ => Err(format!("Unknown audio format '{}' (expected original or wav)", s)),
+    }
+}
+
+fn parse_data_format(s: &str) -> Result<DataFormat, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "original" => Ok(DataFormat::Original),
+        "json" => Ok(DataFormat::Json),
+        "csv" => Ok(DataFormat::Csv),
+        "arrow" => Ok(DataFormat::Arrow),
+        "sqlite" => Ok(DataFormat::Sqlite),
+        _ => Err(format!("Unknown data format '{}' (expected original, json, csv, arrow or sqlite)", s)),
+    }
+}
+
+fn parse_psg_format(s: &str) -> Result<PsgFormat, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "original" => Ok(PsgFormat::Original),
+        "json" => Ok(PsgFormat::Json),
+        _ => Err(format!("Unknown psg format '{}' (expected original or json)", s)),
+    }
+}
+
+/// `ggpk-explorer extract <source> <path-or-glob>... -o <dir>`.
+/// `args` are the arguments after `extract`. Returns an error (and the
+/// process exits non-zero) if anything failed to export.
+pub fn run_extract(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut positional = Vec::new();
+    let mut output: Option<PathBuf> = None;
+    let mut schema_path: Option<PathBuf> = None;
+    let mut settings = ExportSettings::default();
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = |name: &str| {
+            it.next()
+                .cloned()
+                .ok_or_else(|| format!("{} requires a value\n\n{}", name, EXTRACT_USAGE))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", EXTRACT_USAGE);
+                return Ok(());
+            }
+            "-o" | "--output" => output = Some(PathBuf::from(value(arg)?)),
+            "--texture" => settings.texture_format = parse_texture_format(&value(arg)?)?,
+            "--audio" => settings.audio_format = parse_audio_format(&value(arg)?)?,
+            "--data" => settings.data_format = parse_data_format(&value(arg)?)?,
+            "--psg" => settings.psg_format = parse_psg_format(&value(arg)?)?,
+            "--schema" => schema_path = Some(PathBuf::from(value(arg)?)),
+            s if s.starts_with('-') && s.len() > 1 => {
+                return Err(format!("Unknown option '{}'\n\n{}", s, EXTRACT_USAGE).into());
+            }
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    if positional.len() < 2 {
+        return Err(format!("Missing <source> or <path-or-glob>\n\n{}", EXTRACT_USAGE).into());
+    }
+    let output = output.ok_or_else(|| format!("Missing -o <dir>\n\n{}", EXTRACT_USAGE))?;
+    let matchers = positional[1..]
+        .iter()
+        .map(|p| PathMatcher::new(p).map_err(|e| format!("Invalid pattern '{}': {}", p, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let schema = load_schema(schema_path.as_deref())?;
+    if schema.is_none() && settings.data_format != DataFormat::Original {
+        eprintln!("Warning: no schema found, dat files will be exported as-is");
+    }
+
+    eprintln!("Opening {}...", positional[0]);
+    let source = open_source(&positional[0], schema.as_ref())?;
+
+    let mut hashes: Vec<u64> = source
+        .index()
+        .files
+        .iter()
+        .filter(|(_, f)| !f.path.is_empty())
+        .filter(|(_, f)| {
+            let lower = f.path.to_lowercase();
+            matchers.iter().any(|m| m.is_match(&lower))
+        })
+        .map(|(hash, _)| *hash)
+        .collect();
+    hashes.sort_unstable();
+
+    if hashes.is_empty() {
+        return Err("No files matched".into());
+    }
+    eprintln!("Extracting {} files to {}", hashes.len(), output.display());
+    std::fs::create_dir_all(&output)?;
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let worker = std::thread::spawn(move || {
+        let context = crate::export::ExportContext::new(settings, output, schema);
+        crate::export::run_export(hashes, None, Some(Arc::new(source)), context, tx, None);
+    });
+
+    let mut outcome: Result<(), Box<dyn std::error::Error>> = Err("Export ended without a result".into());
+    for status in rx {
+        match status {
+            ExportStatus::Progress { current, total, filename } => {
+                eprintln!("[{}/{}] {}", current, total, filename);
+            }
+            ExportStatus::Complete { count, errors, message } => {
+                eprintln!("{}", message);
+                outcome = if errors > 0 {
+                    Err(format!("{} of {} files failed to export", errors, count + errors).into())
+                } else {
+                    Ok(())
+                };
+            }
+            ExportStatus::Error(e) => outcome = Err(e.into()),
+        }
+    }
+    let _ = worker.join();
+    outcome
+}
+
+const LIST_USAGE: &str = "\
+Usage: ggpk-explorer ls <source> [dir] [options]
+       ggpk-explorer tree <source> [dir] [options]
+
+  ls lists the files and subdirectories directly inside <dir>; tree lists
+  every file below it. <dir> defaults to the root of the index.
+
+Options:
+  --format <fmt>      text | jsonl | csv        (default: text)
+  --schema <file>     schema.min.json used for path enrichment";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListFormat {
+    Text,
+    Jsonl,
+    Csv,
+}
+
+fn csv_field(s: &str) -> std::borrow::Cow<'_, str> {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\"")).into()
+    } else {
+        s.into()
+    }
+}
+
+fn write_entries(out: &mut impl std::io::Write, entries: &[VfsEntry], format: ListFormat) -> std::io::Result<()> {
+    if format == ListFormat::Csv {
+        writeln!(out, "path,size,kind,bundle,offset")?;
+    }
+    for e in entries {
+        match format {
+            ListFormat::Text => {
+                let location = match (e.bundle, e.offset) {
+                    (Some(b), Some(o)) => format!("{}@{}", b, o),
+                    _ => format!("<{}>", e.kind.as_str()),
+                };
+                let slash = if e.kind == EntryKind::Dir { "/" } else { "" };
+                writeln!(out, "{:>12}  {:<40}  {}{}", e.size, location, e.path, slash)?;
+            }
+            ListFormat::Jsonl => {
+                let line = serde_json::json!({
+                    "path": e.path,
+                    "size": e.size,
+                    "kind": e.kind.as_str(),
+                    "bundle": e.bundle,
+                    "offset": e.offset,
+                });
+                writeln!(out, "{}", line)?;
+            }
+            ListFormat::Csv => {
+                writeln!(
+                    out,
+                    "{},{},{},{},{}",
+                    csv_field(&e.path),
+                    e.size,
+                    e.kind.as_str(),
+                    csv_field(e.bundle.unwrap_or("")),
+                    e.offset.map(|o| o.to_string()).unwrap_or_default()
+                )?;
+            }
+        }
+    }
+    out.flush()
+}
+
+/// `ggpk-explorer ls|tree <source> [dir]`. `args` are the arguments after
+/// the subcommand; `recursive` selects `tree`.
+pub fn run_list(args: &[String], recursive: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut positional = Vec::new();
+    let mut format = ListFormat::Text;
+    let mut schema_path: Option<PathBuf> = None;
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = |name: &str| {
+            it.next()
+                .cloned()
+                .ok_or_else(|| format!("{} requires a value\n\n{}", name, LIST_USAGE))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", LIST_USAGE);
+                return Ok(());
+            }
+            "--format" => {
+                format = match value(arg)?.to_ascii_lowercase().as_str() {
+                    "text" => ListFormat::Text,
+                    "jsonl" => ListFormat::Jsonl,
+                    "csv" => ListFormat::Csv,
+                    other => return Err(format!("Unknown format '{}' (expected text, jsonl or csv)", other).into()),
+                }
+            }
+            "--schema" => schema_path = Some(PathBuf::from(value(arg)?)),
+            s if s.starts_with('-') && s.len() > 1 => {
+                return Err(format!("Unknown option '{}'\n\n{}", s, LIST_USAGE).into());
+            }
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    let source_path = positional
+        .first()
+        .ok_or_else(|| format!("Missing <source>\n\n{}", LIST_USAGE))?;
+    let dir = positional.get(1).map(String::as_str).unwrap_or("");
+
+    let schema = load_schema(schema_path.as_deref())?;
+    let source = open_source(source_path, schema.as_ref())?;
+
+    let entries = if recursive { source.walk(dir) } else { source.list_dir(dir) };
+    if entries.is_empty() && !dir.is_empty() {
+        return Err(format!("No such directory: {}", dir).into());
+    }
+    let unresolved = source.index().files.values().filter(|f| f.path.is_empty()).count();
+    if unresolved > 0 {
+        eprintln!("Note: {} files have no known path and are not listed", unresolved);
+    }
+
+    let stdout = std::io::stdout();
+    let mut out = std::io::BufWriter::new(stdout.lock());
+    match write_entries(&mut out, &entries, format) {
+        // `| head` closing the pipe early is not an error.
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        r => Ok(r?),
+    }
+}
+
+const CAT_USAGE: &str = "\
+Usage: ggpk-explorer cat <source> <path> [options]
+
+  Writes one file's decompressed bytes to stdout. <source> is the same as
+  for extract (including cdn:<patch-version>).
+
+Options:
+  --decode            Convert to UTF-8 text or JSON where possible: dat
+                      tables become a JSON array of rows, known text formats
+                      are parsed, and UTF-16 text is re-encoded as UTF-8
+  --schema <file>     schema.min.json used to decode dat tables";
+
+/// Decodes `data` the way `cat --decode` prints it. Falls back to the raw
+/// bytes when nothing applies.
+pub fn decode_for_output(path: &str, data: &[u8], schema: Option<&Schema>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let lower = path.to_ascii_lowercase();
+    let ext = Path::new(&lower).extension().and_then(|e| e.to_str()).unwrap_or("");
+
+    if ext.starts_with("dat") {
+        let schema = schema.ok_or("A schema is required to decode dat files (see --schema)")?;
+        let json = crate::export::dat_to_json(path, data, schema)
+            .ok_or_else(|| format!("No schema table matches {}", path))?;
+        return Ok(serde_json::to_vec_pretty(&json)?);
+    }
+
+    let format = crate::parsers::FileFormat::from_extension(ext);
+    if format != crate::parsers::FileFormat::Unknown {
+        use crate::parsers::ParsedContent;
+        match crate::parsers::parse(format, data)? {
+            ParsedContent::Text { content, .. } => return Ok(content.into_bytes()),
+            ParsedContent::Tree(json) => return Ok(serde_json::to_vec_pretty(&json)?),
+            ParsedContent::Table { rows, .. } => return Ok(serde_json::to_vec_pretty(&rows)?),
+            ParsedContent::Metadata(meta) => return Ok(serde_json::to_vec_pretty(&meta)?),
+            ParsedContent::Binary { .. } => {}
+        }
+    }
+
+    if data.starts_with(&[0xFF, 0xFE]) || data.starts_with(&[0xFE, 0xFF]) {
+        return Ok(crate::parsers::utils::utf16_bom_to_string(data)?.into_bytes());
+    }
+    Ok(data.to_vec())
+}
+
+/// `ggpk-explorer cat <source> <path>`. `args` are the arguments after `cat`.
+pub fn run_cat(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut positional = Vec::new();
+    let mut decode = false;
+    let mut schema_path: Option<PathBuf> = None;
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", CAT_USAGE);
+                return Ok(());
+            }
+            "--decode" => decode = true,
+            "--schema" => {
+                let v = it.next().ok_or_else(|| format!("--schema requires a value\n\n{}", CAT_USAGE))?;
+                schema_path = Some(PathBuf::from(v));
+            }
+            s if s.starts_with('-') && s.len() > 1 => {
+                return Err(format!("Unknown option '{}'\n\n{}", s, CAT_USAGE).into());
+            }
+            _ => positional.push(arg.clone()),
+        }
+    }
+    if positional.len() != 2 {
+        return Err(format!("Expected <source> and <path>\n\n{}", CAT_USAGE).into());
+    }
+
+    let schema = load_schema(schema_path.as_deref())?;
+    let source = open_source(&positional[0], schema.as_ref())?;
+
+    let data = source.read(&positional[1])?;
+    let data = if decode {
+        decode_for_output(&positional[1], &data, schema.as_ref())?
+    } else {
+        data
+    };
+
+    use std::io::Write;
+    let mut out = std::io::stdout().lock();
+    match out.write_all(&data).and_then(|_| out.flush()) {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        r => Ok(r?),
+    }
+}
+
+const VERIFY_USAGE: &str = "\
+Usage: ggpk-explorer verify <Content.ggpk>
+
+  Recomputes the SHA-256 digest of every file and directory record and
+  lists the ones that do not match what is stored, with their offsets.
+  Exits with status 1 if anything is damaged.";
+
+pub fn run_verify(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut positional = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", VERIFY_USAGE);
+                return Ok(());
+            }
+            s if s.starts_with('-') && s.len() > 1 => {
+                return Err(format!("Unknown option '{}'\n\n{}", s, VERIFY_USAGE).into());
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let [ggpk_path] = positional.as_slice() else {
+        return Err(format!("Expected <Content.ggpk>\n\n{}", VERIFY_USAGE).into());
+    };
+
+    let reader = crate::ggpk::reader::GgpkReader::open(ggpk_path)?;
+    let report = crate::ggpk::verify::verify(&reader, |r| {
+        if r.files_checked % 5000 == 0 {
+            eprint!("\r{} files, {} MiB hashed", r.files_checked, r.bytes_hashed >> 20);
+        }
+    });
+    eprintln!("\r{} files, {} MiB hashed", report.files_checked, report.bytes_hashed >> 20);
+
+    for issue in &report.issues {
+        println!("{}", issue);
+    }
+    if report.is_ok() {
+        eprintln!("OK: {} files and {} directories verified", report.files_checked, report.directories_checked);
+        Ok(())
+    } else {
+        Err(format!("{} damaged or mismatched records", report.issues.len()).into())
+    }
+}
+
+const FREE_USAGE: &str = "\
+Usage: ggpk-explorer free <Content.ggpk> [options]
+
+  Reports the space held by FREE records (left behind by patches): the
+  total, the largest block and how it is spread over the file.
+
+Options:
+  --region-size <MiB>   Size of each slice in the map (default: 1024)
+  --list                Also list every FREE record
+  --compact <out.ggpk>  Write a copy without free space to <out.ggpk>";
+
+pub fn run_free(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut positional = Vec::new();
+    let mut region_mib: u64 = 1024;
+    let mut list = false;
+    let mut compact_to: Option<PathBuf> = None;
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", FREE_USAGE);
+                return Ok(());
+            }
+            "--region-size" => {
+                let v = it.next().ok_or_else(|| format!("--region-size requires a value\n\n{}", FREE_USAGE))?;
+                region_mib = v.parse().map_err(|_| format!("Invalid --region-size '{}'", v))?;
+            }
+            "--list" => list = true,
+            "--compact" => {
+                let v = it.next().ok_or_else(|| format!("--compact requires a value\n\n{}", FREE_USAGE))?;
+                compact_to = Some(PathBuf::from(v));
+            }
+            s if s.starts_with('-') && s.len() > 1 => {
+                return Err(format!("Unknown option '{}'\n\n{}", s, FREE_USAGE).into());
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let [ggpk_path] = positional.as_slice() else {
+        return Err(format!("Expected <Content.ggpk>\n\n{}", FREE_USAGE).into());
+    };
+
+    const MIB: u64 = 1 << 20;
+    let reader = crate::ggpk::reader::GgpkReader::open(ggpk_path)?;
+    let report = crate::ggpk::free_space::report(&reader, region_mib.max(1) * MIB)?;
+
+    println!("File size:     {:>10} MiB", report.file_size / MIB);
+    println!(
+        "Free space:    {:>10} MiB ({:.1}%) in {} records",
+        report.total_free / MIB,
+        report.free_ratio() * 100.0,
+        report.free_records.len()
+    );
+    println!("Largest block: {:>10} MiB", report.largest_free / MIB);
+    println!();
+    for region in &report.regions {
+        let ratio = region.free_bytes as f64 / region.len.max(1) as f64;
+        println!(
+            "{:>8} MiB  {:<40}  {:>5.1}% free",
+            region.start / MIB,
+            "#".repeat((ratio * 40.0).round() as usize),
+            ratio * 100.0
+        );
+    }
+    if list {
+        println!();
+        for free in &report.free_records {
+            println!("{:#014x}  {:>12}", free.offset, free.length);
+        }
+    }
+
+    if let Some(dest) = compact_to {
+        eprintln!("Writing compacted copy to {}...", dest.display());
+        let stats = crate::ggpk::free_space::compact(&reader, &dest)?;
+        eprintln!(
+            "Done: {} files, {} directories, {} MiB -> {} MiB",
+            stats.files,
+            stats.directories,
+            stats.old_size / MIB,
+            stats.new_size / MIB
+        );
     }
-    
     Ok(())
 }
+
+const PACK_USAGE: &str = "\
+Usage: ggpk-explorer pack <dir> -o <out.ggpk> [options]
+
+  Builds a GGPK containing every file below <dir>, with paths relative
+  to it (the inverse of extract for loose files).
+
+Options:
+  -o, --output <file>  GGPK file to write (required)
+  --version <n>        GGPK version: 3 (PC, default) or 4 (macOS)";
+
+pub fn run_pack(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut positional = Vec::new();
+    let mut output: Option<PathBuf> = None;
+    let mut version: u32 = 3;
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", PACK_USAGE);
+                return Ok(());
+            }
+            "-o" | "--output" => {
+                let v = it.next().ok_or_else(|| format!("{} requires a value\n\n{}", arg, PACK_USAGE))?;
+                output = Some(PathBuf::from(v));
+            }
+            "--version" => {
+                let v = it.next().ok_or_else(|| format!("--version requires a value\n\n{}", PACK_USAGE))?;
+                version = match v.as_str() {
+                    "3" => 3,
+                    "4" => 4,
+                    _ => return Err(format!("Unsupported GGPK version '{}' (expected 3 or 4)", v).into()),
+                };
+            }
+            s if s.starts_with('-') && s.len() > 1 => {
+                return Err(format!("Unknown option '{}'\n\n{}", s, PACK_USAGE).into());
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let [dir] = positional.as_slice() else {
+        return Err(format!("Expected <dir>\n\n{}", PACK_USAGE).into());
+    };
+    let output = output.ok_or_else(|| format!("Missing -o <out.ggpk>\n\n{}", PACK_USAGE))?;
+
+    let mut builder = crate::ggpk::builder::GgpkBuilder::new().version(version);
+    builder.add_dir_tree(Path::new(dir))?;
+    let stats = builder.write(&output)?;
+    eprintln!(
+        "Wrote {}: {} files in {} directories ({} bytes of data)",
+        output.display(),
+        stats.files,
+        stats.directories,
+        stats.bytes
+    );
+    Ok(())
+}
+
+const DIFF_USAGE: &str = "\
+Usage: ggpk-explorer diff <old-source> <new-source> [options]
+
+  Lists files added, removed or changed between two versions. A source is
+  anything extract accepts (including cdn:<patch-version>) or an index
+  cache file such as the GUI's bundles2.cache. A file is changed if its
+  size or its SHA-256 differs, which means reading both copies of every
+  file whose size did not change; --size-only skips that.
+  An index cache has no file data, so it is always compared by size.
+
+  With --dat, compares the rows of one dat table instead, matching them
+  on the schema's unique column (or by row index) and listing changed
+  column values.
+
+Options:
+  --size-only         Compare sizes only (fast; same-size edits count as
+                      unchanged)
+  --dat <path>        Diff this dat table row by row, e.g.
+                      data/balance/mods.datc64 (needs a schema)
+  --format <fmt>      text | json               (default: text)
+  --schema <file>     schema.min.json used for path enrichment";
+
+/// Like [`open_source`], but also accepts a bincode index cache, which has
+/// no bundles behind it and only supports size comparison.
+pub fn open_diff_source(spec: &str, schema: Option<&Schema>) -> Result<(Vfs, bool), Box<dyn std::error::Error>> {
+    let path = Path::new(spec);
+    if path.is_file() && path.extension().is_some_and(|e| e.eq_ignore_ascii_case("cache")) {
+        let index = crate::bundles::index::Index::load_from_cache(path)
+            .map_err(|e| format!("{}: {}", spec, e))?;
+        let mut vfs = Vfs::from_parts(Arc::new(index), None, None, None);
+        if let Some(schema) = schema {
+            vfs.enrich_paths(schema);
+        }
+        return Ok((vfs, false));
+    }
+    Ok((open_source(spec, schema)?, true))
+}
+
+pub fn run_diff(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut positional = Vec::new();
+    let mut size_only = false;
+    let mut dat: Option<String> = None;
+    let mut json = false;
+    let mut schema_path: Option<PathBuf> = None;
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", DIFF_USAGE);
+                return Ok(());
+            }
+            "--size-only" => size_only = true,
+            "--dat" => {
+                let v = it.next().ok_or_else(|| format!("--dat requires a value\n\n{}", DIFF_USAGE))?;
+                dat = Some(v.clone());
+            }
+            "--format" => {
+                let v = it.next().ok_or_else(|| format!("--format requires a value\n\n{}", DIFF_USAGE))?;
+                json = match v.to_ascii_lowercase().as_str() {
+                    "text" => false,
+                    "json" => true,
+                    other => return Err(format!("Unknown format '{}' (expected text or json)", other).into()),
+                };
+            }
+            "--schema" => {
+                let v = it.next().ok_or_else(|| format!("--schema requires a value\n\n{}", DIFF_USAGE))?;
+                schema_path = Some(PathBuf::from(v));
+            }
+            s if s.starts_with('-') && s.len() > 1 => {
+                return Err(format!("Unknown option '{}'\n\n{}", s, DIFF_USAGE).into());
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let [old, new] = positional.as_slice() else {
+        return Err(format!("Expected <old-source> and <new-source>\n\n{}", DIFF_USAGE).into());
+    };
+
+    let schema = load_schema(schema_path.as_deref())?;
+    let (old_vfs, old_readable) = open_diff_source(old, schema.as_ref())?;
+    let (new_vfs, new_readable) = open_diff_source(new, schema.as_ref())?;
+
+    if let Some(path) = dat {
+        let schema = schema.ok_or("--dat needs a schema (pass --schema or update it from the GUI)")?;
+        if !old_readable || !new_readable {
+            return Err("--dat needs file data; an index cache only has sizes".into());
+        }
+        let diff = crate::dat::diff::diff_dat(&path, old_vfs.read(&path)?, new_vfs.read(&path)?, &schema)?;
+        return print_report(&diff, json, |out| diff.write_text(out));
+    }
+
+    if !size_only && (!old_readable || !new_readable) {
+        eprintln!("Note: an index cache only has sizes; comparing by size");
+    }
+    let diff = if !size_only && old_readable && new_readable {
+        let mut last = 0;
+        let diff = crate::diff::diff(&old_vfs, &new_vfs, |done, total| {
+            let percent = done * 100 / total.max(1);
+            if percent != last {
+                last = percent;
+                eprint!("\rComparing contents: {}%", percent);
+            }
+        });
+        eprintln!();
+        diff
+    } else {
+        crate::diff::diff_sizes(old_vfs.index(), new_vfs.index())
+    };
+    if !diff.unreadable.is_empty() {
+        eprintln!("Note: {} files could not be read and were not compared", diff.unreadable.len());
+    }
+
+    print_report(&diff, json, |out| diff.write_text(out))
+}
+
+const SQLITE_USAGE: &str = "\
+Usage: ggpk-explorer sqlite <source> -o <out.sqlite> [options]
+
+  Decodes every dat table the schema describes (data/balance/<name>.datc64,
+  or data/<name>.datc64 on PoE 1) and writes them all to one SQLite
+  database. Each table has an _index column with the row index that
+  foreign keys point at; array columns become <Table>_<Column> tables.
+  Tables that don't match the schema are skipped and listed.
+
+Options:
+  -o, --output <file> SQLite file to create (required)
+  --schema <file>     schema.min.json describing the tables";
+
+pub fn run_sqlite(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut positional = Vec::new();
+    let mut output: Option<PathBuf> = None;
+    let mut schema_path: Option<PathBuf> = None;
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", SQLITE_USAGE);
+                return Ok(());
+            }
+            "-o" | "--output" => {
+                let v = it.next().ok_or_else(|| format!("{} requires a value\n\n{}", arg, SQLITE_USAGE))?;
+                output = Some(PathBuf::from(v));
+            }
+            "--schema" => {
+                let v = it.next().ok_or_else(|| format!("--schema requires a value\n\n{}", SQLITE_USAGE))?;
+                schema_path = Some(PathBuf::from(v));
+            }
+            s if s.starts_with('-') && s.len() > 1 => {
+                return Err(format!("Unknown option '{}'\n\n{}", s, SQLITE_USAGE).into());
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let [source] = positional.as_slice() else {
+        return Err(format!("Expected <source>\n\n{}", SQLITE_USAGE).into());
+    };
+    let output = output.ok_or_else(|| format!("Missing -o <out.sqlite>\n\n{}", SQLITE_USAGE))?;
+
+    let schema = load_schema(schema_path.as_deref())?
+        .ok_or("sqlite needs a schema (pass --schema or update it from the GUI)")?;
+    eprintln!("Opening {}...", source);
+    let vfs = open_source(source, Some(&schema))?;
+
+    let mut names: Vec<String> = schema.tables.iter().map(|t| t.name.to_ascii_lowercase()).collect();
+    names.sort();
+    names.dedup();
+    let mut export = crate::dat::sqlite::SqliteExport::create(&output, schema)?;
+    let mut skipped = Vec::new();
+    for (i, name) in names.iter().enumerate() {
+        let path = [format!("data/balance/{}.datc64", name), format!("data/{}.datc64", name)]
+            .into_iter()
+            .find(|p| vfs.exists(p));
+        // Schema tables this version of the game doesn't ship.
+        let Some(path) = path else { continue };
+        match vfs.read(&path).and_then(|data| export.add_table(&path, data)) {
+            Ok(rows) => eprintln!("[{}/{}] {} ({} rows)", i + 1, names.len(), path, rows),
+            Err(e) => skipped.push(format!("{}: {}", path, e)),
+        }
+    }
+    let stats = export.finish()?;
+    eprintln!("Wrote {} tables ({} rows) to {}", stats.tables, stats.rows, output.display());
+    if !skipped.is_empty() {
+        eprintln!("Skipped {} tables:", skipped.len());
+        for s in &skipped {
+            eprintln!("  - {}", s);
+        }
+    }
+    Ok(())
+}
+
+const QUERY_USAGE: &str = "\
+Usage: ggpk-explorer query <source> <sql> [options]
+
+  Runs a SQL-style query over the dat tables of <source> (anything extract
+  accepts). Tables and columns are named as in the schema; a dotted column
+  follows foreign keys, and JOIN ... ON <foreign key column> adds a row per
+  referenced row:
+
+    SELECT Id, ModTypeKey.Name FROM Mods
+    WHERE Domain = 'ITEM' AND StatsKey1.Id LIKE '%fire%'
+    ORDER BY Level DESC LIMIT 20
+
+Options:
+  --format <fmt>      table | json              (default: table)
+  --schema <file>     schema.min.json describing the tables";
+
+pub fn run_query(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut positional = Vec::new();
+    let mut json = false;
+    let mut schema_path: Option<PathBuf> = None;
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", QUERY_USAGE);
+                return Ok(());
+            }
+            "--format" => {
+                let v = it.next().ok_or_else(|| format!("--format requires a value\n\n{}", QUERY_USAGE))?;
+                json = match v.to_ascii_lowercase().as_str() {
+                    "table" => false,
+                    "json" => true,
+                    other => return Err(format!("Unknown format '{}' (expected table or json)", other).into()),
+                };
+            }
+            "--schema" => {
+                let v = it.next().ok_or_else(|| format!("--schema requires a value\n\n{}", QUERY_USAGE))?;
+                schema_path = Some(PathBuf::from(v));
+            }
+            s if s.starts_with('-') && s.len() > 1 => {
+                return Err(format!("Unknown option '{}'\n\n{}", s, QUERY_USAGE).into());
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let [source, sql] = positional.as_slice() else {
+        return Err(format!("Expected <source> and <sql>\n\n{}", QUERY_USAGE).into());
+    };
+
+    // Parse before opening the source so typos fail fast.
+    let query = crate::dat::query::parse(sql)?;
+    let schema = load_schema(schema_path.as_deref())?
+        .ok_or("query needs a schema (pass --schema or update it from the GUI)")?;
+    let vfs = open_source(source, Some(&schema))?;
+    let db = crate::dat::relational::Database::from_vfs(Arc::new(vfs), Arc::new(schema));
+    let result = query.execute(&db)?;
+    print_report(&result.to_json(), json, |out| result.write_table(out))
+}
+
+const INFER_USAGE: &str = "\
+Usage: ggpk-explorer infer <source> <path> [options]
+
+  Guesses the columns of a dat table the schema no longer fits, e.g. after
+  a patch added columns. Schema columns are kept while the data agrees with
+  them; the rest of each row is guessed from the values (string and array
+  offsets, 0xFE null foreign keys, floats, bools) and listed with the
+  evidence for each guess.
+
+Options:
+  --format <fmt>      text | json               (default: text)
+  --patch             Print a schema.min.json table entry with the guessed
+                      columns appended, to review and merge by hand
+  --schema <file>     schema.min.json with the table's current columns";
+
+pub fn run_infer(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut positional = Vec::new();
+    let mut json = false;
+    let mut patch = false;
+    let mut schema_path: Option<PathBuf> = None;
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", INFER_USAGE);
+                return Ok(());
+            }
+            "--format" => {
+                let v = it.next().ok_or_else(|| format!("--format requires a value\n\n{}", INFER_USAGE))?;
+                json = match v.to_ascii_lowercase().as_str() {
+                    "text" => false,
+                    "json" => true,
+                    other => return Err(format!("Unknown format '{}' (expected text or json)", other).into()),
+                };
+            }
+            "--patch" => patch = true,
+            "--schema" => {
+                let v = it.next().ok_or_else(|| format!("--schema requires a value\n\n{}", INFER_USAGE))?;
+                schema_path = Some(PathBuf::from(v));
+            }
+            s if s.starts_with('-') && s.len() > 1 => {
+                return Err(format!("Unknown option '{}'\n\n{}", s, INFER_USAGE).into());
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let [source, path] = positional.as_slice() else {
+        return Err(format!("Expected <source> and <path>\n\n{}", INFER_USAGE).into());
+    };
+
+    // Guessing works without a schema; it just has nothing to keep.
+    let schema = load_schema(schema_path.as_deref())?;
+    let vfs = open_source(source, schema.as_ref())?;
+    let inference = crate::dat::infer::infer_dat(path, vfs.read(path)?, schema.as_ref())?;
+    if patch {
+        let stem = Path::new(path.as_str()).file_stem().and_then(|s| s.to_str()).unwrap_or("");
+        let table = schema.as_ref().and_then(|s| s.tables.iter().find(|t| t.name.eq_ignore_ascii_case(stem)));
+        return print_report(&inference.schema_table(table), true, |_| Ok(()));
+    }
+    print_report(&inference, json, |out| inference.write_text(out))
+}
+
+const VALIDATE_USAGE: &str = "\
+Usage: ggpk-explorer validate <source> [options]
+
+  Checks the schema against the dat tables of <source> (anything extract
+  accepts). For every table the schema lists for the game, compares the
+  schema's row width with the file's and checks that string and array
+  offsets land in the variable data section and that foreign keys are in
+  range of the table they reference. Exits with an error if any table is
+  broken.
+
+Options:
+  --game <game>       poe1 | poe2 | all         (default: poe2 if the install
+                      has data/balance, otherwise poe1)
+  --format <fmt>      text | json               (default: text)
+  --schema <file>     schema.min.json to check";
+
+pub fn run_validate(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut positional = Vec::new();
+    let mut game: Option<Option<bool>> = None;
+    let mut json = false;
+    let mut schema_path: Option<PathBuf> = None;
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", VALIDATE_USAGE);
+                return Ok(());
+            }
+            "--game" => {
+                let v = it.next().ok_or_else(|| format!("--game requires a value\n\n{}", VALIDATE_USAGE))?;
+                game = Some(match v.to_ascii_lowercase().as_str() {
+                    "poe1" => Some(false),
+                    "poe2" => Some(true),
+                    "all" => None,
+                    other => return Err(format!("Unknown game '{}' (expected poe1, poe2 or all)", other).into()),
+                });
+            }
+            "--format" => {
+                let v = it.next().ok_or_else(|| format!("--format requires a value\n\n{}", VALIDATE_USAGE))?;
+                json = match v.to_ascii_lowercase().as_str() {
+                    "text" => false,
+                    "json" => true,
+                    other => return Err(format!("Unknown format '{}' (expected text or json)", other).into()),
+                };
+            }
+            "--schema" => {
+                let v = it.next().ok_or_else(|| format!("--schema requires a value\n\n{}", VALIDATE_USAGE))?;
+                schema_path = Some(PathBuf::from(v));
+            }
+            s if s.starts_with('-') && s.len() > 1 => {
+                return Err(format!("Unknown option '{}'\n\n{}", s, VALIDATE_USAGE).into());
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let [source] = positional.as_slice() else {
+        return Err(format!("Expected <source>\n\n{}", VALIDATE_USAGE).into());
+    };
+
+    let schema = load_schema(schema_path.as_deref())?
+        .ok_or("validate needs a schema (pass --schema or update it from the GUI)")?;
+    eprintln!("Opening {}...", source);
+    let vfs = Arc::new(open_source(source, Some(&schema))?);
+    let poe2 = game.unwrap_or_else(|| Some(vfs.stat("data/balance").is_some()));
+
+    let loader = crate::dat::relational::vfs_loader(vfs);
+    let mut last = 0;
+    let report = crate::dat::validate::validate(&schema, poe2, &loader, |done, total| {
+        let percent = done * 100 / total.max(1);
+        if percent != last {
+            last = percent;
+            eprint!("\rValidating: {}%", percent);
+        }
+    });
+    eprintln!();
+    print_report(&report, json, |out| report.write_text(out))?;
+    if !report.is_ok() {
+        return Err(format!("{} tables don't match the schema", report.broken.len()).into());
+    }
+    Ok(())
+}
+
+/// Writes `report` to stdout as pretty JSON, or as text with `write_text`.
+fn print_report<T: serde::Serialize>(
+    report: &T,
+    json: bool,
+    write_text: impl FnOnce(&mut std::io::BufWriter<std::io::StdoutLock<'static>>) -> std::io::Result<()>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    let written = if json {
+        serde_json::to_writer_pretty(&mut out, report)
+            .map_err(std::io::Error::from)
+            .and_then(|()| std::io::Write::write_all(&mut out, b"\n"))
+            .and_then(|()| std::io::Write::flush(&mut out))
+    } else {
+        write_text(&mut out)
+    };
+    match written {
+        // `| head` closing the pipe early is not an error.
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        r => Ok(r?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_path_matches_file_and_directory_contents() {
+        let m = PathMatcher::new("Art/Textures").unwrap();
+        assert!(m.is_match("art/textures"));
+        assert!(m.is_match("art/textures/foo.dds"));
+        assert!(!m.is_match("art/textures2/foo.dds"));
+    }
+
+    #[test]
+    fn glob_star_stays_within_segment() {
+        let m = PathMatcher::new("data/*.datc64").unwrap();
+        assert!(m.is_match("data/mods.datc64"));
+        assert!(!m.is_match("data/balance/mods.datc64"));
+
+        let m = PathMatcher::new("art/**/*.dds").unwrap();
+        assert!(m.is_match("art/foo.dds"));
+        assert!(m.is_match("art/a/b/foo.dds"));
+        assert!(!m.is_match("art/a/b/foo.png"));
+    }
+
+    #[test]
+    fn csv_listing_quotes_fields() {
+        let entries = vec![VfsEntry {
+            path: "a,b.txt".to_string(),
+            hash: None,
+            size: 3,
+            kind: EntryKind::Bundle,
+            bundle: Some("Bundle"),
+            offset: Some(0),
+        }];
+        let mut out = Vec::new();
+        write_entries(&mut out, &entries, ListFormat::Csv).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "path,size,kind,bundle,offset\n\"a,b.txt\",3,bundle,Bundle,0\n");
+    }
+
+    #[test]
+    fn decode_reencodes_utf16_text() {
+        let mut data = vec![0xFF, 0xFE];
+        data.extend("a=b".encode_utf16().flat_map(|u| u.to_le_bytes()));
+        let out = decode_for_output("Metadata/foo.unknownext", &data, None).unwrap();
+        assert_eq!(out, b"a=b");
+    }
+}
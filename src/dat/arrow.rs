@@ -0,0 +1,545 @@
+//! Arrow IPC file (Feather v2) output for dat tables.
+//!
+//! Column types follow the schema: integers keep their width and
+//! signedness, `f32` stays a single-precision float, strings are UTF-8,
+//! `bool` is a bit-packed boolean and foreign keys are nullable `int64` row
+//! indices. Array columns become `list<item>` of the element type; columns
+//! of a type the reader can't decode are left out. Rows are written in
+//! record batches of [`BATCH_ROWS`], so `pyarrow.ipc.open_file`,
+//! `pandas.read_feather` or polars load the result directly.
+//!
+//! The flatbuffers metadata is encoded by hand: each table is laid out
+//! front to back (vtable, then inline fields, then the strings, vectors
+//! and tables it points at), which keeps every offset positive as the
+//! format requires.
+
+use std::io::Write;
+
+use super::reader::{DatReader, DatValue};
+use super::schema::{Column, Table};
+use crate::error::Result;
+
+pub const BATCH_ROWS: u32 = 65536;
+
+const MAGIC: &[u8] = b"ARROW1";
+const METADATA_V5: i16 = 4;
+const HEADER_SCHEMA: u8 = 1;
+const HEADER_RECORD_BATCH: u8 = 3;
+
+// ---- Flatbuffers ----
+
+/// A flatbuffers value; tables list their fields by id, `None` for absent.
+enum Fb {
+    Bool(bool),
+    U8(u8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Str(String),
+    Table(Vec<Option<Fb>>),
+    Tables(Vec<Fb>),
+    /// A vector of 8-byte aligned structs: raw bytes and element count.
+    Structs(Vec<u8>, u32),
+}
+
+impl Fb {
+    fn inline_size(&self) -> usize {
+        match self {
+            Fb::Bool(_) | Fb::U8(_) => 1,
+            Fb::I16(_) => 2,
+            Fb::I64(_) => 8,
+            // I32, and the offset to anything out of line.
+            _ => 4,
+        }
+    }
+}
+
+fn pad_to(buf: &mut Vec<u8>, align: usize) {
+    buf.resize(buf.len().next_multiple_of(align), 0);
+}
+
+fn patch_offset(buf: &mut [u8], slot: usize, target: usize) {
+    buf[slot..slot + 4].copy_from_slice(&((target - slot) as u32).to_le_bytes());
+}
+
+/// Appends `value` and returns its position (for tables, the table itself
+/// rather than its vtable).
+fn write_fb(buf: &mut Vec<u8>, value: &Fb) -> usize {
+    match value {
+        Fb::Str(s) => {
+            pad_to(buf, 4);
+            let pos = buf.len();
+            buf.extend((s.len() as u32).to_le_bytes());
+            buf.extend(s.as_bytes());
+            buf.push(0);
+            pos
+        }
+        Fb::Tables(items) => {
+            pad_to(buf, 4);
+            let pos = buf.len();
+            buf.extend((items.len() as u32).to_le_bytes());
+            let slots: Vec<usize> = (0..items.len()).map(|i| pos + 4 + 4 * i).collect();
+            buf.resize(pos + 4 + 4 * items.len(), 0);
+            for (slot, item) in slots.into_iter().zip(items) {
+                let target = write_fb(buf, item);
+                patch_offset(buf, slot, target);
+            }
+            pos
+        }
+        Fb::Structs(bytes, count) => {
+            // The elements, not the length prefix, are 8-byte aligned.
+            while buf.len() % 8 != 4 {
+                buf.push(0);
+            }
+            let pos = buf.len();
+            buf.extend(count.to_le_bytes());
+            buf.extend(bytes);
+            pos
+        }
+        Fb::Table(fields) => {
+            pad_to(buf, 4);
+            let vtable = buf.len();
+            buf.resize(vtable + 4 + 2 * fields.len(), 0);
+            pad_to(buf, 4);
+            let table = buf.len();
+            buf.extend(((table - vtable) as i32).to_le_bytes());
+            let mut offsets = vec![0u16; fields.len()];
+            let mut children = Vec::new();
+            for (i, field) in fields.iter().enumerate() {
+                let Some(field) = field else { continue };
+                pad_to(buf, field.inline_size());
+                offsets[i] = (buf.len() - table) as u16;
+                match field {
+                    Fb::Bool(b) => buf.push(*b as u8),
+                    Fb::U8(v) => buf.push(*v),
+                    Fb::I16(v) => buf.extend(v.to_le_bytes()),
+                    Fb::I32(v) => buf.extend(v.to_le_bytes()),
+                    Fb::I64(v) => buf.extend(v.to_le_bytes()),
+                    child => {
+                        children.push((buf.len(), child));
+                        buf.extend([0; 4]);
+                    }
+                }
+            }
+            let table_size = buf.len() - table;
+            let mut vt = Vec::with_capacity(4 + 2 * fields.len());
+            vt.extend(((4 + 2 * fields.len()) as u16).to_le_bytes());
+            vt.extend((table_size as u16).to_le_bytes());
+            vt.extend(offsets.iter().flat_map(|o| o.to_le_bytes()));
+            buf[vtable..vtable + vt.len()].copy_from_slice(&vt);
+            for (slot, child) in children {
+                let target = write_fb(buf, child);
+                patch_offset(buf, slot, target);
+            }
+            table
+        }
+        Fb::Bool(_) | Fb::U8(_) | Fb::I16(_) | Fb::I32(_) | Fb::I64(_) => {
+            unreachable!("scalars are only written inline")
+        }
+    }
+}
+
+/// A finished buffer: the root offset, then `root`, padded to 8 bytes.
+fn finish_fb(root: &Fb) -> Vec<u8> {
+    let mut buf = vec![0; 4];
+    let pos = write_fb(&mut buf, root);
+    patch_offset(&mut buf, 0, pos);
+    pad_to(&mut buf, 8);
+    buf
+}
+
+// ---- Arrow ----
+
+#[derive(Debug, Clone, PartialEq)]
+enum ArrowType {
+    Bool,
+    Int { bits: u8, signed: bool },
+    Float,
+    Utf8,
+    List(Box<ArrowType>),
+}
+
+fn element_type(ty: &str) -> Option<ArrowType> {
+    let int = |bits, signed| Some(ArrowType::Int { bits, signed });
+    match ty {
+        "bool" => Some(ArrowType::Bool),
+        "byte" | "u8" => int(8, false),
+        "short" | "i16" => int(16, true),
+        "ushort" | "u16" => int(16, false),
+        "int" | "i32" => int(32, true),
+        "uint" | "u32" | "enumrow" => int(32, false),
+        "long" | "i64" => int(64, true),
+        "ulong" | "u64" => int(64, false),
+        "float" | "f32" => Some(ArrowType::Float),
+        "string" | "ref|string" => Some(ArrowType::Utf8),
+        "foreignrow" | "foreign_row" | "row" => int(64, true),
+        t if t.starts_with("ref|") => int(64, true),
+        _ => None,
+    }
+}
+
+fn column_type(col: &Column) -> Option<ArrowType> {
+    let element = element_type(&col.r#type)?;
+    Some(if col.array { ArrowType::List(Box::new(element)) } else { element })
+}
+
+fn type_fb(ty: &ArrowType) -> (u8, Fb) {
+    match ty {
+        ArrowType::Bool => (6, Fb::Table(Vec::new())),
+        ArrowType::Int { bits, signed } => (2, Fb::Table(vec![Some(Fb::I32(*bits as i32)), Some(Fb::Bool(*signed))])),
+        // Precision SINGLE.
+        ArrowType::Float => (3, Fb::Table(vec![Some(Fb::I16(1))])),
+        ArrowType::Utf8 => (5, Fb::Table(Vec::new())),
+        ArrowType::List(_) => (12, Fb::Table(Vec::new())),
+    }
+}
+
+fn field_fb(name: &str, ty: &ArrowType) -> Fb {
+    let (type_id, type_table) = type_fb(ty);
+    let children = match ty {
+        ArrowType::List(element) => vec![field_fb("item", element)],
+        _ => Vec::new(),
+    };
+    // name, nullable, type_type, type, dictionary, children
+    Fb::Table(vec![
+        Some(Fb::Str(name.to_string())),
+        Some(Fb::Bool(true)),
+        Some(Fb::U8(type_id)),
+        Some(type_table),
+        None,
+        Some(Fb::Tables(children)),
+    ])
+}
+
+fn schema_fb(columns: &[(usize, String, ArrowType)]) -> Fb {
+    // endianness (little), fields
+    Fb::Table(vec![
+        Some(Fb::I16(0)),
+        Some(Fb::Tables(columns.iter().map(|(_, name, ty)| field_fb(name, ty)).collect())),
+    ])
+}
+
+fn bitmap(bits: impl Iterator<Item = bool>) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (i, bit) in bits.enumerate() {
+        if i % 8 == 0 {
+            out.push(0);
+        }
+        if bit {
+            *out.last_mut().expect("pushed above") |= 1 << (i % 8);
+        }
+    }
+    out
+}
+
+fn is_null(value: &DatValue) -> bool {
+    matches!(value, DatValue::ForeignRow(usize::MAX) | DatValue::Unknown)
+}
+
+fn integer(value: &DatValue) -> i64 {
+    match value {
+        DatValue::Bool(b) => *b as i64,
+        DatValue::Int(i) => *i,
+        DatValue::Long(l) => *l as i64,
+        DatValue::ForeignRow(k) if *k != usize::MAX => *k as i64,
+        _ => 0,
+    }
+}
+
+/// Body buffers and field nodes of one record batch, in the depth-first
+/// order the format lists them in.
+#[derive(Default)]
+struct Batch {
+    nodes: Vec<(i64, i64)>,
+    buffers: Vec<Vec<u8>>,
+}
+
+impl Batch {
+    fn append(&mut self, ty: &ArrowType, values: &[DatValue], reader: &DatReader, col: &Column) -> Result<()> {
+        let nulls = values.iter().filter(|v| is_null(v)).count();
+        self.nodes.push((values.len() as i64, nulls as i64));
+        self.buffers.push(if nulls > 0 { bitmap(values.iter().map(|v| !is_null(v))) } else { Vec::new() });
+
+        match ty {
+            ArrowType::Bool => self.buffers.push(bitmap(values.iter().map(|v| matches!(v, DatValue::Bool(true))))),
+            ArrowType::Int { bits, .. } => {
+                let width = *bits as usize / 8;
+                self.buffers.push(values.iter().flat_map(|v| integer(v).to_le_bytes()[..width].to_vec()).collect());
+            }
+            ArrowType::Float => self.buffers.push(
+                values
+                    .iter()
+                    .flat_map(|v| match v {
+                        DatValue::Float(f) => f.to_le_bytes(),
+                        _ => 0f32.to_le_bytes(),
+                    })
+                    .collect(),
+            ),
+            ArrowType::Utf8 => {
+                let mut offsets = vec![0i32];
+                let mut data = Vec::new();
+                for v in values {
+                    if let DatValue::String(s) = v {
+                        data.extend(s.as_bytes());
+                    }
+                    offsets.push(data.len() as i32);
+                }
+                self.buffers.push(offsets.iter().flat_map(|o| o.to_le_bytes()).collect());
+                self.buffers.push(data);
+            }
+            ArrowType::List(element) => {
+                let mut offsets = vec![0i32];
+                let mut items = Vec::new();
+                for v in values {
+                    if let DatValue::List(count, offset) = v {
+                        items.extend(reader.read_list_values(*offset, *count, col)?);
+                    }
+                    offsets.push(items.len() as i32);
+                }
+                self.buffers.push(offsets.iter().flat_map(|o| o.to_le_bytes()).collect());
+                self.append(element, &items, reader, col)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The RecordBatch header for `rows` rows, and the body it describes.
+    fn finish(self, rows: u32) -> (Fb, Vec<u8>) {
+        let mut body = Vec::new();
+        let mut specs = Vec::new();
+        for buffer in &self.buffers {
+            specs.extend((body.len() as i64).to_le_bytes());
+            specs.extend((buffer.len() as i64).to_le_bytes());
+            body.extend(buffer);
+            pad_to(&mut body, 8);
+        }
+        let nodes: Vec<u8> = self.nodes.iter().flat_map(|(len, nulls)| [len.to_le_bytes(), nulls.to_le_bytes()].concat()).collect();
+        // length, nodes, buffers
+        let header = Fb::Table(vec![
+            Some(Fb::I64(rows as i64)),
+            Some(Fb::Structs(nodes, self.nodes.len() as u32)),
+            Some(Fb::Structs(specs, self.buffers.len() as u32)),
+        ]);
+        (header, body)
+    }
+}
+
+/// Tracks the file offset for the footer's blocks.
+struct Counting<W> {
+    out: W,
+    pos: u64,
+}
+
+impl<W: Write> Counting<W> {
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.out.write_all(bytes)?;
+        self.pos += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Writes an encapsulated message and returns its footer block.
+    fn message(&mut self, header_type: u8, header: Fb, body: &[u8]) -> Result<Vec<u8>> {
+        // version, header_type, header, bodyLength
+        let message = Fb::Table(vec![
+            Some(Fb::I16(METADATA_V5)),
+            Some(Fb::U8(header_type)),
+            Some(header),
+            Some(Fb::I64(body.len() as i64)),
+        ]);
+        let metadata = finish_fb(&message);
+        let offset = self.pos;
+        self.write(&0xFFFF_FFFFu32.to_le_bytes())?;
+        self.write(&(metadata.len() as i32).to_le_bytes())?;
+        self.write(&metadata)?;
+        self.write(body)?;
+        // offset, metaDataLength (+ padding), bodyLength
+        let mut block = (offset as i64).to_le_bytes().to_vec();
+        block.extend((8 + metadata.len() as i32).to_le_bytes());
+        block.extend([0; 4]);
+        block.extend((body.len() as i64).to_le_bytes());
+        Ok(block)
+    }
+}
+
+/// Writes every row of `reader`, decoded with `table`, as an Arrow IPC file.
+pub fn write_arrow(reader: &DatReader, table: &Table, out: &mut impl Write) -> Result<()> {
+    reader.check_schema(table)?;
+    let columns: Vec<(usize, String, ArrowType)> = table
+        .columns
+        .iter()
+        .enumerate()
+        .filter_map(|(j, col)| {
+            let name = col.name.clone().unwrap_or_else(|| format!("Col{}", j));
+            Some((j, name, column_type(col)?))
+        })
+        .collect();
+
+    let mut w = Counting { out, pos: 0 };
+    w.write(MAGIC)?;
+    w.write(&[0, 0])?;
+    w.message(HEADER_SCHEMA, schema_fb(&columns), &[])?;
+
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    while start < reader.row_count || (start == 0 && blocks.is_empty()) {
+        let end = reader.row_count.min(start.saturating_add(BATCH_ROWS));
+        let rows = (start..end).map(|i| reader.read_row(i, table)).collect::<Result<Vec<_>>>()?;
+        let mut batch = Batch::default();
+        for (j, _, ty) in &columns {
+            let values: Vec<DatValue> = rows.iter().map(|r| r[*j].clone()).collect();
+            batch.append(ty, &values, reader, &table.columns[*j])?;
+        }
+        let (header, body) = batch.finish(end - start);
+        blocks.push(w.message(HEADER_RECORD_BATCH, header, &body)?);
+        start = end;
+        if end == reader.row_count {
+            break;
+        }
+    }
+    // End-of-stream marker.
+    w.write(&0xFFFF_FFFFu32.to_le_bytes())?;
+    w.write(&0u32.to_le_bytes())?;
+
+    // version, schema, dictionaries, recordBatches
+    let footer = finish_fb(&Fb::Table(vec![
+        Some(Fb::I16(METADATA_V5)),
+        Some(schema_fb(&columns)),
+        Some(Fb::Structs(Vec::new(), 0)),
+        Some(Fb::Structs(blocks.concat(), blocks.len() as u32)),
+    ]));
+    w.write(&footer)?;
+    w.write(&(footer.len() as i32).to_le_bytes())?;
+    w.write(MAGIC)?;
+    w.out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, ty: &str, array: bool) -> Column {
+        Column {
+            name: Some(name.to_string()),
+            description: None,
+            array,
+            r#type: ty.to_string(),
+            unique: false,
+            localized: false,
+            references: None,
+            interval: false,
+        }
+    }
+
+    // Just enough of a flatbuffers reader to walk the output.
+    fn u32_at(b: &[u8], p: usize) -> usize {
+        u32::from_le_bytes(b[p..p + 4].try_into().unwrap()) as usize
+    }
+    fn i64_at(b: &[u8], p: usize) -> i64 {
+        i64::from_le_bytes(b[p..p + 8].try_into().unwrap())
+    }
+    fn field(b: &[u8], table: usize, id: usize) -> Option<usize> {
+        let vtable = table - i32::from_le_bytes(b[table..table + 4].try_into().unwrap()) as usize;
+        let size = u16::from_le_bytes([b[vtable], b[vtable + 1]]) as usize;
+        if 4 + 2 * id >= size {
+            return None;
+        }
+        let off = u16::from_le_bytes([b[vtable + 4 + 2 * id], b[vtable + 5 + 2 * id]]) as usize;
+        (off != 0).then_some(table + off)
+    }
+    fn deref(b: &[u8], p: usize) -> usize {
+        p + u32_at(b, p)
+    }
+    fn string(b: &[u8], p: usize) -> String {
+        let s = deref(b, p);
+        String::from_utf8(b[s + 4..s + 4 + u32_at(b, s)].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn writes_typed_columns_and_footer() {
+        let table = Table {
+            name: "Test".to_string(),
+            columns: vec![
+                column("Id", "string", false),
+                column("Level", "i32", false),
+                column("Key", "foreignrow", false),
+                column("Tags", "u16", true),
+                column("Mystery", "weird", false),
+            ],
+            tags: None,
+            valid_for: None,
+        };
+        let mut var = vec![0xBB; 8];
+        let mut fixed = Vec::new();
+        for (id, level, key, tags) in [("a", -3i32, Some(7u64), vec![1u16, 2]), ("bc", 40, None, vec![9])] {
+            fixed.extend((var.len() as u64).to_le_bytes());
+            var.extend(id.encode_utf16().chain([0, 0]).flat_map(u16::to_le_bytes));
+            fixed.extend(level.to_le_bytes());
+            match key {
+                Some(k) => fixed.extend([k.to_le_bytes(), 0u64.to_le_bytes()].concat()),
+                None => fixed.extend([0xFE; 16]),
+            }
+            fixed.extend((tags.len() as u64).to_le_bytes());
+            fixed.extend((var.len() as u64).to_le_bytes());
+            var.extend(tags.iter().flat_map(|t| t.to_le_bytes()));
+            // "weird" is read as 4 unknown bytes.
+            fixed.extend([0xAA; 4]);
+        }
+        let mut data = 2u32.to_le_bytes().to_vec();
+        data.extend(fixed);
+        data.extend(var);
+        let reader = DatReader::new(data, "Test.datc64").unwrap();
+
+        let mut b = Vec::new();
+        write_arrow(&reader, &table, &mut b).unwrap();
+        assert_eq!(&b[..6], MAGIC);
+        assert_eq!(&b[b.len() - 6..], MAGIC);
+        let footer_len = i32::from_le_bytes(b[b.len() - 10..b.len() - 6].try_into().unwrap()) as usize;
+        let footer_start = b.len() - 10 - footer_len;
+        assert_eq!(footer_start % 8, 0);
+        let footer = &b[footer_start..b.len() - 10];
+        let root = deref(footer, 0);
+
+        let schema = deref(footer, field(footer, root, 1).unwrap());
+        let fields = deref(footer, field(footer, schema, 1).unwrap());
+        assert_eq!(u32_at(footer, fields), 4, "the unknown column is left out");
+        let field_at = |i: usize| deref(footer, fields + 4 + 4 * i);
+        let names: Vec<String> = (0..4).map(|i| string(footer, field(footer, field_at(i), 0).unwrap())).collect();
+        assert_eq!(names, ["Id", "Level", "Key", "Tags"]);
+        let types: Vec<u8> = (0..4).map(|i| footer[field(footer, field_at(i), 2).unwrap()]).collect();
+        assert_eq!(types, [5, 2, 2, 12]);
+        let tags_children = deref(footer, field(footer, field_at(3), 5).unwrap());
+        assert_eq!(u32_at(footer, tags_children), 1);
+
+        // One record batch, found through the footer.
+        let batches = deref(footer, field(footer, root, 3).unwrap());
+        assert_eq!(u32_at(footer, batches), 1);
+        let block = batches + 4;
+        let offset = i64_at(footer, block) as usize;
+        let meta_len = i32::from_le_bytes(footer[block + 8..block + 12].try_into().unwrap()) as usize;
+        assert_eq!(offset % 8, 0);
+        assert_eq!(u32_at(&b, offset), 0xFFFF_FFFF);
+        let meta = &b[offset + 8..offset + meta_len];
+        let message = deref(meta, 0);
+        assert_eq!(meta[field(meta, message, 1).unwrap()], HEADER_RECORD_BATCH);
+        let batch = deref(meta, field(meta, message, 2).unwrap());
+        assert_eq!(i64_at(meta, field(meta, batch, 0).unwrap()), 2);
+
+        // Nodes: Id, Level, Key (one null), Tags, Tags.item (3 values).
+        let nodes = deref(meta, field(meta, batch, 1).unwrap());
+        let node = |i: usize| (i64_at(meta, nodes + 4 + 16 * i), i64_at(meta, nodes + 12 + 16 * i));
+        assert_eq!((0..5).map(node).collect::<Vec<_>>(), [(2, 0), (2, 0), (2, 1), (2, 0), (3, 0)]);
+        // Buffers: Level's values are buffer 4, after Id's validity,
+        // offsets and data and Level's validity.
+        let buffers = deref(meta, field(meta, batch, 2).unwrap());
+        let buffer = |i: usize| (i64_at(meta, buffers + 4 + 16 * i) as usize, i64_at(meta, buffers + 12 + 16 * i) as usize);
+        let body = offset + meta_len;
+        let (start, len) = buffer(4);
+        assert_eq!(b[body + start..body + start + len], [(-3i32).to_le_bytes(), 40i32.to_le_bytes()].concat());
+        let (start, len) = buffer(5);
+        assert_eq!(b[body + start..body + start + len], [0b01], "Key is null in the second row");
+        // Tags.item values as u16.
+        let (start, len) = buffer(10);
+        assert_eq!(b[body + start..body + start + len], [1, 0, 2, 0, 9, 0]);
+    }
+}
@@ -3,6 +3,8 @@
 use serde::Serialize;
 use std::collections::HashSet;
 
+use crate::error::{Error, Result};
+
 #[derive(Debug, Clone, Serialize)]
 pub struct CsdFile {
     pub path: String,
@@ -31,7 +33,7 @@ pub struct CsdParameter {
     pub value: i32,
 }
 
-pub fn parse_csd(data: &[u8], file_path: &str) -> Result<CsdFile, String> {
+pub fn parse_csd(data: &[u8], file_path: &str) -> Result<CsdFile> {
     // 1. Decode UTF-16LE
     let u16_vec: Vec<u16> = data
         .chunks_exact(2)
@@ -39,7 +41,7 @@ pub fn parse_csd(data: &[u8], file_path: &str) -> Result<CsdFile, String> {
         .collect();
 
     let content = String::from_utf16(&u16_vec)
-        .map_err(|e| format!("Failed to decode UTF-16LE: {}", e))?;
+        .map_err(|e| Error::parse("CSD", format!("invalid UTF-16LE: {}", e)))?;
 
     let mut entries = Vec::new();
     let mut languages = HashSet::new();
@@ -0,0 +1,140 @@
+//! CSV output for dat tables.
+//!
+//! A header row with the column names, then one line per dat row. Numbers
+//! stay plain numbers, booleans are `true`/`false` and foreign keys are the
+//! referenced row index, empty for a null key. Array columns are flattened
+//! into one cell holding a JSON array (`[12,40]`, `["a","b"]`), so every
+//! cell still belongs to exactly one column. Fields containing a comma,
+//! quote or line break are quoted as in RFC 4180.
+
+use std::borrow::Cow;
+use std::io::Write;
+
+use serde_json::Value;
+
+use super::reader::{DatReader, DatValue};
+use super::schema::{Column, Table};
+use crate::error::Result;
+
+fn field(s: &str) -> Cow<'_, str> {
+    if s.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", s.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+/// `long`/`i64` columns are read as raw `u64`; show them signed.
+fn signed(col: &Column) -> bool {
+    matches!(col.r#type.as_str(), "long" | "i64")
+}
+
+fn element_json(value: &DatValue, col: &Column) -> Value {
+    match value {
+        DatValue::Bool(b) => Value::from(*b),
+        DatValue::Int(i) => Value::from(*i),
+        DatValue::Long(l) if signed(col) => Value::from(*l as i64),
+        DatValue::Long(l) => Value::from(*l),
+        DatValue::Float(f) => Value::from(*f),
+        DatValue::String(s) => Value::from(s.as_str()),
+        DatValue::ForeignRow(usize::MAX) => Value::Null,
+        DatValue::ForeignRow(k) => Value::from(*k),
+        DatValue::List(..) | DatValue::Unknown => Value::Null,
+    }
+}
+
+fn cell(reader: &DatReader, value: &DatValue, col: &Column) -> Result<String> {
+    Ok(match value {
+        DatValue::List(count, offset) => {
+            let items = reader.read_list_values(*offset, *count, col)?;
+            Value::Array(items.iter().map(|v| element_json(v, col)).collect()).to_string()
+        }
+        DatValue::String(s) => s.clone(),
+        DatValue::ForeignRow(usize::MAX) | DatValue::Unknown => String::new(),
+        DatValue::Float(f) => f.to_string(),
+        v => element_json(v, col).to_string(),
+    })
+}
+
+/// Writes every row of `reader`, decoded with `table`, as CSV.
+pub fn write_csv(reader: &DatReader, table: &Table, out: &mut impl Write) -> Result<()> {
+    reader.check_schema(table)?;
+    let names: Vec<String> = table
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(j, col)| col.name.clone().unwrap_or_else(|| format!("Col{}", j)))
+        .collect();
+    writeln!(out, "{}", names.iter().map(|n| field(n)).collect::<Vec<_>>().join(","))?;
+    for i in 0..reader.row_count {
+        let values = reader.read_row(i, table)?;
+        let cells = values
+            .iter()
+            .zip(&table.columns)
+            .map(|(v, col)| cell(reader, v, col))
+            .collect::<Result<Vec<_>>>()?;
+        writeln!(out, "{}", cells.iter().map(|c| field(c)).collect::<Vec<_>>().join(","))?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, ty: &str, array: bool) -> Column {
+        Column {
+            name: Some(name.to_string()),
+            description: None,
+            array,
+            r#type: ty.to_string(),
+            unique: false,
+            localized: false,
+            references: None,
+            interval: false,
+        }
+    }
+
+    #[test]
+    fn quotes_fields_and_flattens_arrays() {
+        let table = Table {
+            name: "Test".to_string(),
+            columns: vec![
+                column("Id", "string", false),
+                column("Key", "foreignrow", false),
+                column("Values", "i32", true),
+                column("Delta", "i64", false),
+            ],
+            tags: None,
+            valid_for: None,
+        };
+        let mut var = vec![0xBB; 8];
+        let mut fixed = Vec::new();
+        for (id, key, values, delta) in [("a,\"b\"", 3u64, vec![1i32, -2], -5i64), ("plain", u64::MAX, vec![], 7)] {
+            fixed.extend((var.len() as u64).to_le_bytes());
+            var.extend(id.encode_utf16().chain([0, 0]).flat_map(u16::to_le_bytes));
+            if key == u64::MAX {
+                fixed.extend([0xFE; 16]);
+            } else {
+                fixed.extend(key.to_le_bytes());
+                fixed.extend(0u64.to_le_bytes());
+            }
+            fixed.extend((values.len() as u64).to_le_bytes());
+            fixed.extend((var.len() as u64).to_le_bytes());
+            var.extend(values.iter().flat_map(|v| v.to_le_bytes()));
+            fixed.extend(delta.to_le_bytes());
+        }
+        let mut data = 2u32.to_le_bytes().to_vec();
+        data.extend(fixed);
+        data.extend(var);
+        let reader = DatReader::new(data, "Test.datc64").unwrap();
+
+        let mut out = Vec::new();
+        write_csv(&reader, &table, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Id,Key,Values,Delta\n\"a,\"\"b\"\"\",3,\"[1,-2]\",-5\nplain,,[],7\n"
+        );
+    }
+}
@@ -0,0 +1,425 @@
+//! Row-level diff of one dat table between two patches.
+//!
+//! Rows are matched on the table's first `unique` column when it has one
+//! and its values really are unique in both files; otherwise row `i` of
+//! the old file is compared with row `i` of the new one. Values are
+//! compared as [`DatReader::value_to_json`] renders them, so lists are
+//! compared by their elements and not by their offset in the variable
+//! data section (which shifts whenever any string or list changes).
+//!
+//! When a patch changed the row width, only the leading schema columns
+//! that fit in both files' rows are compared, and the width change is
+//! reported with the diff.
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::reader::{get_column_size, DatReader};
+use super::schema::{Schema, Table};
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ColumnChange {
+    pub column: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RowDiff {
+    /// The key column's value, or the row index when matching by index.
+    pub key: Value,
+    pub old_row: Option<u32>,
+    pub new_row: Option<u32>,
+    /// Columns whose value changed (modified rows only).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<ColumnChange>,
+    /// The whole row (inserted and deleted rows only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub values: Option<Map<String, Value>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TableDiff {
+    pub table: String,
+    /// Column rows were matched on; `None` means by row index.
+    pub key_column: Option<String>,
+    pub inserted: Vec<RowDiff>,
+    pub deleted: Vec<RowDiff>,
+    pub modified: Vec<RowDiff>,
+    pub unchanged: usize,
+    /// Row width in bytes of the old and new file, when they differ.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub row_lengths: Option<(usize, usize)>,
+    /// Schema columns that don't fit in one file's rows and were not
+    /// compared.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped_columns: Vec<String>,
+    /// Rows that could not be read, with the reason. They are left out of
+    /// the comparison.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unreadable: Vec<String>,
+}
+
+impl TableDiff {
+    pub fn is_empty(&self) -> bool {
+        self.inserted.is_empty() && self.deleted.is_empty() && self.modified.is_empty()
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{}: {} inserted, {} deleted, {} modified, {} unchanged (matched by {})",
+            self.table,
+            self.inserted.len(),
+            self.deleted.len(),
+            self.modified.len(),
+            self.unchanged,
+            self.key_column.as_deref().unwrap_or("row index")
+        )
+    }
+
+    /// The summary, then one line per inserted/deleted row and one per
+    /// changed column of modified rows.
+    pub fn write_text(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(out, "{}", self.summary())?;
+        if let Some((old, new)) = self.row_lengths {
+            writeln!(out, "! row width changed: {} -> {} bytes", old, new)?;
+        }
+        if !self.skipped_columns.is_empty() {
+            writeln!(out, "! not compared: {}", self.skipped_columns.join(", "))?;
+        }
+        for e in &self.unreadable {
+            writeln!(out, "? {}", e)?;
+        }
+        for r in &self.inserted {
+            writeln!(out, "+ {} (row {})", r.key, r.new_row.unwrap_or_default())?;
+        }
+        for r in &self.deleted {
+            writeln!(out, "- {} (row {})", r.key, r.old_row.unwrap_or_default())?;
+        }
+        for r in &self.modified {
+            match (r.old_row, r.new_row) {
+                (Some(old), Some(new)) if old != new => writeln!(out, "~ {} (row {} -> {})", r.key, old, new)?,
+                _ => writeln!(out, "~ {} (row {})", r.key, r.new_row.unwrap_or_default())?,
+            }
+            for c in &r.changes {
+                writeln!(out, "    {}: {} -> {}", c.column, c.old, c.new)?;
+            }
+        }
+        out.flush()
+    }
+}
+
+fn column_names(table: &Table) -> Vec<String> {
+    table
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(j, col)| col.name.clone().unwrap_or_else(|| format!("Col{}", j)))
+        .collect()
+}
+
+/// How many of `table`'s leading columns fit in `reader`'s rows.
+fn fitting_columns(reader: &DatReader, table: &Table) -> usize {
+    let Some(row_len) = reader.row_length.filter(|_| reader.row_count > 0) else {
+        return table.columns.len();
+    };
+    let mut used = 0;
+    table
+        .columns
+        .iter()
+        .take_while(|c| {
+            used += get_column_size(c, reader.is_64bit);
+            used <= row_len
+        })
+        .count()
+}
+
+/// Every row as JSON values; rows that fail to read are `None`, with the
+/// reason added to `unreadable`.
+fn read_rows(reader: &DatReader, table: &Table, side: &str, unreadable: &mut Vec<String>) -> Vec<Option<Vec<Value>>> {
+    (0..reader.row_count)
+        .map(|i| match reader.read_row(i, table) {
+            Ok(values) => Some(values.iter().zip(&table.columns).map(|(v, col)| reader.value_to_json(v, col)).collect()),
+            Err(e) => {
+                unreadable.push(format!("{} row {}: {}", side, i, e));
+                None
+            }
+        })
+        .collect()
+}
+
+/// Maps each readable row's key to its index, or `None` if a key repeats.
+fn key_map(rows: &[Option<Vec<Value>>], key: usize) -> Option<HashMap<String, u32>> {
+    let mut map = HashMap::with_capacity(rows.len());
+    for (i, row) in rows.iter().enumerate() {
+        let Some(row) = row else { continue };
+        if map.insert(row[key].to_string(), i as u32).is_some() {
+            return None;
+        }
+    }
+    Some(map)
+}
+
+fn row_object(names: &[String], row: &[Value]) -> Map<String, Value> {
+    names.iter().cloned().zip(row.iter().cloned()).collect()
+}
+
+/// Diffs two versions of the table `table` describes. If the files'
+/// row widths differ from each other or from the schema, the leading
+/// columns that fit in both are compared and the rest listed as skipped.
+pub fn diff_tables(old: &DatReader, new: &DatReader, table: &Table) -> Result<TableDiff> {
+    let shared = fitting_columns(old, table).min(fitting_columns(new, table));
+    if shared == 0 && !table.columns.is_empty() {
+        return Err(Error::SchemaMismatch {
+            table: table.name.clone(),
+            reason: "no schema column fits in both files' rows".to_string(),
+        });
+    }
+    let skipped_columns = column_names(table).split_off(shared);
+    let table = &Table { columns: table.columns[..shared].to_vec(), ..table.clone() };
+    let row_lengths = match (old.row_length, new.row_length) {
+        (Some(o), Some(n)) if o != n => Some((o, n)),
+        _ => None,
+    };
+
+    let names = column_names(table);
+    let mut unreadable = Vec::new();
+    let old_rows = read_rows(old, table, "old", &mut unreadable);
+    let new_rows = read_rows(new, table, "new", &mut unreadable);
+    let readable = |rows: &[Option<Vec<Value>>], i: u32| rows[i as usize].is_some();
+
+    // (old row, new row) pairs plus the unmatched rows on either side.
+    let key = table.columns.iter().position(|c| c.unique && !c.array);
+    let keyed = key.and_then(|k| Some((k, key_map(&old_rows, k)?, key_map(&new_rows, k)?)));
+    let (key_column, pairs, deleted, inserted) = match keyed {
+        Some((k, old_keys, new_keys)) => {
+            let mut pairs = Vec::new();
+            let mut deleted = Vec::new();
+            for (i, row) in old_rows.iter().enumerate() {
+                let Some(row) = row else { continue };
+                match new_keys.get(&row[k].to_string()) {
+                    Some(&j) => pairs.push((i as u32, j)),
+                    None => deleted.push(i as u32),
+                }
+            }
+            let inserted = (0..new_rows.len() as u32)
+                .filter(|&j| {
+                    new_rows[j as usize].as_ref().is_some_and(|row| !old_keys.contains_key(&row[k].to_string()))
+                })
+                .collect();
+            pairs.sort_by_key(|&(_, j)| j);
+            (Some(k), pairs, deleted, inserted)
+        }
+        None => {
+            let common = old_rows.len().min(new_rows.len()) as u32;
+            (
+                None,
+                (0..common).filter(|&i| readable(&old_rows, i) && readable(&new_rows, i)).map(|i| (i, i)).collect(),
+                (common..old_rows.len() as u32).filter(|&i| readable(&old_rows, i)).collect(),
+                (common..new_rows.len() as u32).filter(|&j| readable(&new_rows, j)).collect::<Vec<_>>(),
+            )
+        }
+    };
+    // Only readable rows remain past this point.
+    let old_rows: Vec<Vec<Value>> = old_rows.into_iter().map(Option::unwrap_or_default).collect();
+    let new_rows: Vec<Vec<Value>> = new_rows.into_iter().map(Option::unwrap_or_default).collect();
+    let row_key = |row: &[Value], index: u32| match key_column {
+        Some(k) => row[k].clone(),
+        None => Value::from(index),
+    };
+
+    let mut modified = Vec::new();
+    let mut unchanged = 0;
+    for (i, j) in pairs {
+        let (o, n) = (&old_rows[i as usize], &new_rows[j as usize]);
+        let changes: Vec<ColumnChange> = names
+            .iter()
+            .zip(o.iter().zip(n))
+            .filter(|(_, (a, b))| a != b)
+            .map(|(name, (a, b))| ColumnChange { column: name.clone(), old: a.clone(), new: b.clone() })
+            .collect();
+        if changes.is_empty() {
+            unchanged += 1;
+        } else {
+            modified.push(RowDiff { key: row_key(n, j), old_row: Some(i), new_row: Some(j), changes, values: None });
+        }
+    }
+
+    Ok(TableDiff {
+        table: table.name.clone(),
+        key_column: key_column.map(|k| names[k].clone()),
+        inserted: inserted
+            .into_iter()
+            .map(|j| {
+                let row = &new_rows[j as usize];
+                RowDiff { key: row_key(row, j), old_row: None, new_row: Some(j), changes: Vec::new(), values: Some(row_object(&names, row)) }
+            })
+            .collect(),
+        deleted: deleted
+            .into_iter()
+            .map(|i| {
+                let row = &old_rows[i as usize];
+                RowDiff { key: row_key(row, i), old_row: Some(i), new_row: None, changes: Vec::new(), values: Some(row_object(&names, row)) }
+            })
+            .collect(),
+        modified,
+        unchanged,
+        row_lengths,
+        skipped_columns,
+        unreadable,
+    })
+}
+
+/// Parses both versions of the dat file at `path` and diffs them with the
+/// schema table named after the file.
+pub fn diff_dat(path: &str, old: Vec<u8>, new: Vec<u8>, schema: &Schema) -> Result<TableDiff> {
+    let stem = std::path::Path::new(path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("");
+    let table = schema
+        .tables
+        .iter()
+        .find(|t| t.name.eq_ignore_ascii_case(stem))
+        .ok_or_else(|| Error::NotFound(format!("schema table for {}", path)))?;
+    diff_tables(&DatReader::new(old, path)?, &DatReader::new(new, path)?, table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dat::schema::Column;
+
+    fn column(name: &str, ty: &str, unique: bool) -> Column {
+        Column {
+            name: Some(name.to_string()),
+            description: None,
+            array: false,
+            r#type: ty.to_string(),
+            unique,
+            localized: false,
+            references: None,
+            interval: false,
+        }
+    }
+
+    /// A .datc64 with an (Id: string, Level: i32) row per entry.
+    fn dat(rows: &[(&str, i32)]) -> DatReader {
+        let rows: Vec<_> = rows.iter().map(|&(id, level)| (id, vec![level])).collect();
+        wide_dat(&rows)
+    }
+
+    /// A .datc64 with an Id string followed by i32 columns in each row.
+    fn wide_dat(rows: &[(&str, Vec<i32>)]) -> DatReader {
+        let mut fixed = Vec::new();
+        // The variable section starts with the 8-byte marker, so the first
+        // string sits at offset 8.
+        let mut var = vec![0xBB; 8];
+        for (id, ints) in rows {
+            fixed.extend_from_slice(&(var.len() as u64).to_le_bytes());
+            fixed.extend(ints.iter().flat_map(|i| i.to_le_bytes()));
+            var.extend(id.encode_utf16().chain([0, 0]).flat_map(u16::to_le_bytes));
+        }
+        let mut data = (rows.len() as u32).to_le_bytes().to_vec();
+        data.extend(fixed);
+        data.extend(var);
+        DatReader::new(data, "Test.datc64").unwrap()
+    }
+
+    fn table(unique: bool) -> Table {
+        Table {
+            name: "Test".to_string(),
+            columns: vec![column("Id", "string", unique), column("Level", "i32", false)],
+            tags: None,
+            valid_for: None,
+        }
+    }
+
+    #[test]
+    fn matches_rows_by_unique_column() {
+        let old = dat(&[("A", 1), ("B", 2), ("C", 3)]);
+        let new = dat(&[("B", 2), ("C", 4), ("D", 5)]);
+        let d = diff_tables(&old, &new, &table(true)).unwrap();
+
+        assert_eq!(d.key_column.as_deref(), Some("Id"));
+        assert_eq!(d.unchanged, 1);
+        assert_eq!(d.deleted.len(), 1);
+        assert_eq!(d.deleted[0].key, "A");
+        assert_eq!(d.deleted[0].values.as_ref().unwrap()["Level"], 1);
+        assert_eq!(d.inserted.len(), 1);
+        assert_eq!(d.inserted[0].key, "D");
+        assert_eq!(d.inserted[0].new_row, Some(2));
+        assert_eq!(d.modified.len(), 1);
+        let m = &d.modified[0];
+        assert_eq!((m.old_row, m.new_row), (Some(2), Some(1)));
+        assert_eq!(m.changes.len(), 1);
+        assert_eq!((m.changes[0].column.as_str(), &m.changes[0].old, &m.changes[0].new), ("Level", &Value::from(3), &Value::from(4)));
+
+        let mut text = Vec::new();
+        d.write_text(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("~ \"C\" (row 2 -> 1)\n    Level: 3 -> 4\n"), "{}", text);
+    }
+
+    #[test]
+    fn falls_back_to_row_index() {
+        let old = dat(&[("A", 1), ("A", 2)]);
+        let new = dat(&[("A", 1), ("B", 2), ("C", 3)]);
+        // Duplicate keys in the old file: the unique flag can't be trusted.
+        for unique in [false, true] {
+            let d = diff_tables(&old, &new, &table(unique)).unwrap();
+            assert_eq!(d.key_column, None);
+            assert_eq!(d.unchanged, 1);
+            assert_eq!(d.modified.len(), 1);
+            assert_eq!(d.modified[0].key, 1);
+            assert_eq!(d.modified[0].changes[0].column, "Id");
+            assert_eq!(d.inserted.len(), 1);
+            assert_eq!(d.inserted[0].key, 2);
+            assert!(d.deleted.is_empty());
+        }
+    }
+
+    #[test]
+    fn compares_the_columns_both_row_widths_share() {
+        let old = dat(&[("A", 1), ("B", 2)]);
+        let new = wide_dat(&[("A", vec![1, 7]), ("B", vec![3, 7])]);
+        let mut wide = table(true);
+        wide.columns.push(column("Extra", "i32", false));
+
+        // Neither the old schema nor the new one fits both files.
+        for table in [table(true), wide] {
+            let d = diff_tables(&old, &new, &table).unwrap();
+            assert_eq!(d.row_lengths, Some((12, 16)));
+            assert_eq!(d.unchanged, 1);
+            assert_eq!(d.modified.len(), 1);
+            assert_eq!(d.modified[0].changes[0].column, "Level");
+            let skipped: &[&str] = if table.columns.len() == 3 { &["Extra"] } else { &[] };
+            assert_eq!(d.skipped_columns, skipped);
+        }
+    }
+
+    #[test]
+    fn records_unreadable_rows_and_carries_on() {
+        let mut old = dat(&[("A", 1), ("B", 2)]);
+        let new = dat(&[("A", 1), ("B", 3)]);
+        // A row count past the end of the file, as in a truncated download.
+        old.row_count = 6;
+        let d = diff_tables(&old, &new, &table(false)).unwrap();
+
+        assert_eq!(d.unreadable.len(), 2);
+        assert!(d.unreadable[0].starts_with("old row 4: "), "{:?}", d.unreadable);
+        assert!(d.unreadable[1].starts_with("old row 5: "), "{:?}", d.unreadable);
+        assert_eq!(d.unchanged, 1);
+        assert_eq!(d.modified.len(), 1);
+        // Rows 2 and 3 read whatever follows the fixed section.
+        assert_eq!(d.deleted.len(), 2);
+
+        let mut text = Vec::new();
+        d.write_text(&mut text).unwrap();
+        assert!(String::from_utf8(text).unwrap().contains("? old row 4: "));
+    }
+}
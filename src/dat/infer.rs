@@ -0,0 +1,575 @@
+//! Column guessing for dat tables the schema no longer fits.
+//!
+//! When a patch adds columns before the community schema catches up, rows
+//! are longer than the schema says and everything after the first new
+//! column is misread. [`infer`] keeps the schema's columns for as long as
+//! the data agrees with them, then walks the rest of the row and picks, at
+//! each position, the first type every sampled row is consistent with:
+//!
+//! - `string`: an offset to a terminated, mostly printable UTF-16 string
+//!   in the variable data section (or 0)
+//! - array: a count and an offset whose elements fit in the variable data
+//!   section; the element type is guessed from the elements
+//! - `foreignrow`: a row index followed by zeros, or all `0xFE` for null
+//! - `row`: an index below this table's row count, with at least one null
+//! - `i32` when only the low byte is ever set, so small counts and flags
+//!   stored as integers don't turn into a `bool` and three unused bytes
+//! - `bool`: bytes that are only ever 0 or 1
+//! - `f32`: values whose exponent only makes sense for a float
+//! - `i32` otherwise, or `u8` for the last few bytes of a row
+//!
+//! These are guesses. Each comes with the evidence it was picked on, and
+//! [`Inference::schema_table`] turns them into a schema entry to review.
+
+use std::io::Write;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::reader::{get_column_size, DatReader};
+use super::schema::{Column, Schema, Table};
+use crate::error::{Error, Result};
+
+/// Rows looked at per column; larger tables are sampled evenly.
+const SAMPLE_ROWS: u32 = 4096;
+/// Longest string (in UTF-16 units) accepted before giving up on a column.
+const MAX_STRING: usize = 4096;
+const MAX_ARRAY: usize = 65536;
+/// No table has more rows than [`DatReader::new`] accepts.
+const MAX_ROWS: u64 = 10_000_000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InferredColumn {
+    /// Byte offset within the row.
+    pub offset: usize,
+    pub size: usize,
+    /// A schema type name: `string`, `foreignrow`, `i32` and so on.
+    pub r#type: String,
+    pub array: bool,
+    /// What the data looked like, for whoever reviews the guess.
+    pub evidence: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Inference {
+    pub table: String,
+    pub row_count: u32,
+    pub row_length: usize,
+    /// Row length the schema describes, if it has this table.
+    pub schema_length: Option<usize>,
+    /// Leading schema columns the data still agrees with.
+    pub kept_columns: usize,
+    /// Guesses for the bytes after the kept columns.
+    pub columns: Vec<InferredColumn>,
+}
+
+/// Sampled rows of one file, with the checks each guess is built from.
+struct Rows<'a> {
+    data: &'a [u8],
+    /// Start of each sampled row.
+    starts: Vec<usize>,
+    row_length: usize,
+    row_count: u32,
+    /// Where variable-section offset 0 would be; offsets start at 8.
+    var_base: usize,
+    ptr: usize,
+}
+
+struct StringStats {
+    non_empty: usize,
+    chars: usize,
+    printable: usize,
+    example: Option<String>,
+}
+
+impl<'a> Rows<'a> {
+    fn new(reader: &'a DatReader) -> Self {
+        let row_length = reader.row_length.unwrap_or(0);
+        let step = reader.row_count.div_ceil(SAMPLE_ROWS).max(1);
+        Self {
+            data: reader.get_data(),
+            starts: (0..reader.row_count).step_by(step as usize).map(|i| 4 + i as usize * row_length).collect(),
+            row_length,
+            row_count: reader.row_count,
+            var_base: (reader.data_section_offset as usize).saturating_sub(8),
+            ptr: if reader.is_64bit { 8 } else { 4 },
+        }
+    }
+
+    fn bytes(&self, start: usize, p: usize, len: usize) -> &'a [u8] {
+        &self.data[start + p..start + p + len]
+    }
+
+    fn u32_at(&self, start: usize, p: usize) -> u32 {
+        u32::from_le_bytes(self.bytes(start, p, 4).try_into().expect("4 bytes"))
+    }
+
+    fn u64_at(&self, start: usize, p: usize) -> u64 {
+        u64::from_le_bytes(self.bytes(start, p, 8).try_into().expect("8 bytes"))
+    }
+
+    /// A pointer-sized offset; 64-bit files only use the low half.
+    fn offset_at(&self, start: usize, p: usize) -> Option<u64> {
+        if self.ptr == 8 {
+            let v = self.u64_at(start, p);
+            (v >> 32 == 0).then_some(v)
+        } else {
+            Some(self.u32_at(start, p) as u64)
+        }
+    }
+
+    /// `len` bytes at a variable-section offset, if they are in the file.
+    fn var(&self, offset: u64, len: usize) -> Option<&'a [u8]> {
+        if offset < 8 {
+            return None;
+        }
+        let start = self.var_base.checked_add(offset as usize)?;
+        self.data.get(start..start.checked_add(len)?)
+    }
+
+    fn string_at(&self, offset: u64) -> Option<String> {
+        if offset == 0 {
+            return Some(String::new());
+        }
+        let mut units = Vec::new();
+        for i in 0..=MAX_STRING {
+            let b = self.var(offset + 2 * i as u64, 2)?;
+            match u16::from_le_bytes([b[0], b[1]]) {
+                0 => break,
+                u => units.push(u),
+            }
+        }
+        let s = String::from_utf16(&units).ok()?;
+        let clean = units.len() <= MAX_STRING && !s.chars().any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t'));
+        clean.then_some(s)
+    }
+
+    fn strings(&self, p: usize) -> Option<StringStats> {
+        let mut stats = StringStats { non_empty: 0, chars: 0, printable: 0, example: None };
+        for &start in &self.starts {
+            let s = self.string_at(self.offset_at(start, p)?)?;
+            if !s.is_empty() {
+                stats.non_empty += 1;
+                stats.chars += s.chars().count();
+                stats.printable += s.chars().filter(|c| c.is_ascii_graphic() || *c == ' ').count();
+                stats.example.get_or_insert(s);
+            }
+        }
+        Some(stats)
+    }
+
+    /// The (count, offset) of every non-empty array, if the column can be
+    /// an array at all.
+    fn arrays(&self, p: usize) -> Option<Vec<(usize, u64)>> {
+        let mut arrays = Vec::new();
+        for &start in &self.starts {
+            let count = self.offset_at(start, p)? as usize;
+            let offset = self.offset_at(start, p + self.ptr)?;
+            if count > MAX_ARRAY {
+                return None;
+            }
+            // Empty arrays may point anywhere, including 0.
+            if count > 0 {
+                self.var(offset, count)?;
+                arrays.push((count, offset));
+            }
+        }
+        Some(arrays)
+    }
+
+    /// Whether `bytes` is a foreign key; `Some(None)` for null.
+    fn foreign_key(&self, bytes: &[u8]) -> Option<Option<u64>> {
+        if bytes.iter().all(|&b| b == 0xFE) {
+            return Some(None);
+        }
+        let half = bytes.len() / 2;
+        let mut lo = [0u8; 8];
+        lo[..half].copy_from_slice(&bytes[..half]);
+        let key = u64::from_le_bytes(lo);
+        (bytes[half..].iter().all(|&b| b == 0) && key < MAX_ROWS).then_some(Some(key))
+    }
+
+    /// Non-null keys and the null count of a foreign key column.
+    fn foreign_rows(&self, p: usize) -> Option<(Vec<u64>, usize)> {
+        let mut keys = Vec::new();
+        let mut nulls = 0;
+        for &start in &self.starts {
+            match self.foreign_key(self.bytes(start, p, 2 * self.ptr))? {
+                Some(k) => keys.push(k),
+                None => nulls += 1,
+            }
+        }
+        Some((keys, nulls))
+    }
+
+    /// Null count of a self-reference column.
+    fn self_rows(&self, p: usize) -> Option<usize> {
+        let mut nulls = 0;
+        for &start in &self.starts {
+            let b = self.bytes(start, p, self.ptr);
+            if b.iter().all(|&b| b == 0xFE) {
+                nulls += 1;
+            } else if self.offset_at(start, p)? >= self.row_count as u64 {
+                return None;
+            }
+        }
+        Some(nulls)
+    }
+
+    fn u32s(&self, p: usize) -> impl Iterator<Item = u32> + '_ {
+        self.starts.iter().map(move |&start| self.u32_at(start, p))
+    }
+
+    /// Whether the schema's `col` at `p` is contradicted by the data.
+    fn agrees(&self, col: &Column, p: usize) -> bool {
+        if col.array {
+            return self.arrays(p).is_some();
+        }
+        match col.r#type.as_str() {
+            "string" | "ref|string" => self.strings(p).is_some(),
+            "foreignrow" | "foreign_row" => self.foreign_rows(p).is_some(),
+            _ => true,
+        }
+    }
+}
+
+/// Whether `bits` reads as a float of reasonable magnitude (about 1e-8 to
+/// 1e10), which small integers, negative integers and hashes mostly don't.
+fn float_like(bits: u32) -> bool {
+    let exponent = (bits >> 23) & 0xFF;
+    bits == 0 || (100..=160).contains(&exponent)
+}
+
+fn range(values: impl Iterator<Item = i64>) -> String {
+    let (min, max) = values.fold((i64::MAX, i64::MIN), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    if min > max {
+        "no values".to_string()
+    } else if min == max {
+        format!("always {}", min)
+    } else {
+        format!("values {}..={}", min, max)
+    }
+}
+
+fn truncated(s: &str) -> String {
+    let mut out: String = s.chars().take(40).collect();
+    if out.len() < s.len() {
+        out.push_str("...");
+    }
+    out
+}
+
+/// Guesses the element type of non-empty `arrays`: foreign keys, strings,
+/// floats or 32-bit integers, in that order.
+fn element_type(rows: &Rows, arrays: &[(usize, u64)]) -> (&'static str, String) {
+    let elements = |size: usize| {
+        arrays.iter().map(move |&(count, offset)| rows.var(offset, count * size).map(|b| b.chunks(size).collect::<Vec<_>>()))
+    };
+    let max_count = arrays.iter().map(|a| a.0).max().unwrap_or(0);
+
+    let fk = elements(2 * rows.ptr)
+        .map(|chunks| chunks?.into_iter().map(|b| rows.foreign_key(b)).collect::<Option<Vec<_>>>())
+        .collect::<Option<Vec<_>>>();
+    if let Some(keys) = fk.filter(|k| k.iter().flatten().any(|k| k.is_some_and(|k| k > 0))) {
+        let keys: Vec<u64> = keys.into_iter().flatten().flatten().collect();
+        return ("foreignrow", format!("up to {} row indices, largest {}", max_count, keys.iter().max().unwrap_or(&0)));
+    }
+
+    let strings = elements(rows.ptr)
+        .map(|chunks| {
+            chunks?
+                .into_iter()
+                .map(|b| {
+                    let mut v = [0u8; 8];
+                    v[..b.len()].copy_from_slice(b);
+                    let v = u64::from_le_bytes(v);
+                    if v >> 32 != 0 {
+                        return None;
+                    }
+                    rows.string_at(v).filter(|s| !s.is_empty())
+                })
+                .collect::<Option<Vec<_>>>()
+        })
+        .collect::<Option<Vec<_>>>();
+    if let Some(strings) = strings {
+        let example = strings.iter().flatten().next().map(|s| truncated(s)).unwrap_or_default();
+        return ("string", format!("up to {} strings, e.g. {:?}", max_count, example));
+    }
+
+    let words = elements(4)
+        .map(|chunks| Some(chunks?.into_iter().map(|b| u32::from_le_bytes(b.try_into().expect("4 bytes"))).collect::<Vec<_>>()))
+        .collect::<Option<Vec<_>>>();
+    match words {
+        Some(words) => {
+            let words: Vec<u32> = words.into_iter().flatten().collect();
+            if words.iter().all(|&w| float_like(w)) && words.iter().any(|&w| w != 0) {
+                let floats = words.iter().map(|&w| f32::from_bits(w));
+                let (min, max) = floats.fold((f32::MAX, f32::MIN), |(lo, hi), f| (lo.min(f), hi.max(f)));
+                ("f32", format!("up to {} floats, {}..={}", max_count, min, max))
+            } else {
+                ("i32", format!("up to {} integers, {}", max_count, range(words.iter().map(|&w| w as i32 as i64))))
+            }
+        }
+        None => ("u8", format!("up to {} bytes (too short for wider elements)", max_count)),
+    }
+}
+
+/// The first type the data at `p` (with `remaining` bytes left) agrees with.
+fn guess(rows: &Rows, p: usize, remaining: usize) -> InferredColumn {
+    let column = |size: usize, ty: &str, array: bool, evidence: String| InferredColumn {
+        offset: p,
+        size,
+        r#type: ty.to_string(),
+        array,
+        evidence,
+    };
+    let ptr = rows.ptr;
+    let sampled = rows.starts.len();
+
+    if remaining >= ptr {
+        if let Some(s) = rows.strings(p) {
+            if s.non_empty > 0 && s.printable * 2 >= s.chars {
+                let example = s.example.as_deref().map(truncated).unwrap_or_default();
+                return column(ptr, "string", false, format!("{} of {} sampled values are text, e.g. {:?}", s.non_empty, sampled, example));
+            }
+        }
+    }
+    if remaining >= 2 * ptr {
+        if let Some(arrays) = rows.arrays(p).filter(|a| !a.is_empty()) {
+            let (ty, evidence) = element_type(rows, &arrays);
+            return column(2 * ptr, ty, true, format!("count/offset pairs, {} non-empty; {}", arrays.len(), evidence));
+        }
+        if let Some((keys, nulls)) = rows.foreign_rows(p).filter(|(keys, _)| keys.iter().any(|&k| k > 0) || keys.is_empty()) {
+            let evidence = match keys.iter().max() {
+                Some(max) => format!("row indices up to {}, {} of {} null", max, nulls, sampled),
+                None => "always null (0xFE)".to_string(),
+            };
+            return column(2 * ptr, "foreignrow", false, evidence);
+        }
+    }
+    if remaining >= ptr {
+        if let Some(nulls) = rows.self_rows(p).filter(|&n| n > 0 && n < sampled) {
+            return column(ptr, "row", false, format!("indices below the row count, {} of {} null", nulls, sampled));
+        }
+    }
+    if remaining >= 4 && rows.starts.iter().all(|&s| rows.bytes(s, p + 1, 3) == [0, 0, 0]) {
+        return column(4, "i32", false, format!("{}, only the low byte is used", range(rows.u32s(p).map(|v| v as i64))));
+    }
+    let low_bytes = || rows.starts.iter().map(|&s| rows.bytes(s, p, 1)[0]);
+    if low_bytes().all(|b| b <= 1) && low_bytes().any(|b| b == 1) {
+        return column(1, "bool", false, "only 0 and 1".to_string());
+    }
+    if remaining >= 4 {
+        if rows.u32s(p).all(float_like) {
+            let (min, max) = rows.u32s(p).map(f32::from_bits).fold((f32::MAX, f32::MIN), |(lo, hi), f| (lo.min(f), hi.max(f)));
+            return column(4, "f32", false, format!("float values {}..={}", min, max));
+        }
+        return column(4, "i32", false, range(rows.u32s(p).map(|v| v as i32 as i64)));
+    }
+    column(1, "u8", false, range(low_bytes().map(i64::from)))
+}
+
+/// Guesses the columns of `reader` not covered by `table`. Schema columns
+/// are kept up to the first one that runs past the row or that the data
+/// contradicts (a string column whose offsets don't lead to strings, say);
+/// everything after that is guessed.
+pub fn infer(reader: &DatReader, name: &str, table: Option<&Table>) -> Inference {
+    let rows = Rows::new(reader);
+    let mut p = 0;
+    let mut kept_columns = 0;
+    for col in table.map(|t| t.columns.as_slice()).unwrap_or_default() {
+        let size = get_column_size(col, reader.is_64bit);
+        if p + size > rows.row_length || (!rows.starts.is_empty() && !rows.agrees(col, p)) {
+            break;
+        }
+        p += size;
+        kept_columns += 1;
+    }
+
+    let mut columns = Vec::new();
+    if !rows.starts.is_empty() {
+        while p < rows.row_length {
+            let col = guess(&rows, p, rows.row_length - p);
+            p += col.size;
+            columns.push(col);
+        }
+    }
+
+    Inference {
+        table: table.map_or_else(|| name.to_string(), |t| t.name.clone()),
+        row_count: reader.row_count,
+        row_length: rows.row_length,
+        schema_length: table.map(|t| t.columns.iter().map(|c| get_column_size(c, reader.is_64bit)).sum()),
+        kept_columns,
+        columns,
+    }
+}
+
+/// [`infer`] for the file at `path`, against the schema's table for it.
+pub fn infer_dat(path: &str, data: Vec<u8>, schema: Option<&Schema>) -> Result<Inference> {
+    let stem = std::path::Path::new(path).file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let table = schema.and_then(|s| s.tables.iter().find(|t| t.name.eq_ignore_ascii_case(stem)));
+    let reader = DatReader::new(data, path)?;
+    if reader.row_length.is_none() {
+        return Err(Error::InvalidData(format!("{} has no fixed row length", path)));
+    }
+    Ok(infer(&reader, stem, table))
+}
+
+impl Inference {
+    /// Byte offset where guessing started.
+    pub fn guessed_from(&self) -> usize {
+        self.columns.first().map_or(self.row_length, |c| c.offset)
+    }
+
+    /// A schema.min.json table entry: the kept columns of `table` followed
+    /// by the guesses, unnamed and described by their evidence.
+    pub fn schema_table(&self, table: Option<&Table>) -> Value {
+        let kept = table.map(|t| &t.columns[..self.kept_columns]).unwrap_or_default();
+        let mut columns: Vec<Value> = kept
+            .iter()
+            .map(|c| {
+                json!({
+                    "name": c.name,
+                    "description": c.description,
+                    "array": c.array,
+                    "type": c.r#type,
+                    "unique": c.unique,
+                    "localized": c.localized,
+                    "references": c.references.as_ref().map(|r| json!({ "table": r.table, "column": r.column })),
+                    "interval": c.interval,
+                })
+            })
+            .collect();
+        columns.extend(self.columns.iter().map(|c| {
+            json!({
+                "name": null,
+                "description": format!("Inferred: {}", c.evidence),
+                "array": c.array,
+                "type": c.r#type,
+                "unique": false,
+                "localized": false,
+                "references": null,
+                "interval": false,
+            })
+        }));
+        json!({
+            "name": self.table,
+            "columns": columns,
+            "tags": table.and_then(|t| t.tags.clone()).unwrap_or_default(),
+            "validFor": table.and_then(|t| t.valid_for),
+        })
+    }
+
+    pub fn summary(&self) -> String {
+        let schema = match self.schema_length {
+            Some(len) => format!("the schema describes {}", len),
+            None => "no schema table".to_string(),
+        };
+        format!("{}: {} rows of {} bytes, {}", self.table, self.row_count, self.row_length, schema)
+    }
+
+    pub fn write_text(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(out, "{}", self.summary())?;
+        if self.columns.is_empty() {
+            writeln!(out, "Kept {} schema columns; nothing left to guess", self.kept_columns)?;
+            return out.flush();
+        }
+        writeln!(out, "Kept {} schema columns; guessing from byte {}:", self.kept_columns, self.guessed_from())?;
+        writeln!(out, "{:>8}  {:>4}  {:<14}  evidence", "offset", "size", "type")?;
+        for c in &self.columns {
+            let ty = if c.array { format!("[{}]", c.r#type) } else { c.r#type.clone() };
+            writeln!(out, "{:>8}  {:>4}  {:<14}  {}", c.offset, c.size, ty, c.evidence)?;
+        }
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, ty: &str) -> Column {
+        Column {
+            name: Some(name.to_string()),
+            description: None,
+            array: false,
+            r#type: ty.to_string(),
+            unique: false,
+            localized: false,
+            references: None,
+            interval: false,
+        }
+    }
+
+    /// Rows of: Id string, then columns the schema doesn't know yet:
+    /// foreignrow, [i32], bool, f32, i32.
+    fn table_data() -> Vec<u8> {
+        let mut var = vec![0xBB; 8];
+        let mut fixed = Vec::new();
+        for i in 0..6u64 {
+            fixed.extend((var.len() as u64).to_le_bytes());
+            var.extend(format!("Row{}", i).encode_utf16().chain([0, 0]).flat_map(u16::to_le_bytes));
+            if i == 2 {
+                fixed.extend([0xFE; 16]);
+            } else {
+                fixed.extend([(i * 3).to_le_bytes(), 0u64.to_le_bytes()].concat());
+            }
+            let values: Vec<i32> = if i == 0 { vec![] } else { vec![i as i32, 10 * i as i32] };
+            fixed.extend((values.len() as u64).to_le_bytes());
+            fixed.extend((var.len() as u64).to_le_bytes());
+            var.extend(values.iter().flat_map(|v| v.to_le_bytes()));
+            fixed.push((i % 2) as u8);
+            fixed.extend((1.5 * i as f32 + 0.25).to_le_bytes());
+            fixed.extend((1000 + 7 * i as i32).to_le_bytes());
+        }
+        let mut data = 6u32.to_le_bytes().to_vec();
+        data.extend(fixed);
+        data.extend(var);
+        data
+    }
+
+    #[test]
+    fn guesses_columns_after_the_schema() {
+        let table = Table { name: "Test".to_string(), columns: vec![column("Id", "string")], tags: None, valid_for: None };
+        let reader = DatReader::new(table_data(), "Test.datc64").unwrap();
+        let inference = infer(&reader, "Test", Some(&table));
+        assert_eq!(inference.row_length, 8 + 16 + 16 + 1 + 4 + 4);
+        assert_eq!(inference.schema_length, Some(8));
+        assert_eq!(inference.kept_columns, 1);
+        let guessed: Vec<(usize, &str, bool)> = inference.columns.iter().map(|c| (c.offset, c.r#type.as_str(), c.array)).collect();
+        assert_eq!(
+            guessed,
+            [(8, "foreignrow", false), (24, "i32", true), (40, "bool", false), (41, "f32", false), (45, "i32", false)]
+        );
+        assert_eq!(inference.columns[0].evidence, "row indices up to 15, 1 of 6 null");
+
+        let patch = inference.schema_table(Some(&table));
+        let columns = patch["columns"].as_array().unwrap();
+        assert_eq!(columns.len(), 6);
+        assert_eq!(columns[0]["name"], "Id");
+        assert_eq!(columns[2]["array"], true);
+        assert!(columns[5]["name"].is_null());
+    }
+
+    #[test]
+    fn contradicted_schema_columns_are_guessed_again() {
+        // The schema thinks the foreign key is a string.
+        let table = Table {
+            name: "Test".to_string(),
+            columns: vec![column("Id", "string"), column("Name", "string")],
+            tags: None,
+            valid_for: None,
+        };
+        let reader = DatReader::new(table_data(), "Test.datc64").unwrap();
+        let inference = infer(&reader, "Test", Some(&table));
+        assert_eq!(inference.kept_columns, 1);
+        assert_eq!(inference.guessed_from(), 8);
+        assert_eq!(inference.columns[0].r#type, "foreignrow");
+
+        let inference = infer(&reader, "Test", None);
+        assert_eq!(inference.table, "Test");
+        assert_eq!(inference.columns[0].r#type, "string");
+        assert_eq!(inference.columns.len(), 6);
+    }
+}
@@ -1,6 +1,14 @@
 pub mod schema;
 pub mod reader;
+pub mod diff;
 pub mod relational;
+pub mod query;
+pub mod sqlite;
+pub mod csv;
+pub mod arrow;
+pub mod infer;
+pub mod validate;
+pub mod overlay;
 pub mod csd;
 pub mod psg;
 
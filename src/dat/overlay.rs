@@ -0,0 +1,280 @@
+//! Local corrections layered over the downloaded schema.
+//!
+//! The community schema is replaced wholesale on every update, so fixes
+//! made locally live in a separate overlay file ([`OVERLAY_FILE_NAME`] in
+//! the app data directory) that is merged over the schema each time it is
+//! loaded:
+//!
+//! ```json
+//! {
+//!   "columns": [{ "table": "Mods", "index": 12, "original": "Unknown12", "name": "Tier", "type": "i32" }],
+//!   "tables": [{ "name": "NewTable", "columns": [], "tags": [], "validFor": 2 }],
+//!   "enumerations": [{ "name": "ModDomains", "indexing": 1, "enumerators": ["ITEM", "FLASK"] }]
+//! }
+//! ```
+//!
+//! Tables and enumerations replace the schema's entry of the same name (or
+//! are added). Column edits then rename a column, change its type or make
+//! it an array; they find the column by its `original` name when the
+//! schema still has one by that name, so an upstream column insertion
+//! doesn't redirect the edit to the wrong column, then by the edited name
+//! (a schema the overlay was already merged into), and by `index`
+//! otherwise.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::schema::{Column, Enumeration, Schema, Table};
+use crate::error::{Error, Result};
+
+pub const OVERLAY_FILE_NAME: &str = "schema.overlay.json";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SchemaOverlay {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub columns: Vec<ColumnEdit>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tables: Vec<Table>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub enumerations: Vec<Enumeration>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ColumnEdit {
+    pub table: String,
+    /// Position of the column in the table.
+    pub index: usize,
+    /// The column's name in the schema the edit was made against.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub r#type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub array: Option<bool>,
+}
+
+impl ColumnEdit {
+    fn target(&self, table: &Table) -> Option<usize> {
+        let named = |name: &str| {
+            table.columns.iter().position(|c| c.name.as_deref().is_some_and(|n| n.eq_ignore_ascii_case(name)))
+        };
+        self.original
+            .as_deref()
+            .and_then(named)
+            .or_else(|| self.name.as_deref().and_then(named))
+            .or((self.index < table.columns.len()).then_some(self.index))
+    }
+
+    fn apply_to(&self, column: &mut Column) {
+        if let Some(name) = &self.name {
+            column.name = Some(name.clone());
+        }
+        if let Some(ty) = &self.r#type {
+            column.r#type = ty.clone();
+        }
+        if let Some(array) = self.array {
+            column.array = array;
+        }
+    }
+}
+
+/// The overlay file next to the downloaded schema.
+pub fn default_path() -> PathBuf {
+    crate::settings::AppSettings::get_app_data_dir().join(OVERLAY_FILE_NAME)
+}
+
+impl SchemaOverlay {
+    /// Reads the overlay at `path`; a missing file is an empty overlay.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data).map_err(|e| Error::parse("schema overlay", e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_vec_pretty(self).map_err(|e| Error::InvalidData(e.to_string()))?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty() && self.tables.is_empty() && self.enumerations.is_empty()
+    }
+
+    /// Merges the overlay into `schema`. Returns a note for every column
+    /// edit that matched no column, so stale edits don't go unnoticed.
+    pub fn apply(&self, schema: &mut Schema) -> Vec<String> {
+        for table in &self.tables {
+            // Without validFor the table replaces every variant.
+            let replaces = |t: &Table| {
+                t.name.eq_ignore_ascii_case(&table.name) && (table.valid_for.is_none() || t.valid_for == table.valid_for)
+            };
+            schema.tables.retain(|t| !replaces(t));
+            schema.tables.push(table.clone());
+        }
+
+        if !self.enumerations.is_empty() {
+            let enumerations = schema.enumeration.get_or_insert_with(Vec::new);
+            for e in &self.enumerations {
+                enumerations.retain(|x| !x.name.eq_ignore_ascii_case(&e.name));
+                enumerations.push(e.clone());
+            }
+        }
+
+        let mut unmatched = Vec::new();
+        for edit in &self.columns {
+            let mut matched = false;
+            for table in schema.tables.iter_mut().filter(|t| t.name.eq_ignore_ascii_case(&edit.table)) {
+                if let Some(j) = edit.target(table) {
+                    edit.apply_to(&mut table.columns[j]);
+                    matched = true;
+                }
+            }
+            if !matched {
+                unmatched.push(format!("{} column {} matches nothing in the schema", edit.table, edit.index));
+            }
+        }
+        unmatched
+    }
+
+    /// Adds `edit`, merging it with an earlier edit of the same column.
+    pub fn set_column(&mut self, edit: ColumnEdit) {
+        let same = |e: &ColumnEdit| e.table.eq_ignore_ascii_case(&edit.table) && e.index == edit.index;
+        match self.columns.iter_mut().find(|e| same(e)) {
+            Some(existing) => {
+                existing.name = edit.name.or(existing.name.take());
+                existing.r#type = edit.r#type.or(existing.r#type.take());
+                existing.array = edit.array.or(existing.array);
+            }
+            None => self.columns.push(edit),
+        }
+    }
+
+    /// Adds `table`, replacing an overlay table of the same name.
+    pub fn set_table(&mut self, table: Table) {
+        self.tables.retain(|t| !(t.name.eq_ignore_ascii_case(&table.name) && t.valid_for == table.valid_for));
+        self.tables.push(table);
+    }
+}
+
+/// Loads the overlay at [`default_path`] and merges it into `schema`,
+/// logging problems rather than failing: a broken overlay should not stop
+/// the downloaded schema from loading.
+pub fn apply_default(schema: &mut Schema) {
+    let path = default_path();
+    match SchemaOverlay::load(&path) {
+        Ok(overlay) => {
+            for note in overlay.apply(schema) {
+                log::warn!("{}: {}", path.display(), note);
+            }
+        }
+        Err(e) => log::warn!("Ignoring schema overlay {}: {}", path.display(), e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, ty: &str) -> Column {
+        Column {
+            name: Some(name.to_string()),
+            description: None,
+            array: false,
+            r#type: ty.to_string(),
+            unique: false,
+            localized: false,
+            references: None,
+            interval: false,
+        }
+    }
+
+    fn schema() -> Schema {
+        Schema {
+            version: 0,
+            created_at: 0,
+            tables: vec![
+                Table {
+                    name: "Mods".to_string(),
+                    columns: vec![column("Id", "string"), column("Unknown1", "i32"), column("Level", "i32")],
+                    tags: None,
+                    valid_for: Some(3),
+                },
+                Table { name: "Stats".to_string(), columns: vec![column("Id", "string")], tags: None, valid_for: Some(1) },
+                Table { name: "Stats".to_string(), columns: vec![column("Id", "string")], tags: None, valid_for: Some(2) },
+            ],
+            enumeration: None,
+        }
+    }
+
+    #[test]
+    fn merges_tables_enumerations_and_column_edits() {
+        let overlay: SchemaOverlay = serde_json::from_str(
+            r#"{
+                "columns": [
+                    { "table": "mods", "index": 1, "original": "Unknown1", "name": "Tier", "type": "u16" },
+                    { "table": "Mods", "index": 2, "array": true },
+                    { "table": "Gone", "index": 0, "name": "X" }
+                ],
+                "tables": [{ "name": "Stats", "columns": [], "tags": null, "validFor": 2 }],
+                "enumerations": [{ "name": "ModDomains", "indexing": 1, "enumerators": ["ITEM", null] }]
+            }"#,
+        )
+        .unwrap();
+
+        // Upstream inserted a column before Unknown1 since the edit was made.
+        let mut schema = schema();
+        schema.tables[0].columns.insert(1, column("New", "bool"));
+        let notes = overlay.apply(&mut schema);
+        assert_eq!(notes, ["Gone column 0 matches nothing in the schema"]);
+
+        let mods = &schema.tables[0];
+        assert_eq!(mods.columns[2].name.as_deref(), Some("Tier"));
+        assert_eq!(mods.columns[2].r#type, "u16");
+        // Without `original`, an edit goes by position.
+        assert!(mods.columns[2].array);
+        assert_eq!(mods.columns[1].name.as_deref(), Some("New"));
+
+        let stats: Vec<(Option<u32>, usize)> =
+            schema.tables.iter().filter(|t| t.name == "Stats").map(|t| (t.valid_for, t.columns.len())).collect();
+        assert_eq!(stats, [(Some(1), 1), (Some(2), 0)]);
+        assert_eq!(schema.enumeration.unwrap()[0].enumerators, [Some("ITEM".to_string()), None]);
+    }
+
+    #[test]
+    fn applying_twice_leaves_the_schema_unchanged() {
+        let overlay: SchemaOverlay = serde_json::from_str(
+            r#"{ "columns": [{ "table": "Mods", "index": 1, "original": "Unknown1", "name": "Tier", "type": "u16" }] }"#,
+        )
+        .unwrap();
+        let mut schema = schema();
+        schema.tables[0].columns.insert(1, column("New", "bool"));
+
+        assert!(overlay.apply(&mut schema).is_empty());
+        let once = format!("{:?}", schema);
+        assert!(overlay.apply(&mut schema).is_empty());
+        assert_eq!(format!("{:?}", schema), once);
+
+        let mods = &schema.tables[0];
+        assert_eq!((mods.columns[1].name.as_deref(), mods.columns[1].r#type.as_str()), (Some("New"), "bool"));
+        assert_eq!((mods.columns[2].name.as_deref(), mods.columns[2].r#type.as_str()), (Some("Tier"), "u16"));
+    }
+
+    #[test]
+    fn column_edits_merge_and_round_trip() {
+        let mut overlay = SchemaOverlay::default();
+        overlay.set_column(ColumnEdit { table: "Mods".into(), index: 1, name: Some("Tier".into()), ..Default::default() });
+        overlay.set_column(ColumnEdit { table: "mods".into(), index: 1, r#type: Some("u16".into()), ..Default::default() });
+        assert_eq!(overlay.columns.len(), 1);
+
+        let json = serde_json::to_string(&overlay).unwrap();
+        assert_eq!(json, r#"{"columns":[{"table":"Mods","index":1,"name":"Tier","type":"u16"}]}"#);
+        let back: SchemaOverlay = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.columns, overlay.columns);
+    }
+}
@@ -1,6 +1,8 @@
 use serde::{Serialize, Serializer};
 use serde::ser::SerializeStruct;
 
+use crate::error::{Error, Result};
+
 // Orbit radii differ between graph types. The passive skill tree (graph_type 0)
 // and the atlas tree (graph_type 1) place outer orbits at slightly different
 // radii; using the wrong table drifts nodes by a few pixels on the outer rings.
@@ -69,39 +71,21 @@ pub struct PsgNode {
     pub connections: Vec<PsgConnection>,
 }
 
-pub fn parse_psg(data: &[u8]) -> Result<PsgFile, String> {
+pub fn parse_psg(data: &[u8]) -> Result<PsgFile> {
     let mut offset = 0;
-    
-    // Helper to read u8
-    let read_u8 = |offset: &mut usize| -> Result<u8, String> {
-        let val = *data.get(*offset).ok_or_else(|| "Unexpected EOF parsing u8".to_string())?;
-        *offset += 1;
-        Ok(val)
-    };
-    
-    // Helper to read u32 LE
-    let read_u32 = |offset: &mut usize| -> Result<u32, String> {
-        let slice = data.get(*offset..*offset+4).ok_or_else(|| "Unexpected EOF parsing u32".to_string())?;
-        let bytes: [u8; 4] = slice.try_into().map_err(|_| "Failed to convert slice to array".to_string())?;
-        *offset += 4;
-        Ok(u32::from_le_bytes(bytes))
-    };
-    
-    // Helper to read i32 LE (for curvature)
-    let read_i32 = |offset: &mut usize| -> Result<i32, String> {
-        let slice = data.get(*offset..*offset+4).ok_or_else(|| "Unexpected EOF parsing i32".to_string())?;
-        let bytes: [u8; 4] = slice.try_into().map_err(|_| "Failed to convert slice to array".to_string())?;
-        *offset += 4;
-        Ok(i32::from_le_bytes(bytes))
-    };
 
-    // Helper to read f32 LE
-    let read_f32 = |offset: &mut usize| -> Result<f32, String> {
-        let slice = data.get(*offset..*offset+4).ok_or_else(|| "Unexpected EOF parsing f32".to_string())?;
-        let bytes: [u8; 4] = slice.try_into().map_err(|_| "Failed to convert slice to array".to_string())?;
-        *offset += 4;
-        Ok(f32::from_le_bytes(bytes))
+    let read = |offset: &mut usize, len: usize| -> Result<&[u8]> {
+        let slice = data
+            .get(*offset..*offset + len)
+            .ok_or(Error::TruncatedRecord { what: "PSG", offset: *offset as u64 })?;
+        *offset += len;
+        Ok(slice)
     };
+    let read_u8 = |offset: &mut usize| -> Result<u8> { Ok(read(offset, 1)?[0]) };
+    let read_u32 = |offset: &mut usize| -> Result<u32> { Ok(u32::from_le_bytes(read(offset, 4)?.try_into().unwrap())) };
+    // Signed, for the orbit of a connection.
+    let read_i32 = |offset: &mut usize| -> Result<i32> { Ok(i32::from_le_bytes(read(offset, 4)?.try_into().unwrap())) };
+    let read_f32 = |offset: &mut usize| -> Result<f32> { Ok(f32::from_le_bytes(read(offset, 4)?.try_into().unwrap())) };
 
     // Header Parsing
     let _version = read_u8(&mut offset)?;
@@ -115,7 +99,7 @@ pub fn parse_psg(data: &[u8]) -> Result<PsgFile, String> {
     // Root Length (u32)
     let root_length = read_u32(&mut offset)?;
     if root_length > 1000 {
-        return Err(format!("Unrealistic root length: {}", root_length));
+        return Err(Error::parse("PSG", format!("unrealistic root length: {}", root_length)));
     }
     
     let mut roots = Vec::new();
@@ -0,0 +1,880 @@
+//! A small SQL-like query language over dat tables.
+//!
+//! ```text
+//! SELECT Id, ModTypeKey.Name AS Type, Domain
+//! FROM Mods
+//! WHERE Domain = 'ITEM' AND Stat1Key.Id LIKE '%fire%'
+//! ORDER BY Level DESC
+//! LIMIT 20
+//! ```
+//!
+//! Columns are addressed by schema name, case-insensitively. A dotted path
+//! follows foreign keys through a [`Database`] (`ModTypeKey.Name`), list
+//! columns yield arrays, and enumeration columns yield the enumerator's
+//! name. `JOIN Stats s ON m.StatsKeys` adds one result row per row the
+//! foreign key (or each element of a foreign key list) points at, so `s`
+//! can be filtered and selected like the base table; `LEFT JOIN` keeps rows
+//! whose key is null.
+//!
+//! Comparisons against a list are true if any element matches.
+//! `LIKE` takes `%` and `_` wildcards and ignores case; `CONTAINS` tests
+//! for a substring or a list element.
+
+use std::cmp::Ordering;
+use std::io::Write;
+
+use serde_json::{Map, Value};
+use winnow::ascii::{digit1, multispace0, Caseless};
+use winnow::combinator::{alt, cut_err, delimited, not, opt, preceded, repeat, separated, terminated};
+use winnow::error::{ContextError, StrContext, StrContextValue};
+use winnow::prelude::*;
+use winnow::token::{one_of, take_while};
+
+use super::relational::{Database, Reference};
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub select: Vec<SelectItem>,
+    pub from: Source,
+    pub joins: Vec<Join>,
+    pub filter: Option<Expr>,
+    /// Expressions and whether each sorts descending.
+    pub order_by: Vec<(Expr, bool)>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectItem {
+    /// `*`: every column of every source.
+    All,
+    Expr { expr: Expr, alias: Option<String> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    pub table: String,
+    pub alias: Option<String>,
+}
+
+impl Source {
+    fn is_named(&self, name: &str) -> bool {
+        self.alias.as_deref().unwrap_or(&self.table).eq_ignore_ascii_case(name) || self.table.eq_ignore_ascii_case(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Join {
+    pub source: Source,
+    /// Reference column path, optionally starting with an earlier source's
+    /// name or alias.
+    pub on: Vec<String>,
+    pub left: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Like,
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    Path(Vec<String>),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Cmp(Box<Expr>, CmpOp, Box<Expr>),
+    /// `expr IS NULL`, or `IS NOT NULL` when the flag is set.
+    IsNull(Box<Expr>, bool),
+    In(Box<Expr>, Vec<Expr>),
+}
+
+impl Expr {
+    fn label(&self) -> String {
+        match self {
+            Expr::Path(p) => p.join("."),
+            Expr::Literal(v) => v.to_string(),
+            _ => "expr".to_string(),
+        }
+    }
+}
+
+// ---- Parser ----
+
+type PResult<T> = winnow::ModalResult<T, ContextError>;
+
+const KEYWORDS: &[&str] = &[
+    "select", "from", "where", "join", "left", "on", "order", "by", "limit", "offset", "and", "or", "not", "as",
+    "asc", "desc", "is", "null", "in", "like", "contains", "true", "false",
+];
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn expected(what: &'static str) -> StrContext {
+    StrContext::Expected(StrContextValue::Description(what))
+}
+
+fn kw<'i>(word: &'static str) -> impl Parser<&'i str, (), ContextError> {
+    preceded(multispace0, terminated(Caseless(word), not(one_of(is_ident_char)))).void()
+}
+
+fn sym<'i>(s: &'static str) -> impl Parser<&'i str, (), ContextError> {
+    preceded(multispace0, s).void()
+}
+
+fn ident(input: &mut &str) -> PResult<String> {
+    preceded(
+        multispace0,
+        (one_of(|c: char| c.is_ascii_alphabetic() || c == '_'), take_while(0.., is_ident_char))
+            .take()
+            .verify(|s: &str| !KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(s))),
+    )
+    .map(str::to_string)
+    .context(expected("identifier"))
+    .parse_next(input)
+}
+
+fn path(input: &mut &str) -> PResult<Vec<String>> {
+    separated(1.., ident, sym(".")).parse_next(input)
+}
+
+fn string_literal(input: &mut &str) -> PResult<String> {
+    // 'it''s' is "it's".
+    preceded(
+        multispace0,
+        delimited(
+            "'",
+            repeat(0.., alt((take_while(1.., |c| c != '\''), "''".value("'")))).fold(String::new, |mut s, part: &str| {
+                s.push_str(part);
+                s
+            }),
+            cut_err("'").context(expected("closing quote")),
+        ),
+    )
+    .parse_next(input)
+}
+
+fn number(input: &mut &str) -> PResult<Value> {
+    preceded(multispace0, (opt("-"), digit1, opt((".", digit1))).take())
+        .try_map(|s: &str| match s.parse::<i64>() {
+            Ok(i) => Ok(Value::from(i)),
+            Err(_) => s.parse::<f64>().map(Value::from),
+        })
+        .parse_next(input)
+}
+
+fn literal(input: &mut &str) -> PResult<Value> {
+    alt((
+        string_literal.map(Value::from),
+        number,
+        kw("true").value(Value::Bool(true)),
+        kw("false").value(Value::Bool(false)),
+        kw("null").value(Value::Null),
+    ))
+    .parse_next(input)
+}
+
+fn primary(input: &mut &str) -> PResult<Expr> {
+    alt((
+        literal.map(Expr::Literal),
+        delimited(sym("("), expr, cut_err(sym(")")).context(expected("`)`"))),
+        path.map(Expr::Path),
+    ))
+    .context(expected("value or column"))
+    .parse_next(input)
+}
+
+fn cmp_op(input: &mut &str) -> PResult<CmpOp> {
+    alt((
+        sym("<=").value(CmpOp::Le),
+        sym(">=").value(CmpOp::Ge),
+        sym("<>").value(CmpOp::Ne),
+        sym("!=").value(CmpOp::Ne),
+        sym("=").value(CmpOp::Eq),
+        sym("<").value(CmpOp::Lt),
+        sym(">").value(CmpOp::Gt),
+        kw("like").value(CmpOp::Like),
+        kw("contains").value(CmpOp::Contains),
+    ))
+    .parse_next(input)
+}
+
+fn comparison(input: &mut &str) -> PResult<Expr> {
+    let left = primary.parse_next(input)?;
+    if let Some(negated) = opt(delimited(kw("is"), opt(kw("not")), cut_err(kw("null")))).parse_next(input)? {
+        return Ok(Expr::IsNull(Box::new(left), negated.is_some()));
+    }
+    if opt(kw("in")).parse_next(input)?.is_some() {
+        let items = cut_err(delimited(sym("("), separated(1.., primary, sym(",")), sym(")")))
+            .context(expected("parenthesized list"))
+            .parse_next(input)?;
+        return Ok(Expr::In(Box::new(left), items));
+    }
+    match opt(cmp_op).parse_next(input)? {
+        Some(op) => {
+            let right = cut_err(primary).parse_next(input)?;
+            Ok(Expr::Cmp(Box::new(left), op, Box::new(right)))
+        }
+        None => Ok(left),
+    }
+}
+
+fn negation(input: &mut &str) -> PResult<Expr> {
+    alt((preceded(kw("not"), negation).map(|e| Expr::Not(Box::new(e))), comparison)).parse_next(input)
+}
+
+fn conjunction(input: &mut &str) -> PResult<Expr> {
+    let first = negation.parse_next(input)?;
+    repeat(0.., preceded(kw("and"), cut_err(negation)))
+        .fold(move || first.clone(), |acc, e| Expr::And(Box::new(acc), Box::new(e)))
+        .parse_next(input)
+}
+
+fn expr(input: &mut &str) -> PResult<Expr> {
+    let first = conjunction.parse_next(input)?;
+    repeat(0.., preceded(kw("or"), cut_err(conjunction)))
+        .fold(move || first.clone(), |acc, e| Expr::Or(Box::new(acc), Box::new(e)))
+        .parse_next(input)
+}
+
+fn select_item(input: &mut &str) -> PResult<SelectItem> {
+    alt((
+        sym("*").value(SelectItem::All),
+        (expr, opt(preceded(kw("as"), cut_err(ident)))).map(|(expr, alias)| SelectItem::Expr { expr, alias }),
+    ))
+    .parse_next(input)
+}
+
+fn source(input: &mut &str) -> PResult<Source> {
+    (ident, opt(preceded(opt(kw("as")), ident)))
+        .map(|(table, alias)| Source { table, alias })
+        .parse_next(input)
+}
+
+fn join(input: &mut &str) -> PResult<Join> {
+    let left = opt(kw("left")).parse_next(input)?.is_some();
+    kw("join").parse_next(input)?;
+    let source = cut_err(source).parse_next(input)?;
+    cut_err(kw("on")).context(expected("ON <foreign key column>")).parse_next(input)?;
+    let on = cut_err(path).parse_next(input)?;
+    Ok(Join { source, on, left })
+}
+
+fn order_item(input: &mut &str) -> PResult<(Expr, bool)> {
+    (expr, opt(alt((kw("asc").value(false), kw("desc").value(true)))))
+        .map(|(e, desc)| (e, desc.unwrap_or(false)))
+        .parse_next(input)
+}
+
+fn usize_literal(input: &mut &str) -> PResult<usize> {
+    preceded(multispace0, digit1).try_map(str::parse).context(expected("number")).parse_next(input)
+}
+
+fn query(input: &mut &str) -> PResult<Query> {
+    kw("select").context(expected("SELECT")).parse_next(input)?;
+    let select = cut_err(separated(1.., select_item, sym(","))).parse_next(input)?;
+    cut_err(kw("from")).context(expected("FROM")).parse_next(input)?;
+    let from = cut_err(source).parse_next(input)?;
+    let joins = repeat(0.., join).parse_next(input)?;
+    let filter = opt(preceded(kw("where"), cut_err(expr))).parse_next(input)?;
+    let order_by = opt(preceded((kw("order"), cut_err(kw("by"))), cut_err(separated(1.., order_item, sym(",")))))
+        .parse_next(input)?
+        .unwrap_or_default();
+    let limit = opt(preceded(kw("limit"), cut_err(usize_literal))).parse_next(input)?;
+    let offset = opt(preceded(kw("offset"), cut_err(usize_literal))).parse_next(input)?.unwrap_or(0);
+    let _ = (multispace0, opt(";"), multispace0).parse_next(input)?;
+    Ok(Query { select, from, joins, filter, order_by, limit, offset })
+}
+
+/// Parses a query without running it.
+pub fn parse(sql: &str) -> Result<Query> {
+    query.parse(sql).map_err(|e| Error::parse("query", e))
+}
+
+/// Parses and runs `sql` against `db`.
+pub fn run(db: &Database, sql: &str) -> Result<QueryResult> {
+    parse(sql)?.execute(db)
+}
+
+// ---- Evaluation ----
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl QueryResult {
+    /// One object per row, keyed by column name.
+    pub fn to_json(&self) -> Value {
+        Value::Array(
+            self.rows
+                .iter()
+                .map(|row| Value::Object(self.columns.iter().cloned().zip(row.iter().cloned()).collect::<Map<_, _>>()))
+                .collect(),
+        )
+    }
+
+    /// Renders a value for a table cell: strings without quotes, anything
+    /// else as compact JSON.
+    pub fn cell_text(value: &Value) -> String {
+        match value {
+            Value::String(s) => s.clone(),
+            Value::Null => String::new(),
+            v => v.to_string(),
+        }
+    }
+
+    /// Aligned text table, cells cut at 60 characters.
+    pub fn write_table(&self, out: &mut impl Write) -> std::io::Result<()> {
+        const MAX: usize = 60;
+        let cells: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|v| {
+                        let text = Self::cell_text(v).replace(['\n', '\r', '\t'], " ");
+                        match text.char_indices().nth(MAX) {
+                            Some((cut, _)) => format!("{}…", &text[..cut]),
+                            None => text,
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let widths: Vec<usize> = (0..self.columns.len())
+            .map(|c| cells.iter().map(|r| r[c].chars().count()).chain([self.columns[c].len()]).max().unwrap_or(0))
+            .collect();
+        let line = |out: &mut dyn Write, row: &[String]| -> std::io::Result<()> {
+            let padded: Vec<String> = row.iter().zip(&widths).map(|(s, &w)| format!("{:<w$}", s, w = w)).collect();
+            writeln!(out, "{}", padded.join("  ").trim_end())
+        };
+        line(out, &self.columns)?;
+        line(out, &widths.iter().map(|&w| "-".repeat(w)).collect::<Vec<_>>())?;
+        for row in &cells {
+            line(out, row)?;
+        }
+        writeln!(out, "({} rows)", self.rows.len())?;
+        out.flush()
+    }
+}
+
+/// One result candidate: a row index per source, `None` for the null side
+/// of a LEFT JOIN.
+type Binding = Vec<Option<u32>>;
+
+impl Query {
+    pub fn execute(&self, db: &Database) -> Result<QueryResult> {
+        let mut sources = vec![self.from.clone()];
+        // Canonical table names (as the schema spells them).
+        let mut tables = vec![db.table(&self.from.table)?.name().to_string()];
+        for join in &self.joins {
+            tables.push(db.table(&join.source.table)?.name().to_string());
+            sources.push(join.source.clone());
+        }
+        let ctx = Context { db, sources: &sources, tables: &tables };
+
+        let columns = self.column_names(&ctx)?;
+        // ORDER BY may name a select alias.
+        let order_by: Vec<&Expr> = self
+            .order_by
+            .iter()
+            .map(|(e, _)| match e {
+                Expr::Path(p) if p.len() == 1 => self
+                    .select
+                    .iter()
+                    .find_map(|item| match item {
+                        SelectItem::Expr { expr, alias: Some(a) } if a.eq_ignore_ascii_case(&p[0]) => Some(expr),
+                        _ => None,
+                    })
+                    .unwrap_or(e),
+                e => e,
+            })
+            .collect();
+        // Without ORDER BY the first offset + limit matches are the answer.
+        let wanted = match (self.order_by.is_empty(), self.limit) {
+            (true, Some(limit)) => Some(self.offset + limit),
+            _ => None,
+        };
+
+        let mut matches: Vec<(Vec<Value>, Binding)> = Vec::new();
+        let base_rows = db.table(&tables[0])?.row_count();
+        'rows: for row in 0..base_rows {
+            for binding in self.bindings(&ctx, row)? {
+                if let Some(filter) = &self.filter {
+                    if !truthy(&ctx.eval(filter, &binding)?) {
+                        continue;
+                    }
+                }
+                let keys = order_by.iter().map(|e| ctx.eval(e, &binding)).collect::<Result<_>>()?;
+                matches.push((keys, binding));
+                if wanted.is_some_and(|w| matches.len() >= w) {
+                    break 'rows;
+                }
+            }
+        }
+
+        if !self.order_by.is_empty() {
+            matches.sort_by(|(a, _), (b, _)| {
+                a.iter()
+                    .zip(b)
+                    .zip(&self.order_by)
+                    .map(|((x, y), (_, desc))| if *desc { total_cmp(y, x) } else { total_cmp(x, y) })
+                    .find(|o| o.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            });
+        }
+
+        let rows = matches
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .map(|(_, binding)| self.project(&ctx, &binding))
+            .collect::<Result<_>>()?;
+        Ok(QueryResult { columns, rows })
+    }
+
+    /// Expands base row `row` through the joins.
+    fn bindings(&self, ctx: &Context, row: u32) -> Result<Vec<Binding>> {
+        let mut bindings = vec![vec![Some(row)]];
+        for (j, join) in self.joins.iter().enumerate() {
+            let (source, columns) = ctx.split_path(&join.on, j + 1);
+            let mut next = Vec::new();
+            for binding in bindings {
+                let targets: Vec<u32> = match binding[source] {
+                    Some(from_row) => ctx
+                        .references(&ctx.tables[source], from_row, columns)?
+                        .into_iter()
+                        .filter_map(|r| match r {
+                            Reference::Row { table, row } if table.eq_ignore_ascii_case(&ctx.tables[j + 1]) => Some(row),
+                            _ => None,
+                        })
+                        .collect(),
+                    None => Vec::new(),
+                };
+                if targets.is_empty() && join.left {
+                    let mut b = binding.clone();
+                    b.push(None);
+                    next.push(b);
+                }
+                for target in targets {
+                    let mut b = binding.clone();
+                    b.push(Some(target));
+                    next.push(b);
+                }
+            }
+            bindings = next;
+        }
+        Ok(bindings)
+    }
+
+    fn column_names(&self, ctx: &Context) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for item in &self.select {
+            match item {
+                SelectItem::All => {
+                    for (s, table) in ctx.tables.iter().enumerate() {
+                        let t = ctx.db.table(table)?;
+                        for (j, col) in t.table.columns.iter().enumerate() {
+                            let name = col.name.clone().unwrap_or_else(|| format!("Col{}", j));
+                            names.push(if ctx.sources.len() > 1 { format!("{}.{}", ctx.source_name(s), name) } else { name });
+                        }
+                    }
+                }
+                SelectItem::Expr { expr, alias } => names.push(alias.clone().unwrap_or_else(|| expr.label())),
+            }
+        }
+        Ok(names)
+    }
+
+    fn project(&self, ctx: &Context, binding: &Binding) -> Result<Vec<Value>> {
+        let mut values = Vec::new();
+        for item in &self.select {
+            match item {
+                SelectItem::All => {
+                    for (s, table) in ctx.tables.iter().enumerate() {
+                        let t = ctx.db.table(table)?;
+                        match binding[s] {
+                            Some(row) => {
+                                let row = t.row(row)?;
+                                values.extend(row.iter().zip(&t.table.columns).map(|(v, c)| t.reader.value_to_json(v, c)));
+                            }
+                            None => values.extend(std::iter::repeat_n(Value::Null, t.table.columns.len())),
+                        }
+                    }
+                }
+                SelectItem::Expr { expr, .. } => values.push(ctx.eval(expr, binding)?),
+            }
+        }
+        Ok(values)
+    }
+}
+
+struct Context<'a> {
+    db: &'a Database,
+    sources: &'a [Source],
+    tables: &'a [String],
+}
+
+impl Context<'_> {
+    fn source_name(&self, s: usize) -> &str {
+        self.sources[s].alias.as_deref().unwrap_or(&self.tables[s])
+    }
+
+    /// Splits a path into the source it starts from and the column path.
+    /// Only the first `visible` sources can be named.
+    fn split_path<'p>(&self, path: &'p [String], visible: usize) -> (usize, &'p [String]) {
+        if path.len() > 1 {
+            if let Some(s) = self.sources[..visible].iter().rposition(|s| s.is_named(&path[0])) {
+                return (s, &path[1..]);
+            }
+        }
+        (0, path)
+    }
+
+    /// Follows `columns` from `row` of `table` and returns what the last
+    /// (reference) column points at.
+    fn references(&self, table: &str, row: u32, columns: &[String]) -> Result<Vec<Reference>> {
+        let t = self.db.table(table)?;
+        let (first, rest) = columns.split_first().expect("paths are never empty");
+        let c = t
+            .column_index(first)
+            .ok_or_else(|| Error::NotFound(format!("column {}.{}", t.name(), first)))?;
+        let refs = self.db.resolve_all(&t, &t.table.columns[c], &t.row(row)?[c])?;
+        if rest.is_empty() {
+            return Ok(refs);
+        }
+        let mut out = Vec::new();
+        for r in refs {
+            if let Reference::Row { table, row } = r {
+                out.extend(self.references(&table, row, rest)?);
+            }
+        }
+        Ok(out)
+    }
+
+    fn eval(&self, expr: &Expr, binding: &Binding) -> Result<Value> {
+        Ok(match expr {
+            Expr::Literal(v) => v.clone(),
+            Expr::Path(path) => {
+                let (s, columns) = self.split_path(path, self.sources.len());
+                match binding[s] {
+                    Some(row) => self.db.follow(&self.tables[s], row, &columns.join("."))?,
+                    None => Value::Null,
+                }
+            }
+            Expr::Not(e) => Value::Bool(!truthy(&self.eval(e, binding)?)),
+            Expr::And(a, b) => Value::Bool(truthy(&self.eval(a, binding)?) && truthy(&self.eval(b, binding)?)),
+            Expr::Or(a, b) => Value::Bool(truthy(&self.eval(a, binding)?) || truthy(&self.eval(b, binding)?)),
+            Expr::IsNull(e, negated) => Value::Bool(self.eval(e, binding)?.is_null() != *negated),
+            Expr::In(e, items) => {
+                let v = self.eval(e, binding)?;
+                let mut found = false;
+                for item in items {
+                    if compare(&v, CmpOp::Eq, &self.eval(item, binding)?) {
+                        found = true;
+                        break;
+                    }
+                }
+                Value::Bool(found)
+            }
+            Expr::Cmp(a, op, b) => Value::Bool(compare(&self.eval(a, binding)?, *op, &self.eval(b, binding)?)),
+        })
+    }
+}
+
+fn truthy(v: &Value) -> bool {
+    match v {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|f| f != 0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(a) => !a.is_empty(),
+        Value::Object(o) => !o.is_empty(),
+    }
+}
+
+/// SQL-ish comparison: anything against null is false, and a list on the
+/// left matches if any element does.
+fn compare(left: &Value, op: CmpOp, right: &Value) -> bool {
+    if let (Value::Array(items), false) = (left, right.is_array()) {
+        return match op {
+            CmpOp::Ne => !items.iter().any(|i| compare(i, CmpOp::Eq, right)),
+            CmpOp::Contains => items.iter().any(|i| compare(i, CmpOp::Eq, right)),
+            _ => items.iter().any(|i| compare(i, op, right)),
+        };
+    }
+    if left.is_null() || right.is_null() {
+        return false;
+    }
+    match op {
+        CmpOp::Eq => partial_cmp(left, right) == Some(Ordering::Equal),
+        CmpOp::Ne => partial_cmp(left, right) != Some(Ordering::Equal),
+        CmpOp::Lt => partial_cmp(left, right) == Some(Ordering::Less),
+        CmpOp::Le => matches!(partial_cmp(left, right), Some(Ordering::Less | Ordering::Equal)),
+        CmpOp::Gt => partial_cmp(left, right) == Some(Ordering::Greater),
+        CmpOp::Ge => matches!(partial_cmp(left, right), Some(Ordering::Greater | Ordering::Equal)),
+        CmpOp::Like => match (left, right) {
+            (Value::String(s), Value::String(p)) => like(&s.to_lowercase(), &p.to_lowercase()),
+            _ => false,
+        },
+        CmpOp::Contains => match (left, right) {
+            (Value::String(s), Value::String(p)) => s.to_lowercase().contains(&p.to_lowercase()),
+            _ => false,
+        },
+    }
+}
+
+fn partial_cmp(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64()?.partial_cmp(&y.as_f64()?),
+        (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+        (Value::Bool(x), Value::Bool(y)) => Some(x.cmp(y)),
+        (x, y) if x == y => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+/// Orders values of any type for ORDER BY: null, bools, numbers, strings,
+/// then lists and objects.
+fn total_cmp(a: &Value, b: &Value) -> Ordering {
+    fn rank(v: &Value) -> u8 {
+        match v {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Number(_) => 2,
+            Value::String(_) => 3,
+            Value::Array(_) => 4,
+            Value::Object(_) => 5,
+        }
+    }
+    rank(a).cmp(&rank(b)).then_with(|| match (a, b) {
+        (Value::Array(x), Value::Array(y)) => x
+            .iter()
+            .zip(y)
+            .map(|(p, q)| total_cmp(p, q))
+            .find(|o| o.is_ne())
+            .unwrap_or_else(|| x.len().cmp(&y.len())),
+        _ => partial_cmp(a, b).unwrap_or(Ordering::Equal),
+    })
+}
+
+/// `%` matches any run of characters, `_` exactly one.
+fn like(s: &str, pattern: &str) -> bool {
+    let s: Vec<char> = s.chars().collect();
+    let p: Vec<char> = pattern.chars().collect();
+    // Classic wildcard matching with backtracking to the last `%`.
+    let (mut i, mut j) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while i < s.len() {
+        // `%` first: a `%` in the subject must not eat the pattern's.
+        if j < p.len() && p[j] == '%' {
+            star = Some((j, i));
+            j += 1;
+        } else if j < p.len() && (p[j] == '_' || p[j] == s[i]) {
+            i += 1;
+            j += 1;
+        } else if let Some((sj, si)) = star {
+            j = sj + 1;
+            i = si + 1;
+            star = Some((sj, si + 1));
+        } else {
+            return false;
+        }
+    }
+    p[j..].iter().all(|&c| c == '%')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dat::schema::{Column, Enumeration, Schema, Table, TableReference};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn parses_full_query() {
+        let q = parse(
+            "select m.Id, t.Name as Type from Mods m left join ModType t on m.Type \
+             where (Level >= 10 or Id like 'str%') and not Domain is null order by Level desc, Id limit 5 offset 2;",
+        )
+        .unwrap();
+        assert_eq!(q.from, Source { table: "Mods".to_string(), alias: Some("m".to_string()) });
+        assert_eq!(q.joins.len(), 1);
+        assert!(q.joins[0].left);
+        assert_eq!(q.joins[0].on, ["m", "Type"]);
+        assert_eq!(q.select.len(), 2);
+        assert!(matches!(&q.select[1], SelectItem::Expr { alias: Some(a), .. } if a == "Type"));
+        assert!(matches!(q.filter, Some(Expr::And(..))));
+        assert_eq!(q.order_by.len(), 2);
+        assert!(q.order_by[0].1 && !q.order_by[1].1);
+        assert_eq!((q.limit, q.offset), (Some(5), 2));
+
+        assert_eq!(
+            parse("SELECT * FROM T WHERE Name = 'it''s'").unwrap().filter,
+            Some(Expr::Cmp(
+                Box::new(Expr::Path(vec!["Name".to_string()])),
+                CmpOp::Eq,
+                Box::new(Expr::Literal(Value::from("it's")))
+            ))
+        );
+        for bad in ["SELECT FROM Mods", "SELECT * Mods", "SELECT * FROM Mods WHERE", "SELECT * FROM Mods LIMIT x", "SELECT 'a FROM b"] {
+            assert!(matches!(parse(bad), Err(Error::Parse { format: "query", .. })), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn like_wildcards() {
+        assert!(like("strength", "str%"));
+        assert!(like("strength", "%ength"));
+        assert!(like("strength", "s_r%g_h"));
+        assert!(like("", "%"));
+        assert!(!like("strength", "str"));
+        assert!(!like("strength", "%x%"));
+        assert!(like("%1% increased Damage", "%increased%"));
+        assert!(like("%1% increased Damage", "%1%Damage"));
+        assert!(!like("%1% increased Damage", "%reduced%"));
+    }
+
+    fn column(name: &str, ty: &str, references: Option<&str>) -> Column {
+        Column {
+            name: Some(name.to_string()),
+            description: None,
+            array: false,
+            r#type: ty.to_string(),
+            unique: false,
+            localized: false,
+            references: references.map(|t| TableReference { table: t.to_string(), column: None }),
+            interval: false,
+        }
+    }
+
+    /// Mods(Id, Level, Domain: enum, Stats -> [Stats]) and Stats(Id).
+    fn database() -> Database {
+        let schema = Schema {
+            version: 0,
+            created_at: 0,
+            tables: vec![
+                Table {
+                    name: "Mods".to_string(),
+                    columns: vec![
+                        column("Id", "string", None),
+                        column("Level", "i32", None),
+                        column("Domain", "enumrow", Some("ModDomains")),
+                        Column { array: true, ..column("Stats", "foreignrow", Some("Stats")) },
+                    ],
+                    tags: None,
+                    valid_for: None,
+                },
+                Table { name: "Stats".to_string(), columns: vec![column("Id", "string", None)], tags: None, valid_for: None },
+            ],
+            enumeration: Some(vec![Enumeration {
+                name: "ModDomains".to_string(),
+                indexing: 0,
+                enumerators: vec![Some("ITEM".to_string()), Some("MONSTER".to_string())],
+            }]),
+        };
+
+        fn dat(rows: Vec<Vec<u8>>, var: Vec<u8>) -> Vec<u8> {
+            let mut data = (rows.len() as u32).to_le_bytes().to_vec();
+            rows.into_iter().for_each(|r| data.extend(r));
+            data.extend(var);
+            data
+        }
+        fn push_str(var: &mut Vec<u8>, s: &str) -> [u8; 8] {
+            let at = var.len() as u64;
+            var.extend(s.encode_utf16().chain([0]).flat_map(u16::to_le_bytes));
+            at.to_le_bytes()
+        }
+
+        let mut var = vec![0xBB; 8];
+        let stats: Vec<Vec<u8>> = ["fire_damage", "cold_damage", "life"].iter().map(|s| push_str(&mut var, s).to_vec()).collect();
+        let stats_file = dat(stats, var);
+
+        let mut var = vec![0xBB; 8];
+        let mods = [("FireRing", 10i32, 0u32, vec![0u64, 2]), ("ColdAmulet", 20, 0, vec![1]), ("MonsterFire", 5, 1, vec![0]), ("Plain", 1, 0, vec![])];
+        let rows = mods
+            .iter()
+            .map(|(id, level, domain, stats)| {
+                let mut row = push_str(&mut var, id).to_vec();
+                row.extend(level.to_le_bytes());
+                row.extend(domain.to_le_bytes());
+                let list_at = var.len() as u64;
+                for &s in stats {
+                    var.extend(s.to_le_bytes());
+                    var.extend([0; 8]);
+                }
+                row.extend((stats.len() as u64).to_le_bytes());
+                row.extend(list_at.to_le_bytes());
+                row
+            })
+            .collect();
+        let mods_file = dat(rows, var);
+
+        let files: HashMap<&str, Vec<u8>> = [("Mods", mods_file), ("Stats", stats_file)].into();
+        Database::new(Arc::new(schema), move |name| files.get(name).cloned().ok_or_else(|| Error::NotFound(name.to_string())))
+    }
+
+    fn ids(result: &QueryResult) -> Vec<String> {
+        result.rows.iter().map(|r| QueryResult::cell_text(&r[0])).collect()
+    }
+
+    #[test]
+    fn filters_on_enums_and_foreign_key_lists() {
+        let db = database();
+        // "All mods with domain X granting stat Y".
+        let r = run(&db, "SELECT Id, Stats.Id FROM Mods WHERE Domain = 'ITEM' AND Stats.Id = 'fire_damage'").unwrap();
+        assert_eq!(r.columns, ["Id", "Stats.Id"]);
+        assert_eq!(r.rows, vec![vec![Value::from("FireRing"), serde_json::json!(["fire_damage", "life"])]]);
+
+        let r = run(&db, "select id from mods where stats.id contains 'cold_damage'").unwrap();
+        assert_eq!(ids(&r), ["ColdAmulet"]);
+        assert!(matches!(run(&db, "SELECT Nope FROM Mods"), Err(Error::NotFound(_))));
+
+        let r = run(&db, "SELECT Id FROM Mods WHERE Id LIKE '%fire%' ORDER BY Level DESC").unwrap();
+        assert_eq!(ids(&r), ["FireRing", "MonsterFire"]);
+        let r = run(&db, "SELECT Id FROM Mods WHERE Level IN (1, 20) ORDER BY Id").unwrap();
+        assert_eq!(ids(&r), ["ColdAmulet", "Plain"]);
+        let r = run(&db, "SELECT Id FROM Mods WHERE NOT Stats ORDER BY Level LIMIT 1").unwrap();
+        assert_eq!(ids(&r), ["Plain"]);
+        let r = run(&db, "SELECT Id FROM Mods ORDER BY Level LIMIT 2 OFFSET 1").unwrap();
+        assert_eq!(ids(&r), ["MonsterFire", "FireRing"]);
+    }
+
+    #[test]
+    fn joins_fan_out_over_foreign_keys() {
+        let db = database();
+        let r = run(&db, "SELECT m.Id, s.Id AS Stat FROM Mods m JOIN Stats s ON m.Stats ORDER BY Stat, m.Id").unwrap();
+        assert_eq!(r.columns, ["m.Id", "Stat"]);
+        let pairs: Vec<(String, String)> =
+            r.rows.iter().map(|row| (QueryResult::cell_text(&row[0]), QueryResult::cell_text(&row[1]))).collect();
+        let expected = [("ColdAmulet", "cold_damage"), ("FireRing", "fire_damage"), ("MonsterFire", "fire_damage"), ("FireRing", "life")];
+        assert_eq!(pairs, expected.map(|(a, b)| (a.to_string(), b.to_string())));
+
+        let r = run(&db, "SELECT * FROM Mods m LEFT JOIN Stats s ON Stats WHERE s.Id IS NULL").unwrap();
+        assert_eq!(r.columns, ["m.Id", "m.Level", "m.Domain", "m.Stats", "s.Id"]);
+        assert_eq!(r.rows.len(), 1);
+        assert_eq!(r.rows[0][0], "Plain");
+        assert_eq!(r.rows[0][4], Value::Null);
+
+        let json = r.to_json();
+        assert_eq!(json[0]["m.Level"], 1);
+        let mut table = Vec::new();
+        r.write_table(&mut table).unwrap();
+        let table = String::from_utf8(table).unwrap();
+        assert!(table.starts_with("m.Id   m.Level  m.Domain  m.Stats  s.Id\n"), "{}", table);
+        assert!(table.ends_with("(1 rows)\n"));
+    }
+}
@@ -1,6 +1,7 @@
 use byteorder::{ByteOrder, LittleEndian};
 use std::io::{self, Cursor, Read, Seek, SeekFrom};
 use super::schema::{Table, Column};
+use crate::error::{Error, Result};
 
 pub struct DatReader {
     data: Vec<u8>,
@@ -16,13 +17,10 @@ impl DatReader {
         &self.data
     }
 
-    pub fn new(data: Vec<u8>, filename: &str) -> io::Result<Self> {
+    pub fn new(data: Vec<u8>, filename: &str) -> Result<Self> {
         // Minimum valid dat file: 4 bytes row count + 8 bytes separator = 12 bytes
         if data.len() < 12 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("File too small to be a valid dat file ({} bytes): {}", data.len(), filename),
-            ));
+            return Err(Error::TruncatedRecord { what: "dat header", offset: 0 });
         }
 
         let mut cursor = Cursor::new(data.as_slice());
@@ -31,7 +29,7 @@ impl DatReader {
             || filename.ends_with(".datl64");
 
         let row_count = read_u32(&mut cursor)?;
-        println!("DatReader: Loading {}, Row Count: {}, Is 64bit: {}, Size: {} bytes", filename, row_count, is_64bit, data.len());
+        log::debug!("DatReader: Loading {}, Row Count: {}, Is 64bit: {}, Size: {} bytes", filename, row_count, is_64bit, data.len());
 
         // Sanity check: row_count should be reasonable relative to file size.
         // Each row must be at least 1 byte, plus 4 bytes for the count + 8 bytes for separator.
@@ -39,24 +37,18 @@ impl DatReader {
         if row_count > 0 {
             let max_possible_rows = (data.len().saturating_sub(12)) as u64;
             if (row_count as u64) > max_possible_rows {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!(
-                        "Invalid row_count {} for file of {} bytes (max possible: {}). \
-                         Data is likely not a valid dat file: {}",
-                        row_count, data.len(), max_possible_rows, filename
-                    ),
-                ));
+                return Err(Error::InvalidData(format!(
+                    "Invalid row_count {} for file of {} bytes (max possible: {}). \
+                     Data is likely not a valid dat file: {}",
+                    row_count, data.len(), max_possible_rows, filename
+                )));
             }
             // Additional sanity: reject absurdly large row counts (>10M rows)
             if row_count > 10_000_000 {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!(
-                        "Unreasonable row_count {} (>10M) for {}, first 16 bytes: {:02X?}",
-                        row_count, filename, &data[..std::cmp::min(16, data.len())]
-                    ),
-                ));
+                return Err(Error::InvalidData(format!(
+                    "Unreasonable row_count {} (>10M) for {}, first 16 bytes: {:02X?}",
+                    row_count, filename, &data[..std::cmp::min(16, data.len())]
+                )));
             }
         }
         
@@ -80,7 +72,7 @@ impl DatReader {
                           row_length = Some(fixed_data_size / (row_count as usize));
                           data_section_offset = (i + 8) as u64; // Variable data starts after separator
                           found_pattern = true;
-                          println!("DatReader: Found boundary at offset {}, row_length={}, var_data_offset={}",
+                          log::debug!("DatReader: Found boundary at offset {}, row_length={}, var_data_offset={}",
                               i, row_length.unwrap(), data_section_offset);
                           break;
                       }
@@ -89,19 +81,19 @@ impl DatReader {
              }
 
              if !found_pattern {
-                 return Err(io::Error::new(
-                     io::ErrorKind::InvalidData,
-                     format!(
-                         "Aligned data boundary not found for row_count {} in {} ({} bytes). \
-                          First 16 bytes: {:02X?}",
+                 return Err(Error::BadSignature {
+                     offset: 4,
+                     expected: "row-aligned 0xBB data boundary",
+                     found: format!(
+                         "none for row_count {} in {} ({} bytes), first 16 bytes: {:02X?}",
                          row_count, filename, data.len(),
                          &data[..std::cmp::min(16, data.len())]
                      ),
-                 ));
+                 });
              }
 
         } else {
-            println!("DatReader: Row count is 0 for {}", filename);
+            log::debug!("DatReader: Row count is 0 for {}", filename);
             row_length = Some(0);
 
             // For zero-row files, the separator should be immediately after the row count
@@ -123,7 +115,21 @@ impl DatReader {
         })
     }
 
-    pub fn read_row(&self, index: u32, table: &Table) -> io::Result<Vec<DatValue>> {
+    /// Checks that `table` describes rows of the length found in the file.
+    /// A schema for another game version usually fails here; `read_row`
+    /// would otherwise quietly fill the missing columns with `Unknown`.
+    pub fn check_schema(&self, table: &Table) -> Result<()> {
+        let schema_row_len: usize = table.columns.iter().map(|c| get_column_size(c, self.is_64bit)).sum();
+        match self.row_length {
+            Some(row_len) if self.row_count > 0 && schema_row_len != row_len => Err(Error::SchemaMismatch {
+                table: table.name.clone(),
+                reason: format!("schema rows are {} bytes, {} has {} byte rows", schema_row_len, self.filename, row_len),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn read_row(&self, index: u32, table: &Table) -> Result<Vec<DatValue>> {
 
         
         let schema_row_len: usize = table.columns.iter().map(|c| get_column_size(c, self.is_64bit)).sum();
@@ -131,7 +137,7 @@ impl DatReader {
 
         let start = 4 + (index as usize * row_len); // 4 bytes for row count
         if start >= self.data.len() {
-             return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Row index out of bounds"));
+             return Err(Error::TruncatedRecord { what: "dat row", offset: start as u64 });
         }
         
 
@@ -160,7 +166,7 @@ impl DatReader {
     }
 }
 
-fn get_column_size(col: &Column, is_64bit: bool) -> usize {
+pub(crate) fn get_column_size(col: &Column, is_64bit: bool) -> usize {
     if col.array {
         // Arrays are always (length: u64, pointer: u64) = 16 bytes in 64-bit dat files
         return if is_64bit { 16 } else { 8 };
@@ -369,7 +375,7 @@ fn read_u64(cursor: &mut Cursor<&[u8]>) -> io::Result<u64> {
 }
 
 impl DatReader {
-    pub fn read_list_values(&self, offset: u64, count: usize, col: &Column) -> io::Result<Vec<DatValue>> {
+    pub fn read_list_values(&self, offset: u64, count: usize, col: &Column) -> Result<Vec<DatValue>> {
         if count == 0 {
              return Ok(Vec::new());
         }
@@ -538,7 +544,42 @@ mod tests {
         let json_val = reader.value_to_json(&val, &col);
         assert_eq!(json_val, serde_json::json!([18478, 42]));
     }
-}
-
-
 
+    #[test]
+    fn check_schema_reports_row_length_mismatch() {
+        // Two 4-byte rows, then the boundary marker.
+        let mut data = vec![2, 0, 0, 0];
+        data.extend_from_slice(&[1, 0, 0, 0, 2, 0, 0, 0]);
+        data.extend_from_slice(&[0xBB; 8]);
+        let reader = DatReader::new(data, "Foo.datc64").unwrap();
+        assert_eq!(reader.row_length, Some(4));
+
+        let int_col = |name: &str| Column {
+            name: Some(name.to_string()),
+            r#type: "i32".to_string(),
+            references: None,
+            array: false,
+            unique: false,
+            localized: false,
+            description: None,
+            interval: false,
+        };
+        let mut table = Table {
+            name: "Foo".to_string(),
+            columns: vec![int_col("A")],
+            tags: None,
+            valid_for: None,
+        };
+        assert!(reader.check_schema(&table).is_ok());
+
+        table.columns.push(int_col("B"));
+        assert!(matches!(
+            reader.check_schema(&table),
+            Err(Error::SchemaMismatch { table, .. }) if table == "Foo"
+        ));
+        assert!(matches!(
+            DatReader::new(vec![0; 4], "Foo.datc64"),
+            Err(Error::TruncatedRecord { what: "dat header", .. })
+        ));
+    }
+}
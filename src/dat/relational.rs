@@ -1 +1,597 @@
-// TODO: Relational logic
+//! Foreign-key resolution across dat tables.
+//!
+//! A [`Database`] loads tables by schema name the first time they are
+//! needed and follows `foreignrow`, `row` and `enumrow` columns to the row
+//! or enumerator they point at:
+//!
+//! ```no_run
+//! # fn demo(vfs: std::sync::Arc<ggpk_explorer::Vfs>, schema: ggpk_explorer::Schema) -> ggpk_explorer::Result<()> {
+//! use ggpk_explorer::dat::relational::Database;
+//!
+//! let db = Database::from_vfs(vfs, schema.into());
+//! // Mods row 0 -> its ModType -> that row's Name.
+//! let name = db.follow("Mods", 0, "ModTypeKey.Name")?;
+//! # Ok(()) }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use super::reader::{DatReader, DatValue};
+use super::schema::{Column, Enumeration, Schema, Table};
+use crate::error::{Error, Result};
+use crate::vfs::Vfs;
+
+/// Reads a table's file given its schema name, e.g. `Mods`.
+pub type TableLoader = dyn Fn(&str) -> Result<Vec<u8>> + Send + Sync;
+
+/// Reads tables from `data/balance/<name>.datc64` (PoE 2), falling back
+/// to `data/<name>.datc64` (PoE 1).
+pub fn vfs_loader(vfs: Arc<Vfs>) -> impl Fn(&str) -> Result<Vec<u8>> + Send + Sync + 'static {
+    move |name| {
+        let name = name.to_ascii_lowercase();
+        let balance = format!("data/balance/{}.datc64", name);
+        if vfs.exists(&balance) {
+            vfs.read(&balance)
+        } else {
+            vfs.read(&format!("data/{}.datc64", name))
+        }
+    }
+}
+
+/// Row index by key value (as JSON text), for one column of one table.
+type KeyIndex = HashMap<String, u32>;
+
+/// A parsed table together with the schema that describes it.
+pub struct DatTable {
+    pub table: Table,
+    pub reader: DatReader,
+}
+
+impl DatTable {
+    pub fn name(&self) -> &str {
+        &self.table.name
+    }
+
+    pub fn row_count(&self) -> u32 {
+        self.reader.row_count
+    }
+
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.table
+            .columns
+            .iter()
+            .position(|c| c.name.as_deref().is_some_and(|n| n.eq_ignore_ascii_case(name)))
+    }
+
+    pub fn row(&self, index: u32) -> Result<Vec<DatValue>> {
+        if index >= self.reader.row_count {
+            return Err(Error::NotFound(format!("{} row {}", self.table.name, index)));
+        }
+        self.reader.read_row(index, &self.table)
+    }
+}
+
+/// Where a reference column points.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reference {
+    /// The null sentinel, or a key with no matching row.
+    Null,
+    Row { table: String, row: u32 },
+    Enumerator {
+        enumeration: String,
+        value: u32,
+        /// `None` for values past the end of the list or unnamed entries.
+        name: Option<String>,
+    },
+}
+
+pub struct Database {
+    schema: Arc<Schema>,
+    loader: Box<TableLoader>,
+    tables: Mutex<HashMap<String, Arc<DatTable>>>,
+    /// Row lookup by the value of a key column, for references that name
+    /// a column; keyed by (table, column).
+    keys: Mutex<HashMap<(String, String), Arc<KeyIndex>>>,
+}
+
+impl Database {
+    pub fn new(schema: Arc<Schema>, loader: impl Fn(&str) -> Result<Vec<u8>> + Send + Sync + 'static) -> Self {
+        Self {
+            schema,
+            loader: Box::new(loader),
+            tables: Mutex::new(HashMap::new()),
+            keys: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Loads tables through [`vfs_loader`].
+    pub fn from_vfs(vfs: Arc<Vfs>, schema: Arc<Schema>) -> Self {
+        Self::new(schema, vfs_loader(vfs))
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn table_schema(&self, name: &str) -> Option<&Table> {
+        self.schema.tables.iter().find(|t| t.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn enumeration(&self, name: &str) -> Option<&Enumeration> {
+        self.schema
+            .enumeration
+            .as_deref()?
+            .iter()
+            .find(|e| e.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the table, loading and checking it against the schema on
+    /// first use.
+    pub fn table(&self, name: &str) -> Result<Arc<DatTable>> {
+        let key = name.to_ascii_lowercase();
+        if let Some(t) = self.tables.lock().unwrap().get(&key) {
+            return Ok(t.clone());
+        }
+        let table = self
+            .table_schema(name)
+            .ok_or_else(|| Error::NotFound(format!("schema table {}", name)))?
+            .clone();
+        let data = (self.loader)(&table.name)?;
+        let reader = DatReader::new(data, &format!("{}.datc64", table.name))?;
+        reader.check_schema(&table)?;
+        let loaded = Arc::new(DatTable { table, reader });
+        // Another thread may have loaded it meanwhile; keep the first.
+        Ok(self.tables.lock().unwrap().entry(key).or_insert(loaded).clone())
+    }
+
+    /// Resolves a single (non-list) value of `column`, a column of `table`.
+    /// Returns `None` if the column is not a reference.
+    pub fn resolve(&self, table: &str, column: &Column, value: &DatValue) -> Result<Option<Reference>> {
+        // `row` columns point into their own table.
+        let target = match column.r#type.as_str() {
+            "row" => Some(table),
+            _ => column.references.as_ref().map(|r| r.table.as_str()),
+        };
+        let Some(target) = target else { return Ok(None) };
+
+        if column.r#type == "enumrow" {
+            let DatValue::Int(v) = *value else { return Ok(Some(Reference::Null)) };
+            let enumeration = self
+                .enumeration(target)
+                .ok_or_else(|| Error::NotFound(format!("enumeration {}", target)))?;
+            let name = (v as u64)
+                .checked_sub(enumeration.indexing as u64)
+                .and_then(|i| enumeration.enumerators.get(i as usize))
+                .cloned()
+                .flatten();
+            return Ok(Some(Reference::Enumerator { enumeration: enumeration.name.clone(), value: v as u32, name }));
+        }
+
+        // A reference that names a key column matches on that column's
+        // value; otherwise the value is a row index.
+        if let Some(key_column) = column.references.as_ref().and_then(|r| r.column.as_deref()) {
+            let key = match value {
+                DatValue::ForeignRow(usize::MAX) => return Ok(Some(Reference::Null)),
+                DatValue::String(s) => Value::from(s.as_str()),
+                DatValue::Int(i) => Value::from(*i),
+                DatValue::Long(l) => Value::from(*l),
+                DatValue::ForeignRow(r) => Value::from(*r),
+                _ => return Ok(Some(Reference::Null)),
+            };
+            let rows = self.key_index(target, key_column)?;
+            let target_name = self.table(target)?.name().to_string();
+            return Ok(Some(match rows.get(&key.to_string()) {
+                Some(&row) => Reference::Row { table: target_name, row },
+                None => Reference::Null,
+            }));
+        }
+
+        match *value {
+            DatValue::ForeignRow(row) if row != usize::MAX => {
+                let target_table = self.table(target)?;
+                if row >= target_table.row_count() as usize {
+                    return Ok(Some(Reference::Null));
+                }
+                Ok(Some(Reference::Row { table: target_table.name().to_string(), row: row as u32 }))
+            }
+            _ => Ok(Some(Reference::Null)),
+        }
+    }
+
+    /// Resolves a list value of `column` element by element, or a single
+    /// value as a one-element list.
+    pub fn resolve_all(&self, table: &DatTable, column: &Column, value: &DatValue) -> Result<Vec<Reference>> {
+        let items = match *value {
+            DatValue::List(count, offset) => table.reader.read_list_values(offset, count, column)?,
+            _ => vec![value.clone()],
+        };
+        items
+            .iter()
+            .filter_map(|v| self.resolve(table.name(), column, v).transpose())
+            .collect()
+    }
+
+    fn key_index(&self, table: &str, column: &str) -> Result<Arc<KeyIndex>> {
+        let cache_key = (table.to_ascii_lowercase(), column.to_ascii_lowercase());
+        if let Some(index) = self.keys.lock().unwrap().get(&cache_key) {
+            return Ok(index.clone());
+        }
+        let t = self.table(table)?;
+        let c = t
+            .column_index(column)
+            .ok_or_else(|| Error::NotFound(format!("column {}.{}", t.name(), column)))?;
+        let mut index = HashMap::with_capacity(t.row_count() as usize);
+        for row in 0..t.row_count() {
+            let values = t.row(row)?;
+            // First row wins for duplicate keys.
+            index.entry(t.reader.value_to_json(&values[c], &t.table.columns[c]).to_string()).or_insert(row);
+        }
+        let index = Arc::new(index);
+        self.keys.lock().unwrap().insert(cache_key, index.clone());
+        Ok(index)
+    }
+
+    /// Follows a dotted column path from row `row` of `table`, e.g.
+    /// `ModTypeKey.Name` or `Stats.Id`. Every column but the last must be a
+    /// reference; list columns fan out into JSON arrays. The last column is
+    /// returned as [`DatReader::value_to_json`] renders it, except that
+    /// enumerators become their name.
+    pub fn follow(&self, table: &str, row: u32, path: &str) -> Result<Value> {
+        let columns: Vec<&str> = path.split('.').collect();
+        self.follow_columns(&*self.table(table)?, row, &columns)
+    }
+
+    fn follow_columns(&self, table: &DatTable, row: u32, columns: &[&str]) -> Result<Value> {
+        let (first, rest) = columns.split_first().expect("path has at least one column");
+        let c = table
+            .column_index(first)
+            .ok_or_else(|| Error::NotFound(format!("column {}.{}", table.name(), first)))?;
+        let column = &table.table.columns[c];
+        let value = &table.row(row)?[c];
+
+        if rest.is_empty() {
+            if column.r#type == "enumrow" {
+                let names: Vec<Value> = self
+                    .resolve_all(table, column, value)?
+                    .into_iter()
+                    .map(|r| match r {
+                        Reference::Enumerator { name: Some(name), .. } => Value::from(name),
+                        Reference::Enumerator { value, .. } => Value::from(value),
+                        _ => Value::Null,
+                    })
+                    .collect();
+                return Ok(collapse(column, names));
+            }
+            return Ok(table.reader.value_to_json(value, column));
+        }
+
+        let mut out = Vec::new();
+        for reference in self.resolve_all(table, column, value)? {
+            out.push(match reference {
+                Reference::Row { table: target, row } => self.follow_columns(&*self.table(&target)?, row, rest)?,
+                Reference::Null => Value::Null,
+                Reference::Enumerator { .. } => {
+                    return Err(Error::InvalidData(format!(
+                        "{}.{} is an enumeration and has no columns to follow",
+                        table.name(),
+                        first
+                    )))
+                }
+            });
+        }
+        if out.is_empty() && !column.array && column.references.is_none() && column.r#type != "row" {
+            return Err(Error::InvalidData(format!("{}.{} is not a reference column", table.name(), first)));
+        }
+        Ok(collapse(column, out))
+    }
+}
+
+/// A row that points at another row through `column`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Referrer {
+    pub table: String,
+    pub row: u32,
+    pub column: String,
+}
+
+/// Every reference in the schema turned around: for each row, the rows
+/// that point at it. Covers `foreignrow` and `row` columns, list columns
+/// element by element, and references by key column; not enumerations.
+#[derive(Debug, Default)]
+pub struct ReverseIndex {
+    /// Keyed by (lowercased table name, row).
+    refs: HashMap<(String, u32), Vec<Referrer>>,
+    /// Tables or columns that could not be scanned, with the reason
+    /// (typically a table whose file is missing or doesn't match the
+    /// schema).
+    pub skipped: Vec<(String, String)>,
+}
+
+impl ReverseIndex {
+    /// Scans every table in the schema, loading each through `db`.
+    /// `progress` gets (tables scanned, total) after each table.
+    pub fn build(db: &Database, mut progress: impl FnMut(usize, usize)) -> Self {
+        let mut names: Vec<&str> = Vec::new();
+        for t in &db.schema().tables {
+            if !names.iter().any(|n| n.eq_ignore_ascii_case(&t.name)) {
+                names.push(&t.name);
+            }
+        }
+        let mut index = Self::default();
+        for (i, name) in names.iter().enumerate() {
+            if let Err(e) = index.scan(db, name) {
+                index.skipped.push((name.to_string(), e.to_string()));
+            }
+            progress(i + 1, names.len());
+        }
+        index
+    }
+
+    fn scan(&mut self, db: &Database, name: &str) -> Result<()> {
+        let table = db.table(name)?;
+        let mut columns: Vec<usize> = (0..table.table.columns.len())
+            .filter(|&c| {
+                let col = &table.table.columns[c];
+                col.r#type == "row" || (col.references.is_some() && col.r#type != "enumrow")
+            })
+            .collect();
+        if columns.is_empty() {
+            return Ok(());
+        }
+        let names: Vec<String> = table
+            .table
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(j, col)| col.name.clone().unwrap_or_else(|| format!("Col{}", j)))
+            .collect();
+
+        for row in 0..table.row_count() {
+            let values = table.row(row)?;
+            // A column whose target can't be loaded fails on every row;
+            // report it once and stop scanning it.
+            let mut failed = Vec::new();
+            for &c in &columns {
+                match db.resolve_all(&table, &table.table.columns[c], &values[c]) {
+                    Ok(references) => {
+                        for reference in references {
+                            if let Reference::Row { table: target, row: target_row } = reference {
+                                self.refs.entry((target.to_ascii_lowercase(), target_row)).or_default().push(Referrer {
+                                    table: table.name().to_string(),
+                                    row,
+                                    column: names[c].clone(),
+                                });
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        self.skipped.push((format!("{}.{}", table.name(), names[c]), e.to_string()));
+                        failed.push(c);
+                    }
+                }
+            }
+            columns.retain(|c| !failed.contains(c));
+        }
+        Ok(())
+    }
+
+    /// Rows that reference row `row` of `table`, in schema table order and
+    /// then row order.
+    pub fn referrers(&self, table: &str, row: u32) -> &[Referrer] {
+        self.refs
+            .get(&(table.to_ascii_lowercase(), row))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Number of rows that are referenced at least once.
+    pub fn len(&self) -> usize {
+        self.refs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.refs.is_empty()
+    }
+}
+
+fn collapse(column: &Column, mut values: Vec<Value>) -> Value {
+    if column.array {
+        Value::Array(values)
+    } else {
+        values.pop().unwrap_or(Value::Null)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dat::schema::TableReference;
+
+    fn column(name: &str, ty: &str, references: Option<(&str, Option<&str>)>) -> Column {
+        Column {
+            name: Some(name.to_string()),
+            description: None,
+            array: false,
+            r#type: ty.to_string(),
+            unique: false,
+            localized: false,
+            references: references.map(|(table, column)| TableReference {
+                table: table.to_string(),
+                column: column.map(str::to_string),
+            }),
+            interval: false,
+        }
+    }
+
+    /// A .datc64 whose fixed rows are `rows` and whose variable section
+    /// (after the marker) is `var`.
+    fn dat(rows: &[Vec<u8>], var: &[u8]) -> Vec<u8> {
+        let mut data = (rows.len() as u32).to_le_bytes().to_vec();
+        rows.iter().for_each(|r| data.extend(r));
+        data.extend([0xBB; 8]);
+        data.extend(var);
+        data
+    }
+
+    fn utf16(s: &str) -> Vec<u8> {
+        s.encode_utf16().chain([0]).flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn foreign(row: u64) -> Vec<u8> {
+        let mut v = row.to_le_bytes().to_vec();
+        v.extend([0; 8]);
+        v
+    }
+
+    // Mods(Id, Type -> ModType, Domain: enum, Family -> ModType by Name)
+    // ModType(Name, Parent -> ModType, Mods -> [Mods])
+    fn database() -> Database {
+        let schema = Schema {
+            version: 0,
+            created_at: 0,
+            tables: vec![
+                Table {
+                    name: "Mods".to_string(),
+                    columns: vec![
+                        column("Id", "string", None),
+                        column("Type", "foreignrow", Some(("ModType", None))),
+                        column("Domain", "enumrow", Some(("ModDomains", None))),
+                        column("Family", "string", Some(("ModType", Some("Name")))),
+                    ],
+                    tags: None,
+                    valid_for: None,
+                },
+                Table {
+                    name: "ModType".to_string(),
+                    columns: vec![
+                        column("Name", "string", None),
+                        column("Parent", "row", None),
+                        Column { array: true, ..column("Mods", "foreignrow", Some(("Mods", None))) },
+                    ],
+                    tags: None,
+                    valid_for: None,
+                },
+            ],
+            enumeration: Some(vec![Enumeration {
+                name: "ModDomains".to_string(),
+                indexing: 1,
+                enumerators: vec![Some("ITEM".to_string()), None, Some("MONSTER".to_string())],
+            }]),
+        };
+
+        let mut var = vec![0xBB; 8];
+        let mut string = |s: &str| {
+            let at = var.len() as u64;
+            var.extend(utf16(s));
+            at.to_le_bytes().to_vec()
+        };
+        // Mods: "Strength" -> ModType 1, ITEM, family "Base".
+        // "Bogus" -> null type, enum value 9 (past the end), unknown family.
+        let mods = |string: &mut dyn FnMut(&str) -> Vec<u8>| {
+            let mut a = string("Strength");
+            a.extend(foreign(1));
+            a.extend(1u32.to_le_bytes());
+            a.extend(string("Base"));
+            let mut b = string("Bogus");
+            b.extend([0xFE; 16]);
+            b.extend(9u32.to_le_bytes());
+            b.extend(string("Nope"));
+            vec![a, b]
+        };
+        let mod_rows = mods(&mut string);
+        let mods_var = var.split_off(8);
+
+        let mut var = vec![0xBB; 8];
+        let mut string = |s: &str| {
+            let at = var.len() as u64;
+            var.extend(utf16(s));
+            at.to_le_bytes().to_vec()
+        };
+        let mut base = string("Base");
+        let mut attr = string("Attribute");
+        base.extend([0xFE; 8]);
+        attr.extend(0u64.to_le_bytes());
+        // Base lists both mods: (count, offset) of two foreign keys.
+        let list_at = var.len() as u64;
+        var.extend(foreign(0));
+        var.extend(foreign(1));
+        base.extend(2u64.to_le_bytes());
+        base.extend(list_at.to_le_bytes());
+        attr.extend([0; 16]);
+        let type_rows = vec![base, attr];
+        let type_var = var.split_off(8);
+
+        let files: HashMap<String, Vec<u8>> = [
+            ("Mods".to_string(), dat(&mod_rows, &mods_var)),
+            ("ModType".to_string(), dat(&type_rows, &type_var)),
+        ]
+        .into();
+        Database::new(Arc::new(schema), move |name| {
+            files.get(name).cloned().ok_or_else(|| Error::NotFound(name.to_string()))
+        })
+    }
+
+    #[test]
+    fn resolves_each_reference_kind() {
+        let db = database();
+        let mods = db.table("mods").unwrap();
+        let row = mods.row(0).unwrap();
+        let cols = &mods.table.columns;
+
+        assert_eq!(db.resolve("Mods", &cols[0], &row[0]).unwrap(), None);
+        assert_eq!(
+            db.resolve("Mods", &cols[1], &row[1]).unwrap(),
+            Some(Reference::Row { table: "ModType".to_string(), row: 1 })
+        );
+        assert_eq!(
+            db.resolve("Mods", &cols[2], &row[2]).unwrap(),
+            Some(Reference::Enumerator { enumeration: "ModDomains".to_string(), value: 1, name: Some("ITEM".to_string()) })
+        );
+        assert_eq!(
+            db.resolve("Mods", &cols[3], &row[3]).unwrap(),
+            Some(Reference::Row { table: "ModType".to_string(), row: 0 })
+        );
+
+        let row = mods.row(1).unwrap();
+        for c in 1..4 {
+            let r = db.resolve("Mods", &cols[c], &row[c]).unwrap().unwrap();
+            assert!(matches!(r, Reference::Null | Reference::Enumerator { name: None, .. }), "{:?}", r);
+        }
+    }
+
+    #[test]
+    fn reverse_index_finds_every_referrer() {
+        let db = database();
+        let mut calls = Vec::new();
+        let index = ReverseIndex::build(&db, |done, total| calls.push((done, total)));
+        assert_eq!(calls, vec![(1, 2), (2, 2)]);
+        assert!(index.skipped.is_empty(), "{:?}", index.skipped);
+
+        let referrer = |table: &str, row, column: &str| Referrer { table: table.to_string(), row, column: column.to_string() };
+        assert_eq!(index.referrers("ModType", 0), [referrer("Mods", 0, "Family"), referrer("ModType", 1, "Parent")]);
+        assert_eq!(index.referrers("modtype", 1), [referrer("Mods", 0, "Type")]);
+        // Both elements of Base's Mods list.
+        assert_eq!(index.referrers("Mods", 0), [referrer("ModType", 0, "Mods")]);
+        assert_eq!(index.referrers("Mods", 1), [referrer("ModType", 0, "Mods")]);
+        assert_eq!(index.len(), 4);
+    }
+
+    #[test]
+    fn follows_references_several_hops() {
+        let db = database();
+        assert_eq!(db.follow("Mods", 0, "Type.Name").unwrap(), "Attribute");
+        // Mods -> ModType "Attribute" -> its Parent (a self-reference) -> Name.
+        assert_eq!(db.follow("Mods", 0, "Type.Parent.Name").unwrap(), "Base");
+        assert_eq!(db.follow("Mods", 0, "Type.Parent.Parent.Name").unwrap(), Value::Null);
+        assert_eq!(db.follow("Mods", 0, "Domain").unwrap(), "ITEM");
+        assert_eq!(db.follow("Mods", 1, "Type.Name").unwrap(), Value::Null);
+        assert_eq!(db.follow("ModType", 0, "Mods.Id").unwrap(), serde_json::json!(["Strength", "Bogus"]));
+        assert!(matches!(db.follow("Mods", 0, "Id.Name"), Err(Error::InvalidData(_))));
+        assert!(matches!(db.follow("Mods", 0, "Missing"), Err(Error::NotFound(_))));
+        assert!(matches!(db.table("Nope"), Err(Error::NotFound(_))));
+    }
+}
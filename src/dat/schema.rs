@@ -1,5 +1,5 @@
 #![allow(dead_code)]
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Schema {
@@ -10,7 +10,7 @@ pub struct Schema {
     pub enumeration: Option<Vec<Enumeration>>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Table {
     pub name: String,
     pub columns: Vec<Column>,
@@ -19,7 +19,20 @@ pub struct Table {
     pub valid_for: Option<u32>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// `validFor` bits.
+pub const VALID_FOR_POE1: u32 = 1;
+pub const VALID_FOR_POE2: u32 = 2;
+
+impl Table {
+    /// Whether the schema lists this table for PoE 2 (`true`) or PoE 1.
+    /// Tables without `validFor` apply to both.
+    pub fn is_valid_for(&self, poe2: bool) -> bool {
+        let bit = if poe2 { VALID_FOR_POE2 } else { VALID_FOR_POE1 };
+        self.valid_for.is_none_or(|v| v & bit != 0)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Column {
     pub name: Option<String>,
     pub description: Option<String>,
@@ -33,15 +46,19 @@ pub struct Column {
     pub interval: bool,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TableReference {
     pub table: String,
     pub column: Option<String>, // If null, row index?
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Enumeration {
     pub name: String,
-    pub enumerators: Vec<String>,
+    /// Value of the first enumerator (0 or 1).
+    #[serde(default)]
+    pub indexing: u32,
+    /// `None` for values the schema has no name for.
+    pub enumerators: Vec<Option<String>>,
 }
 
@@ -0,0 +1,803 @@
+//! Writes decoded dat tables into one SQLite database.
+//!
+//! Every table gets an `_index INTEGER PRIMARY KEY` column holding the dat
+//! row index, so foreign keys (`foreignrow`, `row` and key-column
+//! references) are declared as `REFERENCES "Target"("_index")` or the
+//! referenced key column. An array column becomes a child table
+//! `<Table>_<Column>(_index, position, value)`, and every enumeration an
+//! exported column uses becomes a table of its enumerator names.
+//!
+//! The file is written directly (format 4, 4 KiB pages, UTF-8): each table
+//! is a rowid b-tree built bottom-up from full leaf pages, with overflow
+//! pages for long records. There are no indexes besides the rowid, and
+//! foreign keys are declarations only; `PRAGMA foreign_keys` is left off.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::reader::{DatReader, DatValue};
+use super::schema::{Column, Schema, Table};
+use crate::error::{Error, Result};
+
+const PAGE_SIZE: usize = 4096;
+/// Bytes in front of the b-tree header on page 1.
+const FILE_HEADER: usize = 100;
+
+// ---- File format ----
+
+#[derive(Debug, Clone, PartialEq)]
+enum SqlValue {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+}
+
+fn put_varint(out: &mut Vec<u8>, v: u64) {
+    if v > 0x00ff_ffff_ffff_ffff {
+        // Nine bytes: eight 7-bit groups, then a full byte.
+        let mut bytes = [0u8; 9];
+        bytes[8] = v as u8;
+        let mut rest = v >> 8;
+        for b in bytes[..8].iter_mut().rev() {
+            *b = (rest & 0x7f) as u8 | 0x80;
+            rest >>= 7;
+        }
+        out.extend_from_slice(&bytes);
+        return;
+    }
+    let mut groups = Vec::with_capacity(9);
+    let mut rest = v;
+    loop {
+        groups.push((rest & 0x7f) as u8);
+        rest >>= 7;
+        if rest == 0 {
+            break;
+        }
+    }
+    for (i, g) in groups.iter().rev().enumerate() {
+        out.push(if i + 1 < groups.len() { g | 0x80 } else { *g });
+    }
+}
+
+fn varint_len(v: u64) -> usize {
+    let mut buf = Vec::with_capacity(9);
+    put_varint(&mut buf, v);
+    buf.len()
+}
+
+/// Serial type and big-endian body of an integer, using the smallest
+/// encoding (0 and 1 take no body bytes).
+fn integer_body(v: i64) -> (u64, Vec<u8>) {
+    let (serial, len) = match v {
+        0 => return (8, Vec::new()),
+        1 => return (9, Vec::new()),
+        -0x80..=0x7f => (1, 1),
+        -0x8000..=0x7fff => (2, 2),
+        -0x80_0000..=0x7f_ffff => (3, 3),
+        -0x8000_0000..=0x7fff_ffff => (4, 4),
+        -0x8000_0000_0000..=0x7fff_ffff_ffff => (5, 6),
+        _ => (6, 8),
+    };
+    (serial, v.to_be_bytes()[8 - len..].to_vec())
+}
+
+/// Encodes a row in the record format: a header of serial types, then the
+/// values.
+fn record(values: &[SqlValue]) -> Vec<u8> {
+    let mut types = Vec::new();
+    let mut body = Vec::new();
+    for v in values {
+        match v {
+            SqlValue::Null => put_varint(&mut types, 0),
+            SqlValue::Integer(i) => {
+                let (serial, bytes) = integer_body(*i);
+                put_varint(&mut types, serial);
+                body.extend(bytes);
+            }
+            SqlValue::Real(f) => {
+                put_varint(&mut types, 7);
+                body.extend(f.to_be_bytes());
+            }
+            SqlValue::Text(s) => {
+                put_varint(&mut types, 13 + 2 * s.len() as u64);
+                body.extend(s.as_bytes());
+            }
+        }
+    }
+    // The header size counts its own varint.
+    let mut header_len = types.len() + 1;
+    while varint_len(header_len as u64) + types.len() != header_len {
+        header_len = varint_len(header_len as u64) + types.len();
+    }
+    let mut out = Vec::with_capacity(header_len + body.len());
+    put_varint(&mut out, header_len as u64);
+    out.extend(types);
+    out.extend(body);
+    out
+}
+
+/// Lays out a b-tree page: header at `offset`, cell pointers after it and
+/// cell contents packed at the end of the page.
+fn build_page(kind: u8, offset: usize, cells: &[Vec<u8>], right_child: Option<u32>) -> Vec<u8> {
+    let mut page = vec![0u8; PAGE_SIZE];
+    let header_len = if right_child.is_some() { 12 } else { 8 };
+    let mut content = PAGE_SIZE;
+    let mut pointer = offset + header_len;
+    for cell in cells {
+        content -= cell.len();
+        page[content..content + cell.len()].copy_from_slice(cell);
+        page[pointer..pointer + 2].copy_from_slice(&(content as u16).to_be_bytes());
+        pointer += 2;
+    }
+    assert!(pointer <= content, "b-tree page overfilled");
+    page[offset] = kind;
+    page[offset + 3..offset + 5].copy_from_slice(&(cells.len() as u16).to_be_bytes());
+    page[offset + 5..offset + 7].copy_from_slice(&(content as u16).to_be_bytes());
+    if let Some(right) = right_child {
+        page[offset + 8..offset + 12].copy_from_slice(&right.to_be_bytes());
+    }
+    page
+}
+
+/// Appends pages in page-number order; page 1 is reserved and written by
+/// [`PageFile::finish`].
+struct PageFile {
+    out: BufWriter<File>,
+    pages: u32,
+}
+
+impl PageFile {
+    fn create(path: &Path) -> Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&[0u8; PAGE_SIZE])?;
+        Ok(Self { out, pages: 1 })
+    }
+
+    fn next_page(&self) -> u32 {
+        self.pages + 1
+    }
+
+    fn append(&mut self, page: &[u8]) -> Result<u32> {
+        debug_assert_eq!(page.len(), PAGE_SIZE);
+        self.out.write_all(page)?;
+        self.pages += 1;
+        Ok(self.pages)
+    }
+
+    /// Writes page 1 (the file header plus the schema table's root).
+    fn finish(mut self, mut page1: Vec<u8>) -> Result<()> {
+        let header = &mut page1[..FILE_HEADER];
+        header[..16].copy_from_slice(b"SQLite format 3\0");
+        header[16..18].copy_from_slice(&(PAGE_SIZE as u16).to_be_bytes());
+        header[18] = 1; // legacy journal
+        header[19] = 1;
+        header[21] = 64; // payload fractions, fixed by the format
+        header[22] = 32;
+        header[23] = 32;
+        header[24..28].copy_from_slice(&1u32.to_be_bytes()); // change counter
+        header[28..32].copy_from_slice(&self.pages.to_be_bytes());
+        header[40..44].copy_from_slice(&1u32.to_be_bytes()); // schema cookie
+        header[44..48].copy_from_slice(&4u32.to_be_bytes()); // schema format
+        header[56..60].copy_from_slice(&1u32.to_be_bytes()); // UTF-8
+        header[92..96].copy_from_slice(&1u32.to_be_bytes()); // valid-for = change counter
+        header[96..100].copy_from_slice(&3_040_000u32.to_be_bytes());
+        self.out.seek(SeekFrom::Start(0))?;
+        self.out.write_all(&page1)?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Builds one table b-tree from rows appended in rowid order.
+struct TreeBuilder {
+    cells: Vec<Vec<u8>>,
+    used: usize,
+    /// Finished leaves: (page, largest rowid).
+    leaves: Vec<(u32, i64)>,
+    last_rowid: i64,
+    rows: u64,
+}
+
+impl TreeBuilder {
+    const LEAF_HEADER: usize = 8;
+    const INTERIOR_HEADER: usize = 12;
+
+    fn new() -> Self {
+        Self { cells: Vec::new(), used: Self::LEAF_HEADER, leaves: Vec::new(), last_rowid: 0, rows: 0 }
+    }
+
+    fn insert(&mut self, file: &mut PageFile, rowid: i64, values: &[SqlValue]) -> Result<()> {
+        let payload = record(values);
+        let mut cell = Vec::with_capacity(payload.len().min(PAGE_SIZE) + 18);
+        put_varint(&mut cell, payload.len() as u64);
+        put_varint(&mut cell, rowid as u64);
+
+        // Table leaf overflow thresholds from the file format.
+        let usable = PAGE_SIZE;
+        let max_local = usable - 35;
+        let min_local = (usable - 12) * 32 / 255 - 23;
+        if payload.len() <= max_local {
+            cell.extend_from_slice(&payload);
+        } else {
+            let k = min_local + (payload.len() - min_local) % (usable - 4);
+            let local = if k <= max_local { k } else { min_local };
+            cell.extend_from_slice(&payload[..local]);
+            cell.extend(file.next_page().to_be_bytes());
+            let chunks: Vec<&[u8]> = payload[local..].chunks(usable - 4).collect();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let mut page = vec![0u8; PAGE_SIZE];
+                let next = if i + 1 < chunks.len() { file.next_page() + 1 } else { 0 };
+                page[..4].copy_from_slice(&next.to_be_bytes());
+                page[4..4 + chunk.len()].copy_from_slice(chunk);
+                file.append(&page)?;
+            }
+        }
+
+        if self.used + cell.len() + 2 > PAGE_SIZE {
+            self.flush(file)?;
+        }
+        self.used += cell.len() + 2;
+        self.cells.push(cell);
+        self.last_rowid = rowid;
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn flush(&mut self, file: &mut PageFile) -> Result<()> {
+        let page = file.append(&build_page(0x0d, 0, &self.cells, None))?;
+        self.leaves.push((page, self.last_rowid));
+        self.cells.clear();
+        self.used = Self::LEAF_HEADER;
+        Ok(())
+    }
+
+    /// Groups `children` into interior pages, at least `min_pages` of them.
+    fn interior_level(file: &mut PageFile, children: &[(u32, i64)], min_pages: usize) -> Result<Vec<(u32, i64)>> {
+        // A cell is a 4-byte child pointer and a rowid varint (at most 9
+        // bytes), plus its 2-byte pointer; the last child needs no cell.
+        let per_page = (PAGE_SIZE - Self::INTERIOR_HEADER) / 15 + 1;
+        let pages = children.len().div_ceil(per_page).max(min_pages);
+        let chunk = children.len().div_ceil(pages);
+        let mut level = Vec::with_capacity(pages);
+        for group in children.chunks(chunk) {
+            let (last, rest) = group.split_last().expect("chunks are never empty");
+            let cells: Vec<Vec<u8>> = rest
+                .iter()
+                .map(|&(page, key)| {
+                    let mut cell = page.to_be_bytes().to_vec();
+                    put_varint(&mut cell, key as u64);
+                    cell
+                })
+                .collect();
+            let page = file.append(&build_page(0x05, 0, &cells, Some(last.0)))?;
+            level.push((page, last.1));
+        }
+        Ok(level)
+    }
+
+    /// Writes the remaining pages and returns the root page.
+    fn finish(mut self, file: &mut PageFile) -> Result<u32> {
+        if !self.cells.is_empty() || self.leaves.is_empty() {
+            self.flush(file)?;
+        }
+        let mut level = self.leaves;
+        while level.len() > 1 {
+            level = Self::interior_level(file, &level, 1)?;
+        }
+        Ok(level[0].0)
+    }
+
+    /// Like [`TreeBuilder::finish`], but the root goes on page 1 after the
+    /// file header, as the schema table's must.
+    fn finish_page1(mut self, file: &mut PageFile) -> Result<Vec<u8>> {
+        if self.leaves.is_empty() && self.used + FILE_HEADER <= PAGE_SIZE {
+            return Ok(build_page(0x0d, FILE_HEADER, &self.cells, None));
+        }
+        if !self.cells.is_empty() {
+            self.flush(file)?;
+        }
+        let root_capacity = (PAGE_SIZE - FILE_HEADER - Self::INTERIOR_HEADER) / 15 + 1;
+        let mut level = self.leaves;
+        while level.len() > root_capacity {
+            level = Self::interior_level(file, &level, 2)?;
+        }
+        let (last, rest) = level.split_last().expect("at least one leaf was written");
+        let cells: Vec<Vec<u8>> = rest
+            .iter()
+            .map(|&(page, key)| {
+                let mut cell = page.to_be_bytes().to_vec();
+                put_varint(&mut cell, key as u64);
+                cell
+            })
+            .collect();
+        Ok(build_page(0x05, FILE_HEADER, &cells, Some(last.0)))
+    }
+}
+
+// ---- Export ----
+
+fn quote(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// Declared SQL type for a schema column type; unknown types get none.
+fn sql_type(ty: &str) -> &'static str {
+    match ty {
+        "float" | "f32" | "f64" => "REAL",
+        "string" | "ref|string" => "TEXT",
+        "bool" | "byte" | "u8" | "short" | "i16" | "ushort" | "u16" | "int" | "i32" | "uint" | "u32" | "long"
+        | "i64" | "ulong" | "u64" | "enumrow" | "foreignrow" | "foreign_row" | "row" => "INTEGER",
+        t if t.starts_with("ref|") => "INTEGER",
+        _ => "",
+    }
+}
+
+fn sql_value(value: &DatValue) -> SqlValue {
+    match value {
+        DatValue::Bool(b) => SqlValue::Integer(*b as i64),
+        DatValue::Int(i) => SqlValue::Integer(*i),
+        // u64 columns above i64::MAX wrap; SQLite integers are signed.
+        DatValue::Long(l) => SqlValue::Integer(*l as i64),
+        // SQLite stores NaN as NULL anyway.
+        DatValue::Float(f) if f.is_nan() => SqlValue::Null,
+        DatValue::Float(f) => SqlValue::Real(*f as f64),
+        DatValue::String(s) => SqlValue::Text(s.clone()),
+        DatValue::ForeignRow(usize::MAX) => SqlValue::Null,
+        DatValue::ForeignRow(k) => SqlValue::Integer(*k as i64),
+        DatValue::List(..) | DatValue::Unknown => SqlValue::Null,
+    }
+}
+
+/// The (table, column) a schema column points at, if any.
+fn reference(table: &Table, col: &Column) -> Option<(String, String)> {
+    if col.r#type == "row" {
+        return Some((table.name.clone(), "_index".to_string()));
+    }
+    let r = col.references.as_ref()?;
+    let column = match col.r#type.as_str() {
+        // Enumerations are keyed by their (indexed) enumerator value.
+        "enumrow" => "_index".to_string(),
+        _ => r.column.clone().unwrap_or_else(|| "_index".to_string()),
+    };
+    Some((r.table.clone(), column))
+}
+
+struct SqlColumn {
+    name: String,
+    ty: &'static str,
+    primary_key: bool,
+    references: Option<(String, String)>,
+}
+
+impl SqlColumn {
+    fn new(name: &str, ty: &'static str) -> Self {
+        Self { name: name.to_string(), ty, primary_key: false, references: None }
+    }
+}
+
+struct SqlTable {
+    name: String,
+    columns: Vec<SqlColumn>,
+    root: u32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SqliteStats {
+    /// SQL tables written, including array and enumeration tables.
+    pub tables: usize,
+    /// Rows across all of them.
+    pub rows: u64,
+}
+
+/// Streams dat tables into a new SQLite file. Add each table with
+/// [`SqliteExport::add_table`], then call [`SqliteExport::finish`]; the
+/// file is not a valid database before that.
+pub struct SqliteExport {
+    file: PageFile,
+    schema: Schema,
+    tables: Vec<SqlTable>,
+    /// Lowercased dat table name -> the file it was exported from.
+    exported: HashMap<String, String>,
+    enumerations: BTreeSet<String>,
+    stats: SqliteStats,
+}
+
+impl SqliteExport {
+    pub fn create(path: &Path, schema: Schema) -> Result<Self> {
+        Ok(Self {
+            file: PageFile::create(path)?,
+            schema,
+            tables: Vec::new(),
+            exported: HashMap::new(),
+            enumerations: BTreeSet::new(),
+            stats: SqliteStats::default(),
+        })
+    }
+
+    /// Decodes the dat file at `path` with the schema table named after it
+    /// and writes it (and its array tables). Returns the row count.
+    pub fn add_table(&mut self, path: &str, data: Vec<u8>) -> Result<u32> {
+        let stem = Path::new(path).file_stem().and_then(|s| s.to_str()).unwrap_or("");
+        let table = self
+            .schema
+            .tables
+            .iter()
+            .find(|t| t.name.eq_ignore_ascii_case(stem))
+            .ok_or_else(|| Error::NotFound(format!("schema table for {}", path)))?
+            .clone();
+        if let Some(first) = self.exported.get(&table.name.to_ascii_lowercase()) {
+            return Err(Error::InvalidData(format!("{} was already exported from {}", table.name, first)));
+        }
+        let reader = DatReader::new(data, path)?;
+        reader.check_schema(&table)?;
+
+        // Column names must be unique, case-insensitively, and not shadow
+        // the row index.
+        let mut seen: HashSet<String> = HashSet::from(["_index".to_string()]);
+        let names: Vec<String> = table
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(j, col)| {
+                let name = col.name.clone().unwrap_or_else(|| format!("Col{}", j));
+                if seen.insert(name.to_ascii_lowercase()) {
+                    name
+                } else {
+                    format!("{}_{}", name, j)
+                }
+            })
+            .collect();
+
+        let mut columns = vec![SqlColumn { primary_key: true, ..SqlColumn::new("_index", "INTEGER") }];
+        let mut arrays = Vec::new();
+        for (j, col) in table.columns.iter().enumerate() {
+            let references = reference(&table, col);
+            if col.r#type == "enumrow" {
+                if let Some((name, _)) = &references {
+                    self.enumerations.insert(name.clone());
+                }
+            }
+            if col.array {
+                let value = SqlColumn { references, ..SqlColumn::new("value", sql_type(&col.r#type)) };
+                let parent = SqlColumn {
+                    references: Some((table.name.clone(), "_index".to_string())),
+                    ..SqlColumn::new("_index", "INTEGER")
+                };
+                arrays.push((j, format!("{}_{}", table.name, names[j]), vec![parent, SqlColumn::new("position", "INTEGER"), value]));
+            } else {
+                columns.push(SqlColumn { references, ..SqlColumn::new(&names[j], sql_type(&col.r#type)) });
+            }
+        }
+
+        // Decode the whole table before writing a page: pages written for a
+        // table that then fails would belong to no b-tree.
+        let mut rows = Vec::with_capacity(reader.row_count as usize);
+        let mut items: Vec<Vec<[SqlValue; 3]>> = arrays.iter().map(|_| Vec::new()).collect();
+        for i in 0..reader.row_count {
+            let values = reader.read_row(i, &table)?;
+            let mut row = vec![SqlValue::Null]; // rowid alias
+            for (value, col) in values.iter().zip(&table.columns) {
+                if !col.array {
+                    row.push(sql_value(value));
+                }
+            }
+            rows.push(row);
+
+            for ((j, _, _), items) in arrays.iter().zip(&mut items) {
+                let DatValue::List(count, offset) = values[*j] else { continue };
+                for (position, item) in reader.read_list_values(offset, count, &table.columns[*j])?.iter().enumerate() {
+                    items.push([SqlValue::Integer(i as i64), SqlValue::Integer(position as i64), sql_value(item)]);
+                }
+            }
+        }
+
+        let mut main = TreeBuilder::new();
+        for (i, row) in rows.iter().enumerate() {
+            main.insert(&mut self.file, i as i64, row)?;
+        }
+        let mut children = Vec::with_capacity(items.len());
+        for items in &items {
+            let mut child = TreeBuilder::new();
+            for (rowid, row) in items.iter().enumerate() {
+                child.insert(&mut self.file, rowid as i64 + 1, row)?;
+            }
+            children.push(child);
+        }
+
+        self.stats.rows += main.rows;
+        let root = main.finish(&mut self.file)?;
+        self.tables.push(SqlTable { name: table.name.clone(), columns, root });
+        for ((_, name, columns), child) in arrays.into_iter().zip(children) {
+            self.stats.rows += child.rows;
+            let root = child.finish(&mut self.file)?;
+            self.tables.push(SqlTable { name, columns, root });
+        }
+        self.exported.insert(table.name.to_ascii_lowercase(), path.to_string());
+        Ok(reader.row_count)
+    }
+
+    /// Writes the enumeration tables and the schema, and closes the file.
+    pub fn finish(mut self) -> Result<SqliteStats> {
+        for name in std::mem::take(&mut self.enumerations) {
+            let Some(enumeration) = self.schema.enumeration.iter().flatten().find(|e| e.name == name) else {
+                continue;
+            };
+            let mut tree = TreeBuilder::new();
+            for (i, enumerator) in enumeration.enumerators.iter().enumerate() {
+                let value = enumerator.clone().map_or(SqlValue::Null, SqlValue::Text);
+                tree.insert(&mut self.file, i as i64 + enumeration.indexing as i64, &[SqlValue::Null, value])?;
+            }
+            self.stats.rows += tree.rows;
+            let root = tree.finish(&mut self.file)?;
+            let columns = vec![
+                SqlColumn { primary_key: true, ..SqlColumn::new("_index", "INTEGER") },
+                SqlColumn::new("Name", "TEXT"),
+            ];
+            self.tables.push(SqlTable { name: enumeration.name.clone(), columns, root });
+        }
+
+        // Array tables are named Table_Column, which could in principle
+        // clash with a real table; the first one keeps the name.
+        let mut names = HashSet::new();
+        for table in &mut self.tables {
+            while !names.insert(table.name.to_ascii_lowercase()) {
+                table.name.push('_');
+            }
+        }
+        let columns_of: HashMap<String, HashSet<String>> = self
+            .tables
+            .iter()
+            .map(|t| (t.name.to_ascii_lowercase(), t.columns.iter().map(|c| c.name.to_ascii_lowercase()).collect()))
+            .collect();
+
+        let mut master = TreeBuilder::new();
+        for (i, table) in self.tables.iter().enumerate() {
+            let columns: Vec<String> = table
+                .columns
+                .iter()
+                .map(|c| {
+                    let mut def = quote(&c.name);
+                    if !c.ty.is_empty() {
+                        def.push(' ');
+                        def.push_str(c.ty);
+                    }
+                    if c.primary_key {
+                        def.push_str(" PRIMARY KEY");
+                    }
+                    // Only declare keys into tables that were exported.
+                    if let Some((target, column)) = &c.references {
+                        if columns_of.get(&target.to_ascii_lowercase()).is_some_and(|cols| cols.contains(&column.to_ascii_lowercase())) {
+                            def.push_str(&format!(" REFERENCES {}({})", quote(target), quote(column)));
+                        }
+                    }
+                    def
+                })
+                .collect();
+            let sql = format!("CREATE TABLE {} ({})", quote(&table.name), columns.join(", "));
+            let row = [
+                SqlValue::Text("table".to_string()),
+                SqlValue::Text(table.name.clone()),
+                SqlValue::Text(table.name.clone()),
+                SqlValue::Integer(table.root as i64),
+                SqlValue::Text(sql),
+            ];
+            master.insert(&mut self.file, i as i64 + 1, &row)?;
+        }
+        let page1 = master.finish_page1(&mut self.file)?;
+        self.file.finish(page1)?;
+        self.stats.tables = self.tables.len();
+        Ok(self.stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dat::schema::{Enumeration, TableReference};
+
+    #[test]
+    fn varints_and_records() {
+        let encode = |v: u64| {
+            let mut out = Vec::new();
+            put_varint(&mut out, v);
+            out
+        };
+        assert_eq!(encode(0), [0x00]);
+        assert_eq!(encode(0x7f), [0x7f]);
+        assert_eq!(encode(0x80), [0x81, 0x00]);
+        assert_eq!(encode(0x3fff), [0xff, 0x7f]);
+        assert_eq!(encode(u64::MAX), [0xff; 9]);
+
+        // Header: its size (5), NULL, literal 1, 1-byte int, 3-char text.
+        let r = record(&[SqlValue::Null, SqlValue::Integer(1), SqlValue::Integer(-2), SqlValue::Text("abc".to_string())]);
+        assert_eq!(r, [5, 0, 9, 1, 19, 0xfe, b'a', b'b', b'c']);
+        assert_eq!(integer_body(0x1_0000_0000).1.len(), 6);
+    }
+
+    fn column(name: &str, ty: &str) -> Column {
+        Column {
+            name: Some(name.to_string()),
+            description: None,
+            array: false,
+            r#type: ty.to_string(),
+            unique: false,
+            localized: false,
+            references: None,
+            interval: false,
+        }
+    }
+
+    /// Items(Id: string, Parent: row, Kind: enumrow, Tags: [i32]) with
+    /// `rows` rows, each Id `long` characters long.
+    fn items(rows: u32, long: usize) -> (Schema, Vec<u8>) {
+        let schema = Schema {
+            version: 0,
+            created_at: 0,
+            tables: vec![Table {
+                name: "Items".to_string(),
+                columns: vec![
+                    column("Id", "string"),
+                    column("Parent", "row"),
+                    Column {
+                        references: Some(TableReference { table: "ItemKinds".to_string(), column: None }),
+                        ..column("Kind", "enumrow")
+                    },
+                    Column { array: true, ..column("Tags", "i32") },
+                ],
+                tags: None,
+                valid_for: None,
+            }],
+            enumeration: Some(vec![Enumeration {
+                name: "ItemKinds".to_string(),
+                indexing: 1,
+                enumerators: vec![Some("Weapon".to_string()), None],
+            }]),
+        };
+        let mut fixed = Vec::new();
+        let mut var = vec![0xBB; 8];
+        for i in 0..rows {
+            fixed.extend((var.len() as u64).to_le_bytes());
+            let id = format!("{:0>width$}", i, width = long);
+            var.extend(id.encode_utf16().chain([0, 0]).flat_map(u16::to_le_bytes));
+            fixed.extend(if i == 0 { u64::MAX - 0x0101_0101_0101_0101 } else { i as u64 - 1 }.to_le_bytes());
+            fixed.extend(1u32.to_le_bytes());
+            fixed.extend(2u64.to_le_bytes());
+            fixed.extend((var.len() as u64).to_le_bytes());
+            var.extend([i as i32, -(i as i32)].iter().flat_map(|t| t.to_le_bytes()));
+        }
+        let mut data = rows.to_le_bytes().to_vec();
+        data.extend(fixed);
+        data.extend(var);
+        (schema, data)
+    }
+
+    fn export(rows: u32, long: usize, name: &str) -> (SqliteStats, Vec<u8>) {
+        let (schema, data) = items(rows, long);
+        let path = std::env::temp_dir().join(format!("dat_sqlite_test_{}_{}.sqlite", name, std::process::id()));
+        let mut export = SqliteExport::create(&path, schema).unwrap();
+        assert_eq!(export.add_table("Data/Items.datc64", data.clone()).unwrap(), rows);
+        assert!(matches!(export.add_table("data/balance/items.datc64", data), Err(Error::InvalidData(_))));
+        let stats = export.finish().unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert_integrity(&path, &stats);
+        let _ = std::fs::remove_file(&path);
+        (stats, bytes)
+    }
+
+    /// Opens the file with SQLite and checks it is a consistent database
+    /// holding `stats.rows` rows.
+    fn assert_integrity(path: &Path, stats: &SqliteStats) {
+        let db = rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY).unwrap();
+        let check: Vec<String> = db
+            .prepare("PRAGMA integrity_check")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap();
+        assert_eq!(check, ["ok"]);
+        let tables: Vec<String> = db
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table'")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap();
+        assert_eq!(tables.len(), stats.tables);
+        let rows: i64 = tables
+            .iter()
+            .map(|t| db.query_row(&format!("SELECT count(*) FROM {}", quote(t)), [], |r| r.get::<_, i64>(0)).unwrap())
+            .sum();
+        assert_eq!(rows as u64, stats.rows);
+    }
+
+    #[test]
+    fn writes_tables_arrays_and_enumerations() {
+        let (stats, file) = export(3, 1, "small");
+        assert_eq!(stats, SqliteStats { tables: 3, rows: 3 + 6 + 2 });
+        assert_eq!(&file[..16], b"SQLite format 3\0");
+        let pages = u32::from_be_bytes(file[28..32].try_into().unwrap()) as usize;
+        assert_eq!(file.len(), pages * PAGE_SIZE);
+        // Small enough for the schema to fit in page 1 as a single leaf.
+        assert_eq!(file[FILE_HEADER], 0x0d);
+        let text = String::from_utf8_lossy(&file);
+        assert!(text.contains(
+            r#"CREATE TABLE "Items" ("_index" INTEGER PRIMARY KEY, "Id" TEXT, "Parent" INTEGER REFERENCES "Items"("_index"), "Kind" INTEGER REFERENCES "ItemKinds"("_index"))"#
+        ));
+        assert!(text.contains(
+            r#"CREATE TABLE "Items_Tags" ("_index" INTEGER REFERENCES "Items"("_index"), "position" INTEGER, "value" INTEGER)"#
+        ));
+        assert!(text.contains(r#"CREATE TABLE "ItemKinds" ("_index" INTEGER PRIMARY KEY, "Name" TEXT)"#));
+    }
+
+    #[test]
+    fn large_tables_get_interior_pages() {
+        // 2000 rows of 1 KB ids: four rows per leaf, so the table needs two
+        // interior levels.
+        let (stats, file) = export(2000, 1000, "large");
+        assert_eq!(stats.rows, 2000 + 4000 + 2);
+        let pages = u32::from_be_bytes(file[28..32].try_into().unwrap()) as usize;
+        assert_eq!(file.len(), pages * PAGE_SIZE);
+        let interior = (1..pages).filter(|p| file[p * PAGE_SIZE] == 0x05).count();
+        assert!(interior > 2, "{} interior pages", interior);
+    }
+
+    #[test]
+    fn long_records_spill_to_overflow_pages() {
+        let path = std::env::temp_dir().join(format!("dat_sqlite_test_overflow_{}.sqlite", std::process::id()));
+        let mut file = PageFile::create(&path).unwrap();
+        let mut tree = TreeBuilder::new();
+        for i in 0..3 {
+            tree.insert(&mut file, i, &[SqlValue::Text("x".repeat(10_000 + i as usize))]).unwrap();
+        }
+        let root = tree.finish(&mut file).unwrap();
+        let mut master = TreeBuilder::new();
+        let row = ["table", "Big", "Big"].map(|s| SqlValue::Text(s.to_string()));
+        let sql = SqlValue::Text(r#"CREATE TABLE "Big" ("Text" TEXT)"#.to_string());
+        master.insert(&mut file, 1, &[row[0].clone(), row[1].clone(), row[2].clone(), SqlValue::Integer(root as i64), sql]).unwrap();
+        let page1 = master.finish_page1(&mut file).unwrap();
+        file.finish(page1).unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_integrity(&path, &SqliteStats { tables: 1, rows: 3 });
+        let _ = std::fs::remove_file(&path);
+        // Page 1, two 4092-byte overflow pages per record, two leaves (a
+        // 1.8 KB local part leaves room for two cells) and their root.
+        assert_eq!(bytes.len(), 10 * PAGE_SIZE);
+        assert_eq!(root, 10);
+        // The first record's overflow chain: page 2 -> 3 -> end.
+        assert_eq!(bytes[PAGE_SIZE..PAGE_SIZE + 4], 3u32.to_be_bytes());
+        assert_eq!(bytes[2 * PAGE_SIZE..2 * PAGE_SIZE + 4], 0u32.to_be_bytes());
+    }
+
+    #[test]
+    fn large_schemas_get_an_interior_root_on_page1() {
+        let path = std::env::temp_dir().join(format!("dat_sqlite_test_schema_{}.sqlite", std::process::id()));
+        let mut file = PageFile::create(&path).unwrap();
+        let mut master = TreeBuilder::new();
+        let columns: Vec<String> = (0..40).map(|c| format!("\"Column{}\" INTEGER", c)).collect();
+        // ~700-byte CREATE statements: more leaves than page 1 can point
+        // at, so there is a level in between.
+        for i in 0..2000 {
+            let root = TreeBuilder::new().finish(&mut file).unwrap();
+            let name = format!("T{}", i);
+            let sql = format!("CREATE TABLE {} ({})", quote(&name), columns.join(", "));
+            let row = ["table", &name, &name].map(|s| SqlValue::Text(s.to_string()));
+            master.insert(&mut file, i + 1, &[row[0].clone(), row[1].clone(), row[2].clone(), SqlValue::Integer(root as i64), SqlValue::Text(sql)]).unwrap();
+        }
+        let page1 = master.finish_page1(&mut file).unwrap();
+        file.finish(page1).unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_integrity(&path, &SqliteStats { tables: 2000, rows: 0 });
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(bytes[FILE_HEADER], 0x05);
+        let children = u16::from_be_bytes([bytes[FILE_HEADER + 3], bytes[FILE_HEADER + 4]]) as usize + 1;
+        let right = u32::from_be_bytes(bytes[FILE_HEADER + 8..FILE_HEADER + 12].try_into().unwrap()) as usize;
+        assert!(children >= 2);
+        assert_eq!(bytes[(right - 1) * PAGE_SIZE], 0x05, "page 1's children are interior pages");
+    }
+}
@@ -0,0 +1,400 @@
+//! Checks a schema against the dat files of an install.
+//!
+//! For every table the schema lists for a game, [`validate`] compares the
+//! row width the schema describes with the one found in the file, and,
+//! where they agree, reads each row raw to check that string and array
+//! offsets land inside the variable data section and that foreign keys are
+//! below the row count of the table they point at. Values are checked on
+//! the bytes rather than through [`DatReader::read_row`], which quietly
+//! turns a bad offset into an empty string or list.
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use serde::Serialize;
+
+use super::reader::{get_column_size, DatReader};
+use super::relational::TableLoader;
+use super::schema::{Column, Schema, Table};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Problem {
+    /// The file couldn't be read or isn't a dat table.
+    Unreadable { error: String },
+    /// The schema's columns don't add up to the file's row length.
+    RowLength { schema: usize, file: usize },
+    /// String or array offsets outside the variable data section.
+    BadOffset { column: String, bad_rows: u32, first_row: u32 },
+    /// Foreign keys at or past the end of the table they reference.
+    KeyOutOfRange { column: String, target: String, target_rows: u32, bad_rows: u32, first_row: u32, max_key: u64 },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TableReport {
+    pub table: String,
+    pub rows: u32,
+    pub problems: Vec<Problem>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ValidationReport {
+    /// `Some(true)` if only PoE 2 tables were checked, `Some(false)` for
+    /// PoE 1, `None` for every table in the schema.
+    pub poe2: Option<bool>,
+    pub checked: usize,
+    /// Schema tables with no file in this install.
+    pub absent: Vec<String>,
+    /// Tables with at least one problem.
+    pub broken: Vec<TableReport>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.broken.is_empty()
+    }
+
+    pub fn summary(&self) -> String {
+        let game = match self.poe2 {
+            Some(true) => " for PoE 2",
+            Some(false) => " for PoE 1",
+            None => "",
+        };
+        format!(
+            "Checked {} tables{}: {} ok, {} broken, {} not in this install",
+            self.checked,
+            game,
+            self.checked - self.broken.len(),
+            self.broken.len(),
+            self.absent.len()
+        )
+    }
+
+    pub fn write_text(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(out, "{}", self.summary())?;
+        for t in &self.broken {
+            writeln!(out, "{} ({} rows)", t.table, t.rows)?;
+            for p in &t.problems {
+                match p {
+                    Problem::Unreadable { error } => writeln!(out, "    unreadable: {}", error)?,
+                    Problem::RowLength { schema, file } => {
+                        writeln!(out, "    row length: schema describes {} bytes, file has {}", schema, file)?
+                    }
+                    Problem::BadOffset { column, bad_rows, first_row } => writeln!(
+                        out,
+                        "    {}: {} rows with offsets outside the variable section (first: row {})",
+                        column, bad_rows, first_row
+                    )?,
+                    Problem::KeyOutOfRange { column, target, target_rows, bad_rows, first_row, max_key } => writeln!(
+                        out,
+                        "    {}: {} rows with keys past the end of {} ({} rows; first: row {}, largest key {})",
+                        column, bad_rows, target, target_rows, first_row, max_key
+                    )?,
+                }
+            }
+        }
+        out.flush()
+    }
+}
+
+fn column_name(col: &Column, j: usize) -> String {
+    col.name.clone().unwrap_or_else(|| format!("Col{}", j))
+}
+
+fn uint(bytes: &[u8]) -> u64 {
+    let mut b = [0u8; 8];
+    b[..bytes.len()].copy_from_slice(bytes);
+    u64::from_le_bytes(b)
+}
+
+/// Tallies bad rows of one column.
+#[derive(Default)]
+struct Tally {
+    bad_rows: u32,
+    first_row: u32,
+    max_key: u64,
+}
+
+impl Tally {
+    fn add(&mut self, row: u32) {
+        if self.bad_rows == 0 {
+            self.first_row = row;
+        }
+        self.bad_rows += 1;
+    }
+}
+
+struct Validator<'a> {
+    loader: &'a TableLoader,
+    /// Row counts by lowercase table name; `None` if the file is missing
+    /// or unreadable.
+    row_counts: HashMap<String, Option<u32>>,
+}
+
+impl Validator<'_> {
+    fn row_count(&mut self, table: &str) -> Option<u32> {
+        let key = table.to_ascii_lowercase();
+        if let Some(&count) = self.row_counts.get(&key) {
+            return count;
+        }
+        // The row count is the file's first four bytes.
+        let count = (self.loader)(table).ok().and_then(|d| Some(u32::from_le_bytes(d.get(..4)?.try_into().ok()?)));
+        self.row_counts.insert(key, count);
+        count
+    }
+
+    fn check_table(&mut self, table: &Table, reader: &DatReader) -> Vec<Problem> {
+        let schema_length: usize = table.columns.iter().map(|c| get_column_size(c, reader.is_64bit)).sum();
+        let row_length = reader.row_length.unwrap_or(0);
+        if reader.row_count > 0 && schema_length != row_length {
+            return vec![Problem::RowLength { schema: schema_length, file: row_length }];
+        }
+
+        let data = reader.get_data();
+        let ptr = if reader.is_64bit { 8 } else { 4 };
+        let var_base = (reader.data_section_offset as usize).saturating_sub(8);
+        // Whether `len` bytes at variable-section offset `offset` exist.
+        let in_var = |offset: u64, len: usize| {
+            offset >= 8 && (var_base as u64).saturating_add(offset).saturating_add(len as u64) <= data.len() as u64
+        };
+        let string_ok = |offset: u64| offset == 0 || in_var(offset, 2);
+
+        let mut problems = Vec::new();
+        let mut at = 0;
+        for (j, col) in table.columns.iter().enumerate() {
+            let size = get_column_size(col, reader.is_64bit);
+            let p = at;
+            at += size;
+
+            let ty = col.r#type.as_str();
+            let is_string = matches!(ty, "string" | "ref|string");
+            let is_foreign = matches!(ty, "foreignrow" | "foreign_row");
+            let is_key = is_foreign || ty == "row";
+            // Keyed references match on a column value, not a row index.
+            let target = match ty {
+                "row" => Some(table.name.clone()),
+                _ if is_key && col.references.as_ref().is_some_and(|r| r.column.is_none()) => {
+                    col.references.as_ref().map(|r| r.table.clone())
+                }
+                _ => None,
+            };
+            if !col.array && !is_string && target.is_none() {
+                continue;
+            }
+            let target_rows = target.as_deref().and_then(|t| self.row_count(t));
+            let element_size = get_column_size(&Column { array: false, ..col.clone() }, reader.is_64bit);
+
+            let mut offsets = Tally::default();
+            let mut keys = Tally::default();
+            for row in 0..reader.row_count {
+                let start = 4 + row as usize * row_length + p;
+                let cell = &data[start..start + size];
+                // The bytes of each array element, or of the single value.
+                let elements: Vec<&[u8]> = if col.array {
+                    let count = uint(&cell[..4]) as usize;
+                    let offset = uint(&cell[ptr..ptr + 4]);
+                    if count == 0 {
+                        continue;
+                    }
+                    match count.checked_mul(element_size).filter(|&len| in_var(offset, len)) {
+                        Some(len) => {
+                            let at = var_base + offset as usize;
+                            data[at..at + len].chunks(element_size).collect()
+                        }
+                        None => {
+                            offsets.add(row);
+                            continue;
+                        }
+                    }
+                } else {
+                    vec![cell]
+                };
+
+                if is_string && !elements.iter().all(|e| string_ok(uint(&e[..4]))) {
+                    offsets.add(row);
+                }
+                if let Some(target_rows) = target_rows {
+                    let mut bad = None;
+                    for e in elements {
+                        if e.iter().all(|&b| b == 0xFE) {
+                            continue;
+                        }
+                        // A foreignrow's upper half is always zero.
+                        let (key, high) = if is_foreign { e.split_at(e.len() / 2) } else { (e, &[][..]) };
+                        let key = uint(key);
+                        if key >= target_rows as u64 || uint(high) != 0 {
+                            bad = Some(bad.unwrap_or(0).max(key));
+                        }
+                    }
+                    if let Some(key) = bad {
+                        keys.add(row);
+                        keys.max_key = keys.max_key.max(key);
+                    }
+                }
+            }
+
+            if offsets.bad_rows > 0 {
+                problems.push(Problem::BadOffset {
+                    column: column_name(col, j),
+                    bad_rows: offsets.bad_rows,
+                    first_row: offsets.first_row,
+                });
+            }
+            if keys.bad_rows > 0 {
+                problems.push(Problem::KeyOutOfRange {
+                    column: column_name(col, j),
+                    target: target.unwrap_or_default(),
+                    target_rows: target_rows.unwrap_or_default(),
+                    bad_rows: keys.bad_rows,
+                    first_row: keys.first_row,
+                    max_key: keys.max_key,
+                });
+            }
+        }
+        problems
+    }
+}
+
+/// Checks every table `schema` lists for the game (`poe2`, or all tables
+/// for `None`) against the files `loader` returns, calling `progress` with
+/// (done, total) after each table.
+pub fn validate(
+    schema: &Schema,
+    poe2: Option<bool>,
+    loader: &TableLoader,
+    mut progress: impl FnMut(usize, usize),
+) -> ValidationReport {
+    let tables: Vec<&Table> = schema.tables.iter().filter(|t| poe2.is_none_or(|poe2| t.is_valid_for(poe2))).collect();
+    let mut validator = Validator { loader, row_counts: HashMap::new() };
+    let mut report = ValidationReport { poe2, ..Default::default() };
+
+    for (i, table) in tables.iter().enumerate() {
+        let reader = match loader(&table.name) {
+            Err(e) if e.is_not_found() => {
+                report.absent.push(table.name.clone());
+                progress(i + 1, tables.len());
+                continue;
+            }
+            loaded => loaded.and_then(|data| DatReader::new(data, &format!("{}.datc64", table.name))),
+        };
+        report.checked += 1;
+        let (rows, problems) = match reader {
+            Ok(reader) => {
+                validator.row_counts.insert(table.name.to_ascii_lowercase(), Some(reader.row_count));
+                (reader.row_count, validator.check_table(table, &reader))
+            }
+            Err(e) => (0, vec![Problem::Unreadable { error: e.to_string() }]),
+        };
+        if !problems.is_empty() {
+            report.broken.push(TableReport { table: table.name.clone(), rows, problems });
+        }
+        progress(i + 1, tables.len());
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dat::schema::TableReference;
+    use crate::error::Error;
+
+    fn column(name: &str, ty: &str, references: Option<&str>) -> Column {
+        Column {
+            name: Some(name.to_string()),
+            description: None,
+            array: false,
+            r#type: ty.to_string(),
+            unique: false,
+            localized: false,
+            references: references.map(|t| TableReference { table: t.to_string(), column: None }),
+            interval: false,
+        }
+    }
+
+    fn table(name: &str, columns: Vec<Column>, valid_for: Option<u32>) -> Table {
+        Table { name: name.to_string(), columns, tags: None, valid_for }
+    }
+
+    fn dat(rows: &[Vec<u8>], var: &[u8]) -> Vec<u8> {
+        let mut data = (rows.len() as u32).to_le_bytes().to_vec();
+        data.extend(rows.concat());
+        data.extend([0xBB; 8]);
+        data.extend(var);
+        data
+    }
+
+    fn foreign(row: u64) -> Vec<u8> {
+        [row.to_le_bytes(), 0u64.to_le_bytes()].concat()
+    }
+
+    #[test]
+    fn reports_widths_offsets_and_keys() {
+        let schema = Schema {
+            version: 0,
+            created_at: 0,
+            tables: vec![
+                // Name, Stat -> Stats, Tags -> [Stats]
+                table(
+                    "Mods",
+                    vec![
+                        column("Name", "string", None),
+                        column("Stat", "foreignrow", Some("Stats")),
+                        Column { array: true, ..column("Tags", "foreignrow", Some("Stats")) },
+                    ],
+                    None,
+                ),
+                // Three bytes short of the file's rows.
+                table("Stats", vec![column("Id", "string", None)], None),
+                table("Missing", vec![column("Id", "string", None)], Some(3)),
+                table("Poe1Only", vec![column("Id", "string", None)], Some(1)),
+            ],
+            enumeration: None,
+        };
+
+        // Variable section: "A\0" at 8, then a two-element key list at 12.
+        let mut var = vec![0xBB; 8];
+        var.extend([b'A', 0, 0, 0]);
+        var.extend([foreign(0), foreign(5)].concat());
+        let row = |name: u64, stat: Vec<u8>, tags: (u64, u64)| {
+            [name.to_le_bytes().to_vec(), stat, tags.0.to_le_bytes().to_vec(), tags.1.to_le_bytes().to_vec()].concat()
+        };
+        let mods = dat(
+            &[
+                row(8, foreign(1), (0, 0)),
+                row(9000, vec![0xFE; 16], (2, 12)),
+                row(0, foreign(2), (1, 12)),
+                row(8, foreign(1), (4, 9000)),
+            ],
+            &var[8..],
+        );
+        let stats = dat(&[vec![0; 11], vec![0; 11]], &[]);
+        let loader = move |name: &str| match name {
+            "Mods" => Ok(mods.clone()),
+            "Stats" => Ok(stats.clone()),
+            _ => Err(Error::NotFound(name.to_string())),
+        };
+
+        let mut calls = 0;
+        let report = validate(&schema, Some(true), &loader, |_, _| calls += 1);
+        assert_eq!(calls, 3, "the PoE 1 table is skipped");
+        assert_eq!(report.checked, 2);
+        assert_eq!(report.absent, ["Missing"]);
+        let problems: Vec<String> = report
+            .broken
+            .iter()
+            .flat_map(|t| t.problems.iter().map(move |p| format!("{} {}", t.table, serde_json::to_string(p).unwrap())))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                r#"Mods {"kind":"bad_offset","column":"Name","bad_rows":1,"first_row":1}"#,
+                r#"Mods {"kind":"key_out_of_range","column":"Stat","target":"Stats","target_rows":2,"bad_rows":1,"first_row":2,"max_key":2}"#,
+                r#"Mods {"kind":"bad_offset","column":"Tags","bad_rows":1,"first_row":3}"#,
+                r#"Mods {"kind":"key_out_of_range","column":"Tags","target":"Stats","target_rows":2,"bad_rows":1,"first_row":1,"max_key":5}"#,
+                r#"Stats {"kind":"row_length","schema":8,"file":11}"#,
+            ]
+        );
+        assert_eq!(report.summary(), "Checked 2 tables for PoE 2: 0 ok, 2 broken, 1 not in this install");
+    }
+}
@@ -0,0 +1,419 @@
+//! Patch diff between two bundle indexes.
+//!
+//! Files are matched by path (case-insensitive), or by path hash when an
+//! index has no name for them. A file is changed if its size or its
+//! SHA-256 differs; [`diff`] reads both copies of every file whose size did
+//! not change to hash them (GGPK FILE records already store theirs), and
+//! [`diff_sizes`] skips that for a fast, size-only comparison.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::bundles::bundle::BlockCache;
+use crate::bundles::index::{FileInfo, Index, GGPK_LOOSE_FILE_SENTINEL};
+use crate::vfs::Vfs;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl ChangeKind {
+    /// One-letter marker used in text output, as in `git diff --name-status`.
+    pub fn marker(self) -> char {
+        match self {
+            ChangeKind::Added => 'A',
+            ChangeKind::Removed => 'D',
+            ChangeKind::Changed => 'M',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileChange {
+    /// Empty if neither index knows the file's path.
+    pub path: String,
+    #[serde(serialize_with = "hex_u64")]
+    pub path_hash: u64,
+    pub kind: ChangeKind,
+    pub old_size: Option<u32>,
+    pub new_size: Option<u32>,
+    /// SHA-256 of both copies, for files whose contents were compared.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_sha256: Option<String>,
+}
+
+impl FileChange {
+    /// The path, or `#<hash>` for files without one.
+    pub fn display_path(&self) -> String {
+        if self.path.is_empty() {
+            format!("#{:016x}", self.path_hash)
+        } else {
+            self.path.clone()
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct IndexDiff {
+    /// Sorted by path; files without a path come last.
+    pub changes: Vec<FileChange>,
+    pub unchanged: usize,
+    /// Whether files of equal size were compared by content. If not (see
+    /// [`diff_sizes`]), they all count as unchanged.
+    pub contents_compared: bool,
+    /// Files whose contents could not be read for comparison, with the
+    /// reason. They are counted as unchanged.
+    pub unreadable: Vec<String>,
+}
+
+impl IndexDiff {
+    pub fn count(&self, kind: ChangeKind) -> usize {
+        self.changes.iter().filter(|c| c.kind == kind).count()
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} added, {} removed, {} changed, {} unchanged",
+            self.count(ChangeKind::Added),
+            self.count(ChangeKind::Removed),
+            self.count(ChangeKind::Changed),
+            self.unchanged
+        )
+    }
+
+    /// One line per change, then the summary.
+    pub fn write_text(&self, out: &mut impl Write) -> std::io::Result<()> {
+        for c in &self.changes {
+            let size = match (c.old_size, c.new_size) {
+                (Some(old), Some(new)) if old != new => format!("{} -> {}", old, new),
+                (_, Some(size)) | (Some(size), None) => size.to_string(),
+                (None, None) => String::new(),
+            };
+            writeln!(out, "{}  {:>25}  {}", c.kind.marker(), size, c.display_path())?;
+        }
+        for e in &self.unreadable {
+            writeln!(out, "?  {}", e)?;
+        }
+        writeln!(out, "{}", self.summary())?;
+        out.flush()
+    }
+}
+
+fn hex_u64<S: serde::Serializer>(v: &u64, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&format!("{:016x}", v))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Key {
+    // Ordered so that named files sort before unnamed ones.
+    Path(String),
+    Hash(u64),
+}
+
+/// Keys `index`'s files by path. A file `index` has no name for takes the
+/// name `other` has under the same hash, so it still pairs up with its
+/// named counterpart instead of showing as removed and added.
+fn keyed<'a>(index: &'a Index, other: &Index) -> HashMap<Key, &'a FileInfo> {
+    index
+        .files
+        .values()
+        .map(|f| {
+            let path = match f.path.as_str() {
+                "" => other.files.get(&f.path_hash).map_or("", |o| o.path.as_str()),
+                path => path,
+            };
+            let key = if path.is_empty() {
+                Key::Hash(f.path_hash)
+            } else {
+                Key::Path(path.to_ascii_lowercase())
+            };
+            (key, f)
+        })
+        .collect()
+}
+
+/// Compares two indexes by size only: the fast mode, for when reading
+/// both copies of every file is too slow or an index has no data behind
+/// it. Same-size edits count as unchanged.
+pub fn diff_sizes(old: &Index, new: &Index) -> IndexDiff {
+    diff_with(old, new, |_| None)
+}
+
+/// Builds the diff; `same_size` is asked about every pair of equal size
+/// and returns their digests if it compared them.
+fn diff_with(
+    old: &Index,
+    new: &Index,
+    mut same_size: impl FnMut(&[(&FileInfo, &FileInfo)]) -> Option<Vec<Option<([u8; 32], [u8; 32])>>>,
+) -> IndexDiff {
+    let old_files = keyed(old, new);
+    let new_files = keyed(new, old);
+    let mut changes = BTreeMap::new();
+    let mut pairs = Vec::new();
+    let mut pair_keys = Vec::new();
+
+    for (key, &o) in &old_files {
+        match new_files.get(key) {
+            None => {
+                changes.insert(key.clone(), change(Some(o), None, ChangeKind::Removed));
+            }
+            Some(&n) if n.file_size != o.file_size => {
+                changes.insert(key.clone(), change(Some(o), Some(n), ChangeKind::Changed));
+            }
+            Some(&n) => {
+                pairs.push((o, n));
+                pair_keys.push(key.clone());
+            }
+        }
+    }
+    for (key, &n) in &new_files {
+        if !old_files.contains_key(key) {
+            changes.insert(key.clone(), change(None, Some(n), ChangeKind::Added));
+        }
+    }
+
+    let mut unchanged = pairs.len();
+    let digests = same_size(&pairs);
+    let contents_compared = digests.is_some();
+    for ((key, &(o, n)), digest) in pair_keys.into_iter().zip(&pairs).zip(digests.unwrap_or_default()) {
+        if let Some((old_digest, new_digest)) = digest {
+            if old_digest != new_digest {
+                unchanged -= 1;
+                let mut c = change(Some(o), Some(n), ChangeKind::Changed);
+                c.old_sha256 = Some(hex(&old_digest));
+                c.new_sha256 = Some(hex(&new_digest));
+                changes.insert(key, c);
+            }
+        }
+    }
+
+    IndexDiff {
+        changes: changes.into_values().collect(),
+        unchanged,
+        contents_compared,
+        unreadable: Vec::new(),
+    }
+}
+
+/// The change between `old` and `new`, named after whichever side has a
+/// path (the new one if both do).
+fn change(old: Option<&FileInfo>, new: Option<&FileInfo>, kind: ChangeKind) -> FileChange {
+    let named = [new, old].into_iter().flatten().find(|f| !f.path.is_empty());
+    let any = new.or(old).expect("a change has at least one side");
+    FileChange {
+        path: named.map_or_else(String::new, |f| f.path.clone()),
+        path_hash: named.unwrap_or(any).path_hash,
+        kind,
+        old_size: old.map(|f| f.file_size),
+        new_size: new.map(|f| f.file_size),
+        old_sha256: None,
+        new_sha256: None,
+    }
+}
+
+/// Compares two sources: files are changed if their size differs or, for
+/// files of equal size, their SHA-256 does. `progress` gets (files hashed,
+/// total) as both sides are read. See [`diff_sizes`] for the fast mode.
+pub fn diff(old: &Vfs, new: &Vfs, mut progress: impl FnMut(usize, usize)) -> IndexDiff {
+    let mut unreadable = Vec::new();
+    let mut diff = diff_with(old.index(), new.index(), |pairs| {
+        let total = pairs.len() * 2;
+        let olds: Vec<&FileInfo> = pairs.iter().map(|p| p.0).collect();
+        let news: Vec<&FileInfo> = pairs.iter().map(|p| p.1).collect();
+        let old_digests = digests(old, &olds, &mut |done| progress(done, total));
+        let new_digests = digests(new, &news, &mut |done| progress(pairs.len() + done, total));
+        Some(
+            old_digests
+                .into_iter()
+                .zip(new_digests)
+                .zip(pairs)
+                .map(|((o, n), (file, _))| match (o, n) {
+                    (Ok(o), Ok(n)) => Some((o, n)),
+                    (Err(e), _) | (_, Err(e)) => {
+                        let path = if file.path.is_empty() { format!("#{:016x}", file.path_hash) } else { file.path.clone() };
+                        unreadable.push(format!("{}: {}", path, e));
+                        None
+                    }
+                })
+                .collect(),
+        )
+    });
+    diff.unreadable = unreadable;
+    diff
+}
+
+/// Hashes `files` from `vfs`, in the given order. Bundled files are read
+/// bundle by bundle so each bundle is fetched once and each of its blocks
+/// decoded once.
+fn digests(vfs: &Vfs, files: &[&FileInfo], progress: &mut impl FnMut(usize)) -> Vec<Result<[u8; 32], String>> {
+    let sources = vfs.sources();
+    let index = vfs.index();
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    order.sort_by_key(|&i| (files[i].bundle_index, files[i].file_offset));
+
+    let mut out: Vec<Result<[u8; 32], String>> = vec![Err(String::new()); files.len()];
+    let mut current: Option<(u32, Result<BlockCache, String>)> = None;
+    for (done, i) in order.into_iter().enumerate() {
+        let file = files[i];
+        // FILE records carry the SHA-256 of their data; no need to read it.
+        if let Some(digest) = ggpk_digest(vfs, file) {
+            out[i] = Ok(digest);
+            progress(done + 1);
+            continue;
+        }
+        let data = match sources.read_loose(file) {
+            Some(loose) => loose.map_err(|e| e.to_string()),
+            None => {
+                if current.as_ref().is_none_or(|(b, _)| *b != file.bundle_index) {
+                    let bundle = index
+                        .bundles
+                        .get(file.bundle_index as usize)
+                        .ok_or_else(|| format!("bundle index {} out of range", file.bundle_index))
+                        .and_then(|b| {
+                            sources
+                                .fetch_raw_bundle(&b.name)
+                                .and_then(BlockCache::new)
+                                .map_err(|e| format!("bundle {}: {}", b.name, e))
+                        });
+                    current = Some((file.bundle_index, bundle));
+                }
+                match &mut current {
+                    Some((_, Ok(cache))) => cache
+                        .read(file.file_offset as u64, file.file_size as u64)
+                        .map_err(|e| e.to_string()),
+                    Some((_, Err(e))) => Err(e.clone()),
+                    None => unreachable!(),
+                }
+            }
+        };
+        out[i] = data.map(|d| Sha256::digest(&d).into());
+        progress(done + 1);
+    }
+    out
+}
+
+/// The digest a GGPK FILE record stores for `file`, if it is one.
+fn ggpk_digest(vfs: &Vfs, file: &FileInfo) -> Option<[u8; 32]> {
+    if file.bundle_index != GGPK_LOOSE_FILE_SENTINEL {
+        return None;
+    }
+    let record = vfs.reader()?.read_file_by_path(&file.path).ok()??;
+    Some(record.hash)
+}
+
+fn hex(hash: &[u8; 32]) -> String {
+    hash.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "rust-oodle"))]
+    use crate::bundles::bundle::{BundleBuilder, Compressor};
+    #[cfg(not(feature = "rust-oodle"))]
+    use crate::bundles::steam::SteamBundleLoader;
+    #[cfg(not(feature = "rust-oodle"))]
+    use std::sync::Arc;
+
+    fn index(files: &[(&str, u32)]) -> Index {
+        let mut index = Index::default();
+        for (i, &(path, size)) in files.iter().enumerate() {
+            let hash = i as u64 + 1;
+            index.files.insert(hash, FileInfo { path_hash: hash, bundle_index: 0, file_offset: 0, file_size: size, path: path.to_string() });
+        }
+        index
+    }
+
+    #[test]
+    fn sizes_only_diff_reports_added_removed_and_resized() {
+        let old = index(&[("Data/A.datc64", 10), ("Data/B.datc64", 20), ("Data/C.datc64", 30), ("", 5)]);
+        // Hashes differ between the two, paths do not: matching is by path.
+        let new = index(&[("data/c.datc64", 30), ("Data/B.datc64", 21), ("Data/D.datc64", 40), ("", 5)]);
+
+        let d = diff_sizes(&old, &new);
+        let got: Vec<_> = d.changes.iter().map(|c| (c.kind, c.display_path(), c.old_size, c.new_size)).collect();
+        assert_eq!(got, vec![
+            (ChangeKind::Removed, "Data/A.datc64".to_string(), Some(10), None),
+            (ChangeKind::Changed, "Data/B.datc64".to_string(), Some(20), Some(21)),
+            (ChangeKind::Added, "Data/D.datc64".to_string(), None, Some(40)),
+            // Unnamed files are matched by hash: #4 in old, #4 in new.
+        ]);
+        assert_eq!(d.unchanged, 2);
+        assert!(!d.contents_compared);
+        assert_eq!(d.summary(), "1 added, 1 removed, 1 changed, 2 unchanged");
+
+        let json = serde_json::to_value(&d).unwrap();
+        assert_eq!(json["changes"][1]["kind"], "changed");
+        assert_eq!(json["changes"][1]["path_hash"], "0000000000000002");
+
+        let mut text = Vec::new();
+        d.write_text(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("M                   20 -> 21  Data/B.datc64"), "{}", text);
+    }
+
+    #[test]
+    fn unnamed_files_pair_with_their_named_counterpart() {
+        let mut old = index(&[("Data/A.datc64", 10), ("Data/B.datc64", 20)]);
+        let new = index(&[("Data/A.datc64", 10), ("Data/B.datc64", 25)]);
+        // The old index never resolved either path.
+        for f in old.files.values_mut() {
+            f.path.clear();
+        }
+
+        let d = diff_sizes(&old, &new);
+        let got: Vec<_> = d.changes.iter().map(|c| (c.kind, c.display_path(), c.old_size, c.new_size)).collect();
+        assert_eq!(got, vec![(ChangeKind::Changed, "Data/B.datc64".to_string(), Some(20), Some(25))]);
+        assert_eq!(d.unchanged, 1);
+    }
+
+    #[cfg(not(feature = "rust-oodle"))]
+    fn steam_install(dir: &std::path::Path, files: &[(&str, &[u8])]) -> Vfs {
+        let mut builder = BundleBuilder::new().compressor(Compressor::Leviathan);
+        for (path, data) in files {
+            builder.add_file(path, data).unwrap();
+        }
+        let size = builder.uncompressed_size();
+        let (bytes, placed) = builder.build().unwrap();
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join("Data.bundle.bin"), bytes).unwrap();
+        let mut index = Index::default();
+        index.add_bundle("Data", size, &placed);
+        for f in index.files.values_mut() {
+            f.path = f.path.to_ascii_lowercase();
+        }
+        Vfs::from_parts(Arc::new(index), None, Some(SteamBundleLoader::new(dir.to_path_buf())), None)
+    }
+
+    #[cfg(not(feature = "rust-oodle"))]
+    #[test]
+    fn content_diff_finds_same_size_edits() {
+        let tmp = std::env::temp_dir().join(format!("ggpk_index_diff_test_{}", std::process::id()));
+        let old = steam_install(&tmp.join("old"), &[("data/a.datc64", b"aaaa"), ("data/b.datc64", b"bbbb")]);
+        let new = steam_install(&tmp.join("new"), &[("data/a.datc64", b"aaaa"), ("data/b.datc64", b"BBBB")]);
+
+        assert!(diff_sizes(old.index(), new.index()).changes.is_empty());
+        let mut calls = 0;
+        let d = diff(&old, &new, |done, total| {
+            calls += 1;
+            assert!(done <= total && total == 4);
+        });
+        assert_eq!(calls, 4);
+        assert!(d.contents_compared && d.unreadable.is_empty(), "{:?}", d.unreadable);
+        assert_eq!(d.changes.len(), 1);
+        assert_eq!(d.changes[0].path, "data/b.datc64");
+        assert_eq!(d.changes[0].new_sha256.as_deref(), Some(hex(&Sha256::digest(b"BBBB").into()).as_str()));
+        assert_eq!(d.unchanged, 1);
+
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+}
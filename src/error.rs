@@ -0,0 +1,124 @@
+use std::io;
+
+/// Errors returned by the GGPK, bundle, dat and parser APIs.
+///
+/// Match on the variant to react to a specific failure; the `Display`
+/// output is meant for logs and the UI.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    /// A record or file did not start with the expected magic/tag.
+    #[error("bad signature at offset {offset}: expected {expected}, found {found}")]
+    BadSignature {
+        offset: u64,
+        expected: &'static str,
+        found: String,
+    },
+
+    /// A record, header or table claims more bytes than are available.
+    #[error("truncated {what} at offset {offset}")]
+    TruncatedRecord { what: &'static str, offset: u64 },
+
+    #[error("path hash {0:016x} not found in bundle index")]
+    HashNotInIndex(u64),
+
+    #[error("not found: {0}")]
+    NotFound(String),
+
+    #[error("bundle {0} not found (GGPK, Steam, or CDN)")]
+    BundleMissing(String),
+
+    /// The Oodle decoder rejected a block or produced the wrong size.
+    #[error("Oodle decompression failed in block {block}: returned {returned}, expected {expected}")]
+    Oodle {
+        block: usize,
+        returned: i32,
+        expected: usize,
+    },
+
+    #[error("Oodle compression failed in block {block}: returned {returned}")]
+    OodleCompress { block: usize, returned: i32 },
+
+    #[error("CDN 404 Not Found: {url} — patch version '{patch_version}' may be incorrect or the bundle doesn't exist on CDN")]
+    CdnNotFound { url: String, patch_version: String },
+
+    #[error("CDN request failed: {url} ({status}) — using patch version '{patch_version}'")]
+    CdnStatus {
+        url: String,
+        status: u16,
+        patch_version: String,
+    },
+
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// A dat table does not have the layout its schema describes.
+    #[error("schema mismatch for {table}: {reason}")]
+    SchemaMismatch { table: String, reason: String },
+
+    /// A file's contents could not be parsed as `format`.
+    #[error("{format} parse error: {message}")]
+    Parse { format: &'static str, message: String },
+
+    #[error("invalid data: {0}")]
+    InvalidData(String),
+
+    /// Valid input this build can't handle, e.g. a codec or feature that
+    /// isn't implemented.
+    #[error("unsupported: {0}")]
+    Unsupported(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub fn parse(format: &'static str, message: impl std::fmt::Display) -> Self {
+        Error::Parse {
+            format,
+            message: message.to_string(),
+        }
+    }
+
+    /// For `map_err` on a read of `what`: running out of input becomes
+    /// [`Error::TruncatedRecord`], any other I/O error is passed through.
+    pub fn truncated(what: &'static str, offset: u64) -> impl FnOnce(io::Error) -> Error {
+        move |e| {
+            if e.kind() == io::ErrorKind::UnexpectedEof {
+                Error::TruncatedRecord { what, offset }
+            } else {
+                Error::Io(e)
+            }
+        }
+    }
+
+    /// True for the "doesn't exist" family (missing file, hash, bundle, CDN 404).
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Error::Io(e) => e.kind() == io::ErrorKind::NotFound,
+            Error::HashNotInIndex(_)
+            | Error::NotFound(_)
+            | Error::BundleMissing(_)
+            | Error::CdnNotFound { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncated_maps_only_unexpected_eof() {
+        let eof = io::Error::new(io::ErrorKind::UnexpectedEof, "eof");
+        assert!(matches!(
+            Error::truncated("PDIR", 12)(eof),
+            Error::TruncatedRecord { what: "PDIR", offset: 12 }
+        ));
+
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied, "nope");
+        assert!(matches!(Error::truncated("PDIR", 12)(denied), Error::Io(_)));
+    }
+}
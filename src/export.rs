@@ -1,12 +1,101 @@
+use crate::bundles::bundle::BlockCache;
 use crate::bundles::index::Index as BundleIndex;
-use crate::dat::schema::Schema;
+use crate::dat::reader::DatReader;
+use crate::dat::schema::{Schema, Table};
+use crate::dat::sqlite::SqliteExport;
 use crate::ggpk::reader::GgpkReader;
-use crate::ui::export_window::{AudioFormat, DataFormat, ExportSettings, PsgFormat, TextureFormat};
+use crate::vfs::Vfs;
 use std::collections::{HashSet, VecDeque};
+use std::io::BufWriter;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{mpsc::Sender, Arc, Mutex};
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureFormat {
+    OriginalDds,
+    WebP,
+    Png,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioFormat {
+    Original,
+    Wav,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataFormat {
+    Original,
+    Json,
+    /// All exported tables in one SQLite database, [`SQLITE_FILE_NAME`]
+    /// in the target directory.
+    Sqlite,
+    /// One `.csv` per table, arrays as JSON array cells.
+    Csv,
+    /// One Arrow IPC (Feather v2) `.arrow` file per table.
+    Arrow,
+}
+
+pub const SQLITE_FILE_NAME: &str = "tables.sqlite";
+
+type TableWriter = fn(&DatReader, &Table, &mut BufWriter<std::fs::File>) -> crate::error::Result<()>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PsgFormat {
+    Original,
+    Json,
+}
+
+#[derive(Clone)]
+pub struct ExportSettings {
+    pub texture_format: TextureFormat,
+    pub audio_format: AudioFormat,
+    pub data_format: DataFormat,
+    pub psg_format: PsgFormat,
+    pub recursive: bool,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            texture_format: TextureFormat::OriginalDds,
+            audio_format: AudioFormat::Original,
+            data_format: DataFormat::Original,
+            psg_format: PsgFormat::Original,
+            recursive: true,
+        }
+    }
+}
+
+/// What every file of one export shares: the output settings, the
+/// destination and the schema for dat conversion.
+pub struct ExportContext {
+    pub settings: ExportSettings,
+    pub target_dir: PathBuf,
+    pub schema: Option<Schema>,
+    /// Created by [`run_export`] for [`DataFormat::Sqlite`].
+    sqlite: Option<Mutex<SqliteExport>>,
+}
+
+impl ExportContext {
+    pub fn new(settings: ExportSettings, target_dir: PathBuf, schema: Option<Schema>) -> Self {
+        Self { settings, target_dir, schema, sqlite: None }
+    }
+}
+
+/// Per-thread caches, kept across the files one thread exports.
+struct ExportCaches<'v> {
+    bundles: BundleCache<'v>,
+    directories: DirectoryCache,
+}
+
+impl ExportCaches<'_> {
+    fn new() -> Self {
+        Self { bundles: BundleCache::new(), directories: DirectoryCache::new() }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum ExportStatus {
     Progress {
@@ -22,10 +111,11 @@ pub enum ExportStatus {
     Error(String),
 }
 
-/// Keeps the most recently decompressed bundles so bulk exports don't
-/// re-decompress the same bundle for every file it contains.
-struct BundleCache {
-    entries: Vec<(u32, Vec<u8>)>,
+/// Keeps the most recently used raw bundles, with the blocks decoded from
+/// them so far, so bulk exports decode each block once and exporting a few
+/// files from a large bundle doesn't inflate all of it.
+struct BundleCache<'a> {
+    entries: Vec<(u32, BlockCache<'a>)>,
 }
 
 struct DirectoryCache {
@@ -71,21 +161,7 @@ impl ProgressLimiter {
     }
 }
 
-enum RawBundleData<'a> {
-    Borrowed(&'a [u8]),
-    Owned(Vec<u8>),
-}
-
-impl<'a> RawBundleData<'a> {
-    fn as_slice(&self) -> &[u8] {
-        match self {
-            Self::Borrowed(data) => data,
-            Self::Owned(data) => data,
-        }
-    }
-}
-
-impl BundleCache {
+impl<'a> BundleCache<'a> {
     // Two entries is enough once hashes are sorted by bundle; the second
     // slot absorbs files whose paths interleave two bundles.
     const MAX_ENTRIES: usize = 2;
@@ -96,20 +172,20 @@ impl BundleCache {
         }
     }
 
-    fn get(&mut self, bundle_index: u32) -> Option<&[u8]> {
+    fn get(&mut self, bundle_index: u32) -> Option<&mut BlockCache<'a>> {
         let pos = self.entries.iter().position(|(b, _)| *b == bundle_index)?;
         // Move to the back (most recently used)
         let entry = self.entries.remove(pos);
         self.entries.push(entry);
-        Some(&self.entries.last().unwrap().1)
+        Some(&mut self.entries.last_mut().unwrap().1)
     }
 
-    fn insert(&mut self, bundle_index: u32, data: Vec<u8>) -> &[u8] {
+    fn insert(&mut self, bundle_index: u32, bundle: BlockCache<'a>) -> &mut BlockCache<'a> {
         while self.entries.len() >= Self::MAX_ENTRIES {
             self.entries.remove(0);
         }
-        self.entries.push((bundle_index, data));
-        &self.entries.last().unwrap().1
+        self.entries.push((bundle_index, bundle));
+        &mut self.entries.last_mut().unwrap().1
     }
 }
 
@@ -161,34 +237,30 @@ fn export_worker_count(group_count: usize) -> usize {
     available.min(32).min(group_count.max(1))
 }
 
+/// Exports `hashes` into the context's `target_dir`. With a `vfs` they are
+/// path hashes from its bundle index; without one they are FILE record
+/// offsets in `reader`.
 pub fn run_export(
     hashes: Vec<u64>,
     reader: Option<Arc<GgpkReader>>,
-    bundle_index: Option<Arc<BundleIndex>>,
-    settings: ExportSettings,
-    target_dir: PathBuf,
-    cdn_loader: Option<crate::bundles::cdn::CdnBundleLoader>,
-    steam_loader: Option<crate::bundles::steam::SteamBundleLoader>,
-    schema: Option<Schema>,
+    vfs: Option<Arc<Vfs>>,
+    mut context: ExportContext,
     tx: Sender<ExportStatus>,
     cancel_flag: Option<Arc<AtomicBool>>,
 ) {
     let total = hashes.len();
-    if let Some(index) = bundle_index {
-        let groups = build_export_work_groups(hashes, &index);
-        run_grouped_export(
-            groups,
-            total,
-            reader,
-            index,
-            settings,
-            target_dir,
-            cdn_loader,
-            steam_loader,
-            schema,
-            tx,
-            cancel_flag,
-        );
+    if let (DataFormat::Sqlite, Some(schema)) = (context.settings.data_format, &context.schema) {
+        match SqliteExport::create(&context.target_dir.join(SQLITE_FILE_NAME), schema.clone()) {
+            Ok(export) => context.sqlite = Some(Mutex::new(export)),
+            Err(e) => {
+                let _ = tx.send(ExportStatus::Error(format!("Failed to create {}: {}", SQLITE_FILE_NAME, e)));
+                return;
+            }
+        }
+    }
+    if let Some(vfs) = vfs {
+        let groups = build_export_work_groups(hashes, vfs.index());
+        run_grouped_export(groups, total, vfs, Arc::new(context), tx, cancel_flag);
         return;
     }
 
@@ -196,8 +268,8 @@ pub fn run_export(
     let mut error_count = 0;
     let mut errors = Vec::new();
     let mut error_log: Option<std::fs::File> = None;
-    let mut bundle_cache = BundleCache::new();
-    let mut directory_cache = DirectoryCache::new();
+    let mut caches = ExportCaches::new();
+    let target_dir = context.target_dir.clone();
     let progress_limiter = ProgressLimiter::new(64);
 
     for (i, hash) in hashes.iter().enumerate() {
@@ -213,18 +285,7 @@ pub fn run_export(
         // We can't know the exact filename easily without looking it up, but we'll try to get it inside the loop
 
         let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-            match export_single_file(
-                *hash,
-                reader.as_deref(),
-                bundle_index.as_deref(),
-                &settings,
-                &target_dir,
-                &cdn_loader,
-                &steam_loader,
-                &schema,
-                &mut bundle_cache,
-                &mut directory_cache,
-            ) {
+            match export_single_file(*hash, reader.as_deref(), None, &context, &mut caches) {
                 Ok(name) => Ok(name),
                 Err(e) => Err(format!("Export failed: {}", e)),
             }
@@ -271,7 +332,7 @@ pub fn run_export(
         }
     }
 
-    let final_msg = if error_count == 0 {
+    let mut final_msg = if error_count == 0 {
         format!("Successfully exported {} files.", success_count)
     } else {
         format!(
@@ -279,6 +340,9 @@ pub fn run_export(
             success_count, error_count
         )
     };
+    if let Some(sqlite) = context.sqlite {
+        final_msg.push_str(&finish_sqlite(sqlite, &target_dir));
+    }
 
     // Errors were appended to export_errors.log as they happened (so a crash
     // mid-export still leaves a log); finish with a summary line.
@@ -288,12 +352,12 @@ pub fn run_export(
             &target_dir,
             &format!("--- {} of {} files failed ---", error_count, total),
         );
-        println!(
+        eprintln!(
             "Export Errors (also in {}):",
             target_dir.join("export_errors.log").display()
         );
         for e in &errors {
-            println!("  - {}", e);
+            eprintln!("  - {}", e);
         }
     }
 
@@ -307,13 +371,8 @@ pub fn run_export(
 fn run_grouped_export(
     groups: Vec<ExportWorkGroup>,
     total: usize,
-    reader: Option<Arc<GgpkReader>>,
-    bundle_index: Arc<BundleIndex>,
-    settings: ExportSettings,
-    target_dir: PathBuf,
-    cdn_loader: Option<crate::bundles::cdn::CdnBundleLoader>,
-    steam_loader: Option<crate::bundles::steam::SteamBundleLoader>,
-    schema: Option<Schema>,
+    vfs: Arc<Vfs>,
+    context: Arc<ExportContext>,
     tx: Sender<ExportStatus>,
     cancel_flag: Option<Arc<AtomicBool>>,
 ) {
@@ -324,11 +383,6 @@ fn run_grouped_export(
     let error_count = Arc::new(AtomicUsize::new(0));
     let errors = Arc::new(Mutex::new(Vec::new()));
     let error_log = Arc::new(Mutex::new(None));
-    let target_dir = Arc::new(target_dir);
-    let settings = Arc::new(settings);
-    let schema = Arc::new(schema);
-    let cdn_loader = Arc::new(cdn_loader);
-    let steam_loader = Arc::new(steam_loader);
 
     let mut workers = Vec::with_capacity(worker_count);
     for _ in 0..worker_count {
@@ -338,19 +392,14 @@ fn run_grouped_export(
         let error_count = Arc::clone(&error_count);
         let errors = Arc::clone(&errors);
         let error_log = Arc::clone(&error_log);
-        let target_dir = Arc::clone(&target_dir);
-        let settings = Arc::clone(&settings);
-        let schema = Arc::clone(&schema);
-        let cdn_loader = Arc::clone(&cdn_loader);
-        let steam_loader = Arc::clone(&steam_loader);
-        let reader = reader.clone();
-        let bundle_index = Arc::clone(&bundle_index);
+        let context = Arc::clone(&context);
+        let vfs = Arc::clone(&vfs);
         let tx = tx.clone();
         let cancel_flag = cancel_flag.clone();
 
         workers.push(std::thread::spawn(move || {
-            let mut bundle_cache = BundleCache::new();
-            let mut directory_cache = DirectoryCache::new();
+            let mut caches = ExportCaches::new();
+            let target_dir = &context.target_dir;
             let progress_limiter = ProgressLimiter::new(64);
 
             loop {
@@ -373,18 +422,7 @@ fn run_grouped_export(
 
                 for hash in group.hashes {
                     let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-                        export_single_file(
-                            hash,
-                            reader.as_deref(),
-                            Some(bundle_index.as_ref()),
-                            &settings,
-                            &target_dir,
-                            cdn_loader.as_ref(),
-                            steam_loader.as_ref(),
-                            schema.as_ref(),
-                            &mut bundle_cache,
-                            &mut directory_cache,
-                        )
+                        export_single_file(hash, None, Some(vfs.as_ref()), &context, &mut caches)
                         .map_err(|e| format!("Export failed: {}", e))
                     }));
 
@@ -406,7 +444,7 @@ fn run_grouped_export(
                                 list.push(e.clone());
                             }
                             if let Ok(mut log) = error_log.lock() {
-                                append_error_log(&mut log, &target_dir, &e);
+                                append_error_log(&mut log, target_dir, &e);
                             }
                             let _ = tx.send(ExportStatus::Progress {
                                 current,
@@ -427,7 +465,7 @@ fn run_grouped_export(
                                 list.push(msg.clone());
                             }
                             if let Ok(mut log) = error_log.lock() {
-                                append_error_log(&mut log, &target_dir, &msg);
+                                append_error_log(&mut log, target_dir, &msg);
                             }
                             let _ = tx.send(ExportStatus::Progress {
                                 current,
@@ -447,7 +485,7 @@ fn run_grouped_export(
 
     let success_count = success_count.load(Ordering::Relaxed);
     let error_count = error_count.load(Ordering::Relaxed);
-    let final_msg = if error_count == 0 {
+    let mut final_msg = if error_count == 0 {
         format!("Successfully exported {} files.", success_count)
     } else {
         format!(
@@ -455,6 +493,12 @@ fn run_grouped_export(
             success_count, error_count
         )
     };
+    // The workers are joined, so this is the last reference.
+    let context = Arc::try_unwrap(context).ok();
+    let target_dir = context.as_ref().map(|c| c.target_dir.clone()).unwrap_or_default();
+    if let Some(sqlite) = context.and_then(|c| c.sqlite) {
+        final_msg.push_str(&finish_sqlite(sqlite, &target_dir));
+    }
 
     if error_count > 0 {
         if let Ok(mut log) = error_log.lock() {
@@ -465,12 +509,12 @@ fn run_grouped_export(
             );
         }
         if let Ok(errors) = errors.lock() {
-            println!(
+            eprintln!(
                 "Export Errors (also in {}):",
                 target_dir.join("export_errors.log").display()
             );
             for e in errors.iter() {
-                println!("  - {}", e);
+                eprintln!("  - {}", e);
             }
         }
     }
@@ -516,15 +560,12 @@ mod tests {
 
         let (tx, rx) = std::sync::mpsc::channel();
         let t = std::time::Instant::now();
+        let vfs = Arc::new(Vfs::from_parts(index, Some(reader), None, None));
         run_export(
             hashes,
-            Some(reader),
-            Some(index),
-            ExportSettings::default(),
-            target.clone(),
-            None,
-            None,
             None,
+            Some(vfs),
+            ExportContext::new(ExportSettings::default(), target.clone(), None),
             tx,
             None,
         );
@@ -553,10 +594,7 @@ mod tests {
 
     #[test]
     fn groups_bundle_exports_by_bundle_index() {
-        let mut index = BundleIndex {
-            bundles: Vec::new(),
-            files: std::collections::HashMap::new(),
-        };
+        let mut index = BundleIndex::default();
         index.files.insert(
             10,
             crate::bundles::index::FileInfo {
@@ -633,137 +671,49 @@ fn append_error_log(log: &mut Option<std::fs::File>, target_dir: &Path, msg: &st
     }
 }
 
-fn export_single_file(
+fn export_single_file<'v>(
     hash: u64,
     reader: Option<&GgpkReader>,
-    bundle_index: Option<&BundleIndex>,
-    settings: &ExportSettings,
-    target_dir: &Path,
-    cdn_loader: &Option<crate::bundles::cdn::CdnBundleLoader>,
-    steam_loader: &Option<crate::bundles::steam::SteamBundleLoader>,
-    schema: &Option<Schema>,
-    bundle_cache: &mut BundleCache,
-    directory_cache: &mut DirectoryCache,
+    vfs: Option<&'v Vfs>,
+    context: &ExportContext,
+    caches: &mut ExportCaches<'v>,
 ) -> Result<String, String> {
-    if let Some(idx) = bundle_index {
+    if let Some(vfs) = vfs {
+        let idx = vfs.index();
         let file_info = idx
             .files
             .get(&hash)
             .ok_or("File hash not found in bundle index")?;
         let path = file_info.path.clone();
+        let sources = vfs.sources();
 
-        if file_info.bundle_index == crate::bundles::index::GGPK_LOOSE_FILE_SENTINEL {
-            let r = reader.ok_or("GGPK reader required for loose GGPK file export")?;
-            let rec = r
-                .read_file_by_path(&path)
-                .map_err(|e| format!("Failed to look up loose GGPK file {}: {}", path, e))?
-                .ok_or_else(|| format!("Loose GGPK file not found: {}", path))?;
-            let bytes = r
-                .get_data_slice(rec.data_offset, rec.data_length)
-                .map_err(|e| format!("Failed to read loose GGPK file data: {}", e))?;
-            export_file_data(&path, bytes, settings, target_dir, schema, directory_cache)?;
-            Ok(path)
-        } else if file_info.bundle_index == crate::bundles::steam::LOOSE_FILE_SENTINEL {
-            if let Some(steam) = steam_loader {
-                if let Some(loose_path) = steam.loose_file_path(&path) {
-                    let bytes = std::fs::read(&loose_path).map_err(|e| {
-                        format!("Failed to read loose file {}: {}", loose_path.display(), e)
-                    })?;
-                    export_file_data(&path, &bytes, settings, target_dir, schema, directory_cache)?;
-                    Ok(path)
-                } else {
-                    Err(format!("Loose file not found on disk: {}", path))
-                }
-            } else {
-                Err("Steam loader unavailable for loose-file export".to_string())
-            }
-        } else {
-            let bundle_info = idx
-                .bundles
-                .get(file_info.bundle_index as usize)
-                .ok_or("Bundle info not found")?;
-
-            if bundle_cache.get(file_info.bundle_index).is_none() {
-                let mut raw_bundle_data = None;
-
-                if let Some(r) = reader {
-                    let candidates = vec![
-                        format!("Bundles2/{}", bundle_info.name),
-                        format!("Bundles2/{}.bundle.bin", bundle_info.name),
-                        bundle_info.name.clone(),
-                        format!("{}.bundle.bin", bundle_info.name),
-                    ];
-
-                    for cand in &candidates {
-                        if let Ok(Some(file_record)) = r.read_file_by_path(cand) {
-                            if let Ok(data) =
-                                r.get_data_slice(file_record.data_offset, file_record.data_length)
-                            {
-                                raw_bundle_data = Some(RawBundleData::Borrowed(data));
-                                break;
-                            }
-                        }
-                    }
-                }
-
-                if raw_bundle_data.is_none() {
-                    if let Some(steam) = steam_loader {
-                        if let Ok(data) = steam.fetch_bundle(&bundle_info.name) {
-                            raw_bundle_data = Some(RawBundleData::Owned(data));
-                        }
-                    }
-                }
-
-                if raw_bundle_data.is_none() {
-                    if let Some(cdn) = cdn_loader {
-                        let fetch_name = if bundle_info.name.ends_with(".bundle.bin") {
-                            bundle_info.name.clone()
-                        } else {
-                            format!("{}.bundle.bin", bundle_info.name)
-                        };
-                        if let Ok(data) = cdn.fetch_bundle(&fetch_name) {
-                            raw_bundle_data = Some(RawBundleData::Owned(data));
-                        }
-                    }
-                }
-
-                let raw_bundle_data =
-                    raw_bundle_data.ok_or("Failed to load bundle data (local, Steam, or CDN)")?;
-                let data = raw_bundle_data.as_slice();
-                let mut cursor = std::io::Cursor::new(data);
-                let bundle = crate::bundles::bundle::Bundle::read_header(&mut cursor)
-                    .map_err(|e| format!("Bundle Header: {}", e))?;
-                let decompressed_data = bundle
-                    .decompress_from_slice(data)
-                    .map_err(|e| format!("Decompress: {}", e))?;
-                bundle_cache.insert(file_info.bundle_index, decompressed_data);
-            }
+        if let Some(loose) = sources.read_loose(file_info) {
+            let bytes = loose.map_err(|e| format!("Failed to read loose file {}: {}", path, e))?;
+            export_file_data(&path, &bytes, context, &mut caches.directories)?;
+            return Ok(path);
+        }
 
-            let decompressed_data = bundle_cache
-                .get(file_info.bundle_index)
-                .ok_or("Bundle cache miss")?;
-
-            let start = file_info.file_offset as usize;
-            let end = start + file_info.file_size as usize;
-            if end > decompressed_data.len() {
-                return Err(format!(
-                    "File range {}..{} out of bundle bounds {}",
-                    start,
-                    end,
-                    decompressed_data.len()
-                ));
+        let bundle_info = idx
+            .bundles
+            .get(file_info.bundle_index as usize)
+            .ok_or("Bundle info not found")?;
+
+        let bundle = match caches.bundles.get(file_info.bundle_index) {
+            Some(bundle) => bundle,
+            None => {
+                let raw = sources
+                    .fetch_raw_bundle(&bundle_info.name)
+                    .and_then(BlockCache::new)
+                    .map_err(|e| format!("Bundle {}: {}", bundle_info.name, e))?;
+                caches.bundles.insert(file_info.bundle_index, raw)
             }
+        };
+        let bytes = bundle
+            .read(file_info.file_offset as u64, file_info.file_size as u64)
+            .map_err(|e| format!("Bundle {}: {}", bundle_info.name, e))?;
 
-            export_file_data(
-                &path,
-                &decompressed_data[start..end],
-                settings,
-                target_dir,
-                schema,
-                directory_cache,
-            )?;
-            Ok(path)
-        }
+        export_file_data(&path, &bytes, context, &mut caches.directories)?;
+        Ok(path)
     } else {
         let r = reader.ok_or("GGPK reader is required for raw export")?;
         let file = r
@@ -772,14 +722,7 @@ fn export_single_file(
         let bytes = r
             .get_data_slice(file.data_offset, file.data_length)
             .map_err(|e| format!("Failed to read GGPK file data: {}", e))?;
-        export_file_data(
-            &file.name,
-            bytes,
-            settings,
-            target_dir,
-            schema,
-            directory_cache,
-        )?;
+        export_file_data(&file.name, bytes, context, &mut caches.directories)?;
         Ok(file.name)
     }
 }
@@ -787,11 +730,10 @@ fn export_single_file(
 fn export_file_data(
     path_str: &str,
     file_data: &[u8],
-    settings: &ExportSettings,
-    target_dir: &Path,
-    schema: &Option<Schema>,
+    context: &ExportContext,
     directory_cache: &mut DirectoryCache,
 ) -> Result<(), String> {
+    let ExportContext { settings, target_dir, schema, sqlite } = context;
     let relative_path = std::path::Path::new(&path_str);
     let full_path = target_dir.join(relative_path);
 
@@ -841,20 +783,22 @@ fn export_file_data(
     } else if path_lower.ends_with(".ogg") {
         match settings.audio_format {
             AudioFormat::Wav => {
-                let cursor = std::io::Cursor::new(file_data.to_vec());
-                if let Ok(source) = rodio::Decoder::new(cursor) {
-                    use rodio::Source;
+                let cursor = std::io::Cursor::new(file_data);
+                if let Ok(mut ogg) = lewton::inside_ogg::OggStreamReader::new(cursor) {
                     let spec = hound::WavSpec {
-                        channels: source.channels(),
-                        sample_rate: source.sample_rate(),
+                        channels: ogg.ident_hdr.audio_channels as u16,
+                        sample_rate: ogg.ident_hdr.audio_sample_rate,
                         bits_per_sample: 16,
                         sample_format: hound::SampleFormat::Int,
                     };
                     let dest = full_path.with_extension("wav");
                     let mut writer =
                         hound::WavWriter::create(dest, spec).map_err(|e| e.to_string())?;
-                    for sample in source {
-                        let _ = writer.write_sample(sample);
+                    // Interleaved packets, the same layout rodio produced.
+                    while let Ok(Some(packet)) = ogg.read_dec_packet_itl() {
+                        for sample in packet {
+                            let _ = writer.write_sample(sample);
+                        }
                     }
                     writer.finalize().map_err(|e| e.to_string())?;
                 } else {
@@ -865,6 +809,36 @@ fn export_file_data(
                 std::fs::write(&full_path, file_data).map_err(|e| e.to_string())?;
             }
         }
+    } else if path_lower.ends_with(".bank") {
+        match settings.audio_format {
+            AudioFormat::Wav => {
+                // A bank holds many streams; decode each into a folder named
+                // after the bank, like the viewer's "Export all streams".
+                let mut converted = false;
+                if let Ok(info) = crate::parsers::fmod_bank::parse_bank_info(file_data) {
+                    if !info.streams.is_empty() {
+                        let bank_dir = full_path.with_extension("");
+                        std::fs::create_dir_all(&bank_dir).map_err(|e| e.to_string())?;
+                        for (i, stream) in info.streams.iter().enumerate() {
+                            let wav = crate::parsers::fmod_bank::decode_stream(file_data, i).map_err(|e| e.to_string())?;
+                            let dest = bank_dir.join(format!(
+                                "{}.{}",
+                                sanitize_filename(&stream.name),
+                                info.extension
+                            ));
+                            std::fs::write(dest, wav).map_err(|e| e.to_string())?;
+                        }
+                        converted = true;
+                    }
+                }
+                if !converted {
+                    std::fs::write(&full_path, file_data).map_err(|e| e.to_string())?;
+                }
+            }
+            AudioFormat::Original => {
+                std::fs::write(&full_path, file_data).map_err(|e| e.to_string())?;
+            }
+        }
     } else if path_lower.ends_with(".dat")
         || path_lower.ends_with(".dat64")
         || path_lower.ends_with(".datc64")
@@ -874,51 +848,53 @@ fn export_file_data(
         match settings.data_format {
             DataFormat::Json => {
                 let mut converted = false;
-                if let Some(schema) = schema {
-                    let stem = std::path::Path::new(&path_str)
-                        .file_stem()
-                        .and_then(|s| s.to_str())
-                        .unwrap_or("");
-                    if let Some(table_def) = schema
-                        .tables
-                        .iter()
-                        .find(|t| t.name.eq_ignore_ascii_case(stem))
-                    {
-                        if let Ok(r) =
-                            crate::dat::reader::DatReader::new(file_data.to_vec(), path_str)
-                        {
-                            use serde_json::{Map, Value};
-
-                            let mut rows = Vec::new();
-                            for i in 0..r.row_count {
-                                if let Ok(vals) = r.read_row(i, table_def) {
-                                    let mut map = Map::new();
-                                    for (j, val) in vals.iter().enumerate() {
-                                        if let Some(col) = table_def.columns.get(j) {
-                                            let col_name = col
-                                                .name
-                                                .clone()
-                                                .unwrap_or_else(|| format!("Col{}", j));
-                                            let v = r.value_to_json(val, col);
-                                            map.insert(col_name, v);
-                                        }
-                                    }
-                                    rows.push(Value::Object(map));
-                                }
-                            }
-                            let json_out = Value::Array(rows);
-                            let dest = full_path.with_extension("json");
-                            let s = serde_json::to_string_pretty(&json_out)
-                                .map_err(|e| e.to_string())?;
-                            std::fs::write(dest, s).map_err(|e| e.to_string())?;
-                            converted = true;
-                        }
-                    }
+                if let Some(json_out) = schema
+                    .as_ref()
+                    .and_then(|schema| dat_to_json(path_str, file_data, schema))
+                {
+                    let dest = full_path.with_extension("json");
+                    let s = serde_json::to_string_pretty(&json_out).map_err(|e| e.to_string())?;
+                    std::fs::write(dest, s).map_err(|e| e.to_string())?;
+                    converted = true;
                 }
                 if !converted {
                     std::fs::write(&full_path, file_data).map_err(|e| e.to_string())?;
                 }
             }
+            DataFormat::Csv | DataFormat::Arrow => {
+                let (extension, write): (&str, TableWriter) = match settings.data_format {
+                    DataFormat::Csv => ("csv", crate::dat::csv::write_csv),
+                    _ => ("arrow", crate::dat::arrow::write_arrow),
+                };
+                match schema.as_ref().and_then(|schema| open_dat(path_str, file_data, schema)) {
+                    Some((reader, table)) => {
+                        let file = std::fs::File::create(full_path.with_extension(extension)).map_err(|e| e.to_string())?;
+                        write(&reader, table, &mut BufWriter::new(file)).map_err(|e| format!("{}: {}", path_str, e))?;
+                    }
+                    None => {
+                        std::fs::write(&full_path, file_data).map_err(|e| e.to_string())?;
+                    }
+                }
+            }
+            DataFormat::Sqlite => {
+                let added = sqlite.as_ref().map(|db| {
+                    db.lock()
+                        .unwrap_or_else(std::sync::PoisonError::into_inner)
+                        .add_table(path_str, file_data.to_vec())
+                });
+                match added {
+                    Some(Ok(_)) => {}
+                    // Tables the schema doesn't describe are kept as-is,
+                    // like the JSON export does.
+                    Some(Err(e)) if e.is_not_found() => {
+                        std::fs::write(&full_path, file_data).map_err(|e| e.to_string())?;
+                    }
+                    Some(Err(e)) => return Err(format!("{}: {}", path_str, e)),
+                    None => {
+                        std::fs::write(&full_path, file_data).map_err(|e| e.to_string())?;
+                    }
+                }
+            }
             DataFormat::Original => {
                 std::fs::write(&full_path, file_data).map_err(|e| e.to_string())?;
             }
@@ -956,3 +932,58 @@ fn export_file_data(
 
     Ok(())
 }
+
+/// Completes the SQLite database and describes the outcome for the final
+/// status message.
+fn finish_sqlite(sqlite: Mutex<SqliteExport>, target_dir: &Path) -> String {
+    let export = sqlite.into_inner().unwrap_or_else(std::sync::PoisonError::into_inner);
+    match export.finish() {
+        Ok(stats) => format!(
+            " Wrote {} tables ({} rows) to {}.",
+            stats.tables,
+            stats.rows,
+            target_dir.join(SQLITE_FILE_NAME).display()
+        ),
+        Err(e) => format!(" Failed to write {}: {}.", SQLITE_FILE_NAME, e),
+    }
+}
+
+/// The schema table named after `path`'s file stem, and a reader over `data`.
+fn open_dat<'a>(path: &str, data: &[u8], schema: &'a Schema) -> Option<(DatReader, &'a Table)> {
+    let stem = Path::new(path).file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let table = schema.tables.iter().find(|t| t.name.eq_ignore_ascii_case(stem))?;
+    let reader = DatReader::new(data.to_vec(), path).ok()?;
+    Some((reader, table))
+}
+
+/// Converts a dat table to a JSON array of row objects keyed by column name.
+/// Returns `None` if the schema has no table for this file or it fails to parse.
+pub fn dat_to_json(path: &str, data: &[u8], schema: &Schema) -> Option<serde_json::Value> {
+    use serde_json::{Map, Value};
+
+    let (r, table_def) = open_dat(path, data, schema)?;
+    let mut rows = Vec::new();
+    for i in 0..r.row_count {
+        if let Ok(vals) = r.read_row(i, table_def) {
+            let mut map = Map::new();
+            for (j, val) in vals.iter().enumerate() {
+                if let Some(col) = table_def.columns.get(j) {
+                    let col_name = col.name.clone().unwrap_or_else(|| format!("Col{}", j));
+                    map.insert(col_name, r.value_to_json(val, col));
+                }
+            }
+            rows.push(Value::Object(map));
+        }
+    }
+    Some(Value::Array(rows))
+}
+
+/// Replaces characters that are not allowed in file names on Windows.
+pub fn sanitize_filename(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect()
+}
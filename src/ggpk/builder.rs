@@ -0,0 +1,212 @@
+//! Writes a new GGPK from scratch.
+//!
+//! Records are laid out children first, so every directory can be written
+//! once with its final entry offsets and digest; the root directory comes
+//! last and the GGPK record at offset 0 is patched to point at it. The
+//! result has no FREE records.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use super::record::{name_hash, DirectoryEntry, DirectoryRecord, FileRecord, GgpkRecord};
+use crate::error::{Error, Result};
+
+enum Source {
+    Bytes(Vec<u8>),
+    Disk(PathBuf),
+}
+
+#[derive(Default)]
+struct Dir {
+    // Keyed by lowercased name: GGPK lookups are case-insensitive, so
+    // `Foo` and `foo` are the same entry. The original spelling is kept.
+    dirs: BTreeMap<String, (String, Dir)>,
+    files: BTreeMap<String, (String, Source)>,
+}
+
+#[derive(Debug, Default)]
+pub struct BuildStats {
+    pub files: u64,
+    pub directories: u64,
+    pub bytes: u64,
+}
+
+pub struct GgpkBuilder {
+    version: u32,
+    root: Dir,
+}
+
+impl Default for GgpkBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GgpkBuilder {
+    /// A PC (UTF-16 names, version 3) GGPK.
+    pub fn new() -> Self {
+        Self { version: 3, root: Dir::default() }
+    }
+
+    /// Version 4 stores names as UTF-32 (the macOS client).
+    pub fn version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Adds (or replaces) the file at `path`, e.g. `Data/Mods.datc64`.
+    pub fn add_file(&mut self, path: &str, data: impl Into<Vec<u8>>) -> Result<&mut Self> {
+        self.insert(path, Source::Bytes(data.into()))?;
+        Ok(self)
+    }
+
+    /// Adds every file below `dir`, keeping paths relative to it. Contents
+    /// are read when the GGPK is written.
+    pub fn add_dir_tree(&mut self, dir: &Path) -> Result<&mut Self> {
+        for entry in walkdir::WalkDir::new(dir).sort_by_file_name() {
+            let entry = entry.map_err(|e| Error::Io(e.into()))?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let rel = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+            let rel: Vec<String> = rel.components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect();
+            self.insert(&rel.join("/"), Source::Disk(entry.path().to_path_buf()))?;
+        }
+        Ok(self)
+    }
+
+    fn insert(&mut self, path: &str, source: Source) -> Result<()> {
+        let parts: Vec<&str> = path.split(['/', '\\']).filter(|s| !s.is_empty()).collect();
+        let Some((name, dirs)) = parts.split_last() else {
+            return Err(Error::InvalidData(format!("empty file path '{}'", path)));
+        };
+        let mut dir = &mut self.root;
+        for part in dirs {
+            let key = part.to_lowercase();
+            if dir.files.contains_key(&key) {
+                return Err(Error::InvalidData(format!("{} is both a file and a directory", part)));
+            }
+            dir = &mut dir.dirs.entry(key).or_insert_with(|| (part.to_string(), Dir::default())).1;
+        }
+        let key = name.to_lowercase();
+        if dir.dirs.contains_key(&key) {
+            return Err(Error::InvalidData(format!("{} is both a file and a directory", path)));
+        }
+        dir.files.insert(key, (name.to_string(), source));
+        Ok(())
+    }
+
+    pub fn write(&self, dest: &Path) -> Result<BuildStats> {
+        let mut out = BufWriter::new(File::create(dest)?);
+        out.write_all(&GgpkRecord::encode(self.version, 0, 0))?;
+        let mut pos = GgpkRecord::SIZE as u64;
+        let mut stats = BuildStats::default();
+
+        let (root_offset, _) = self.write_dir("", &self.root, &mut out, &mut pos, &mut stats)?;
+
+        out.seek(SeekFrom::Start(0))?;
+        out.write_all(&GgpkRecord::encode(self.version, root_offset, 0))?;
+        out.flush()?;
+        Ok(stats)
+    }
+
+    /// Returns the directory's offset and digest.
+    fn write_dir(
+        &self,
+        name: &str,
+        dir: &Dir,
+        out: &mut impl Write,
+        pos: &mut u64,
+        stats: &mut BuildStats,
+    ) -> Result<(u64, [u8; 32])> {
+        let mut children = Vec::with_capacity(dir.dirs.len() + dir.files.len());
+        for (sub_name, sub) in dir.dirs.values() {
+            let (offset, hash) = self.write_dir(sub_name, sub, out, pos, stats)?;
+            children.push((name_hash(sub_name, self.version), offset, hash));
+        }
+        for (file_name, source) in dir.files.values() {
+            let data = match source {
+                Source::Bytes(b) => std::borrow::Cow::Borrowed(b.as_slice()),
+                Source::Disk(p) => std::borrow::Cow::Owned(std::fs::read(p)?),
+            };
+            let hash: [u8; 32] = Sha256::digest(&data).into();
+            let header = FileRecord::encode_header(file_name, &hash, data.len() as u64, self.version);
+            let offset = *pos;
+            out.write_all(&header)?;
+            out.write_all(&data)?;
+            *pos += (header.len() + data.len()) as u64;
+            stats.files += 1;
+            stats.bytes += data.len() as u64;
+            children.push((name_hash(file_name, self.version), offset, hash));
+        }
+
+        // Entries are kept sorted by name hash, as the game's patcher does.
+        children.sort_by_key(|&(hash, ..)| hash);
+        let mut hasher = Sha256::new();
+        let entries: Vec<DirectoryEntry> = children
+            .iter()
+            .map(|&(name_hash, offset, hash)| {
+                hasher.update(hash);
+                DirectoryEntry { name_hash, offset }
+            })
+            .collect();
+        let hash: [u8; 32] = hasher.finalize().into();
+
+        let record = DirectoryRecord::encode(name, &hash, &entries, self.version);
+        let offset = *pos;
+        out.write_all(&record)?;
+        *pos += record.len() as u64;
+        stats.directories += 1;
+        Ok((offset, hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ggpk::reader::GgpkReader;
+
+    fn read(reader: &GgpkReader, path: &str) -> Vec<u8> {
+        let rec = reader.read_file_by_path(path).unwrap().unwrap();
+        reader.get_data_slice(rec.data_offset, rec.data_length).unwrap().to_vec()
+    }
+
+    #[test]
+    fn builds_readable_ggpk_from_directory() {
+        let tmp = std::env::temp_dir().join(format!("ggpk_builder_test_{}", std::process::id()));
+        let src = tmp.join("src");
+        std::fs::create_dir_all(src.join("Data/Balance")).unwrap();
+        std::fs::create_dir_all(src.join("Art")).unwrap();
+        std::fs::write(src.join("Data/Balance/Mods.datc64"), b"mods").unwrap();
+        std::fs::write(src.join("Data/Stats.datc64"), b"stats").unwrap();
+        std::fs::write(src.join("Art/empty.dds"), b"").unwrap();
+
+        for version in [3, 4] {
+            let dest = tmp.join(format!("v{}.ggpk", version));
+            let mut builder = GgpkBuilder::new().version(version);
+            builder.add_dir_tree(&src).unwrap().add_file("README.txt", "hi").unwrap();
+            let stats = builder.write(&dest).unwrap();
+            assert_eq!((stats.files, stats.directories, stats.bytes), (4, 4, 11));
+
+            let reader = GgpkReader::open(&dest).unwrap();
+            assert_eq!(reader.version, version);
+            assert_eq!(read(&reader, "data/balance/mods.datc64"), b"mods");
+            assert_eq!(read(&reader, "Data/Stats.datc64"), b"stats");
+            assert_eq!(read(&reader, "Art/empty.dds"), b"");
+            assert_eq!(read(&reader, "README.txt"), b"hi");
+            assert_eq!(reader.free_offset, 0);
+            assert!(crate::ggpk::verify::verify(&reader, |_| {}).is_ok());
+
+            let root = reader.read_directory(reader.root_offset).unwrap();
+            let hashes: Vec<u32> = root.entries.iter().map(|e| e.name_hash).collect();
+            assert!(hashes.windows(2).all(|w| w[0] <= w[1]));
+            assert!(root.entries.iter().any(|e| e.name_hash == name_hash("data", version)));
+        }
+
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+}
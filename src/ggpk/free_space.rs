@@ -0,0 +1,270 @@
+//! FREE-record accounting and compaction.
+//!
+//! Patching never shrinks a GGPK: replaced records become FREE records
+//! linked from `GgpkRecord::free_offset` and are only reused when a new
+//! record happens to fit. [`report`] measures that space; [`compact`]
+//! writes a copy containing only the records reachable from the root.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::reader::GgpkReader;
+use super::record::{DirectoryEntry, DirectoryRecord, FreeRecord, GgpkRecord, RecordTag};
+use crate::error::{Error, Result};
+
+/// Free bytes within one fixed-size slice of the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    pub start: u64,
+    pub len: u64,
+    pub free_bytes: u64,
+}
+
+#[derive(Debug)]
+pub struct FreeSpaceReport {
+    pub file_size: u64,
+    /// In free-list order.
+    pub free_records: Vec<FreeRecord>,
+    pub total_free: u64,
+    pub largest_free: u64,
+    pub regions: Vec<Region>,
+}
+
+impl FreeSpaceReport {
+    pub fn free_ratio(&self) -> f64 {
+        if self.file_size == 0 {
+            0.0
+        } else {
+            self.total_free as f64 / self.file_size as f64
+        }
+    }
+}
+
+/// Follows the free list from the GGPK record.
+pub fn free_records(reader: &GgpkReader) -> Result<Vec<FreeRecord>> {
+    let mut out = Vec::new();
+    let mut seen = HashSet::new();
+    let mut offset = reader.free_offset;
+    while offset != 0 {
+        if !seen.insert(offset) {
+            return Err(Error::InvalidData(format!("free list loops back to {}", offset)));
+        }
+        let header = reader.read_record_header(offset)?;
+        if header.tag != RecordTag::FREE {
+            return Err(Error::BadSignature {
+                offset,
+                expected: "FREE",
+                found: format!("{:?}", header.tag),
+            });
+        }
+        let data = reader.get_data_slice(offset, FreeRecord::MIN_SIZE as u64)?;
+        let free = FreeRecord::read(data, offset)?;
+        offset = free.next;
+        out.push(free);
+    }
+    Ok(out)
+}
+
+/// Walks the free list and buckets the free bytes into `region_size` slices
+/// of the file.
+pub fn report(reader: &GgpkReader, region_size: u64) -> Result<FreeSpaceReport> {
+    let region_size = region_size.max(1);
+    let file_size = reader.file_size();
+    let free_records = free_records(reader)?;
+
+    let mut regions: Vec<Region> = (0..file_size.div_ceil(region_size))
+        .map(|i| {
+            let start = i * region_size;
+            Region { start, len: region_size.min(file_size - start), free_bytes: 0 }
+        })
+        .collect();
+    for free in &free_records {
+        // A record can straddle region boundaries.
+        let mut start = free.offset;
+        let end = (free.offset + free.length as u64).min(file_size);
+        while start < end {
+            let region = &mut regions[(start / region_size) as usize];
+            let chunk_end = end.min(region.start + region.len);
+            region.free_bytes += chunk_end - start;
+            start = chunk_end;
+        }
+    }
+
+    Ok(FreeSpaceReport {
+        file_size,
+        total_free: free_records.iter().map(|f| f.length as u64).sum(),
+        largest_free: free_records.iter().map(|f| f.length as u64).max().unwrap_or(0),
+        free_records,
+        regions,
+    })
+}
+
+#[derive(Debug, Default)]
+pub struct CompactStats {
+    pub files: u64,
+    pub directories: u64,
+    pub old_size: u64,
+    pub new_size: u64,
+}
+
+/// Writes a copy of the GGPK to `dest` that holds only the records
+/// reachable from the root, with no FREE records. Record contents and
+/// digests are unchanged; only directory entry offsets are rewritten.
+///
+/// The copy is written next to `dest` and renamed over it once complete,
+/// so a failed run leaves `dest` as it was. `dest` may not be the GGPK
+/// `reader` has mapped.
+pub fn compact(reader: &GgpkReader, dest: &Path) -> Result<CompactStats> {
+    if let (Ok(source), Ok(target)) = (reader.path().canonicalize(), dest.canonicalize()) {
+        if source == target {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is the GGPK being compacted", dest.display()),
+            )
+            .into());
+        }
+    }
+    let name = dest.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no file name"))?;
+    let mut tmp_name = name.to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = dest.with_file_name(tmp_name);
+
+    match write_compacted(reader, &tmp) {
+        Ok(stats) => {
+            std::fs::rename(&tmp, dest)?;
+            Ok(stats)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+fn write_compacted(reader: &GgpkReader, dest: &Path) -> Result<CompactStats> {
+    let mut out = BufWriter::new(File::create(dest)?);
+    out.write_all(&GgpkRecord::encode(reader.version, 0, 0))?;
+    let mut stats = CompactStats { old_size: reader.file_size(), ..Default::default() };
+    let mut pos = GgpkRecord::SIZE as u64;
+
+    let root = reader.read_directory(reader.root_offset)?;
+    let root_offset = copy_dir(reader, &root, &mut out, &mut pos, &mut stats)?;
+
+    out.seek(SeekFrom::Start(0))?;
+    out.write_all(&GgpkRecord::encode(reader.version, root_offset, 0))?;
+    out.flush()?;
+    stats.new_size = pos;
+    Ok(stats)
+}
+
+/// Copies `dir`'s children, then `dir` itself (it needs their new offsets).
+/// Returns the offset `dir` was written at.
+fn copy_dir(
+    reader: &GgpkReader,
+    dir: &DirectoryRecord,
+    out: &mut impl Write,
+    pos: &mut u64,
+    stats: &mut CompactStats,
+) -> Result<u64> {
+    let mut entries = Vec::with_capacity(dir.entries.len());
+    for entry in &dir.entries {
+        let header = reader.read_record_header(entry.offset)?;
+        let offset = match header.tag {
+            RecordTag::PDIR => {
+                let sub = reader.read_directory(entry.offset)?;
+                copy_dir(reader, &sub, out, pos, stats)?
+            }
+            RecordTag::FILE => {
+                let record = reader.get_data_slice(entry.offset, header.length as u64)?;
+                out.write_all(record)?;
+                stats.files += 1;
+                let offset = *pos;
+                *pos += record.len() as u64;
+                offset
+            }
+            other => {
+                return Err(Error::BadSignature {
+                    offset: entry.offset,
+                    expected: "PDIR or FILE",
+                    found: format!("{:?}", other),
+                })
+            }
+        };
+        entries.push(DirectoryEntry { name_hash: entry.name_hash, offset });
+    }
+
+    let record = DirectoryRecord::encode(&dir.name, &dir.hash, &entries, reader.version);
+    out.write_all(&record)?;
+    stats.directories += 1;
+    let offset = *pos;
+    *pos += record.len() as u64;
+    Ok(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ggpk::testutil::write_fixture;
+    use crate::ggpk::writer::GgpkWriter;
+
+    fn read(reader: &GgpkReader, path: &str) -> Vec<u8> {
+        let rec = reader.read_file_by_path(path).unwrap().unwrap();
+        reader.get_data_slice(rec.data_offset, rec.data_length).unwrap().to_vec()
+    }
+
+    #[test]
+    fn report_counts_freed_records_and_compact_drops_them() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("ggpk_free_space_test_{}.ggpk", std::process::id()));
+        let compacted = dir.join(format!("ggpk_free_space_test_{}.compact.ggpk", std::process::id()));
+        write_fixture(&path, b"alpha", b"beta");
+
+        let mut writer = GgpkWriter::open(&path).unwrap();
+        writer.replace_file("Data/a.txt", b"alpha, but longer than it was").unwrap();
+        writer.replace_file("Data/b.txt", b"beta, also longer than before").unwrap();
+        drop(writer);
+
+        let reader = GgpkReader::open(&path).unwrap();
+        let report = report(&reader, 64).unwrap();
+        assert_eq!(report.free_records.len(), 2);
+        let a_old = report.free_records[1];
+        assert_eq!(a_old.offset, GgpkRecord::SIZE as u64);
+        assert_eq!(report.total_free, report.free_records.iter().map(|f| f.length as u64).sum::<u64>());
+        assert_eq!(report.largest_free, report.free_records.iter().map(|f| f.length as u64).max().unwrap());
+        assert_eq!(report.regions.iter().map(|r| r.free_bytes).sum::<u64>(), report.total_free);
+        assert_eq!(report.regions.iter().map(|r| r.len).sum::<u64>(), report.file_size);
+        assert_eq!(report.regions[0].free_bytes, 64 - GgpkRecord::SIZE as u64);
+
+        let stats = compact(&reader, &compacted).unwrap();
+        assert_eq!((stats.files, stats.directories), (2, 2));
+        assert_eq!(stats.new_size, report.file_size - report.total_free);
+
+        let copy = GgpkReader::open(&compacted).unwrap();
+        assert_eq!(copy.free_offset, 0);
+        assert_eq!(read(&copy, "Data/a.txt"), b"alpha, but longer than it was");
+        assert_eq!(read(&copy, "Data/b.txt"), b"beta, also longer than before");
+        assert!(crate::ggpk::verify::verify(&copy, |_| {}).is_ok());
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&compacted);
+    }
+
+    #[test]
+    fn compact_refuses_to_overwrite_its_source() {
+        let path = std::env::temp_dir().join(format!("ggpk_free_space_self_test_{}.ggpk", std::process::id()));
+        write_fixture(&path, b"alpha", b"beta");
+        let before = std::fs::read(&path).unwrap();
+
+        let reader = GgpkReader::open(&path).unwrap();
+        // Reached through a different spelling of the same path.
+        let same = path.parent().unwrap().join(".").join(path.file_name().unwrap());
+        assert!(compact(&reader, &same).is_err());
+        drop(reader);
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+        assert!(!path.with_extension("ggpk.tmp").exists());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
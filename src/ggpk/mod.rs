@@ -1,3 +1,10 @@
+pub mod builder;
+pub mod free_space;
 pub mod reader;
 pub mod record;
 pub mod tree;
+pub mod verify;
+pub mod writer;
+
+#[cfg(test)]
+pub(crate) mod testutil;
@@ -1,29 +1,37 @@
 use super::record::{GgpkRecord, RecordHeader, RecordTag, DirectoryRecord, FileRecord};
 use memmap2::Mmap;
+use crate::error::{Error, Result};
 use std::fs::File;
-use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub struct GgpkReader {
     mmap: Mmap,
+    path: PathBuf,
     pub root_offset: u64,
+    /// First FREE record, 0 if there is none.
+    pub free_offset: u64,
     pub version: u32,
 }
 
 impl GgpkReader {
-    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let file = File::open(path)?;
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::open(&path)?;
         let mmap = unsafe { Mmap::map(&file)? };
 
 
         if mmap.len() < 8 {
-             return Err(io::Error::new(io::ErrorKind::InvalidData, "File too small"));
+             return Err(Error::TruncatedRecord { what: "GGPK header", offset: 0 });
         }
         
 
         let header = RecordHeader::read(&mmap[0..8]);
         if header.tag != RecordTag::GGPK {
-             return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid GGPK signature"));
+             return Err(Error::BadSignature {
+                 offset: 0,
+                 expected: "GGPK",
+                 found: format!("{:?}", header.tag),
+             });
         }
 
         let ggpk_rec = GgpkRecord::read(&mmap, 0)?;
@@ -31,50 +39,62 @@ impl GgpkReader {
         
         Ok(Self {
             mmap,
+            path,
             root_offset: ggpk_rec.root_offset,
+            free_offset: ggpk_rec.free_offset,
             version: ggpk_rec.version,
         })
     }
 
-    pub fn read_record_header(&self, offset: u64) -> io::Result<RecordHeader> {
-        let offset = offset as usize;
-        if offset + 8 > self.mmap.len() {
-             return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Offset out of bounds"));
+    /// The path the GGPK was opened from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn read_record_header(&self, offset: u64) -> Result<RecordHeader> {
+        let start = offset as usize;
+        if start.saturating_add(8) > self.mmap.len() {
+             return Err(Error::TruncatedRecord { what: "record header", offset });
         }
-        Ok(RecordHeader::read(&self.mmap[offset..offset+8]))
+        Ok(RecordHeader::read(&self.mmap[start..start+8]))
     }
 
-    pub fn read_directory(&self, offset: u64) -> io::Result<DirectoryRecord> {
+    pub fn read_directory(&self, offset: u64) -> Result<DirectoryRecord> {
         let header = self.read_record_header(offset)?;
         if header.tag != RecordTag::PDIR {
-             return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Expected PDIR at {}, found {:?}", offset, header.tag)));
+             return Err(Error::BadSignature { offset, expected: "PDIR", found: format!("{:?}", header.tag) });
         }
         let data = self.get_slice(offset, header.length as u64)?;
         DirectoryRecord::read(data, offset, self.version)
     }
 
-    pub fn read_file_record(&self, offset: u64) -> io::Result<FileRecord> {
+    pub fn read_file_record(&self, offset: u64) -> Result<FileRecord> {
         let header = self.read_record_header(offset)?;
         if header.tag != RecordTag::FILE {
-             return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Expected FILE at {}, found {:?}", offset, header.tag)));
+             return Err(Error::BadSignature { offset, expected: "FILE", found: format!("{:?}", header.tag) });
         }
         let data = self.get_slice(offset, header.length as u64)?;
         FileRecord::read(data, offset, self.version)
     }
 
-    fn get_slice(&self, offset: u64, length: u64) -> io::Result<&[u8]> {
+    fn get_slice(&self, offset: u64, length: u64) -> Result<&[u8]> {
         let start = offset as usize;
-        let end = start + length as usize;
+        let end = start.saturating_add(length as usize);
         if end > self.mmap.len() {
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Record length out of bounds"));
+            return Err(Error::TruncatedRecord { what: "record data", offset });
         }
         Ok(&self.mmap[start..end])
     }
     
-    pub fn get_data_slice(&self, offset: u64, length: u64) -> io::Result<&[u8]> {
+    pub fn get_data_slice(&self, offset: u64, length: u64) -> Result<&[u8]> {
         self.get_slice(offset, length)
     }
 
+    /// Size of the GGPK file in bytes.
+    pub fn file_size(&self) -> u64 {
+        self.mmap.len() as u64
+    }
+
     #[allow(dead_code)]
     pub fn is_poe2_heuristic(&self) -> bool {
 
@@ -171,7 +191,7 @@ impl GgpkReader {
         }
     }
 
-    pub fn read_file_by_path(&self, path: &str) -> io::Result<Option<FileRecord>> {
+    pub fn read_file_by_path(&self, path: &str) -> Result<Option<FileRecord>> {
         let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
         if parts.is_empty() {
             return Ok(None);
@@ -219,7 +239,7 @@ impl GgpkReader {
         None
     }
 
-    pub fn list_files_in_directory(&self, path: &str) -> io::Result<Vec<String>> {
+    pub fn list_files_in_directory(&self, path: &str) -> Result<Vec<String>> {
          let parts: Vec<&str> = path.split('/').collect();
          let mut current_offset = self.root_offset;
          
@@ -240,7 +260,7 @@ impl GgpkReader {
              if let Some(offset) = found_offset {
                  current_offset = offset;
              } else {
-                 return Err(io::Error::new(io::ErrorKind::NotFound, format!("Directory {} not found", part)));
+                 return Err(Error::NotFound(format!("directory {}", part)));
              }
          }
          
@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 use byteorder::{ByteOrder, LittleEndian};
+use crate::error::{Error, Result};
 use std::io::{self, Cursor, Read};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -54,7 +55,24 @@ pub struct GgpkRecord {
 }
 
 impl GgpkRecord {
-    pub fn read(data: &[u8], _offset: u64) -> io::Result<Self> {
+    pub const SIZE: u32 = 28;
+    /// Byte offset of `free_offset` within the record.
+    pub const FREE_OFFSET_POS: u64 = 20;
+
+    pub fn encode(version: u32, root_offset: u64, free_offset: u64) -> Vec<u8> {
+        let mut out = Vec::with_capacity(Self::SIZE as usize);
+        write_header(&mut out, Self::SIZE, RecordTag::TAG_GGPK);
+        out.extend_from_slice(&version.to_le_bytes());
+        out.extend_from_slice(&root_offset.to_le_bytes());
+        out.extend_from_slice(&free_offset.to_le_bytes());
+        out
+    }
+
+    pub fn read(data: &[u8], offset: u64) -> Result<Self> {
+        Self::parse(data).map_err(Error::truncated("GGPK record", offset))
+    }
+
+    fn parse(data: &[u8]) -> io::Result<Self> {
         // data starts at the record offset
         // Structure: Length(4), Tag(4), Version(4), RootOffset(8), FreeOffset(8)
         let mut cursor = Cursor::new(data);
@@ -86,7 +104,36 @@ pub struct DirectoryEntry {
 }
 
 impl DirectoryRecord {
-    pub fn read(data: &[u8], offset: u64, version: u32) -> io::Result<Self> {
+    /// Byte offset of the SHA-256 digest within the record.
+    pub const HASH_POS: u64 = 16;
+
+    pub fn encode(name: &str, hash: &[u8; 32], entries: &[DirectoryEntry], version: u32) -> Vec<u8> {
+        let (name_len, name_bytes) = encode_name(name, version);
+        let length = 8 + 4 + 4 + 32 + name_bytes.len() + entries.len() * 12;
+        let mut out = Vec::with_capacity(length);
+        write_header(&mut out, length as u32, RecordTag::TAG_PDIR);
+        out.extend_from_slice(&name_len.to_le_bytes());
+        out.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        out.extend_from_slice(hash);
+        out.extend_from_slice(&name_bytes);
+        for entry in entries {
+            out.extend_from_slice(&entry.name_hash.to_le_bytes());
+            out.extend_from_slice(&entry.offset.to_le_bytes());
+        }
+        out
+    }
+
+    /// Offset of entry `index`'s `offset` field from the start of the record.
+    pub fn entry_offset_pos(&self, index: usize, version: u32) -> u64 {
+        let name_bytes = encode_name(&self.name, version).1.len() as u64;
+        8 + 4 + 4 + 32 + name_bytes + index as u64 * 12 + 4
+    }
+
+    pub fn read(data: &[u8], offset: u64, version: u32) -> Result<Self> {
+        Self::parse(data, offset, version).map_err(Error::truncated("PDIR record", offset))
+    }
+
+    fn parse(data: &[u8], offset: u64, version: u32) -> io::Result<Self> {
         let mut cursor = Cursor::new(data);
         cursor.set_position(8); // header
 
@@ -160,7 +207,26 @@ pub struct FileRecord {
 }
 
 impl FileRecord {
-    pub fn read(data: &[u8], offset: u64, version: u32) -> io::Result<Self> {
+    /// Byte offset of the SHA-256 digest within the record.
+    pub const HASH_POS: u64 = 12;
+
+    /// Encodes the record header; the payload follows it directly.
+    pub fn encode_header(name: &str, hash: &[u8; 32], data_len: u64, version: u32) -> Vec<u8> {
+        let (name_len, name_bytes) = encode_name(name, version);
+        let header_len = 8 + 4 + 32 + name_bytes.len();
+        let mut out = Vec::with_capacity(header_len);
+        write_header(&mut out, (header_len as u64 + data_len) as u32, RecordTag::TAG_FILE);
+        out.extend_from_slice(&name_len.to_le_bytes());
+        out.extend_from_slice(hash);
+        out.extend_from_slice(&name_bytes);
+        out
+    }
+
+    pub fn read(data: &[u8], offset: u64, version: u32) -> Result<Self> {
+        Self::parse(data, offset, version).map_err(Error::truncated("FILE record", offset))
+    }
+
+    fn parse(data: &[u8], offset: u64, version: u32) -> io::Result<Self> {
         let mut cursor = Cursor::new(data);
         cursor.set_position(8); // header
 
@@ -209,7 +275,9 @@ impl FileRecord {
         // So `header_end` is the index of start of data.
         
         let data_offset = offset + header_end;
-        let data_length = length as u64 - header_end;
+        let data_length = (length as u64)
+            .checked_sub(header_end)
+            .ok_or(io::ErrorKind::UnexpectedEof)?;
 
         Ok(Self {
             length,
@@ -222,6 +290,102 @@ impl FileRecord {
     }
 }
 
+/// Unused space. FREE records form a singly linked list starting at
+/// `GgpkRecord::free_offset`; a `next` of 0 ends it.
+#[derive(Debug, Clone, Copy)]
+pub struct FreeRecord {
+    pub offset: u64,
+    pub length: u32,
+    pub next: u64,
+}
+
+impl FreeRecord {
+    /// Length + tag + next pointer; smaller gaps cannot be tracked.
+    pub const MIN_SIZE: u32 = 16;
+    /// Byte offset of `next` within the record.
+    pub const NEXT_POS: u64 = 8;
+
+    pub fn read(data: &[u8], offset: u64) -> Result<Self> {
+        if data.len() < Self::MIN_SIZE as usize {
+            return Err(Error::TruncatedRecord { what: "FREE record", offset });
+        }
+        Ok(Self {
+            offset,
+            length: LittleEndian::read_u32(&data[0..4]),
+            next: LittleEndian::read_u64(&data[8..16]),
+        })
+    }
+
+    /// Only the 16 leading bytes; the rest of the record is left as is.
+    pub fn encode(length: u32, next: u64) -> Vec<u8> {
+        let mut out = Vec::with_capacity(Self::MIN_SIZE as usize);
+        write_header(&mut out, length, RecordTag::TAG_FREE);
+        out.extend_from_slice(&next.to_le_bytes());
+        out
+    }
+}
+
+fn write_header(out: &mut Vec<u8>, length: u32, tag: u32) {
+    out.extend_from_slice(&length.to_le_bytes());
+    out.extend_from_slice(&tag.to_le_bytes());
+}
+
+fn char_width(version: u32) -> u64 {
+    if version == 4 { 4 } else { 2 }
+}
+
+/// Returns the stored name length (characters plus terminator) and the
+/// encoded name: UTF-32LE for version 4, UTF-16LE otherwise.
+pub fn encode_name(name: &str, version: u32) -> (u32, Vec<u8>) {
+    let mut bytes = Vec::new();
+    let mut count = 0u32;
+    if version == 4 {
+        for c in name.chars() {
+            bytes.extend_from_slice(&(c as u32).to_le_bytes());
+            count += 1;
+        }
+        bytes.extend_from_slice(&[0; 4]);
+    } else {
+        for unit in name.encode_utf16() {
+            bytes.extend_from_slice(&unit.to_le_bytes());
+            count += 1;
+        }
+        bytes.extend_from_slice(&[0; 2]);
+    }
+    (count + 1, bytes)
+}
+
+/// Hash stored next to each directory entry: MurmurHash2 (seed 0) of the
+/// lowercased name in the record's character encoding.
+pub fn name_hash(name: &str, version: u32) -> u32 {
+    let (_, mut bytes) = encode_name(&name.to_lowercase(), version);
+    bytes.truncate(bytes.len() - char_width(version) as usize);
+    murmur_hash2(&bytes, 0)
+}
+
+pub fn murmur_hash2(data: &[u8], seed: u32) -> u32 {
+    const M: u32 = 0x5bd1e995;
+    let mut h = seed ^ data.len() as u32;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = LittleEndian::read_u32(chunk);
+        k = k.wrapping_mul(M);
+        k ^= k >> 24;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M) ^ k;
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, &b) in tail.iter().enumerate() {
+            h ^= (b as u32) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^ (h >> 15)
+}
+
 pub fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
     let mut buf = [0u8; 4];
     r.read_exact(&mut buf)?;
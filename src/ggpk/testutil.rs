@@ -0,0 +1,20 @@
+//! Tiny synthetic GGPKs for unit tests.
+
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use super::builder::GgpkBuilder;
+
+pub fn sha(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+/// Root -> Data/ -> { a.txt, b.txt }, with a.txt the first record after
+/// the GGPK header.
+pub fn write_fixture(path: &Path, a: &[u8], b: &[u8]) {
+    let mut builder = GgpkBuilder::new();
+    builder.add_file("Data/a.txt", a).unwrap();
+    builder.add_file("Data/b.txt", b).unwrap();
+    builder.write(path).unwrap();
+}
@@ -0,0 +1,326 @@
+//! In-place edits to an existing GGPK.
+//!
+//! Replacing a file never rewrites it where it stands: the new FILE record
+//! goes into a FREE record that is large enough (or the end of the file),
+//! the parent directory entry is repointed, the SHA-256 digests of every
+//! directory up to the root are recomputed, and the old record is turned
+//! into a FREE record at the head of the free list. This is what the game
+//! patcher does too, so patched files stay readable by the game and tools
+//! like LibGGPK3.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use super::record::{
+    DirectoryRecord, FileRecord, FreeRecord, GgpkRecord, RecordHeader, RecordTag,
+};
+use crate::error::{Error, Result};
+
+pub struct GgpkWriter {
+    file: File,
+    pub root_offset: u64,
+    pub free_offset: u64,
+    pub version: u32,
+}
+
+impl GgpkWriter {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut buf = [0u8; GgpkRecord::SIZE as usize];
+        file.read_exact(&mut buf)
+            .map_err(Error::truncated("GGPK header", 0))?;
+        let header = RecordHeader::read(&buf);
+        if header.tag != RecordTag::GGPK {
+            return Err(Error::BadSignature {
+                offset: 0,
+                expected: "GGPK",
+                found: format!("{:?}", header.tag),
+            });
+        }
+        let ggpk = GgpkRecord::read(&buf, 0)?;
+        Ok(Self {
+            file,
+            root_offset: ggpk.root_offset,
+            free_offset: ggpk.free_offset,
+            version: ggpk.version,
+        })
+    }
+
+    /// Replaces the contents of the FILE record at `path` (case-insensitive,
+    /// `/`-separated). Returns the offset of the new record.
+    pub fn replace_file(&mut self, path: &str, data: &[u8]) -> Result<u64> {
+        let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let chain = self
+            .resolve(self.root_offset, &parts)?
+            .ok_or_else(|| Error::NotFound(format!("GGPK file {}", path)))?;
+        let (parent, entry_index) = *chain.last().expect("resolved path has a parent");
+        let parent_dir = self.read_directory(parent)?;
+        let old_offset = parent_dir.entries[entry_index].offset;
+        let old = self.read_file_record(old_offset)?;
+
+        let hash: [u8; 32] = Sha256::digest(data).into();
+        let mut record = FileRecord::encode_header(&old.name, &hash, data.len() as u64, self.version);
+        record.extend_from_slice(data);
+        let record_len = u32::try_from(record.len())
+            .map_err(|_| Error::InvalidData(format!("{} is too large for a GGPK record", path)))?;
+
+        let new_offset = self.allocate(record_len)?;
+        self.write_at(new_offset, &record)?;
+
+        let entry_pos = parent + parent_dir.entry_offset_pos(entry_index, self.version);
+        self.write_at(entry_pos, &new_offset.to_le_bytes())?;
+        for &(dir_offset, _) in chain.iter().rev() {
+            self.update_directory_hash(dir_offset)?;
+        }
+
+        self.free_record(old_offset, old.length)?;
+        self.file.flush()?;
+        Ok(new_offset)
+    }
+
+    /// Finds the FILE record at `parts` below `dir_offset` and returns the
+    /// (directory offset, entry index) of every step. Mirrors
+    /// `GgpkReader::find_file_in_dir`: later duplicates win, dead ends
+    /// backtrack.
+    fn resolve(&mut self, dir_offset: u64, parts: &[&str]) -> Result<Option<Vec<(u64, usize)>>> {
+        let Some((part, rest)) = parts.split_first() else {
+            return Ok(None);
+        };
+        let dir = self.read_directory(dir_offset)?;
+        for (i, entry) in dir.entries.iter().enumerate().rev() {
+            let Ok(header) = self.read_header(entry.offset) else {
+                continue;
+            };
+            match header.tag {
+                RecordTag::PDIR if !rest.is_empty() => {
+                    let sub = self.read_directory(entry.offset)?;
+                    if sub.name.eq_ignore_ascii_case(part) {
+                        if let Some(mut chain) = self.resolve(entry.offset, rest)? {
+                            chain.insert(0, (dir_offset, i));
+                            return Ok(Some(chain));
+                        }
+                    }
+                }
+                RecordTag::FILE if rest.is_empty() => {
+                    let file = self.read_file_record(entry.offset)?;
+                    if file.name.eq_ignore_ascii_case(part) {
+                        return Ok(Some(vec![(dir_offset, i)]));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// Takes `len` bytes from the first FREE record that fits exactly or
+    /// leaves a remainder big enough to stay a FREE record; otherwise
+    /// appends to the end of the file.
+    fn allocate(&mut self, len: u32) -> Result<u64> {
+        let mut prev: Option<u64> = None;
+        let mut current = self.free_offset;
+        while current != 0 {
+            let free = self.read_free(current)?;
+            let remainder = free.length.checked_sub(len);
+            if let Some(remainder) = remainder.filter(|&r| r == 0 || r >= FreeRecord::MIN_SIZE) {
+                let replacement = if remainder == 0 {
+                    free.next
+                } else {
+                    let rest = current + len as u64;
+                    self.write_at(rest, &FreeRecord::encode(remainder, free.next))?;
+                    rest
+                };
+                self.set_free_link(prev, replacement)?;
+                return Ok(current);
+            }
+            prev = Some(current);
+            current = free.next;
+        }
+        Ok(self.file.seek(SeekFrom::End(0))?)
+    }
+
+    /// Turns the record at `offset` into a FREE record and puts it at the
+    /// head of the free list.
+    fn free_record(&mut self, offset: u64, length: u32) -> Result<()> {
+        self.write_at(offset, &FreeRecord::encode(length, self.free_offset))?;
+        self.set_free_link(None, offset)
+    }
+
+    /// Points the free list link of `prev` (or the GGPK record's head
+    /// pointer when `None`) at `next`.
+    fn set_free_link(&mut self, prev: Option<u64>, next: u64) -> Result<()> {
+        match prev {
+            Some(p) => self.write_at(p + FreeRecord::NEXT_POS, &next.to_le_bytes()),
+            None => {
+                self.write_at(GgpkRecord::FREE_OFFSET_POS, &next.to_le_bytes())?;
+                self.free_offset = next;
+                Ok(())
+            }
+        }
+    }
+
+    /// Recomputes a directory's digest from its children's digests.
+    fn update_directory_hash(&mut self, dir_offset: u64) -> Result<()> {
+        let dir = self.read_directory(dir_offset)?;
+        let mut hasher = Sha256::new();
+        for entry in &dir.entries {
+            hasher.update(self.read_record_hash(entry.offset)?);
+        }
+        let hash: [u8; 32] = hasher.finalize().into();
+        self.write_at(dir_offset + DirectoryRecord::HASH_POS, &hash)
+    }
+
+    fn read_record_hash(&mut self, offset: u64) -> Result<[u8; 32]> {
+        let header = self.read_header(offset)?;
+        let pos = match header.tag {
+            RecordTag::PDIR => DirectoryRecord::HASH_POS,
+            RecordTag::FILE => FileRecord::HASH_POS,
+            other => {
+                return Err(Error::BadSignature {
+                    offset,
+                    expected: "PDIR or FILE",
+                    found: format!("{:?}", other),
+                })
+            }
+        };
+        let mut hash = [0u8; 32];
+        self.read_at(offset + pos, &mut hash, "record hash")?;
+        Ok(hash)
+    }
+
+    fn read_header(&mut self, offset: u64) -> Result<RecordHeader> {
+        let mut buf = [0u8; RecordHeader::SIZE];
+        self.read_at(offset, &mut buf, "record header")?;
+        Ok(RecordHeader::read(&buf))
+    }
+
+    fn read_record(&mut self, offset: u64, expected: RecordTag, what: &'static str) -> Result<Vec<u8>> {
+        let header = self.read_header(offset)?;
+        if header.tag != expected {
+            return Err(Error::BadSignature {
+                offset,
+                expected: what,
+                found: format!("{:?}", header.tag),
+            });
+        }
+        let mut buf = vec![0u8; header.length as usize];
+        self.read_at(offset, &mut buf, what)?;
+        Ok(buf)
+    }
+
+    fn read_directory(&mut self, offset: u64) -> Result<DirectoryRecord> {
+        let data = self.read_record(offset, RecordTag::PDIR, "PDIR")?;
+        DirectoryRecord::read(&data, offset, self.version)
+    }
+
+    /// Only the record header: the payload can be large and is not needed.
+    fn read_file_record(&mut self, offset: u64) -> Result<FileRecord> {
+        let header = self.read_header(offset)?;
+        if header.tag != RecordTag::FILE {
+            return Err(Error::BadSignature {
+                offset,
+                expected: "FILE",
+                found: format!("{:?}", header.tag),
+            });
+        }
+        // Name length is the first field after the header.
+        let mut name_len = [0u8; 4];
+        self.read_at(offset + 8, &mut name_len, "FILE record")?;
+        let char_width = if self.version == 4 { 4 } else { 2 };
+        let header_len = 8 + 4 + 32 + u32::from_le_bytes(name_len) as usize * char_width;
+        let mut buf = vec![0u8; header_len.min(header.length as usize)];
+        self.read_at(offset, &mut buf, "FILE record")?;
+        // `FileRecord::read` derives the payload size from the length field.
+        FileRecord::read(&buf, offset, self.version)
+    }
+
+    fn read_free(&mut self, offset: u64) -> Result<FreeRecord> {
+        let mut buf = [0u8; FreeRecord::MIN_SIZE as usize];
+        self.read_at(offset, &mut buf, "FREE record")?;
+        let header = RecordHeader::read(&buf);
+        if header.tag != RecordTag::FREE {
+            return Err(Error::BadSignature {
+                offset,
+                expected: "FREE",
+                found: format!("{:?}", header.tag),
+            });
+        }
+        FreeRecord::read(&buf, offset)
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8], what: &'static str) -> Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(buf).map_err(Error::truncated(what, offset))
+    }
+
+    fn write_at(&mut self, offset: u64, data: &[u8]) -> Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(data)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ggpk::reader::GgpkReader;
+    use crate::ggpk::testutil::{sha, write_fixture};
+
+    fn read(reader: &GgpkReader, path: &str) -> Vec<u8> {
+        let rec = reader.read_file_by_path(path).unwrap().unwrap();
+        reader.get_data_slice(rec.data_offset, rec.data_length).unwrap().to_vec()
+    }
+
+    fn data_dir(reader: &GgpkReader) -> DirectoryRecord {
+        let root = reader.read_directory(reader.root_offset).unwrap();
+        reader.read_directory(root.entries[0].offset).unwrap()
+    }
+
+    #[test]
+    fn replace_file_round_trips_and_reuses_free_space() {
+        let path = std::env::temp_dir().join(format!("ggpk_writer_test_{}.ggpk", std::process::id()));
+        write_fixture(&path, b"first file, long enough to be reused later", b"second");
+
+        let mut writer = GgpkWriter::open(&path).unwrap();
+        let a_offset = GgpkRecord::SIZE as u64;
+        let appended = writer.replace_file("data/A.TXT", b"first file, replaced by a noticeably longer payload").unwrap();
+        assert!(appended > a_offset, "a larger payload goes to the end of the file");
+        assert_eq!(writer.free_offset, a_offset, "old record heads the free list");
+
+        // Small enough to leave a >= 16 byte FREE remainder in a.txt's old slot.
+        let reused = writer.replace_file("Data/b.txt", b"2nd").unwrap();
+        assert_eq!(reused, a_offset);
+        drop(writer);
+
+        let reader = GgpkReader::open(&path).unwrap();
+        assert_eq!(read(&reader, "Data/a.txt"), b"first file, replaced by a noticeably longer payload");
+        assert_eq!(read(&reader, "Data/b.txt"), b"2nd");
+
+        let a = reader.read_file_by_path("Data/a.txt").unwrap().unwrap();
+        let file = reader.read_file_by_path("Data/b.txt").unwrap().unwrap();
+        assert_eq!(a.hash, sha(b"first file, replaced by a noticeably longer payload"));
+        assert_eq!(file.hash, sha(b"2nd"));
+        let dir = data_dir(&reader);
+        let child_hashes: Vec<[u8; 32]> = dir
+            .entries
+            .iter()
+            .map(|e| reader.read_file_record(e.offset).unwrap().hash)
+            .collect();
+        let expected_dir = sha(&child_hashes.concat());
+        assert_eq!(dir.hash, expected_dir);
+        assert_eq!(reader.read_directory(reader.root_offset).unwrap().hash, sha(&expected_dir));
+
+        // Free list: b's old record, then the remainder of a's old slot.
+        let data = std::fs::read(&path).unwrap();
+        let ggpk = GgpkRecord::read(&data, 0).unwrap();
+        let head = FreeRecord::read(&data[ggpk.free_offset as usize..], ggpk.free_offset).unwrap();
+        let rest = FreeRecord::read(&data[head.next as usize..], head.next).unwrap();
+        assert_eq!(rest.offset, a_offset + file.length as u64);
+        assert_eq!(rest.next, 0);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
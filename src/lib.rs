@@ -0,0 +1,26 @@
+//! GGPK, bundle and dat readers behind GGPK Explorer.
+//!
+//! Most tools start from [`Vfs`], which opens a `Content.ggpk`, a Steam
+//! `Bundles2` directory or a CDN patch version and reads files by virtual
+//! path. The egui application is the `ggpk-explorer` binary and only builds
+//! with the `gui` feature (on by default); depend on this crate with
+//! `default-features = false` to leave eframe, rfd and rodio out.
+
+pub mod adapters;
+pub mod bundles;
+pub mod dat;
+pub mod diff;
+pub mod error;
+pub mod export;
+pub mod ggpk;
+pub mod ooz;
+pub mod parsers;
+pub mod settings;
+pub mod vfs;
+
+pub use bundles::index::{FileInfo, Index};
+pub use dat::reader::{DatReader, DatValue};
+pub use dat::schema::Schema;
+pub use error::{Error, Result};
+pub use ggpk::reader::GgpkReader;
+pub use vfs::{BundleSources, Vfs, VfsEntry};
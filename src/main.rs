@@ -1,18 +1,17 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-mod ggpk;
-mod dat;
-mod ooz;
-pub mod bundles;
+// The readers live in the library crate; re-importing them at the root keeps
+// `crate::ggpk::...` style paths working in the binary-only modules.
+#[allow(unused_imports)]
+use ggpk_explorer::{adapters, bundles, dat, diff, error, export, ggpk, ooz, parsers, settings, vfs};
+
+#[cfg(feature = "gui")]
 mod ui;
-pub mod settings;
 pub mod cli;
+#[cfg(feature = "gui")]
 pub mod update;
-pub mod export;
-pub mod parsers;
-pub mod adapters;
 
-fn main() -> eframe::Result<()> {
+fn main() {
     std::panic::set_hook(Box::new(|info| {
         let msg = match info.payload().downcast_ref::<&str>() {
             Some(s) => *s,
@@ -55,50 +54,117 @@ fn main() -> eframe::Result<()> {
 
         if let Err(e) = cli::run_inspect() {
             eprintln!("Inspection failed: {}", e);
+            std::process::exit(1);
         }
-        return Ok(());
+        return;
     }
 
+    if args.len() > 1 && (args[1] == "ls" || args[1] == "tree") {
+        if let Err(e) = cli::run_list(&args[2..], args[1] == "tree") {
+            eprintln!("Listing failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    ui::run()
-}
+    if args.len() > 1 && args[1] == "cat" {
+        if let Err(e) = cli::run_cat(&args[2..]) {
+            eprintln!("cat failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    if args.len() > 1 && args[1] == "verify" {
+        if let Err(e) = cli::run_verify(&args[2..]) {
+            eprintln!("Verify failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    fn local_murmur_hash64a(key: &[u8], seed: u64) -> u64 {
-        let m: u64 = 0xc6a4a7935bd1e995;
-        let r: u8 = 47;
-        let len = key.len() as u64;
-        let mut h: u64 = seed ^ (len.wrapping_mul(m));
-        let n_blocks = len / 8;
-        let md = key;
-        for i in 0..n_blocks {
-            let idx = (i * 8) as usize;
-            let mut k: u64 = u64::from_le_bytes(md[idx..idx+8].try_into().unwrap());
-            k = k.wrapping_mul(m);
-            k ^= k >> r;
-            k = k.wrapping_mul(m);
-            h ^= k;
-            h = h.wrapping_mul(m);
+    if args.len() > 1 && args[1] == "free" {
+        if let Err(e) = cli::run_free(&args[2..]) {
+            eprintln!("free failed: {}", e);
+            std::process::exit(1);
         }
-        let remainder_idx = (n_blocks * 8) as usize;
-        let remaining_len = (len & 7) as usize;
-        if remaining_len > 0 {
-            let mut k: u64 = 0;
-            for i in 0..remaining_len {
-                 k ^= (md[remainder_idx + i] as u64) << (8 * i);
-            }
-            h ^= k;
-            h = h.wrapping_mul(m);
+        return;
+    }
+
+    if args.len() > 1 && args[1] == "pack" {
+        if let Err(e) = cli::run_pack(&args[2..]) {
+            eprintln!("pack failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.len() > 1 && args[1] == "diff" {
+        if let Err(e) = cli::run_diff(&args[2..]) {
+            eprintln!("diff failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.len() > 1 && args[1] == "sqlite" {
+        if let Err(e) = cli::run_sqlite(&args[2..]) {
+            eprintln!("sqlite failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.len() > 1 && args[1] == "query" {
+        if let Err(e) = cli::run_query(&args[2..]) {
+            eprintln!("query failed: {}", e);
+            std::process::exit(1);
         }
-        h ^= h >> r;
-        h = h.wrapping_mul(m);
-        h ^= h >> r;
-        h
+        return;
     }
 
+    if args.len() > 1 && args[1] == "infer" {
+        if let Err(e) = cli::run_infer(&args[2..]) {
+            eprintln!("infer failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.len() > 1 && args[1] == "validate" {
+        if let Err(e) = cli::run_validate(&args[2..]) {
+            eprintln!("validate failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.len() > 1 && args[1] == "extract" {
+        if let Err(e) = cli::run_extract(&args[2..]) {
+            eprintln!("Extract failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    #[cfg(feature = "gui")]
+    if let Err(e) = ui::run() {
+        eprintln!("Failed to start the UI: {}", e);
+        std::process::exit(1);
+    }
+
+    #[cfg(not(feature = "gui"))]
+    {
+        eprintln!("This build has no UI (built without the `gui` feature).");
+        eprintln!("Available commands: extract, ls, tree, cat, diff, query, infer, validate, sqlite, verify, free, pack, inspect");
+        std::process::exit(2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
     #[test]
     #[ignore]
     fn test_find_psg() {
@@ -108,110 +174,19 @@ mod tests {
             settings.ggpk_path, settings.steam_path
         );
         
-        let file_bytes = if let Some(steam_path) = &settings.steam_path {
-            let steam = crate::bundles::steam::SteamBundleLoader::new(std::path::PathBuf::from(steam_path));
-            if let Ok(index_bytes) = steam.load_index_bytes() {
-                let mut cursor = std::io::Cursor::new(&index_bytes);
-                if let Ok(bundle) = crate::bundles::bundle::Bundle::read_header(&mut cursor) {
-                    if let Ok(decompressed) = bundle.decompress(&mut cursor) {
-                        if let Ok(idx) = crate::bundles::index::Index::read(&decompressed) {
-                            let hash = local_murmur_hash64a(b"metadata/passiveskillgraph.psg" as &[u8], 0x1337b33f);
-                            if let Some(file_info) = idx.files.get(&hash) {
-                                if let Some(bundle_info) = idx.bundles.get(file_info.bundle_index as usize) {
-                                    if let Ok(bundle_data) = steam.fetch_bundle(&bundle_info.name) {
-                                        let mut cursor = std::io::Cursor::new(bundle_data);
-                                        if let Ok(b) = crate::bundles::bundle::Bundle::read_header(&mut cursor) {
-                                            if let Ok(decomp) = b.decompress(&mut cursor) {
-                                                let start = file_info.file_offset as usize;
-                                                let end = start + file_info.file_size as usize;
-                                                Some(decomp[start..end].to_vec())
-                                            } else { msg.push_str("Steam: Decompress bundle failed\n"); None }
-                                        } else { msg.push_str("Steam: Read bundle header failed\n"); None }
-                                    } else { msg.push_str("Steam: Fetch bundle failed\n"); None }
-                                } else { msg.push_str("Steam: Bundle info not found\n"); None }
-                            } else { msg.push_str("Steam: File hash not in index\n"); None }
-                        } else { msg.push_str("Steam: Index read failed\n"); None }
-                    } else { msg.push_str("Steam: Decompress index failed\n"); None }
-                } else { msg.push_str("Steam: Read index header failed\n"); None }
-            } else { msg.push_str("Steam: Load index bytes failed\n"); None }
+        let vfs = if let Some(steam_path) = &settings.steam_path {
+            crate::vfs::Vfs::open_steam(steam_path)
         } else if let Some(ggpk_path) = &settings.ggpk_path {
-            match crate::ggpk::reader::GgpkReader::open(ggpk_path) {
-                Ok(reader) => {
-                    let mut found_bytes = None;
-                    match reader.read_file_by_path("Bundles2/_.index.bin") {
-                        Ok(Some(index_rec)) => {
-                            match reader.get_data_slice(index_rec.data_offset, index_rec.data_length) {
-                                Ok(data) => {
-                                    let mut cursor = std::io::Cursor::new(data);
-                                    match crate::bundles::bundle::Bundle::read_header(&mut cursor) {
-                                        Ok(bundle) => {
-                                            match bundle.decompress(&mut cursor) {
-                                                Ok(decompressed) => {
-                                                    match crate::bundles::index::Index::read(&decompressed) {
-                                                        Ok(idx) => {
-                                                            let hash = local_murmur_hash64a(b"metadata/passiveskillgraph.psg" as &[u8], 0x1337b33f);
-                                                            if let Some(file_info) = idx.files.get(&hash) {
-                                                                if let Some(bundle_info) = idx.bundles.get(file_info.bundle_index as usize) {
-                                                                    let candidates = vec![
-                                                                        format!("Bundles2/{}", bundle_info.name),
-                                                                        format!("Bundles2/{}.bundle.bin", bundle_info.name),
-                                                                        bundle_info.name.clone(),
-                                                                        format!("{}.bundle.bin", bundle_info.name),
-                                                                    ];
-                                                                    let mut b_rec = None;
-                                                                    for cand in &candidates {
-                                                                        if let Ok(Some(rec)) = reader.read_file_by_path(cand) {
-                                                                            b_rec = Some(rec);
-                                                                            break;
-                                                                        }
-                                                                    }
-                                                                    if let Some(rec) = b_rec {
-                                                                        match reader.get_data_slice(rec.data_offset, rec.data_length) {
-                                                                            Ok(b_data) => {
-                                                                                let mut cursor = std::io::Cursor::new(b_data);
-                                                                                match crate::bundles::bundle::Bundle::read_header(&mut cursor) {
-                                                                                    Ok(b) => {
-                                                                                        match b.decompress(&mut cursor) {
-                                                                                            Ok(decomp) => {
-                                                                                                let start = file_info.file_offset as usize;
-                                                                                                let end = start + file_info.file_size as usize;
-                                                                                                found_bytes = Some(decomp[start..end].to_vec());
-                                                                                            }
-                                                                                            Err(e) => msg.push_str(&format!("GGPK: Decompress bundle failed: {}\n", e)),
-                                                                                        }
-                                                                                    }
-                                                                                    Err(e) => msg.push_str(&format!("GGPK: Read bundle header failed: {}\n", e)),
-                                                                                }
-                                                                            }
-                                                                            Err(e) => msg.push_str(&format!("GGPK: Get data slice failed for bundle: {}\n", e)),
-                                                                        }
-                                                                    } else {
-                                                                        msg.push_str(&format!("GGPK: Bundle candidates not found: {:?}\n", candidates));
-                                                                    }
-                                                                } else { msg.push_str("GGPK: Bundle info not found\n"); }
-                                                            } else { msg.push_str("GGPK: File hash not in index\n"); }
-                                                        }
-                                                        Err(e) => msg.push_str(&format!("GGPK: Index read failed: {}\n", e)),
-                                                    }
-                                                }
-                                                Err(e) => msg.push_str(&format!("GGPK: Decompress index failed: {}\n", e)),
-                                            }
-                                        }
-                                        Err(e) => msg.push_str(&format!("GGPK: Read index header failed: {}\n", e)),
-                                    }
-                                }
-                                Err(e) => msg.push_str(&format!("GGPK: Get data slice failed for index: {}\n", e)),
-                            }
-                        }
-                        Ok(std::option::Option::None) => msg.push_str("GGPK: Bundles2/_.index.bin not found in GGPK\n"),
-                        Err(e) => msg.push_str(&format!("GGPK: Read index file from GGPK failed: {}\n", e)),
-                    }
-                    found_bytes
-                }
-                Err(e) => { msg.push_str(&format!("GGPK: Open reader failed: {}\n", e)); None }
-            }
+            crate::vfs::Vfs::open_ggpk(ggpk_path)
         } else {
-            None
+            Err(crate::error::Error::NotFound("GGPK or Steam path in settings".to_string()))
+        };
+        let file_bytes = match vfs.and_then(|vfs| vfs.read("metadata/passiveskillgraph.psg")) {
+            Ok(bytes) => Some(bytes),
+            Err(e) => {
+                msg.push_str(&format!("Load failed: {}\n", e));
+                None
+            }
         };
 
         if let Some(bytes) = file_bytes {
@@ -231,6 +206,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(not(feature = "rust-oodle"))]
     fn test_ooz_link() {
         println!("Testing ooz linking...");
         unsafe {
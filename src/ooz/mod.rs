@@ -1,14 +1,80 @@
 #![allow(dead_code)]
+//! Oodle backends. The C++ `ooz` library is the default; the `rust-oodle`
+//! feature swaps in [`rust`] and skips building the C++ sources.
+pub mod rust;
+#[cfg(not(feature = "rust-oodle"))]
 pub mod sys;
 
+#[cfg(not(feature = "rust-oodle"))]
 use std::ffi::CString;
+#[cfg(not(feature = "rust-oodle"))]
 use std::ptr;
 // use crate::ooz::sys;
 
+/// Decodes the Oodle stream `src` into `dst[..dst_len]`. Returns `dst_len`
+/// on success, like `Ooz_Decompress`. `dst` must have 64 bytes of slack
+/// past `dst_len`: the native decoder may write there.
+pub fn decompress(src: &[u8], dst: &mut [u8], dst_len: usize) -> i32 {
+    #[cfg(feature = "rust-oodle")]
+    {
+        rust::decompress(src, dst, dst_len)
+    }
+    #[cfg(not(feature = "rust-oodle"))]
+    unsafe {
+        sys::Ooz_Decompress(
+            src.as_ptr(),
+            src.len() as i32,
+            dst.as_mut_ptr(),
+            dst_len,
+            0,
+            0,
+            0,
+            ptr::null_mut(),
+            0,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            0,
+            0,
+        )
+    }
+}
+
+/// Compresses `src` into `dst` with the OodleLZ_Compressor id `codec`.
+/// Returns what `Ooz_Compress` does: the compressed size, or <= 0 on
+/// failure. The `rust-oodle` backend only decodes, so there this is always
+/// [`Error::Unsupported`](crate::error::Error::Unsupported).
+pub fn compress(codec: i32, src: &[u8], dst: &mut [u8], level: i32) -> crate::error::Result<i32> {
+    #[cfg(feature = "rust-oodle")]
+    {
+        let _ = (codec, src, dst, level);
+        Err(crate::error::Error::Unsupported(
+            "compressing needs the native ooz backend (built without the rust-oodle feature)".into(),
+        ))
+    }
+    #[cfg(not(feature = "rust-oodle"))]
+    unsafe {
+        Ok(sys::Ooz_Compress(
+            codec,
+            src.as_ptr(),
+            src.len(),
+            dst.as_mut_ptr(),
+            level,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            0,
+        ))
+    }
+}
+
+#[cfg(not(feature = "rust-oodle"))]
 pub struct Bun {
     inner: *mut sys::Bun,
 }
 
+#[cfg(not(feature = "rust-oodle"))]
 impl Bun {
     pub fn new(decompressor_path: &str, decompressor_export: &str) -> Result<Self, String> {
         let c_path = CString::new(decompressor_path).map_err(|e| e.to_string())?;
@@ -37,6 +103,7 @@ impl Bun {
     }
 }
 
+#[cfg(not(feature = "rust-oodle"))]
 impl Drop for Bun {
     fn drop(&mut self) {
         unsafe { sys::BunDelete(self.inner) };
@@ -0,0 +1,153 @@
+//! Bit readers for the Oodle streams. Reads past the end of the data see
+//! zeros, as in ooz; decoders check afterwards that each stream ended
+//! where the next one starts.
+
+/// Reads bits most significant first. A backward reader takes the bytes
+/// from the end of `data` towards its start.
+pub(super) struct BitReader<'a> {
+    data: &'a [u8],
+    backward: bool,
+    /// Bits consumed so far.
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, backward: false, pos: 0 }
+    }
+
+    pub fn backward(data: &'a [u8]) -> Self {
+        Self { data, backward: true, pos: 0 }
+    }
+
+    fn byte(&self, i: usize) -> u64 {
+        match self.data.len().checked_sub(i + 1) {
+            Some(from_end) => self.data[if self.backward { from_end } else { i }] as u64,
+            None => 0,
+        }
+    }
+
+    /// The next `n` (at most 32) bits, without consuming them.
+    pub fn peek(&self, n: u32) -> u32 {
+        if n == 0 {
+            return 0;
+        }
+        let i = self.pos / 8;
+        let window = (0..5).fold(0u64, |w, k| w << 8 | self.byte(i + k));
+        let shift = 40 - (self.pos % 8) as u32 - n;
+        ((window >> shift) & ((1u64 << n) - 1)) as u32
+    }
+
+    pub fn read(&mut self, n: u32) -> u32 {
+        let v = self.peek(n);
+        self.pos += n as usize;
+        v
+    }
+
+    pub fn bit(&mut self) -> bool {
+        self.read(1) != 0
+    }
+
+    pub fn skip(&mut self, n: u32) {
+        self.pos += n as usize;
+    }
+
+    /// Zero bits before the next set bit, 32 if the next 32 are all zero.
+    pub fn leading_zeros(&self) -> u32 {
+        self.peek(32).leading_zeros()
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn seek(&mut self, bits: usize) {
+        self.pos = bits;
+    }
+
+    /// Whole or partly consumed bytes.
+    pub fn bytes_used(&self) -> usize {
+        self.pos.div_ceil(8)
+    }
+
+    pub fn overran(&self) -> bool {
+        self.bytes_used() > self.data.len()
+    }
+}
+
+/// Reads bits least significant first, as the Huffman and tANS streams
+/// store them. A backward reader takes the bytes from the end of `data`.
+pub(super) struct LsbReader<'a> {
+    data: &'a [u8],
+    backward: bool,
+    pos: usize,
+}
+
+impl<'a> LsbReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, backward: false, pos: 0 }
+    }
+
+    pub fn backward(data: &'a [u8]) -> Self {
+        Self { data, backward: true, pos: 0 }
+    }
+
+    fn byte(&self, i: usize) -> u64 {
+        match self.data.len().checked_sub(i + 1) {
+            Some(from_end) => self.data[if self.backward { from_end } else { i }] as u64,
+            None => 0,
+        }
+    }
+
+    /// The next `n` (at most 32) bits, without consuming them.
+    pub fn peek(&self, n: u32) -> u32 {
+        let i = self.pos / 8;
+        let window = (0..5).rev().fold(0u64, |w, k| w << 8 | self.byte(i + k));
+        ((window >> (self.pos % 8)) & ((1u64 << n) - 1)) as u32
+    }
+
+    pub fn read(&mut self, n: u32) -> u32 {
+        let v = self.peek(n);
+        self.pos += n as usize;
+        v
+    }
+
+    pub fn skip(&mut self, n: u32) {
+        self.pos += n as usize;
+    }
+
+    /// Whole or partly consumed bytes.
+    pub fn bytes_used(&self) -> usize {
+        self.pos.div_ceil(8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_in_both_bit_orders_and_directions() {
+        let data = [0b1010_0000, 0xFF, 0x01];
+        let mut msb = BitReader::new(&data);
+        assert_eq!(msb.read(3), 0b101);
+        assert_eq!(msb.read(9), 0b0_0000_1111);
+        assert_eq!(msb.bytes_used(), 2);
+        assert_eq!(msb.read(32), 0b1111_0000_0001 << 20);
+        assert!(msb.overran());
+
+        let mut back = BitReader::backward(&data);
+        assert_eq!(back.read(8), 0x01);
+        assert_eq!(back.leading_zeros(), 0);
+
+        let mut lsb = LsbReader::new(&data);
+        assert_eq!(lsb.read(6), 0b10_0000);
+        assert_eq!(lsb.read(4), 0b11_10);
+        let mut lsb_back = LsbReader::backward(&data);
+        assert_eq!(lsb_back.read(9), 0x101);
+    }
+}
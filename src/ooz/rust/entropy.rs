@@ -0,0 +1,995 @@
+//! Entropy-coded byte arrays, the building block of every Kraken, Mermaid
+//! and Leviathan stream (`Kraken_DecodeBytes` in ooz).
+//!
+//! An array opens with a chunk header giving its type and sizes, followed
+//! by the payload: stored bytes, Huffman codes split over three (or six)
+//! interleaved bit streams, tANS with five interleaved states, a run-length
+//! command list, or a list of further arrays.
+
+use super::bits::{BitReader, LsbReader};
+use super::Result;
+
+/// Arrays nest (RLE commands and recursive arrays are arrays themselves);
+/// the encoder uses a couple of levels, this bounds hostile input.
+const MAX_DEPTH: u32 = 16;
+
+/// No array decodes to more than this (18-bit size fields).
+const MAX_ARRAY: usize = 0x40000;
+
+struct ChunkHeader {
+    kind: u8,
+    header_len: usize,
+    src_size: usize,
+    dst_size: usize,
+}
+
+fn chunk_header(src: &[u8]) -> Result<ChunkHeader> {
+    if src.len() < 2 {
+        return Err("array header truncated".into());
+    }
+    let kind = (src[0] >> 4) & 7;
+    let be = |n: usize| src[..n].iter().fold(0usize, |v, &b| v << 8 | b as usize);
+    if kind == 0 {
+        let (size, header_len) = if src[0] >= 0x80 {
+            (be(2) & 0xFFF, 2)
+        } else {
+            if src.len() < 3 {
+                return Err("array header truncated".into());
+            }
+            let size = be(3);
+            if size > 0x3FFFF {
+                return Err("stored array too large".into());
+            }
+            (size, 3)
+        };
+        return Ok(ChunkHeader { kind, header_len, src_size: size, dst_size: size });
+    }
+    if src[0] >= 0x80 {
+        // Short form: 10-bit sizes.
+        if src.len() < 3 {
+            return Err("array header truncated".into());
+        }
+        let bits = be(3);
+        let src_size = bits & 0x3FF;
+        Ok(ChunkHeader { kind, header_len: 3, src_size, dst_size: src_size + ((bits >> 10) & 0x3FF) + 1 })
+    } else {
+        if src.len() < 5 {
+            return Err("array header truncated".into());
+        }
+        let bits = be(5);
+        let src_size = bits & 0x3FFFF;
+        let dst_size = ((bits >> 18) & 0x3FFFF) + 1;
+        if src_size >= dst_size {
+            return Err("coded array larger than its output".into());
+        }
+        Ok(ChunkHeader { kind, header_len: 5, src_size, dst_size })
+    }
+}
+
+/// Decodes the array at the start of `src`, which may hold at most
+/// `capacity` bytes. Returns the bytes and the length of the array in `src`.
+pub(super) fn decode_bytes(src: &[u8], capacity: usize) -> Result<(Vec<u8>, usize)> {
+    decode_nested(src, capacity, 0)
+}
+
+fn decode_nested(src: &[u8], capacity: usize, depth: u32) -> Result<(Vec<u8>, usize)> {
+    if depth > MAX_DEPTH {
+        return Err("arrays nested too deeply".into());
+    }
+    let h = chunk_header(src)?;
+    let payload = src
+        .get(h.header_len..h.header_len + h.src_size)
+        .ok_or("array truncated")?;
+    if h.dst_size > capacity {
+        return Err(format!("array of {} bytes where at most {} fit", h.dst_size, capacity));
+    }
+    let used = h.header_len + h.src_size;
+    if h.kind == 0 {
+        return Ok((payload.to_vec(), used));
+    }
+    let mut out = vec![0u8; h.dst_size];
+    match h.kind {
+        1 => tans(payload, &mut out)?,
+        2 | 4 => huffman(payload, &mut out, h.kind >> 1)?,
+        3 => rle(payload, &mut out, depth)?,
+        5 => recursive(payload, &mut out, depth)?,
+        kind => return Err(format!("unknown array type {}", kind)),
+    }
+    Ok((out, used))
+}
+
+/// The decoded size of the array at the start of `src`, without decoding
+/// it (`Kraken_GetBlockSize`).
+fn decoded_size(src: &[u8], capacity: usize) -> Result<usize> {
+    let h = chunk_header(src)?;
+    if h.kind >= 6 {
+        return Err(format!("unknown array type {}", h.kind));
+    }
+    if h.header_len + h.src_size > src.len() || h.dst_size > capacity {
+        return Err("bad array size".into());
+    }
+    Ok(h.dst_size)
+}
+
+fn le16(src: &[u8]) -> usize {
+    u16::from_le_bytes([src[0], src[1]]) as usize
+}
+
+// ---------------------------------------------------------------------------
+// Huffman
+
+/// Where the symbols of each code length start in the symbol list built
+/// while reading the code lengths (index = length, 1 to 11).
+const CODE_PREFIX: [usize; 12] = [0x0, 0x0, 0x2, 0x6, 0xE, 0x1E, 0x3E, 0x7E, 0xFE, 0x1FE, 0x2FE, 0x3FE];
+const CODE_PREFIX_END: usize = 0x4FE;
+
+/// Symbols grouped by code length, in the order their codes are assigned.
+struct CodeLengths {
+    syms: [u8; CODE_PREFIX_END],
+    next: [usize; 12],
+}
+
+impl CodeLengths {
+    fn new() -> Self {
+        Self { syms: [0; CODE_PREFIX_END], next: CODE_PREFIX }
+    }
+
+    fn push(&mut self, len: usize, sym: usize) -> Result<()> {
+        let end = CODE_PREFIX.get(len + 1).copied().unwrap_or(CODE_PREFIX_END);
+        if !(1..=11).contains(&len) || self.next[len] >= end || sym > 255 {
+            return Err("bad Huffman code lengths".into());
+        }
+        self.syms[self.next[len]] = sym as u8;
+        self.next[len] += 1;
+        Ok(())
+    }
+}
+
+/// Canonical code lookup on the next 11 bits, read least significant
+/// first.
+struct HuffLut {
+    len: [u8; 2048],
+    sym: [u8; 2048],
+}
+
+impl HuffLut {
+    fn new(lengths: &CodeLengths) -> Result<Self> {
+        let mut lut = Self { len: [0; 2048], sym: [0; 2048] };
+        let mut slot = 0;
+        for (len, (&first, &end)) in CODE_PREFIX.iter().zip(&lengths.next).enumerate().skip(1) {
+            let step = 1 << (11 - len);
+            for &sym in &lengths.syms[first..end] {
+                if slot + step > 2048 {
+                    return Err("Huffman code oversubscribed".into());
+                }
+                // Codes are stored bit-reversed, so the table is indexed
+                // by the reversed 11-bit prefix.
+                for code in slot..slot + step {
+                    let k = ((code as u16).reverse_bits() >> 5) as usize;
+                    lut.len[k] = len as u8;
+                    lut.sym[k] = sym;
+                }
+                slot += step;
+            }
+        }
+        if slot != 2048 {
+            return Err("Huffman code incomplete".into());
+        }
+        Ok(lut)
+    }
+
+    fn decode(&self, r: &mut LsbReader) -> u8 {
+        let k = r.peek(11) as usize;
+        r.skip(self.len[k] as u32);
+        self.sym[k]
+    }
+}
+
+fn huffman(src: &[u8], out: &mut [u8], streams: u8) -> Result<()> {
+    let mut br = BitReader::new(src);
+    let mut lengths = CodeLengths::new();
+    let num_syms = if !br.bit() {
+        code_lengths_old(&mut br, &mut lengths)?
+    } else if !br.bit() {
+        code_lengths_new(&mut br, &mut lengths)?
+    } else {
+        return Err("reserved Huffman table type".into());
+    };
+    if br.overran() {
+        return Err("Huffman table truncated".into());
+    }
+    let src = &src[br.bytes_used()..];
+
+    if num_syms == 1 {
+        out.fill(lengths.syms[0]);
+        return if src.is_empty() { Ok(()) } else { Err("data after a single-symbol Huffman table".into()) };
+    }
+    let lut = HuffLut::new(&lengths)?;
+
+    if streams == 1 {
+        if src.len() < 3 {
+            return Err("Huffman streams truncated".into());
+        }
+        huffman_streams(&lut, &src[2..], le16(src), out)
+    } else {
+        if src.len() < 6 {
+            return Err("Huffman streams truncated".into());
+        }
+        let split = u32::from_le_bytes([src[0], src[1], src[2], 0]) as usize;
+        let src = &src[3..];
+        if split > src.len() {
+            return Err("bad Huffman stream split".into());
+        }
+        let (left, right) = src.split_at(split);
+        if left.len() < 2 || right.len() < 3 {
+            return Err("bad Huffman stream split".into());
+        }
+        let (split_left, split_right) = (le16(left), le16(right));
+        if left.len() - 2 < split_left + 2 || right.len() - 2 < split_right + 2 {
+            return Err("bad Huffman stream split".into());
+        }
+        let half = out.len().div_ceil(2);
+        let (first, second) = out.split_at_mut(half);
+        huffman_streams(&lut, &left[2..], split_left, first)?;
+        huffman_streams(&lut, &right[2..], split_right, second)
+    }
+}
+
+/// Decodes `out` from three interleaved streams: forwards from the start
+/// of `src`, backwards from its end and forwards from `split`. The first
+/// stream must end at `split` and the other two must meet.
+fn huffman_streams(lut: &HuffLut, src: &[u8], split: usize, out: &mut [u8]) -> Result<()> {
+    if split > src.len() {
+        return Err("bad Huffman stream split".into());
+    }
+    let (head, tail) = src.split_at(split);
+    let mut streams = [LsbReader::new(head), LsbReader::backward(tail), LsbReader::new(tail)];
+    for (i, o) in out.iter_mut().enumerate() {
+        *o = lut.decode(&mut streams[i % 3]);
+    }
+    let [front, back, middle] = &streams;
+    if front.bytes_used() != head.len() || back.bytes_used() + middle.bytes_used() != tail.len() {
+        return Err("Huffman streams don't line up".into());
+    }
+    Ok(())
+}
+
+/// Gamma-coded code lengths for all 256 symbols, or a short list of
+/// symbols with explicit lengths.
+fn code_lengths_old(br: &mut BitReader, lengths: &mut CodeLengths) -> Result<usize> {
+    if !br.bit() {
+        let num_syms = br.read(8) as usize;
+        if num_syms == 0 {
+            return Err("empty Huffman table".into());
+        }
+        if num_syms == 1 {
+            lengths.syms[0] = br.read(8) as u8;
+            return Ok(1);
+        }
+        let len_bits = br.read(3);
+        if len_bits > 4 {
+            return Err("bad Huffman code length width".into());
+        }
+        for _ in 0..num_syms {
+            let sym = br.read(8) as usize;
+            let len = br.read(len_bits) as usize + 1;
+            lengths.push(len, sym)?;
+        }
+        return Ok(num_syms);
+    }
+
+    let forced = br.read(2);
+    let min_gamma_bits = 1u32 << (31 - (20 >> forced));
+    let mut sym = 0usize;
+    let mut num_syms = 0;
+    let mut avg_x4 = 32i32;
+    let mut skip_zeros = br.bit();
+    loop {
+        if !skip_zeros {
+            // A run of unused symbols.
+            if br.peek(32) & 0xFF00_0000 == 0 {
+                return Err("bad Huffman zero run".into());
+            }
+            let lz = br.leading_zeros();
+            sym += br.read(2 * (lz + 1)) as usize - 1;
+            if sym >= 256 {
+                break;
+            }
+        }
+        skip_zeros = false;
+        if br.peek(32) & 0xFF00_0000 == 0 {
+            return Err("bad Huffman symbol run".into());
+        }
+        let lz = br.leading_zeros();
+        let n = br.read(2 * (lz + 1)) as usize - 1;
+        if sym + n > 256 {
+            return Err("Huffman symbol run past 255".into());
+        }
+        num_syms += n;
+        for _ in 0..n {
+            let bits = br.peek(32);
+            if bits < min_gamma_bits {
+                return Err("bad Huffman code length".into());
+            }
+            let lz = bits.leading_zeros();
+            let v = br.read(lz + forced + 1) as i32 + ((lz as i32 - 1) << forced);
+            let len = (-(v & 1) ^ (v >> 1)) + ((avg_x4 + 2) >> 2);
+            if !(1..=11).contains(&len) {
+                return Err("bad Huffman code length".into());
+            }
+            avg_x4 = len + ((3 * avg_x4 + 2) >> 2);
+            lengths.push(len as usize, sym)?;
+            sym += 1;
+        }
+        if sym == 256 {
+            break;
+        }
+    }
+    if sym != 256 || num_syms < 2 {
+        return Err("bad Huffman symbol count".into());
+    }
+    Ok(num_syms)
+}
+
+/// Golomb-Rice coded code lengths for symbol ranges.
+fn code_lengths_new(br: &mut BitReader, lengths: &mut CodeLengths) -> Result<usize> {
+    let forced = br.read(2);
+    let num_syms = br.read(8) as usize + 1;
+    let fluff = read_fluff(br, num_syms);
+
+    let mut code_len = vec![0u8; num_syms + fluff];
+    let mut pos = br.position();
+    golomb_rice_lengths(br.data(), &mut pos, &mut code_len)?;
+    golomb_rice_bits(br.data(), &mut pos, &mut code_len[..num_syms], forced)?;
+    br.seek(pos);
+
+    let mut running_sum = 0x1Ei32;
+    for len in &mut code_len[..num_syms] {
+        let v = *len as i32;
+        let v = -(v & 1) ^ (v >> 1);
+        let l = v + (running_sum >> 2) + 1;
+        if !(1..=11).contains(&l) {
+            return Err("bad Huffman code length".into());
+        }
+        *len = l as u8;
+        running_sum += v;
+    }
+
+    let ranges = symbol_ranges(br, num_syms, fluff, &code_len[num_syms..])?;
+    let mut lens = code_len.iter();
+    for (start, count) in ranges {
+        for sym in start..start + count {
+            lengths.push(*lens.next().ok_or("bad Huffman symbol ranges")? as usize, sym)?;
+        }
+    }
+    Ok(num_syms)
+}
+
+/// How many range-size values follow the code lengths.
+fn read_fluff(br: &mut BitReader, num_syms: usize) -> usize {
+    if num_syms == 256 {
+        return 0;
+    }
+    let x = 2 * (257 - num_syms).min(num_syms) as u32;
+    let y = 32 - (x - 1).leading_zeros();
+    let v = br.peek(y);
+    let z = (1 << y) - x;
+    if (v >> 1) >= z {
+        br.skip(y);
+        (v - z) as usize
+    } else {
+        br.skip(y - 1);
+        (v >> 1) as usize
+    }
+}
+
+/// Unary values: the zero bits before each one bit.
+fn golomb_rice_lengths(data: &[u8], pos: &mut usize, out: &mut [u8]) -> Result<()> {
+    for v in out {
+        let mut zeros = 0u32;
+        loop {
+            let byte = *data.get(*pos / 8).ok_or("Golomb-Rice lengths truncated")?;
+            let bit = byte >> (7 - *pos % 8) & 1;
+            *pos += 1;
+            if bit == 1 {
+                break;
+            }
+            zeros += 1;
+        }
+        *v = u8::try_from(zeros).map_err(|_| "bad Golomb-Rice length")?;
+    }
+    Ok(())
+}
+
+/// Appends `bits` low bits to each value.
+fn golomb_rice_bits(data: &[u8], pos: &mut usize, out: &mut [u8], bits: u32) -> Result<()> {
+    if bits == 0 {
+        return Ok(());
+    }
+    if (*pos + bits as usize * out.len()).div_ceil(8) > data.len() {
+        return Err("Golomb-Rice bits truncated".into());
+    }
+    let mut br = BitReader::new(data);
+    br.seek(*pos);
+    for v in out {
+        *v = (*v << bits) | br.read(bits) as u8;
+    }
+    *pos = br.position();
+    Ok(())
+}
+
+/// Splits the `num_syms` used symbols into (first symbol, count) runs,
+/// using the range sizes in `sizes`.
+fn symbol_ranges(br: &mut BitReader, num_syms: usize, fluff: usize, sizes: &[u8]) -> Result<Vec<(usize, usize)>> {
+    let bad = || "bad symbol ranges".to_string();
+    let mut sizes = sizes.iter().map(|&v| v as u32);
+    let mut sym = 0usize;
+    if fluff & 1 != 0 {
+        let v = sizes.next().ok_or_else(bad)?;
+        if v >= 8 {
+            return Err(bad());
+        }
+        sym = (br.read(v + 1) + (1 << (v + 1)) - 1) as usize;
+    }
+    let mut ranges = Vec::with_capacity(fluff / 2 + 1);
+    let mut used = 0;
+    for _ in 0..fluff / 2 {
+        let (v, w) = (sizes.next().ok_or_else(bad)?, sizes.next().ok_or_else(bad)?);
+        if v >= 9 || w >= 8 {
+            return Err(bad());
+        }
+        let count = (br.read(v) + (1 << v)) as usize;
+        let space = (br.read(w + 1) + (1 << (w + 1)) - 1) as usize;
+        ranges.push((sym, count));
+        used += count;
+        sym += count + space;
+    }
+    if sym >= 256 || used >= num_syms || sym + num_syms - used > 256 {
+        return Err(bad());
+    }
+    ranges.push((sym, num_syms - used));
+    Ok(ranges)
+}
+
+// ---------------------------------------------------------------------------
+// tANS
+
+/// Symbols of weight 1, and (symbol << 16 | weight) for the rest.
+struct TansTable {
+    singles: Vec<u8>,
+    weighted: Vec<u32>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct TansEntry {
+    symbol: u8,
+    bits: u8,
+    base: u32,
+}
+
+fn tans_table(br: &mut BitReader, l_bits: u32) -> Result<TansTable> {
+    let l = 1u32 << l_bits;
+    let mut table = TansTable { singles: Vec::new(), weighted: Vec::new() };
+    if br.bit() {
+        let q = br.read(3);
+        let num_syms = br.read(8) as usize + 1;
+        if num_syms < 2 {
+            return Err("tANS table with one symbol".into());
+        }
+        let fluff = read_fluff(br, num_syms);
+        let mut rice = vec![0u8; num_syms + fluff];
+        let mut pos = br.position();
+        golomb_rice_lengths(br.data(), &mut pos, &mut rice)?;
+        br.seek(pos);
+        let ranges = symbol_ranges(br, num_syms, fluff, &rice[num_syms..])?;
+
+        let mut rice = rice.iter();
+        let mut average = 6i32;
+        let mut total = 0i32;
+        for (start, count) in ranges {
+            for sym in start..start + count {
+                let extra = q + *rice.next().ok_or("bad tANS weights")? as u32;
+                if extra > 15 {
+                    return Err("bad tANS weight".into());
+                }
+                let mut v = br.read(extra) as i32 + (1 << extra) - (1 << q);
+                let avg_div4 = average >> 2;
+                let mut limit = 2 * avg_div4;
+                if v <= limit {
+                    v = avg_div4 + (-(v & 1) ^ (v >> 1));
+                }
+                limit = limit.min(v);
+                v += 1;
+                average += limit - avg_div4;
+                // Symbols of weight 0 or less take no states.
+                if v == 1 {
+                    table.singles.push(sym as u8);
+                } else if v >= 2 {
+                    table.weighted.push((sym as u32) << 16 | v as u32);
+                }
+                total += v;
+            }
+        }
+        if total != l as i32 {
+            return Err("tANS weights don't add up".into());
+        }
+    } else {
+        let count = br.read(3) + 1;
+        let bits_per_sym = 32 - l_bits.leading_zeros();
+        let max_delta_bits = br.read(bits_per_sym);
+        if max_delta_bits == 0 || max_delta_bits > l_bits {
+            return Err("bad tANS delta width".into());
+        }
+        let mut seen = [false; 256];
+        let mut weight = 0u32;
+        let mut total = 0u32;
+        for _ in 0..count {
+            let sym = br.read(8);
+            if seen[sym as usize] {
+                return Err("repeated tANS symbol".into());
+            }
+            weight += br.read(max_delta_bits);
+            if weight == 0 {
+                return Err("zero tANS weight".into());
+            }
+            seen[sym as usize] = true;
+            if weight == 1 {
+                table.singles.push(sym as u8);
+            } else {
+                table.weighted.push(sym << 16 | weight);
+            }
+            total += weight;
+        }
+        let sym = br.read(8);
+        if seen[sym as usize] || total >= l || l - total < weight || l - total <= 1 {
+            return Err("bad tANS weights".into());
+        }
+        table.weighted.push(sym << 16 | (l - total));
+        table.singles.sort_unstable();
+        table.weighted.sort_unstable();
+    }
+    Ok(table)
+}
+
+/// Spreads the symbols over the `1 << l_bits` states.
+fn tans_lut(table: &TansTable, l_bits: u32) -> Result<Vec<TansEntry>> {
+    let l = 1usize << l_bits;
+    let slots = l.checked_sub(table.singles.len()).ok_or("too many tANS symbols")?;
+    let mut lut = vec![TansEntry::default(); l];
+
+    // The weighted symbols fill the first `slots` states in four
+    // interleaved runs.
+    let quarter = slots >> 2;
+    let mut next = [0usize; 4];
+    for j in 1..4 {
+        next[j] = next[j - 1] + quarter + usize::from(slots & 3 > j - 1);
+    }
+    for (entry, &symbol) in lut[slots..].iter_mut().zip(&table.singles) {
+        *entry = TansEntry { symbol, bits: l_bits as u8, base: 0 };
+    }
+
+    let bad = || "bad tANS table".to_string();
+    let mut weights_sum = 0i32;
+    for &w in &table.weighted {
+        let weight = (w & 0xFFFF) as i32;
+        let symbol = (w >> 16) as u8;
+        if weight > 4 {
+            let sym_bits = 31 - (weight as u32).leading_zeros();
+            let mut z = l_bits - sym_bits;
+            let mut entry = TansEntry { symbol, bits: z as u8, base: ((l - 1) as u32) & ((weight as u32) << z) };
+            let mut step = 1u32 << z;
+            let mut x = (1i32 << (sym_bits + 1)) - weight;
+            for (j, next) in next.iter_mut().enumerate() {
+                let y = (weight + ((weights_sum - j as i32 - 1) & 3)) >> 2;
+                let mut put = |n: i32, entry: &mut TansEntry, step: u32| -> Result<()> {
+                    for _ in 0..n {
+                        *lut.get_mut(*next).filter(|_| *next < slots).ok_or_else(bad)? = *entry;
+                        *next += 1;
+                        entry.base += step;
+                    }
+                    Ok(())
+                };
+                if x >= y {
+                    put(y, &mut entry, step)?;
+                    x -= y;
+                } else {
+                    put(x, &mut entry, step)?;
+                    z = z.checked_sub(1).ok_or_else(bad)?;
+                    step >>= 1;
+                    entry.bits = z as u8;
+                    entry.base = 0;
+                    put(y - x, &mut entry, step)?;
+                    x = weight;
+                }
+            }
+        } else {
+            if weight <= 0 {
+                return Err(bad());
+            }
+            let mut bits = ((1u32 << weight) - 1) << (weights_sum & 3);
+            bits |= bits >> 4;
+            for ww in weight..2 * weight {
+                let j = bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                let slot = next[j];
+                if slot >= slots {
+                    return Err(bad());
+                }
+                next[j] += 1;
+                let weight_bits = 31 - (ww as u32).leading_zeros();
+                let z = l_bits - weight_bits;
+                lut[slot] = TansEntry { symbol, bits: z as u8, base: ((l - 1) as u32) & ((ww as u32) << z) };
+            }
+        }
+        weights_sum += weight;
+    }
+    Ok(lut)
+}
+
+fn tans(src: &[u8], out: &mut [u8]) -> Result<()> {
+    if src.len() < 8 || out.len() < 5 {
+        return Err("tANS array too small".into());
+    }
+    let mut br = BitReader::new(src);
+    if br.bit() {
+        return Err("reserved tANS bit set".into());
+    }
+    let l_bits = br.read(2) + 8;
+    let table = tans_table(&mut br, l_bits)?;
+    if br.bytes_used() >= src.len() {
+        return Err("tANS table truncated".into());
+    }
+    let lut = tans_lut(&table, l_bits)?;
+    let data = &src[br.bytes_used()..];
+
+    // Two streams, one read forwards and one backwards, drive five states.
+    let mut streams = [LsbReader::new(data), LsbReader::backward(data)];
+    let mut states = [0u32; 5];
+    for (i, state) in states.iter_mut().enumerate() {
+        *state = streams[i & 1].read(l_bits);
+    }
+    // Five symbols from the forward stream, then five from the backward
+    // one; the final states are the last five bytes.
+    let (body, last) = out.split_at_mut(out.len() - 5);
+    for (i, o) in body.iter_mut().enumerate() {
+        let state = &mut states[i % 5];
+        let e = lut[*state as usize];
+        *o = e.symbol;
+        *state = streams[(i / 5) & 1].read(e.bits as u32) + e.base;
+        if *state as usize >= lut.len() {
+            return Err("tANS state out of range".into());
+        }
+    }
+    let [forward, backward] = &streams;
+    if forward.bytes_used() + backward.bytes_used() != data.len() {
+        return Err("tANS streams don't line up".into());
+    }
+    for (o, &state) in last.iter_mut().zip(&states) {
+        *o = u8::try_from(state).map_err(|_| "bad final tANS state")?;
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Run-length and nested arrays
+
+/// Literal bytes read from the front of the command buffer, run commands
+/// from its back.
+fn rle(src: &[u8], out: &mut [u8], depth: u32) -> Result<()> {
+    if src.len() <= 1 {
+        let &byte = src.first().ok_or("empty RLE array")?;
+        out.fill(byte);
+        return Ok(());
+    }
+    // A non-zero first byte starts an array holding the head of the
+    // command buffer; the rest follows as is.
+    let owned;
+    let cmds: &[u8] = if src[0] != 0 {
+        let (mut head, used) = decode_nested(src, MAX_ARRAY, depth + 1)?;
+        head.extend_from_slice(&src[used..]);
+        owned = head;
+        &owned
+    } else {
+        &src[1..]
+    };
+
+    let bad = || "bad RLE command".to_string();
+    let (mut front, mut back, mut d) = (0, cmds.len(), 0);
+    let mut run_byte = 0u8;
+    while front < back {
+        let cmd = cmds[back - 1] as usize;
+        let word = || (back - front >= 2).then(|| le16(&cmds[back - 2..])).ok_or_else(bad);
+        let (copy, run) = if cmd == 0 || cmd >= 0x30 {
+            back -= 1;
+            (!cmd & 0xF, cmd >> 4)
+        } else if cmd >= 0x10 {
+            let v = word()? - 0x1000;
+            back -= 2;
+            (v & 0x3F, v >> 6)
+        } else if cmd == 1 {
+            if back - front < 2 {
+                return Err(bad());
+            }
+            run_byte = cmds[front];
+            front += 1;
+            back -= 1;
+            continue;
+        } else if cmd >= 9 {
+            let v = word()?;
+            back -= 2;
+            (0, (v - 0x8FF) * 128)
+        } else {
+            let v = word()?;
+            back -= 2;
+            ((v - 511) * 64, 0)
+        };
+        if out.len() - d < copy + run || back - front < copy {
+            return Err(bad());
+        }
+        out[d..d + copy].copy_from_slice(&cmds[front..front + copy]);
+        front += copy;
+        d += copy;
+        out[d..d + run].fill(run_byte);
+        d += run;
+    }
+    if front != back || d != out.len() {
+        return Err("RLE array doesn't fill its output".into());
+    }
+    Ok(())
+}
+
+/// Several arrays back to back, or a multi-array holding one.
+fn recursive(src: &[u8], out: &mut [u8], depth: u32) -> Result<()> {
+    if src.len() < 6 {
+        return Err("recursive array too small".into());
+    }
+    let n = src[0] & 0x7F;
+    if n < 2 {
+        return Err("recursive array with fewer than two parts".into());
+    }
+    if src[0] & 0x80 == 0 {
+        let (mut pos, mut d) = (1, 0);
+        for _ in 0..n {
+            let (part, used) = decode_nested(&src[pos..], out.len() - d, depth + 1)?;
+            out[d..d + part.len()].copy_from_slice(&part);
+            d += part.len();
+            pos += used;
+        }
+        if d != out.len() || pos != src.len() {
+            return Err("recursive array doesn't fill its output".into());
+        }
+    } else {
+        let (arrays, used) = multi_array_nested(src, out.len(), 1, depth + 1)?;
+        if arrays[0].len() != out.len() || used != src.len() {
+            return Err("recursive array doesn't fill its output".into());
+        }
+        out.copy_from_slice(&arrays[0]);
+    }
+    Ok(())
+}
+
+/// Decodes `count` arrays that were coded as interleaved pieces of a few
+/// entropy-coded arrays. Their total size is at most `capacity`. Returns
+/// the arrays and the bytes used from `src`.
+pub(super) fn multi_array(src: &[u8], capacity: usize, count: usize) -> Result<(Vec<Vec<u8>>, usize)> {
+    multi_array_nested(src, capacity, count, 0)
+}
+
+fn multi_array_nested(src: &[u8], capacity: usize, count: usize, depth: u32) -> Result<(Vec<Vec<u8>>, usize)> {
+    let bad = |what: &str| format!("bad multi-array {}", what);
+    if src.len() < 4 || src[0] & 0x80 == 0 {
+        return Err(bad("header"));
+    }
+    let sources = (src[0] & 0x3F) as usize;
+    let mut pos = 1;
+
+    if sources == 0 {
+        // Stored one after the other.
+        let mut arrays = Vec::with_capacity(count);
+        let mut total = 0;
+        for _ in 0..count {
+            let (array, used) = decode_nested(&src[pos..], capacity - total, depth)?;
+            total += array.len();
+            pos += used;
+            arrays.push(array);
+        }
+        return Ok((arrays, pos));
+    }
+
+    let mut entropy = Vec::with_capacity(sources);
+    let mut total = 0;
+    for _ in 0..sources {
+        let (array, used) = decode_nested(&src[pos..], MAX_ARRAY, depth)?;
+        total += array.len();
+        pos += used;
+        entropy.push(array);
+    }
+    if src.len() - pos < 3 {
+        return Err(bad("interval table"));
+    }
+    let q = le16(&src[pos..]);
+    pos += 2;
+
+    // Each output array is a list of (source, length) intervals ended by
+    // source 0; lengths are stored as their log2 and the bits below the
+    // leading one.
+    let num_indexes = decoded_size(&src[pos..], total)?;
+    let num_lens = num_indexes.checked_sub(count).filter(|&n| n >= 1).ok_or_else(|| bad("interval count"))?;
+    let (indexes, lenlog2, num_lens) = if q & 0x8000 != 0 {
+        let (packed, used) = decode_nested(&src[pos..], num_indexes, depth)?;
+        if packed.len() != num_indexes {
+            return Err(bad("interval count"));
+        }
+        pos += used;
+        let indexes = packed.iter().map(|t| t & 0xF).collect::<Vec<_>>();
+        let lenlog2 = packed.iter().map(|t| t >> 4).collect::<Vec<_>>();
+        (indexes, lenlog2, num_indexes)
+    } else {
+        let (indexes, used) = decode_nested(&src[pos..], num_indexes, depth)?;
+        if indexes.len() != num_indexes {
+            return Err(bad("interval count"));
+        }
+        pos += used;
+        let (lenlog2, used) = decode_nested(&src[pos..], num_lens, depth)?;
+        if lenlog2.len() != num_lens || lenlog2.iter().any(|&l| l > 16) {
+            return Err(bad("interval lengths"));
+        }
+        pos += used;
+        (indexes, lenlog2, num_lens)
+    };
+
+    let varbits_len = q & 0x3FFF;
+    let varbits = src.get(pos..pos + varbits_len).ok_or_else(|| bad("interval lengths"))?;
+    let mut streams = [BitReader::new(varbits), BitReader::backward(varbits)];
+    let lens: Vec<usize> = lenlog2
+        .iter()
+        .take(num_lens)
+        .enumerate()
+        .map(|(i, &n)| (1 << n) | streams[i & 1].read(n as u32) as usize)
+        .collect();
+
+    if indexes[num_indexes - 1] != 0 {
+        return Err(bad("interval list"));
+    }
+    let mut taken = vec![0usize; sources];
+    let (mut indi, mut leni, mut out_total) = (0, 0, 0);
+    let skip_terminator_len = usize::from(q & 0x8000 != 0);
+    let mut arrays = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mut array = Vec::new();
+        if indi >= num_indexes {
+            return Err(bad("interval list"));
+        }
+        loop {
+            let source = indexes[indi] as usize;
+            indi += 1;
+            if source == 0 {
+                break;
+            }
+            if source > sources || leni >= num_lens {
+                return Err(bad("interval"));
+            }
+            let len = lens[leni];
+            leni += 1;
+            let from = &entropy[source - 1];
+            let at = taken[source - 1];
+            if len > from.len() - at || len > capacity - out_total {
+                return Err(bad("interval"));
+            }
+            array.extend_from_slice(&from[at..at + len]);
+            taken[source - 1] += len;
+            out_total += len;
+        }
+        leni += skip_terminator_len;
+        arrays.push(array);
+    }
+    if indi != num_indexes || leni != num_lens || taken.iter().zip(&entropy).any(|(&t, e)| t != e.len()) {
+        return Err(bad("interval list"));
+    }
+    Ok((arrays, pos + varbits_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stored array with the 3-byte header.
+    fn stored(data: &[u8]) -> Vec<u8> {
+        let mut out = (data.len() as u32).to_be_bytes()[1..].to_vec();
+        out.extend_from_slice(data);
+        out
+    }
+
+    /// A coded array of `kind` with the 5-byte header.
+    fn coded(kind: u8, payload: &[u8], dst_size: usize) -> Vec<u8> {
+        let bits = ((dst_size as u64 - 1) << 18) | payload.len() as u64;
+        let mut out = bits.to_be_bytes()[3..].to_vec();
+        out[0] |= kind << 4;
+        out.extend_from_slice(payload);
+        out
+    }
+
+    #[test]
+    fn reads_stored_arrays_in_both_header_forms() {
+        assert_eq!(decode_bytes(&stored(b"abc"), 10).unwrap(), (b"abc".to_vec(), 6));
+        // 12-bit size form.
+        assert_eq!(decode_bytes(&[0x80, 0x02, b'h', b'i', 0xFF], 10).unwrap(), (b"hi".to_vec(), 4));
+        assert!(decode_bytes(&stored(b"abc"), 2).is_err());
+        assert!(decode_bytes(&stored(b"abc")[..5], 10).is_err());
+    }
+
+    #[test]
+    fn decodes_run_length_arrays() {
+        // Commands run from the back: set the run byte to 'x' (consuming
+        // the leading literal 'x'), copy "ab" with a run of three, then a
+        // run of five with no literals.
+        let copy_ab_run_3 = 0x30 | (!2u8 & 0xF);
+        let run_5 = 0x50 | 0xF;
+        let payload = [0, b'x', b'a', b'b', run_5, copy_ab_run_3, 1];
+        let (out, _) = decode_bytes(&coded(3, &payload, 10), 64).unwrap();
+        assert_eq!(out, b"abxxxxxxxx");
+        // Output size disagrees with the commands.
+        assert!(decode_bytes(&coded(3, &payload, 11), 64).is_err());
+        // A single byte fills the output.
+        assert_eq!(decode_bytes(&coded(3, &[7], 4), 64).unwrap().0, [7; 4]);
+    }
+
+    #[test]
+    fn decodes_recursive_arrays() {
+        // Two single-byte run-length arrays back to back.
+        let mut payload = vec![2];
+        payload.extend(coded(3, b"a", 20));
+        payload.extend(coded(3, b"b", 30));
+        let mut want = vec![b'a'; 20];
+        want.extend([b'b'; 30]);
+        assert_eq!(decode_bytes(&coded(5, &payload, 50), 64).unwrap().0, want);
+        assert!(decode_bytes(&coded(5, &payload, 51), 64).is_err());
+    }
+
+    #[test]
+    fn decodes_sparse_huffman_tables() {
+        // Old-style sparse table: 'a' and 'b', both one bit long, so 'a'
+        // is code 0 and 'b' code 1.
+        let mut table = BitWriter::default();
+        table.put(0, 1); // old table format
+        table.put(0, 1); // sparse
+        table.put(2, 8); // two symbols
+        table.put(0, 3); // lengths take 0 bits (all 1)
+        table.put(b'a' as u32, 8);
+        table.put(b'b' as u32, 8);
+        let mut payload = table.finish();
+        // Three streams take the symbols in turn: the first from the
+        // start, the second backwards from the end and the third forwards
+        // from the split, each least significant bit first.
+        let text = b"abbaabbbaaabababbbbaaaba";
+        let stream = |first: usize| (first..text.len()).step_by(3).enumerate().fold(0u8, |v, (i, p)| v | (text[p] - b'a') << i);
+        payload.extend_from_slice(&1u16.to_le_bytes());
+        payload.extend([stream(0), stream(2), stream(1)]);
+        assert_eq!(decode_bytes(&coded(2, &payload, text.len()), 64).unwrap().0, text);
+        // Streams that don't meet.
+        payload.push(0);
+        assert!(decode_bytes(&coded(2, &payload, text.len()), 64).is_err());
+    }
+
+    #[derive(Default)]
+    struct BitWriter {
+        bytes: Vec<u8>,
+        bits: usize,
+    }
+
+    impl BitWriter {
+        fn put(&mut self, v: u32, n: u32) {
+            for i in (0..n).rev() {
+                if self.bits.is_multiple_of(8) {
+                    self.bytes.push(0);
+                }
+                *self.bytes.last_mut().unwrap() |= ((v >> i & 1) as u8) << (7 - self.bits % 8);
+                self.bits += 1;
+            }
+        }
+
+        fn finish(self) -> Vec<u8> {
+            self.bytes
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Kraken chunks (`Kraken_ReadLzTable` and `Kraken_ProcessLzRuns` in ooz),
+//! plus the offset and length unpacking Leviathan shares.
+
+use super::bits::BitReader;
+use super::entropy::decode_bytes;
+use super::{copy_match, put_literals, Result};
+
+/// Decodes the chunk `src` into `dst[start..]`. Matches may reach back to
+/// the start of `dst`; `mode` 0 adds literals to the byte at the last
+/// match offset, mode 1 stores them as is.
+pub(super) fn decode_chunk(mode: u32, src: &[u8], dst: &mut [u8], start: usize) -> Result<()> {
+    if mode > 1 {
+        return Err(format!("unknown Kraken chunk mode {}", mode));
+    }
+    if src.len() < 13 {
+        return Err("Kraken chunk too small".into());
+    }
+    let count = dst.len() - start;
+    let (mut pos, mut d) = (0, start);
+    // The stream opens with eight raw bytes.
+    if start == 0 {
+        dst[..8].copy_from_slice(&src[..8]);
+        pos = 8;
+        d = 8;
+    }
+    if src[pos] & 0x80 != 0 {
+        return Err("Kraken chunks with excess bytes are not supported".into());
+    }
+
+    let (lits, n) = decode_bytes(&src[pos..], count)?;
+    pos += n;
+    let (cmds, n) = decode_bytes(&src[pos..], count)?;
+    pos += n;
+    if src.len() - pos < 3 {
+        return Err("Kraken chunk truncated".into());
+    }
+
+    let mut scale = 0;
+    if src[pos] & 0x80 != 0 {
+        scale = src[pos] as i64 - 127;
+        pos += 1;
+    }
+    let (packed_offsets, n) = decode_bytes(&src[pos..], cmds.len())?;
+    pos += n;
+    let mut extra = None;
+    if scale > 1 {
+        let (low, n) = decode_bytes(&src[pos..], packed_offsets.len())?;
+        if low.len() != packed_offsets.len() {
+            return Err("Kraken offset arrays differ in length".into());
+        }
+        extra = Some(low);
+        pos += n;
+    }
+    let (packed_lens, n) = decode_bytes(&src[pos..], count >> 2)?;
+    pos += n;
+    let (offsets, lens) = unpack_offsets(&src[pos..], &packed_offsets, extra.as_deref(), scale, &packed_lens)?;
+
+    // Each command is literals, then a match: two bits of literal length
+    // (3 = from the length stream), four of match length (15 = likewise)
+    // and two picking one of three recent offsets or a new one.
+    let mut recent = [-8i64; 7];
+    let mut last = -8i64;
+    let (mut lit, mut oi, mut li) = (0, 0, 0);
+    let mut next_len = || -> Result<usize> {
+        let len = *lens.get(li).ok_or("Kraken length stream runs out")?;
+        li += 1;
+        Ok(len)
+    };
+    for &cmd in &cmds {
+        let cmd = cmd as usize;
+        let litlen = match cmd & 3 {
+            3 => next_len()?,
+            n => n,
+        };
+        let literals = lits.get(lit..lit + litlen).ok_or("Kraken literals run out")?;
+        put_literals(dst, d, literals, (mode == 0).then_some(last))?;
+        d += litlen;
+        lit += litlen;
+
+        let idx = cmd >> 6;
+        recent[6] = offsets.get(oi).copied().unwrap_or(0);
+        let offset = recent[idx + 3];
+        recent.copy_within(idx..idx + 3, idx + 1);
+        recent[3] = offset;
+        last = offset;
+        if idx == 3 {
+            oi += 1;
+        }
+
+        let len = match (cmd >> 2) & 0xF {
+            15 => 14 + next_len()?,
+            n => n + 2,
+        };
+        copy_match(dst, d, offset, len)?;
+        d += len;
+    }
+    if oi != offsets.len() || li != lens.len() {
+        return Err("Kraken offset or length stream not used up".into());
+    }
+    if lits.len() - lit != dst.len() - d {
+        return Err("Kraken literals don't fill the chunk".into());
+    }
+    put_literals(dst, d, &lits[lit..], (mode == 0).then_some(last))
+}
+
+/// Expands the packed offsets and lengths (`Kraken_UnpackOffsets`). The
+/// extra bits they need come from `src`, read from both ends at once.
+/// Returns negative offsets and lengths.
+pub(super) fn unpack_offsets(
+    src: &[u8],
+    packed_offsets: &[u8],
+    extra: Option<&[u8]>,
+    scale: i64,
+    packed_lens: &[u8],
+) -> Result<(Vec<i64>, Vec<usize>)> {
+    let mut streams = [BitReader::new(src), BitReader::backward(src)];
+
+    // The number of lengths too long for a byte leads the backward stream.
+    if streams[1].peek(32) < 0x2000 {
+        return Err("bad long length count".into());
+    }
+    let lz = streams[1].leading_zeros();
+    streams[1].skip(lz);
+    let long_count = streams[1].read(lz + 1) as usize - 1;
+    if long_count > 512 {
+        return Err("too many long lengths".into());
+    }
+
+    let mut offsets = Vec::with_capacity(packed_offsets.len());
+    for (i, &v) in packed_offsets.iter().enumerate() {
+        let bits = &mut streams[i & 1];
+        let offset = if scale == 0 {
+            -read_distance(bits, v as u32)
+        } else {
+            let n = v as u32 >> 3;
+            if n > 26 {
+                return Err("bad offset width".into());
+            }
+            8 - (((8 + (v & 7) as i64) << n) | bits.read(n) as i64)
+        };
+        offsets.push(offset);
+    }
+    if scale > 1 {
+        let extra = extra.ok_or("missing low offset bits")?;
+        for (offset, &low) in offsets.iter_mut().zip(extra) {
+            *offset = scale * *offset - low as i64;
+        }
+    }
+
+    let mut long_lens = Vec::with_capacity(long_count);
+    for i in 0..long_count {
+        long_lens.push(read_length(&mut streams[i & 1])?);
+    }
+    let [forward, backward] = &streams;
+    if forward.bytes_used() + backward.bytes_used() != src.len() {
+        return Err("offset bit streams don't line up".into());
+    }
+
+    let mut long_lens = long_lens.into_iter();
+    let lens = packed_lens
+        .iter()
+        .map(|&v| match v {
+            255 => long_lens.next().map(|long| long + 255 + 3).ok_or("long lengths run out"),
+            v => Ok(v as usize + 3),
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    if long_lens.next().is_some() {
+        return Err("long lengths left over".into());
+    }
+    Ok((offsets, lens))
+}
+
+/// A distance coded as a byte (bit count and low bits) plus extra bits.
+fn read_distance(bits: &mut BitReader, v: u32) -> i64 {
+    if v < 0xF0 {
+        let n = (v >> 4) + 4;
+        let top = (1 << n) | bits.read(n) as i64;
+        (top << 4) + (v & 0xF) as i64 - 248
+    } else {
+        let n = v - 0xF0 + 4;
+        let top = (1 << n) | bits.read(n) as i64;
+        let low = bits.read(12) as i64;
+        8_322_816 + (top << 12) + low
+    }
+}
+
+/// An Elias-gamma style length: zeros, then that many plus seven bits.
+fn read_length(bits: &mut BitReader) -> Result<usize> {
+    let lz = bits.leading_zeros();
+    if lz > 12 {
+        return Err("bad long length".into());
+    }
+    bits.skip(lz);
+    Ok(bits.read(lz + 7) as usize - 64)
+}
@@ -0,0 +1,164 @@
+//! Leviathan chunks (`Leviathan_ReadLzTable` and `Leviathan_ProcessLzRuns`
+//! in ooz). Like Kraken, but with seven recent offsets, literals that may
+//! be split over several arrays by context, and optionally eight command
+//! arrays picked by output position.
+
+use super::entropy::{decode_bytes, multi_array};
+use super::kraken::unpack_offsets;
+use super::{back_ref, copy_match, Result};
+
+/// Decodes the chunk `src` into `dst[start..]`, like
+/// [`super::kraken::decode_chunk`]. `mode` says how literals are coded.
+pub(super) fn decode_chunk(mode: u32, src: &[u8], dst: &mut [u8], start: usize) -> Result<()> {
+    if mode > 5 {
+        return Err(format!("unknown Leviathan chunk mode {}", mode));
+    }
+    if src.len() < 13 {
+        return Err("Leviathan chunk too small".into());
+    }
+    let count = dst.len() - start;
+    let (mut pos, mut d) = (0, start);
+    if start == 0 {
+        dst[..8].copy_from_slice(&src[..8]);
+        pos = 8;
+        d = 8;
+    }
+
+    let mut scale = 0;
+    if src[pos] & 0x80 != 0 {
+        scale = src[pos] as i64 - 127;
+        pos += 1;
+    }
+    let (packed_offsets, n) = decode_bytes(&src[pos..], count / 3)?;
+    pos += n;
+    let mut extra = None;
+    if scale > 1 {
+        let (low, n) = decode_bytes(&src[pos..], count / 3)?;
+        if low.len() != packed_offsets.len() {
+            return Err("Leviathan offset arrays differ in length".into());
+        }
+        extra = Some(low);
+        pos += n;
+    }
+    let (packed_lens, n) = decode_bytes(&src[pos..], count / 5)?;
+    pos += n;
+
+    let lits = if mode <= 1 {
+        let (lits, n) = decode_bytes(&src[pos..], count)?;
+        pos += n;
+        vec![lits]
+    } else {
+        let arrays = match mode {
+            2 => 2,
+            3 => 4,
+            _ => 16,
+        };
+        let (lits, n) = multi_array(&src[pos..], count, arrays)?;
+        pos += n;
+        lits
+    };
+
+    let cmds = match src.get(pos) {
+        Some(0x83) => {
+            let (cmds, n) = multi_array(&src[pos + 1..], count, 8)?;
+            pos += 1 + n;
+            cmds
+        }
+        Some(b) if b & 0x80 != 0 => return Err("bad Leviathan command arrays".into()),
+        Some(_) => {
+            let (cmds, n) = decode_bytes(&src[pos..], count)?;
+            pos += n;
+            vec![cmds]
+        }
+        None => return Err("Leviathan chunk truncated".into()),
+    };
+    let (offsets, lens) = unpack_offsets(&src[pos..], &packed_offsets, extra.as_deref(), scale, &packed_lens)?;
+
+    // Each command is literals, then a match: three bits of match length
+    // (9 = from the back of the length stream), two of literal length (3 =
+    // from its front) and three picking one of seven recent offsets or a
+    // new one.
+    let mut lits = Literals { mode, pos: vec![0; lits.len()], arrays: lits };
+    let mut cmd_pos = vec![0; cmds.len()];
+    let mut recent = [-8i64; 16];
+    let mut offset = -8i64;
+    let (mut oi, mut li, mut lens_end) = (0, 0, lens.len());
+    loop {
+        let array = if cmds.len() == 1 { 0 } else { d & 7 };
+        let Some(&cmd) = cmds[array].get(cmd_pos[array]) else {
+            break;
+        };
+        cmd_pos[array] += 1;
+        let cmd = cmd as usize;
+
+        let litlen = match (cmd >> 3) & 3 {
+            3 => {
+                let len = *lens[..lens_end].get(li).ok_or("Leviathan length stream runs out")?;
+                li += 1;
+                len
+            }
+            n => n,
+        };
+        lits.copy(dst, d, litlen, offset)?;
+        d += litlen;
+
+        let idx = cmd >> 5;
+        recent[15] = offsets.get(oi).copied().unwrap_or(0);
+        offset = recent[idx + 8];
+        recent.copy_within(8..8 + idx, 9);
+        recent[8] = offset;
+        if idx == 7 {
+            oi += 1;
+        }
+
+        let len = match (cmd & 7) + 2 {
+            9 => {
+                if lens_end <= li {
+                    return Err("Leviathan length stream runs out".into());
+                }
+                lens_end -= 1;
+                lens[lens_end] + 6
+            }
+            n => n,
+        };
+        copy_match(dst, d, offset, len)?;
+        d += len;
+    }
+    if oi != offsets.len() || li != lens_end {
+        return Err("Leviathan offset or length stream not used up".into());
+    }
+    lits.copy(dst, d, dst.len() - d, offset)
+}
+
+/// Literal arrays and how far each has been read.
+struct Literals {
+    mode: u32,
+    arrays: Vec<Vec<u8>>,
+    pos: Vec<usize>,
+}
+
+impl Literals {
+    /// Writes `len` literals at `dst[d..]`. Modes 0, 2, 3 and 5 add each
+    /// to the byte at the last match offset; 2 takes the first of each run
+    /// from its own array, 3 and 5 pick the array by output position and
+    /// 4 by the top bits of the previous byte.
+    fn copy(&mut self, dst: &mut [u8], d: usize, len: usize, offset: i64) -> Result<()> {
+        if len > dst.len() - d {
+            return Err("Leviathan literals overrun the chunk".into());
+        }
+        for at in d..d + len {
+            let (array, sub) = match self.mode {
+                0 => (0, true),
+                1 => (0, false),
+                2 => (usize::from(at == d), true),
+                3 => (at & 3, true),
+                4 => ((dst[at.checked_sub(1).ok_or("Leviathan literal context out of range")?] >> 4) as usize, false),
+                _ => (at & 15, true),
+            };
+            let &b = self.arrays[array].get(self.pos[array]).ok_or("Leviathan literals run out")?;
+            self.pos[array] += 1;
+            dst[at] = if sub { b.wrapping_add(dst[back_ref(at, offset)?]) } else { b };
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+//! Mermaid chunks (`Mermaid_ReadLzTable` and `Mermaid_ProcessLzRuns` in
+//! ooz). A chunk is decoded as up to two 64 KiB halves, each with its own
+//! commands and far offsets; literals, near offsets and lengths run on.
+
+use super::entropy::decode_bytes;
+use super::{copy_match, put_literals, Result};
+
+const HALF: usize = 0x10000;
+
+fn le16(src: &[u8], pos: usize) -> Result<usize> {
+    let b = src.get(pos..pos + 2).ok_or("Mermaid chunk truncated")?;
+    Ok(u16::from_le_bytes([b[0], b[1]]) as usize)
+}
+
+/// Decodes the chunk `src` into `dst[start..]`, like
+/// [`super::kraken::decode_chunk`].
+pub(super) fn decode_chunk(mode: u32, src: &[u8], dst: &mut [u8], start: usize) -> Result<()> {
+    if mode > 1 {
+        return Err(format!("unknown Mermaid chunk mode {}", mode));
+    }
+    if src.len() < 10 {
+        return Err("Mermaid chunk too small".into());
+    }
+    let count = dst.len() - start;
+    let mut pos = 0;
+    if start == 0 {
+        dst[..8].copy_from_slice(&src[..8]);
+        pos = 8;
+    }
+
+    let (lits, n) = decode_bytes(&src[pos..], count)?;
+    pos += n;
+    let (cmds, n) = decode_bytes(&src[pos..], count)?;
+    pos += n;
+    let split = if count <= HALF {
+        cmds.len()
+    } else {
+        let split = le16(src, pos)?;
+        pos += 2;
+        if split > cmds.len() {
+            return Err("bad Mermaid command split".into());
+        }
+        split
+    };
+
+    // Near offsets: raw, or split into entropy-coded high and low bytes.
+    let near_count = le16(src, pos)?;
+    pos += 2;
+    let near: Vec<i64> = if near_count == 0xFFFF {
+        let (hi, n) = decode_bytes(&src[pos..], count >> 1)?;
+        pos += n;
+        let (lo, n) = decode_bytes(&src[pos..], count >> 1)?;
+        pos += n;
+        if lo.len() != hi.len() {
+            return Err("Mermaid offset arrays differ in length".into());
+        }
+        lo.iter().zip(&hi).map(|(&lo, &hi)| (hi as i64) << 8 | lo as i64).collect()
+    } else {
+        let raw = src.get(pos..pos + 2 * near_count).ok_or("Mermaid chunk truncated")?;
+        pos += raw.len();
+        raw.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]]) as i64).collect()
+    };
+
+    // Far offsets, counted back from the start of each half.
+    let sizes = src.get(pos..pos + 3).ok_or("Mermaid chunk truncated")?;
+    let sizes = u32::from_le_bytes([sizes[0], sizes[1], sizes[2], 0]) as usize;
+    pos += 3;
+    let (mut far1, mut far2) = (Vec::new(), Vec::new());
+    if sizes != 0 {
+        let (mut size1, mut size2) = (sizes >> 12, sizes & 0xFFF);
+        if size1 == 4095 {
+            size1 = le16(src, pos)?;
+            pos += 2;
+        }
+        if size2 == 4095 {
+            size2 = le16(src, pos)?;
+            pos += 2;
+        }
+        far1 = far_offsets(src, &mut pos, size1, start)?;
+        far2 = far_offsets(src, &mut pos, size2, start + HALF)?;
+    }
+
+    // The rest of the chunk is the length stream.
+    let mut runs = Runs { mode, lits: &lits, lit: 0, near: &near, ni: 0, src, len_pos: pos, recent: -8 };
+    runs.half(dst, start, &cmds[..split], &far1)?;
+    if count > HALF {
+        runs.half(dst, start + HALF, &cmds[split..], &far2)?;
+    }
+    if runs.len_pos != src.len() {
+        return Err("Mermaid length stream not used up".into());
+    }
+    Ok(())
+}
+
+fn far_offsets(src: &[u8], pos: &mut usize, count: usize, bound: usize) -> Result<Vec<usize>> {
+    let mut out = Vec::with_capacity(count);
+    for _ in 0..count {
+        let b = src.get(*pos..*pos + 3).ok_or("Mermaid chunk truncated")?;
+        let mut off = u32::from_le_bytes([b[0], b[1], b[2], 0]) as usize;
+        *pos += 3;
+        // Past 12 MiB a fourth byte extends the offset.
+        if bound >= 0xBF_FFFF && off >= 0xC0_0000 {
+            off += (*src.get(*pos).ok_or("Mermaid chunk truncated")? as usize) << 22;
+            *pos += 1;
+        }
+        if off > bound {
+            return Err("Mermaid far offset out of range".into());
+        }
+        out.push(off);
+    }
+    Ok(out)
+}
+
+/// Decoding state carried from one half to the next.
+struct Runs<'a> {
+    mode: u32,
+    lits: &'a [u8],
+    lit: usize,
+    near: &'a [i64],
+    ni: usize,
+    src: &'a [u8],
+    len_pos: usize,
+    recent: i64,
+}
+
+impl Runs<'_> {
+    fn literals(&mut self, dst: &mut [u8], d: usize, len: usize) -> Result<()> {
+        let lits = self.lits.get(self.lit..self.lit + len).ok_or("Mermaid literals run out")?;
+        put_literals(dst, d, lits, (self.mode == 0).then_some(self.recent))?;
+        self.lit += len;
+        Ok(())
+    }
+
+    fn near_offset(&mut self) -> Result<i64> {
+        let off = *self.near.get(self.ni).ok_or("Mermaid near offsets run out")?;
+        self.ni += 1;
+        Ok(-off)
+    }
+
+    /// A byte, or for values past 251 the byte plus four times the
+    /// following 16-bit word.
+    fn length(&mut self) -> Result<usize> {
+        let &b = self.src.get(self.len_pos).ok_or("Mermaid length stream runs out")?;
+        let mut len = b as usize;
+        if b > 251 {
+            len += 4 * le16(self.src, self.len_pos + 1)?;
+            self.len_pos += 2;
+        }
+        self.len_pos += 1;
+        Ok(len)
+    }
+
+    fn half(&mut self, dst: &mut [u8], begin: usize, cmds: &[u8], far: &[usize]) -> Result<()> {
+        let end = (begin + HALF).min(dst.len());
+        let dst = &mut dst[..end];
+        // The stream's first eight bytes came raw.
+        let mut d = if begin == 0 { 8 } else { begin };
+        let mut far = far.iter();
+        let mut far_offset = |d: usize| -> Result<i64> {
+            let off = far.next().ok_or("Mermaid far offsets run out")?;
+            Ok((begin - off) as i64 - d as i64)
+        };
+        for &cmd in cmds {
+            let cmd = cmd as usize;
+            let len = match cmd {
+                // Literal run.
+                0 => {
+                    let len = self.length()? + 64;
+                    self.literals(dst, d, len)?;
+                    d += len;
+                    continue;
+                }
+                // Long match at a new near offset.
+                1 => {
+                    let len = self.length()? + 91;
+                    self.recent = self.near_offset()?;
+                    len
+                }
+                // Long match at a far offset.
+                2 => {
+                    let len = self.length()? + 29;
+                    self.recent = far_offset(d)?;
+                    len
+                }
+                // Medium match at a far offset.
+                3..=23 => {
+                    self.recent = far_offset(d)?;
+                    cmd + 5
+                }
+                // Up to seven literals, then a short match at the last
+                // offset or, below 0x80, a new near one.
+                _ => {
+                    let litlen = cmd & 7;
+                    self.literals(dst, d, litlen)?;
+                    d += litlen;
+                    if cmd < 0x80 {
+                        self.recent = self.near_offset()?;
+                    }
+                    (cmd >> 3) & 0xF
+                }
+            };
+            copy_match(dst, d, self.recent, len)?;
+            d += len;
+        }
+        let rest = dst.len().checked_sub(d).ok_or("Mermaid commands overrun the chunk")?;
+        self.literals(dst, d, rest)
+    }
+}
@@ -0,0 +1,401 @@
+//! Pure-Rust Oodle backend, used instead of the C++ `ooz` library when the
+//! `rust-oodle` feature is enabled.
+//!
+//! An Oodle stream is a series of 256 KiB blocks, each opening with a
+//! two-byte header (codec and flags), split into quanta with a 3-byte
+//! header of their own. A quantum is stored, a single repeated byte, or up
+//! to two 128 KiB chunks of Kraken, Mermaid or Leviathan LZ data; the LZ
+//! streams (literals, commands, offsets, lengths) are themselves
+//! entropy-coded arrays. This is a port of the ooz decoder. There is no
+//! encoder: compressing needs the native backend.
+
+mod bits;
+mod entropy;
+mod kraken;
+mod leviathan;
+mod mermaid;
+
+type Result<T> = std::result::Result<T, String>;
+
+/// Oodle splits the output into blocks of this size; each starts with a
+/// block header.
+const BLOCK_SIZE: usize = 0x40000;
+
+/// Quanta are decoded in chunks of at most this size.
+const CHUNK_SIZE: usize = 0x20000;
+
+/// Decoder type in the block header.
+const KRAKEN: u8 = 6;
+const MERMAID: u8 = 10;
+const LEVIATHAN: u8 = 12;
+
+#[derive(Debug)]
+struct BlockHeader {
+    decoder_type: u8,
+    uncompressed: bool,
+    use_checksums: bool,
+}
+
+fn parse_block_header(src: &[u8]) -> Option<BlockHeader> {
+    let (&b0, &b1) = (src.first()?, src.get(1)?);
+    // Low nibble is a fixed 0xC; bits 4-5 are reserved.
+    if b0 & 0xF != 0xC || (b0 >> 4) & 3 != 0 {
+        return None;
+    }
+    Some(BlockHeader {
+        decoder_type: b1 & 0x7F,
+        uncompressed: (b0 >> 6) & 1 != 0,
+        use_checksums: b1 >> 7 != 0,
+    })
+}
+
+#[derive(Debug, PartialEq)]
+enum Quantum {
+    /// `size` bytes of entropy-coded (or, if it equals the quantum, raw) data.
+    Compressed { size: usize },
+    /// The whole quantum is this byte.
+    Memset(u8),
+}
+
+/// Returns the quantum and the length of its header.
+fn parse_quantum_header(src: &[u8], use_checksums: bool) -> Option<(Quantum, usize)> {
+    let v = (*src.first()? as u32) << 16 | (*src.get(1)? as u32) << 8 | *src.get(2)? as u32;
+    let size = v & 0x3FFFF;
+    if size != 0x3FFFF {
+        // The 3-byte checksum, if present, is not verified (ooz skips it too
+        // unless asked).
+        let header_len = if use_checksums { 6 } else { 3 };
+        if src.len() < header_len {
+            return None;
+        }
+        return Some((Quantum::Compressed { size: size as usize + 1 }, header_len));
+    }
+    match v >> 18 {
+        1 => Some((Quantum::Memset(*src.get(3)?), 4)),
+        _ => None,
+    }
+}
+
+/// Same contract as `Ooz_Decompress`: decodes `src` into exactly `dst_len`
+/// bytes of `dst` and returns `dst_len`, or -1 if the stream is malformed.
+pub fn decompress(src: &[u8], dst: &mut [u8], dst_len: usize) -> i32 {
+    match decode(src, &mut dst[..dst_len]) {
+        Ok(()) => dst_len as i32,
+        Err(reason) => {
+            log::error!("rust-oodle: {}", reason);
+            -1
+        }
+    }
+}
+
+fn decode(mut src: &[u8], dst: &mut [u8]) -> Result<()> {
+    let mut dst_offset = 0;
+    let mut header = None;
+    while dst_offset < dst.len() {
+        if dst_offset % BLOCK_SIZE == 0 {
+            let parsed = parse_block_header(src).ok_or("bad block header")?;
+            if !matches!(parsed.decoder_type, KRAKEN | MERMAID | LEVIATHAN) {
+                return Err(format!("unsupported decoder type {}", parsed.decoder_type));
+            }
+            src = &src[2..];
+            header = Some(parsed);
+        }
+        let header = header.as_ref().ok_or("missing block header")?;
+        let out_len = (BLOCK_SIZE - dst_offset % BLOCK_SIZE).min(dst.len() - dst_offset);
+
+        if header.uncompressed {
+            let raw = src.get(..out_len).ok_or("stored block truncated")?;
+            dst[dst_offset..dst_offset + out_len].copy_from_slice(raw);
+            src = &src[out_len..];
+        } else {
+            let (quantum, header_len) =
+                parse_quantum_header(src, header.use_checksums).ok_or("bad quantum header")?;
+            src = &src[header_len..];
+            match quantum {
+                Quantum::Memset(byte) => dst[dst_offset..dst_offset + out_len].fill(byte),
+                Quantum::Compressed { size } if size > src.len() => {
+                    return Err("quantum truncated".to_string());
+                }
+                Quantum::Compressed { size } if size == out_len => {
+                    dst[dst_offset..dst_offset + out_len].copy_from_slice(&src[..size]);
+                    src = &src[size..];
+                }
+                Quantum::Compressed { size } => {
+                    // Matches may reach back into earlier blocks.
+                    decode_quantum(header.decoder_type, &src[..size], &mut dst[..dst_offset + out_len], dst_offset)?;
+                    src = &src[size..];
+                }
+            }
+        }
+        dst_offset += out_len;
+    }
+    Ok(())
+}
+
+/// Decodes the LZ quantum `src` into `dst[start..]` (`Kraken_DecodeQuantum`
+/// and its Mermaid and Leviathan twins). Each chunk is entropy-coded bytes
+/// with no matches, stored, or an LZ chunk for the block's codec.
+fn decode_quantum(decoder_type: u8, src: &[u8], dst: &mut [u8], start: usize) -> Result<()> {
+    let (mut pos, mut d) = (0, start);
+    while d < dst.len() {
+        let count = (dst.len() - d).min(CHUNK_SIZE);
+        let header = src.get(pos..pos + 4).ok_or("chunk truncated")?;
+        let header = (header[0] as usize) << 16 | (header[1] as usize) << 8 | header[2] as usize;
+        if header & 0x80_0000 == 0 {
+            let (bytes, used) = entropy::decode_bytes(&src[pos..], count)?;
+            if bytes.len() != count {
+                return Err(format!("chunk of {} bytes where {} were expected", bytes.len(), count));
+            }
+            dst[d..d + count].copy_from_slice(&bytes);
+            pos += used;
+        } else {
+            pos += 3;
+            let used = header & 0x7_FFFF;
+            let mode = (header >> 19) as u32 & 0xF;
+            let chunk = src.get(pos..pos + used).ok_or("chunk truncated")?;
+            if used < count {
+                let dst = &mut dst[..d + count];
+                match decoder_type {
+                    KRAKEN => kraken::decode_chunk(mode, chunk, dst, d)?,
+                    MERMAID => mermaid::decode_chunk(mode, chunk, dst, d)?,
+                    _ => leviathan::decode_chunk(mode, chunk, dst, d)?,
+                }
+            } else if used == count && mode == 0 {
+                dst[d..d + count].copy_from_slice(chunk);
+            } else {
+                return Err("bad stored chunk".into());
+            }
+            pos += used;
+        }
+        d += count;
+    }
+    if pos != src.len() {
+        return Err("quantum not used up".into());
+    }
+    Ok(())
+}
+
+/// The index `-offset` bytes before `at`, if `offset` is negative and
+/// stays inside the output.
+fn back_ref(at: usize, offset: i64) -> Result<usize> {
+    offset
+        .checked_neg()
+        .and_then(|back| usize::try_from(back).ok())
+        .filter(|&back| back >= 1 && back <= at)
+        .map(|back| at - back)
+        .ok_or_else(|| format!("offset {} out of range at {}", offset, at))
+}
+
+/// Copies a `len`-byte match from `-offset` bytes back to `dst[at..]`, a
+/// byte at a time so that overlapping matches repeat.
+fn copy_match(dst: &mut [u8], at: usize, offset: i64, len: usize) -> Result<()> {
+    let from = back_ref(at, offset)?;
+    if len > dst.len() - at {
+        return Err("match runs past the chunk".into());
+    }
+    for i in 0..len {
+        dst[at + i] = dst[from + i];
+    }
+    Ok(())
+}
+
+/// Writes `lits` to `dst[at..]`. With `delta`, each is added to the byte
+/// that far back (the "sub" literal mode).
+fn put_literals(dst: &mut [u8], at: usize, lits: &[u8], delta: Option<i64>) -> Result<()> {
+    if lits.len() > dst.len() - at {
+        return Err("literals run past the chunk".into());
+    }
+    match delta {
+        None => dst[at..at + lits.len()].copy_from_slice(lits),
+        Some(offset) if !lits.is_empty() => {
+            let from = back_ref(at, offset)?;
+            for (i, &lit) in lits.iter().enumerate() {
+                dst[at + i] = lit.wrapping_add(dst[from + i]);
+            }
+        }
+        Some(_) => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_vec(src: &[u8], len: usize) -> Option<Vec<u8>> {
+        let mut out = vec![0u8; len];
+        (decompress(src, &mut out, len) == len as i32).then_some(out)
+    }
+
+    #[test]
+    fn decodes_stored_and_memset_quanta() {
+        // Stored quantum: size field is (len - 1) and equals the output.
+        let mut stream = vec![0x8C, KRAKEN, 0x00, 0x00, 0x04];
+        stream.extend_from_slice(b"hello");
+        assert_eq!(decode_vec(&stream, 5).unwrap(), b"hello");
+
+        // Memset quantum: size field all ones, type 1 in the top bits.
+        let stream = [0x8C, LEVIATHAN, 0x07, 0xFF, 0xFF, 0x2A];
+        assert_eq!(decode_vec(&stream, 1000).unwrap(), vec![0x2A; 1000]);
+
+        // With checksums the quantum header carries three more bytes.
+        let mut stream = vec![0x8C, MERMAID | 0x80, 0x00, 0x00, 0x02, 0xAB, 0xCD, 0xEF];
+        stream.extend_from_slice(b"abc");
+        assert_eq!(decode_vec(&stream, 3).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn decodes_kraken_lz_chunks() {
+        // Eight raw bytes, three 16-byte matches at the initial offset of
+        // -8, then two literals; everything but the offset stream stored.
+        let mut chunk = b"abcdefgh".to_vec();
+        chunk.extend_from_slice(&[0, 0, 2, b'x', b'y']); // literals
+        chunk.extend_from_slice(&[0, 0, 3, 0x38, 0x38, 0x38]); // commands
+        chunk.extend_from_slice(&[0, 0, 0]); // packed offsets
+        chunk.extend_from_slice(&[0, 0, 0]); // packed lengths
+        chunk.push(0x80); // no long lengths
+        for mode in [0u8, 1] {
+            let mut quantum = vec![0x80 | mode << 3, 0, chunk.len() as u8];
+            quantum.extend_from_slice(&chunk);
+            let mut stream = vec![0x8C, KRAKEN, 0, 0, quantum.len() as u8 - 1];
+            stream.extend_from_slice(&quantum);
+
+            let mut want = b"abcdefgh".repeat(7);
+            want.extend_from_slice(b"xy");
+            if mode == 0 {
+                // Literals are added to the byte at the last offset.
+                want[56] = b'x'.wrapping_add(b'a');
+                want[57] = b'y'.wrapping_add(b'b');
+            }
+            assert_eq!(decode_vec(&stream, want.len()).unwrap(), want, "mode {}", mode);
+            // One byte short of what the commands produce.
+            assert!(decode_vec(&stream, want.len() - 1).is_none());
+        }
+    }
+
+    #[test]
+    fn decodes_chunks_without_matches() {
+        // A stored array as the whole chunk, no LZ header.
+        let stream = [0x8C, MERMAID, 0, 0, 7, 0, 0, 5, b'h', b'e', b'l', b'l', b'o'];
+        assert_eq!(decode_vec(&stream, 5).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn rejects_malformed_streams() {
+        // Garbled quantum (4 bytes of payload for 100 bytes out).
+        assert!(decode_vec(&[0x8C, KRAKEN, 0x00, 0x00, 0x03, 1, 2, 3, 4], 100).is_none());
+        // LZNA, bad magic nibble, truncated stored block.
+        assert!(decode_vec(&[0xCC, 5, 0], 1).is_none());
+        assert!(decode_vec(&[0xCD, KRAKEN, 0], 1).is_none());
+        assert!(decode_vec(&[0xCC, KRAKEN, 1, 2], 3).is_none());
+    }
+
+    // The C++ library is only built without `rust-oodle`; the Rust decoder
+    // must reproduce every stream it writes.
+    #[cfg(not(feature = "rust-oodle"))]
+    mod differential {
+        use super::*;
+        use crate::ooz::sys::{Ooz_Compress, Ooz_Decompress};
+        use std::ptr;
+
+        fn native_compress(codec: i32, level: i32, src: &[u8]) -> Vec<u8> {
+            let mut out = vec![0u8; src.len() + 274 * src.len().div_ceil(BLOCK_SIZE).max(1) + 64];
+            let len = unsafe {
+                Ooz_Compress(
+                    codec,
+                    src.as_ptr(),
+                    src.len(),
+                    out.as_mut_ptr(),
+                    level,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    0,
+                )
+            };
+            assert!(len > 0, "Ooz_Compress failed: {}", len);
+            out.truncate(len as usize);
+            out
+        }
+
+        fn native_decompress(src: &[u8], len: usize) -> Vec<u8> {
+            let mut out = vec![0u8; len + 64];
+            let ret = unsafe {
+                Ooz_Decompress(
+                    src.as_ptr(),
+                    src.len() as i32,
+                    out.as_mut_ptr(),
+                    len,
+                    0,
+                    0,
+                    0,
+                    ptr::null_mut(),
+                    0,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    0,
+                    0,
+                )
+            };
+            assert_eq!(ret, len as i32);
+            out.truncate(len);
+            out
+        }
+
+        fn noise(len: usize, mut state: u32) -> Vec<u8> {
+            (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    state as u8
+                })
+                .collect()
+        }
+
+        /// Inputs that steer the encoder into each quantum and array type:
+        /// stored, memset, Huffman and tANS literals, RLE, near and far
+        /// matches, and (for Leviathan) the structured literal modes.
+        fn inputs() -> Vec<(&'static str, Vec<u8>)> {
+            let text = b"Metadata/Items/Weapons/OneHandWeapons/Claws/Claw1 ".repeat(6000);
+            let records: Vec<u8> = (0u32..80_000).flat_map(|i| [i, i.wrapping_mul(7) & 0xFF, 0x3F80_0000]).flat_map(u32::to_le_bytes).collect();
+            let mut mixed = Vec::new();
+            for (i, piece) in noise(BLOCK_SIZE, 3).chunks(4096).enumerate() {
+                mixed.extend_from_slice(piece);
+                mixed.extend_from_slice(&text[i * 700..i * 700 + 9000]);
+                mixed.extend(std::iter::repeat_n(i as u8, 300));
+            }
+            // A far repeat: the same noise 300 KiB apart.
+            let mut far = noise(100_000, 11);
+            far.extend(noise(200_000, 12));
+            far.extend(noise(100_000, 11));
+            vec![
+                ("noise", noise(BLOCK_SIZE + 1000, 0x9E37_79B9)),
+                ("constant", vec![0x5A; 70_000]),
+                ("short", b"ggpk".to_vec()),
+                ("text", text),
+                ("records", records),
+                ("mixed", mixed),
+                ("far", far),
+            ]
+        }
+
+        #[test]
+        fn matches_native_on_generated_streams() {
+            for (name, data) in inputs() {
+                for codec in [8, 9, 13] {
+                    for level in [1, 4, 8] {
+                        let stream = native_compress(codec, level, &data);
+                        let expected = native_decompress(&stream, data.len());
+                        assert_eq!(expected, data, "{} codec {} level {}: native round trip", name, codec, level);
+                        let decoded = decode_vec(&stream, data.len())
+                            .unwrap_or_else(|| panic!("{} codec {} level {}: rejected", name, codec, level));
+                        assert!(decoded == expected, "{} codec {} level {}: output differs", name, codec, level);
+                    }
+                }
+            }
+        }
+    }
+}
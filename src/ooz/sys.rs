@@ -36,4 +36,20 @@ extern "C" {
         scratch_size: size_t, 
         threadPhase: i32
     ) -> i32;
+
+    // Mirrors OodleLZ_Compress. `codec` is an OodleLZ_Compressor id
+    // (8 = Kraken, 9 = Mermaid, 13 = Leviathan); returns the compressed
+    // size, or <= 0 on failure.
+    pub fn Ooz_Compress(
+        codec: i32,
+        src_buf: *const u8,
+        src_len: size_t,
+        dst_buf: *mut u8,
+        level: i32,
+        opts: *mut c_void,
+        dictionary_base: *mut c_void,
+        lrm: *mut c_void,
+        scratch: *mut c_void,
+        scratch_size: size_t
+    ) -> i32;
 }
@@ -13,6 +13,8 @@
 use std::collections::HashMap;
 use std::sync::OnceLock;
 
+use crate::error::{Error, Result};
+
 /// crc32 -> full Vorbis setup-header packet, parsed lazily from the
 /// embedded binary table: [count u32] then per entry [crc u32][len u32][bytes].
 fn setup_header_table() -> &'static HashMap<u32, &'static [u8]> {
@@ -131,16 +133,16 @@ fn find_fsb5(data: &[u8]) -> Option<&[u8]> {
     None
 }
 
-fn read_u32(data: &[u8], pos: usize) -> Result<u32, String> {
+fn read_u32(data: &[u8], pos: usize) -> Result<u32> {
     data.get(pos..pos + 4)
         .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
-        .ok_or_else(|| "FSB5 truncated".to_string())
+        .ok_or(Error::TruncatedRecord { what: "FSB5", offset: pos as u64 })
 }
 
-fn read_u64(data: &[u8], pos: usize) -> Result<u64, String> {
+fn read_u64(data: &[u8], pos: usize) -> Result<u64> {
     data.get(pos..pos + 8)
         .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
-        .ok_or_else(|| "FSB5 truncated".to_string())
+        .ok_or(Error::TruncatedRecord { what: "FSB5", offset: pos as u64 })
 }
 
 fn frequency_from_id(id: u64) -> Option<u32> {
@@ -159,9 +161,13 @@ fn frequency_from_id(id: u64) -> Option<u32> {
     }
 }
 
-fn parse_fsb5(fsb: &[u8]) -> Result<Fsb5, String> {
+fn parse_fsb5(fsb: &[u8]) -> Result<Fsb5> {
     if !fsb.starts_with(b"FSB5") {
-        return Err("Not an FSB5 payload".to_string());
+        return Err(Error::BadSignature {
+            offset: 0,
+            expected: "FSB5",
+            found: String::from_utf8_lossy(&fsb[..fsb.len().min(4)]).into_owned(),
+        });
     }
     let version = read_u32(fsb, 4)?;
     let num_samples = read_u32(fsb, 8)? as usize;
@@ -216,7 +222,7 @@ fn parse_fsb5(fsb: &[u8]) -> Result<Fsb5, String> {
         }
 
         raw_samples.push((
-            sample_rate.ok_or_else(|| format!("Unknown frequency id {}", freq_id))?,
+            sample_rate.ok_or_else(|| Error::parse("FSB5", format!("unknown frequency id {}", freq_id)))?,
             channels,
             data_offset,
             sample_count,
@@ -269,7 +275,7 @@ fn parse_fsb5(fsb: &[u8]) -> Result<Fsb5, String> {
 
 /// Parses bank/FSB5 headers and the stream name table without decoding any
 /// audio. Cheap enough to run on selection.
-pub fn parse_bank_info(data: &[u8]) -> Result<FmodBankInfo, String> {
+pub fn parse_bank_info(data: &[u8]) -> Result<FmodBankInfo> {
     let Some(fsb) = find_fsb5(data) else {
         if data.starts_with(b"RIFF") {
             // Valid FMOD bank with no embedded audio: event/mixer metadata or
@@ -280,7 +286,7 @@ pub fn parse_bank_info(data: &[u8]) -> Result<FmodBankInfo, String> {
                 streams: Vec::new(),
             });
         }
-        return Err("No FSB5 payload found in bank".to_string());
+        return Err(Error::parse("FMOD bank", "no FSB5 payload found"));
     };
     let bank = parse_fsb5(fsb)?;
     Ok(FmodBankInfo {
@@ -301,16 +307,16 @@ pub fn parse_bank_info(data: &[u8]) -> Result<FmodBankInfo, String> {
 }
 
 /// Decodes a single stream (by index) to WAV bytes.
-pub fn decode_stream(data: &[u8], index: usize) -> Result<Vec<u8>, String> {
-    let fsb = find_fsb5(data).ok_or("No FSB5 payload found in bank")?;
+pub fn decode_stream(data: &[u8], index: usize) -> Result<Vec<u8>> {
+    let fsb = find_fsb5(data).ok_or_else(|| Error::parse("FMOD bank", "no FSB5 payload found"))?;
     let bank = parse_fsb5(fsb)?;
     let sample = bank
         .samples
         .get(index)
-        .ok_or_else(|| format!("Stream index {} out of range", index))?;
+        .ok_or_else(|| Error::NotFound(format!("stream {} of {}", index, bank.samples.len())))?;
     let stream_data = fsb
         .get(sample.data_start..sample.data_end)
-        .ok_or("Sample data out of bounds")?;
+        .ok_or(Error::TruncatedRecord { what: "FSB5 sample data", offset: sample.data_start as u64 })?;
 
     let pcm: Vec<i16> = match bank.mode {
         MODE_VORBIS => decode_vorbis(sample, stream_data)?,
@@ -330,10 +336,10 @@ pub fn decode_stream(data: &[u8], index: usize) -> Result<Vec<u8>, String> {
             })
             .collect(),
         other => {
-            return Err(format!(
-                "Codec {} is not supported for playback",
+            return Err(Error::Unsupported(format!(
+                "playback of {} streams",
                 mode_name(other)
-            ))
+            )))
         }
     };
 
@@ -343,28 +349,28 @@ pub fn decode_stream(data: &[u8], index: usize) -> Result<Vec<u8>, String> {
 /// Decodes FMOD's headerless Vorbis: rebuild ident/comment headers, fetch
 /// the setup header from the CRC table, then decode the length-prefixed
 /// packet stream with lewton.
-fn decode_vorbis(sample: &Fsb5Sample, stream_data: &[u8]) -> Result<Vec<i16>, String> {
+fn decode_vorbis(sample: &Fsb5Sample, stream_data: &[u8]) -> Result<Vec<i16>> {
     let crc = sample
         .vorbis_crc32
-        .ok_or("Vorbis stream missing setup-header CRC chunk")?;
+        .ok_or_else(|| Error::parse("Vorbis", "stream has no setup-header CRC chunk"))?;
     let setup_packet = *setup_header_table()
         .get(&crc)
-        .ok_or_else(|| format!("Unknown Vorbis setup header (crc32 {:#010x})", crc))?;
+        .ok_or_else(|| Error::parse("Vorbis", format!("unknown setup header (crc32 {:#010x})", crc)))?;
 
     let ident_packet = build_ident_packet(sample.channels, sample.sample_rate);
     let comment_packet = build_comment_packet();
 
     let ident = lewton::header::read_header_ident(&ident_packet)
-        .map_err(|e| format!("Ident header: {:?}", e))?;
+        .map_err(|e| Error::parse("Vorbis", format!("ident header: {:?}", e)))?;
     let comment = lewton::header::read_header_comment(&comment_packet)
-        .map_err(|e| format!("Comment header: {:?}", e))?;
+        .map_err(|e| Error::parse("Vorbis", format!("comment header: {:?}", e)))?;
     let _ = comment;
     let setup = lewton::header::read_header_setup(
         setup_packet,
         sample.channels,
         (ident.blocksize_0, ident.blocksize_1),
     )
-    .map_err(|e| format!("Setup header: {:?}", e))?;
+    .map_err(|e| Error::parse("Vorbis", format!("setup header: {:?}", e)))?;
 
     let expected = (sample.sample_count as usize).saturating_mul(sample.channels as usize);
     let mut pcm: Vec<i16> = Vec::with_capacity(expected);
@@ -384,7 +390,7 @@ fn decode_vorbis(sample: &Fsb5Sample, stream_data: &[u8]) -> Result<Vec<i16>, St
             &ident, &setup, packet, &mut pwr,
         ) {
             Ok(samples) => pcm.extend_from_slice(&samples.samples),
-            Err(e) => return Err(format!("Packet decode: {:?}", e)),
+            Err(e) => return Err(Error::parse("Vorbis", format!("packet decode: {:?}", e))),
         }
         if pcm.len() >= expected && expected > 0 {
             break;
@@ -425,7 +431,7 @@ fn build_comment_packet() -> Vec<u8> {
     p
 }
 
-fn pcm_to_wav(pcm: &[i16], channels: u8, sample_rate: u32) -> Result<Vec<u8>, String> {
+fn pcm_to_wav(pcm: &[i16], channels: u8, sample_rate: u32) -> Result<Vec<u8>> {
     let spec = hound::WavSpec {
         channels: channels as u16,
         sample_rate,
@@ -435,17 +441,24 @@ fn pcm_to_wav(pcm: &[i16], channels: u8, sample_rate: u32) -> Result<Vec<u8>, St
     let mut cursor = std::io::Cursor::new(Vec::new());
     {
         let mut writer =
-            hound::WavWriter::new(&mut cursor, spec).map_err(|e| e.to_string())?;
+            hound::WavWriter::new(&mut cursor, spec).map_err(wav_error)?;
         let mut i16_writer = writer.get_i16_writer(pcm.len() as u32);
         for &s in pcm {
             i16_writer.write_sample(s);
         }
-        i16_writer.flush().map_err(|e| e.to_string())?;
-        writer.finalize().map_err(|e| e.to_string())?;
+        i16_writer.flush().map_err(wav_error)?;
+        writer.finalize().map_err(wav_error)?;
     }
     Ok(cursor.into_inner())
 }
 
+fn wav_error(e: hound::Error) -> Error {
+    match e {
+        hound::Error::IoError(e) => Error::Io(e),
+        e => Error::InvalidData(e.to_string()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -512,6 +525,8 @@ mod tests {
 
     // Spike test against banks extracted from the local Content.ggpk.
     // Run with: cargo test fmod_bank -- --ignored --nocapture
+    // Checks the output with rodio, so it needs the `gui` feature.
+    #[cfg(feature = "gui")]
     #[test]
     #[ignore]
     fn test_parse_real_banks() {
@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 
+use crate::error::{Error, Result};
 use crate::parsers::utils::utf16_bom_to_string;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -94,12 +95,12 @@ pub struct FxGraph {
 /// `.fxgraph` files are UTF-16LE JSON (with BOM) describing a shader/particle
 /// node graph: `nodes` carry an editor `ui_position`, and `links` wire named
 /// ports between nodes identified by `(type, index)` rather than array index.
-pub fn parse_fxgraph(bytes: &[u8]) -> Result<FxGraph, String> {
+pub fn parse_fxgraph(bytes: &[u8]) -> Result<FxGraph> {
     let text = match utf16_bom_to_string(bytes) {
         Ok(s) => s,
         Err(_) => String::from_utf8_lossy(bytes).to_string(),
     };
-    serde_json::from_str::<FxGraph>(&text).map_err(|e| format!("FxGraph JSON parse error: {}", e))
+    serde_json::from_str::<FxGraph>(&text).map_err(|e| Error::parse("FxGraph", e))
 }
 
 #[cfg(test)]
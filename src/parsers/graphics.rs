@@ -1,3 +1,4 @@
+use crate::error::{Error, Result};
 use crate::parsers::types::{FileFormatParser, ParsedContent};
 use std::collections::HashMap;
 
@@ -5,7 +6,7 @@ use std::collections::HashMap;
 pub struct GraphicsParser;
 
 impl FileFormatParser for GraphicsParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         // For now, provide basic metadata about the binary format
         let mut metadata = HashMap::new();
         metadata.insert("format".to_string(), "binary".to_string());
@@ -31,9 +32,9 @@ impl FileFormatParser for GraphicsParser {
 pub struct FMTParser;
 
 impl FileFormatParser for FMTParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         if bytes.len() < 20 {
-            return Err("FMT file too small".to_string());
+            return Err(Error::TruncatedRecord { what: "FMT header", offset: 0 });
         }
 
         let mut metadata = HashMap::new();
@@ -81,7 +82,7 @@ impl FileFormatParser for FMTParser {
 pub struct GTBinaryParser;
 
 impl FileFormatParser for GTBinaryParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         let mut metadata = HashMap::new();
         metadata.insert("format".to_string(), "GT Graphics Template".to_string());
         metadata.insert("size".to_string(), format!("{} bytes", bytes.len()));
@@ -102,7 +103,7 @@ impl FileFormatParser for GTBinaryParser {
 pub struct ECFBinaryParser;
 
 impl FileFormatParser for ECFBinaryParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         let mut metadata = HashMap::new();
         metadata.insert("format".to_string(), "ECF Effect Configuration".to_string());
         metadata.insert("size".to_string(), format!("{} bytes", bytes.len()));
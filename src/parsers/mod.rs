@@ -8,6 +8,8 @@ pub mod utils;
 
 pub use types::{FileFormat, FileFormatParser, ParsedContent};
 
+use crate::error::Result;
+
 use graphics::*;
 use skeletal::*;
 use text_config::*;
@@ -46,7 +48,7 @@ pub fn get_parser(format: FileFormat) -> Box<dyn FileFormatParser> {
 }
 
 /// Parse bytes into structured content based on file format
-pub fn parse(format: FileFormat, bytes: &[u8]) -> Result<ParsedContent, String> {
+pub fn parse(format: FileFormat, bytes: &[u8]) -> Result<ParsedContent> {
     let parser = get_parser(format);
     parser.parse(bytes)
 }
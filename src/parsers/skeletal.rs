@@ -1,3 +1,4 @@
+use crate::error::{Error, Result};
 use crate::parsers::types::{FileFormatParser, ParsedContent};
 use std::collections::HashMap;
 
@@ -5,9 +6,9 @@ use std::collections::HashMap;
 pub struct SMDParser;
 
 impl FileFormatParser for SMDParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         if bytes.is_empty() {
-            return Err("SMD file is empty".to_string());
+            return Err(Error::TruncatedRecord { what: "SMD header", offset: 0 });
         }
 
         let mut metadata = HashMap::new();
@@ -42,7 +43,7 @@ impl FileFormatParser for SMDParser {
 pub struct TSISkelParser;
 
 impl FileFormatParser for TSISkelParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         let mut metadata = HashMap::new();
         metadata.insert("format".to_string(), "TSI Configuration".to_string());
         metadata.insert("size".to_string(), format!("{} bytes", bytes.len()));
@@ -58,7 +59,7 @@ impl FileFormatParser for TSISkelParser {
 pub struct TMOSkelParser;
 
 impl FileFormatParser for TMOSkelParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         let mut metadata = HashMap::new();
         metadata.insert("format".to_string(), "TMO Transform/Texture Override".to_string());
         metadata.insert("size".to_string(), format!("{} bytes", bytes.len()));
@@ -1,3 +1,4 @@
+use crate::error::{Error, Result};
 use crate::parsers::types::{FileFormatParser, ParsedContent};
 use crate::parsers::utils::*;
 use serde_json::{Map, Value};
@@ -6,7 +7,7 @@ use serde_json::{Map, Value};
 pub struct TextConfigParser;
 
 impl FileFormatParser for TextConfigParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         // Try UTF-16 with BOM first (most common for PoE formats)
         match utf16_bom_to_string(bytes) {
             Ok(content) => {
@@ -23,7 +24,7 @@ impl FileFormatParser for TextConfigParser {
                         content,
                         language: Some("text".to_string()),
                     }),
-                    Err(e) => Err(format!("Failed to decode text: {}", e)),
+                    Err(e) => Err(Error::parse("text", e)),
                 }
             }
         }
@@ -34,7 +35,7 @@ impl FileFormatParser for TextConfigParser {
 pub struct AMDParser;
 
 impl FileFormatParser for AMDParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         match utf16_bom_to_string(bytes) {
             Ok(content_str) => {
                 // Extract metadata (version and group count) for future use
@@ -49,7 +50,7 @@ impl FileFormatParser for AMDParser {
                     language: Some("text".to_string()),
                 })
             }
-            Err(e) => Err(format!("AMD parse error: {}", e)),
+            Err(e) => Err(Error::parse("AMD", e)),
         }
     }
 }
@@ -58,7 +59,7 @@ impl FileFormatParser for AMDParser {
 pub struct AOParser;
 
 impl FileFormatParser for AOParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         match utf16_bom_to_string(bytes) {
             Ok(content_str) => {
                 let mut tree = Map::new();
@@ -79,7 +80,7 @@ impl FileFormatParser for AOParser {
 
                 Ok(ParsedContent::Tree(Value::Object(tree)))
             }
-            Err(e) => Err(format!("AO parse error: {}", e)),
+            Err(e) => Err(Error::parse("AO", e)),
         }
     }
 }
@@ -88,13 +89,13 @@ impl FileFormatParser for AOParser {
 pub struct ARMParser;
 
 impl FileFormatParser for ARMParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         match utf16_bom_to_string(bytes) {
             Ok(content) => Ok(ParsedContent::Text {
                 content,
                 language: Some("text".to_string()),
             }),
-            Err(e) => Err(format!("ARM parse error: {}", e)),
+            Err(e) => Err(Error::parse("ARM", e)),
         }
     }
 }
@@ -103,13 +104,13 @@ impl FileFormatParser for ARMParser {
 pub struct MATParser;
 
 impl FileFormatParser for MATParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         match utf16_bom_to_string(bytes) {
             Ok(content) => Ok(ParsedContent::Text {
                 content,
                 language: Some("text".to_string()),
             }),
-            Err(e) => Err(format!("MAT parse error: {}", e)),
+            Err(e) => Err(Error::parse("MAT", e)),
         }
     }
 }
@@ -118,13 +119,13 @@ impl FileFormatParser for MATParser {
 pub struct PETParser;
 
 impl FileFormatParser for PETParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         match utf16_bom_to_string(bytes) {
             Ok(content) => Ok(ParsedContent::Text {
                 content,
                 language: Some("text".to_string()),
             }),
-            Err(e) => Err(format!("PET parse error: {}", e)),
+            Err(e) => Err(Error::parse("PET", e)),
         }
     }
 }
@@ -133,13 +134,13 @@ impl FileFormatParser for PETParser {
 pub struct ETParser;
 
 impl FileFormatParser for ETParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         match utf16_bom_to_string(bytes) {
             Ok(content) => Ok(ParsedContent::Text {
                 content,
                 language: Some("text".to_string()),
             }),
-            Err(e) => Err(format!("ET parse error: {}", e)),
+            Err(e) => Err(Error::parse("ET", e)),
         }
     }
 }
@@ -148,13 +149,13 @@ impl FileFormatParser for ETParser {
 pub struct TRLParser;
 
 impl FileFormatParser for TRLParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         match utf16_bom_to_string(bytes) {
             Ok(content) => Ok(ParsedContent::Text {
                 content,
                 language: Some("text".to_string()),
             }),
-            Err(e) => Err(format!("TRL parse error: {}", e)),
+            Err(e) => Err(Error::parse("TRL", e)),
         }
     }
 }
@@ -163,7 +164,7 @@ impl FileFormatParser for TRLParser {
 pub struct TSIParser;
 
 impl FileFormatParser for TSIParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         match utf16_bom_to_string(bytes) {
             Ok(content_str) => {
                 let mut tree = Map::new();
@@ -183,7 +184,7 @@ impl FileFormatParser for TSIParser {
 
                 Ok(ParsedContent::Tree(Value::Object(tree)))
             }
-            Err(e) => Err(format!("TSI parse error: {}", e)),
+            Err(e) => Err(Error::parse("TSI", e)),
         }
     }
 }
@@ -192,7 +193,7 @@ impl FileFormatParser for TSIParser {
 pub struct GFTParser;
 
 impl FileFormatParser for GFTParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         match utf16_bom_to_string(bytes) {
             Ok(content_str) => {
                 let mut sections = Vec::new();
@@ -235,7 +236,7 @@ impl FileFormatParser for GFTParser {
 
                 Ok(ParsedContent::Tree(Value::Array(sections)))
             }
-            Err(e) => Err(format!("GFT parse error: {}", e)),
+            Err(e) => Err(Error::parse("GFT", e)),
         }
     }
 }
@@ -244,13 +245,13 @@ impl FileFormatParser for GFTParser {
 pub struct GTParser;
 
 impl FileFormatParser for GTParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         match utf16_bom_to_string(bytes) {
             Ok(content) => Ok(ParsedContent::Text {
                 content,
                 language: Some("text".to_string()),
             }),
-            Err(e) => Err(format!("GT parse error: {}", e)),
+            Err(e) => Err(Error::parse("GT", e)),
         }
     }
 }
@@ -259,13 +260,13 @@ impl FileFormatParser for GTParser {
 pub struct ECFParser;
 
 impl FileFormatParser for ECFParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         match utf16_bom_to_string(bytes) {
             Ok(content) => Ok(ParsedContent::Text {
                 content,
                 language: Some("text".to_string()),
             }),
-            Err(e) => Err(format!("ECF parse error: {}", e)),
+            Err(e) => Err(Error::parse("ECF", e)),
         }
     }
 }
@@ -274,13 +275,13 @@ impl FileFormatParser for ECFParser {
 pub struct TMOParser;
 
 impl FileFormatParser for TMOParser {
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String> {
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent> {
         match utf16_bom_to_string(bytes) {
             Ok(content) => Ok(ParsedContent::Text {
                 content,
                 language: Some("text".to_string()),
             }),
-            Err(e) => Err(format!("TMO parse error: {}", e)),
+            Err(e) => Err(Error::parse("TMO", e)),
         }
     }
 }
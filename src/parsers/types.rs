@@ -1,10 +1,11 @@
+use crate::error::Result;
 use serde_json::Value as JsonValue;
 use std::collections::HashMap;
 
 /// Trait for parsing different file formats
 pub trait FileFormatParser: Send + Sync {
     /// Parse raw bytes into structured output
-    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent, String>;
+    fn parse(&self, bytes: &[u8]) -> Result<ParsedContent>;
 }
 
 /// Normalized output from all format parsers
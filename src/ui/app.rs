@@ -46,6 +46,8 @@ pub struct ExplorerApp {
     pub settings: crate::settings::AppSettings,
     pub settings_window: crate::ui::settings_window::SettingsWindow,
     pub export_window: crate::ui::export_window::ExportWindow,
+    pub diff_window: crate::ui::diff_window::DiffWindow,
+    pub query_window: crate::ui::query_window::QueryWindow,
     pub show_about: bool,
     pub update_state: crate::update::UpdateState,
     pub sidebar_expanded: bool,
@@ -103,7 +105,8 @@ impl ExplorerApp {
                     })
                     .unwrap_or_else(|| "Unknown".to_string());
                  
-                 if let Ok(s) = serde_json::from_value::<crate::dat::schema::Schema>(value) {
+                 if let Ok(mut s) = serde_json::from_value::<crate::dat::schema::Schema>(value) {
+                     crate::dat::overlay::apply_default(&mut s);
                      content_view.set_dat_schema(s, created_at);
                  } else {
                      println!("Failed to parse schema structure");
@@ -145,6 +148,8 @@ impl ExplorerApp {
             settings: settings.clone(),
             settings_window: crate::ui::settings_window::SettingsWindow::new(),
             export_window: crate::ui::export_window::ExportWindow::new(),
+            diff_window: crate::ui::diff_window::DiffWindow::new(),
+            query_window: crate::ui::query_window::QueryWindow::new(),
             show_about: false,
             update_state: crate::update::UpdateState::new(),
             sidebar_expanded: true,
@@ -371,9 +376,13 @@ impl ExplorerApp {
                         let unresolved = index.files.values().filter(|f| f.path.is_empty()).count();
                         if unresolved > 0 {
                             if let Some(ref schema) = schema_for_enrich {
-                                let cdn_ref = cdn_for_enrich.as_ref();
+                                let sources = crate::vfs::BundleSources {
+                                    reader: Some(&*reader),
+                                    steam: None,
+                                    cdn: cdn_for_enrich.as_ref(),
+                                };
                                 let enriched = crate::bundles::path_enrichment::enrich_paths_from_dat(
-                                    &mut index, schema, Some(&*reader), cdn_ref, None,
+                                    &mut index, schema, sources,
                                 );
                                 if enriched > 0 {
                                     println!("Path enrichment resolved {} new paths from dat files", enriched);
@@ -503,8 +512,12 @@ impl ExplorerApp {
                 if let Some(ref schema) = schema_for_enrich {
                     let unresolved = index.files.values().filter(|f| f.path.is_empty()).count();
                     if unresolved > 0 {
+                        let sources = crate::vfs::BundleSources {
+                            steam: Some(&steam),
+                            ..Default::default()
+                        };
                         let enriched = crate::bundles::path_enrichment::enrich_paths_from_dat(
-                            &mut index, schema, None, None, Some(&steam),
+                            &mut index, schema, sources,
                         );
                         if enriched > 0 {
                             println!("Steam path enrichment resolved {} paths", enriched);
@@ -531,6 +544,17 @@ impl ExplorerApp {
         self.content_view.set_steam_loader(loader);
     }
 
+    /// A [`Vfs`](crate::vfs::Vfs) over the open install, for background jobs.
+    fn current_vfs(&self) -> Option<crate::vfs::Vfs> {
+        let index = self.bundle_index.clone()?;
+        Some(crate::vfs::Vfs::from_parts(
+            index,
+            self.reader.clone(),
+            self.content_view.steam_loader.clone(),
+            self.content_view.cdn_loader.clone(),
+        ))
+    }
+
     fn current_location_label(&self) -> String {
         match &self.selected_file {
             Some(FileSelection::BundleFile(hash)) => self
@@ -833,6 +857,8 @@ impl eframe::App for ExplorerApp {
                              Ok((reader_opt, index, is_poe2, path, extra_status, tree_view)) => {
                                  self.reader = reader_opt.clone();
                                  self.bundle_index = index;
+                                 self.content_view.dat_viewer.clear_reverse_index();
+                                 self.query_window.reset();
                                  self.is_poe2 = is_poe2;
                                  self.tree_view = tree_view;
                                  self.command_palette_needs_refresh = true;
@@ -881,6 +907,12 @@ impl eframe::App for ExplorerApp {
         if chrome_actions.open_settings {
             self.settings_window.open();
         }
+        if chrome_actions.open_diff {
+            self.diff_window.open();
+        }
+        if chrome_actions.open_query {
+            self.query_window.open();
+        }
         if chrome_actions.open_about {
             self.show_about = true;
         }
@@ -925,8 +957,15 @@ impl eframe::App for ExplorerApp {
                  };
                  
                  if self.reader.is_some() || self.bundle_index.is_some() {
-                     let bundle_index = self.bundle_index.clone();
                      let reader_clone = self.reader.clone();
+                     let vfs = self.bundle_index.clone().map(|index| {
+                         Arc::new(crate::vfs::Vfs::from_parts(
+                             index,
+                             self.reader.clone(),
+                             self.content_view.steam_loader.clone(),
+                             self.content_view.cdn_loader.clone(),
+                         ))
+                     });
                      
                      let (tx, rx) = std::sync::mpsc::channel();
                      self.export_status_rx = Some(rx);
@@ -934,19 +973,13 @@ impl eframe::App for ExplorerApp {
                      self.is_loading = true;
                      
                      let schema_clone = self.content_view.dat_viewer.schema.clone();
-                     let cdn_loader = self.content_view.cdn_loader.clone();
-                     let steam_loader = self.content_view.steam_loader.clone();
                      
                      std::thread::spawn(move || {
                          crate::export::run_export(
                             hashes,
                             reader_clone,
-                            bundle_index,
-                            settings,
-                            target_dir,
-                            cdn_loader,
-                            steam_loader,
-                            schema_clone,
+                            vfs,
+                            crate::export::ExportContext::new(settings, target_dir, schema_clone),
                             tx,
                             None
                          );
@@ -955,6 +988,46 @@ impl eframe::App for ExplorerApp {
             }
         }
 
+        self.diff_window.show(ctx);
+        if self.diff_window.confirmed {
+            self.diff_window.confirmed = false;
+            if let Some(vfs) = self.current_vfs() {
+                let schema = self.content_view.dat_viewer.schema.clone();
+                self.diff_window.start(vfs, schema);
+            }
+        }
+
+        self.query_window.show(ctx);
+        if self.query_window.confirmed {
+            self.query_window.confirmed = false;
+            match (self.current_vfs(), self.content_view.dat_viewer.schema.clone()) {
+                (Some(vfs), Some(schema)) => self.query_window.start(vfs, schema),
+                _ => self.status_msg = "Querying tables needs an open install and a schema".to_string(),
+            }
+        }
+
+        if self.content_view.dat_viewer.request_reverse_index {
+            match self.current_vfs() {
+                Some(vfs) => self.content_view.dat_viewer.build_reverse_index(Arc::new(vfs)),
+                None => self.content_view.dat_viewer.request_reverse_index = false,
+            }
+        }
+        if let Some((table, row)) = self.content_view.dat_viewer.navigate_to.take() {
+            let target = self.current_vfs().and_then(|vfs| {
+                let name = table.to_ascii_lowercase();
+                [format!("data/balance/{}.datc64", name), format!("data/{}.datc64", name)]
+                    .iter()
+                    .find_map(|path| vfs.lookup(path).map(|(hash, _)| hash))
+            });
+            match target {
+                Some(hash) => {
+                    self.selected_file = Some(FileSelection::BundleFile(hash));
+                    self.content_view.dat_viewer.pending_row = Some(row);
+                }
+                None => self.status_msg = format!("Could not find the file for table {}", table),
+            }
+        }
+
         if self.tree_view.is_searching() {
             ctx.request_repaint();
         }
@@ -1137,7 +1210,9 @@ impl eframe::App for ExplorerApp {
                              })
                              .unwrap_or_else(|| "Unknown".to_string());
                           
-                          if let Ok(s) = serde_json::from_value::<crate::dat::schema::Schema>(value) {
+                          if let Ok(mut s) = serde_json::from_value::<crate::dat::schema::Schema>(value) {
+                              // Local fixes survive the update.
+                              crate::dat::overlay::apply_default(&mut s);
                               self.content_view.set_dat_schema(s, created_at);
                           } else {
                               self.status_msg = "Failed to parse new schema structure".to_string();
@@ -1163,6 +1238,13 @@ impl eframe::App for ExplorerApp {
              }
         }
 
+        // An overlay edit keeps the schema's createdAt, so the query window
+        // can't tell its loaded tables are stale.
+        if self.content_view.dat_viewer.schema_edited {
+            self.content_view.dat_viewer.schema_edited = false;
+            self.query_window.reset();
+        }
+
         // Poll Schema Check
         if let Some(rx) = &self.schema_check_rx {
              match rx.try_recv() {
@@ -4,6 +4,8 @@ pub struct ChromeActions {
     pub open_ggpk: bool,
     pub open_steam: bool,
     pub open_settings: bool,
+    pub open_diff: bool,
+    pub open_query: bool,
     pub open_about: bool,
     pub open_command_palette: bool,
     pub toggle_inspector: bool,
@@ -15,6 +17,8 @@ impl ChromeActions {
             open_ggpk: false,
             open_steam: false,
             open_settings: false,
+            open_diff: false,
+            open_query: false,
             open_about: false,
             open_command_palette: false,
             toggle_inspector: false,
@@ -233,7 +237,7 @@ impl AppChrome {
         ctx: &egui::Context,
         location: &str,
         _status_msg: &str,
-        _has_reader: bool,
+        has_reader: bool,
         _is_loading: bool,
         _inspector_open: &mut bool,
     ) -> ChromeActions {
@@ -298,6 +302,8 @@ impl AppChrome {
 
                         let mut open_ggpk = false;
                         let mut open_steam = false;
+                        let mut open_diff = false;
+                        let mut open_query = false;
                         let mut toggle_inspector = false;
                         Self::nav_button_menu(ui, "File", |ui| {
                             if ui.button("Open GGPK...").clicked() {
@@ -309,6 +315,15 @@ impl AppChrome {
                                 ui.close_menu();
                             }
                             ui.separator();
+                            if ui.add_enabled(has_reader, egui::Button::new("Compare Versions...")).clicked() {
+                                open_diff = true;
+                                ui.close_menu();
+                            }
+                            if ui.add_enabled(has_reader, egui::Button::new("Query Tables...")).clicked() {
+                                open_query = true;
+                                ui.close_menu();
+                            }
+                            ui.separator();
                             if ui.button("Toggle Inspector (Ctrl+I)").clicked() {
                                 toggle_inspector = true;
                                 ui.close_menu();
@@ -325,6 +340,12 @@ impl AppChrome {
                         if open_steam {
                             actions.open_steam = true;
                         }
+                        if open_diff {
+                            actions.open_diff = true;
+                        }
+                        if open_query {
+                            actions.open_query = true;
+                        }
                         if toggle_inspector {
                             actions.toggle_inspector = true;
                         }
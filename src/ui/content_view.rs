@@ -1298,7 +1298,14 @@ impl ContentView {
 
                     // Load bytes synchronously from cache or bundle
                     let bytes = self.raw_data_cache.get(&hash).cloned()
-                        .or_else(|| extract_bundle_file_sync(file_info, index?, reader, self.steam_loader.as_ref()));
+                        .or_else(|| {
+                            let sources = crate::vfs::BundleSources {
+                                reader,
+                                steam: self.steam_loader.as_ref(),
+                                cdn: self.cdn_loader.as_ref(),
+                            };
+                            sources.read_file(index?, file_info).ok()
+                        });
 
                     match bytes {
                         Some(data) => {
@@ -1344,7 +1351,7 @@ impl ContentView {
         self.bank_decoding = Some((hash, index));
         self.bank_decode_intent = intent;
         std::thread::spawn(move || {
-            let result = crate::parsers::fmod_bank::decode_stream(&raw, index);
+            let result = crate::parsers::fmod_bank::decode_stream(&raw, index).map_err(|e| e.to_string());
             let _ = tx.send((hash, index, result));
         });
     }
@@ -1380,7 +1387,7 @@ impl ContentView {
             .and_then(|info| info.streams.get(index).map(|s| (s.name.clone(), info.extension)))
             .unwrap_or_else(|| (format!("stream_{:03}", index), "ogg"));
         if let Some(path) = rfd::FileDialog::new()
-            .set_file_name(format!("{}.{}", sanitize_filename(&name), ext))
+            .set_file_name(format!("{}.{}", crate::export::sanitize_filename(&name), ext))
             .save_file()
         {
             if let Err(e) = std::fs::write(&path, bytes) {
@@ -1600,7 +1607,7 @@ impl ContentView {
                     Ok(bytes) => {
                         let path = dir.join(format!(
                             "{}.{}",
-                            sanitize_filename(&stream.name),
+                            crate::export::sanitize_filename(&stream.name),
                             info.extension
                         ));
                         if std::fs::write(&path, bytes).is_ok() {
@@ -1740,164 +1747,29 @@ impl ContentView {
              }
          }
 
-         // Loose file (Steam Art/ directory) — read directly from disk
-         if file_info.bundle_index == crate::bundles::steam::LOOSE_FILE_SENTINEL {
-             // Resolve the path first so the immutable steam_loader borrow is
-             // released before we mutate the caches.
-             let loose_path = self.steam_loader.as_ref()
-                 .and_then(|steam| steam.loose_file_path(&file_info.path));
-             if let Some(loose_path) = loose_path {
-                 match std::fs::read(&loose_path) {
-                     Ok(data) => {
-                         self.failed_loads.remove(&hash);
-                         self.last_error = None;
-                         self.route_file_data(ctx, &file_info.path, hash, data);
-                     }
-                     Err(e) => {
-                         self.last_error = Some(format!("Failed to read loose file: {}", e));
-                         self.failed_loads.insert(hash);
-                     }
-                 }
-             } else if self.steam_loader.is_some() {
-                 self.last_error = Some(format!("Loose file not found on disk: {}", file_info.path));
-                 self.failed_loads.insert(hash);
-             }
-             return;
-         }
-
-         // Loose GGPK record (FMOD/*.bank, Media/*.bk2, ...) — read directly
-         // from the GGPK file records instead of a bundle.
-         if file_info.bundle_index == crate::bundles::index::GGPK_LOOSE_FILE_SENTINEL {
-             let data = reader.and_then(|r| {
-                 r.read_file_by_path(&file_info.path).ok().flatten().and_then(|rec| {
-                     r.get_data_slice(rec.data_offset, rec.data_length).ok().map(|d| d.to_vec())
-                 })
-             });
-             match data {
-                 Some(data) => {
-                     self.failed_loads.remove(&hash);
-                     self.last_error = None;
-                     self.route_file_data(ctx, &file_info.path, hash, data);
-                 }
-                 None => {
-                     self.last_error = Some(format!("Failed to read loose GGPK file: {}", file_info.path));
-                     self.failed_loads.insert(hash);
-                 }
-             }
-             return;
-         }
-
-         if let Some(bundle_info) = index.bundles.get(file_info.bundle_index as usize) {
-             let mut raw_bundle_data: Option<Vec<u8>> = None;
-
-             // 1. Try Local GGPK
-             if let Some(reader) = reader {
-                 let candidates = vec![
-                     format!("Bundles2/{}", bundle_info.name),
-                     format!("Bundles2/{}.bundle.bin", bundle_info.name),
-                     bundle_info.name.clone(),
-                     format!("{}.bundle.bin", bundle_info.name),
-                 ];
-                 for cand in &candidates {
-                     if let Ok(Some(rec)) = reader.read_file_by_path(cand) {
-                         println!("Bundle found in GGPK: {}", cand);
-                         if let Ok(data) = reader.get_data_slice(rec.data_offset, rec.data_length) {
-                             raw_bundle_data = Some(data.to_vec());
-                             break;
-                         }
-                     }
-                 }
-             }
-
-             // 1.5. Try Steam directory
-             if raw_bundle_data.is_none() {
-                 if let Some(steam) = &self.steam_loader {
-                     if let Ok(data) = steam.fetch_bundle(&bundle_info.name) {
-                         println!("Bundle found in Steam dir: {}", bundle_info.name);
-                         raw_bundle_data = Some(data);
-                     }
-                 }
-             }
-
-             // 2. Try CDN Fallback
-             if raw_bundle_data.is_none() {
-                 if let Some(cdn) = &self.cdn_loader {
-                     // PoE2 CDN expects .bundle.bin suffix usually
-                     let fetch_name = if bundle_info.name.ends_with(".bundle.bin") {
-                         bundle_info.name.clone()
-                     } else {
-                         format!("{}.bundle.bin", bundle_info.name)
-                     };
-                     
-                     println!("Bundle missing from GGPK. Attempting CDN fetch for: {}", fetch_name);
-                     match cdn.fetch_bundle(&fetch_name) {
-                         Ok(data) => {
-                             println!("Bundle fetched from CDN. Size: {}", data.len());
-                             raw_bundle_data = Some(data);
-                         },
-                         Err(e) => {
-                             let msg = format!("CDN Fetch Failed: {}", e);
-                             println!("{}", msg);
-                             self.last_error = Some(msg);
-                             self.failed_loads.insert(hash);
-                         }
-                     }
-                 } else {
-                     let msg = format!("Bundle not found in GGPK and CDN Loader not initialized. Hash: {}", hash);
-                     println!("{}", msg);
-                     self.last_error = Some(msg);
-                     self.failed_loads.insert(hash);
-                 }
-             }
-
-             let mut decompressed_bundle_data: Option<Vec<u8>> = None;
-
-             if let Some(data) = raw_bundle_data {
-                 let mut cursor = std::io::Cursor::new(data);
-                 if let Ok(bundle) = crate::bundles::bundle::Bundle::read_header(&mut cursor) {
-                     if let Ok(decompressed) = bundle.decompress(&mut cursor) {
-                         decompressed_bundle_data = Some(decompressed);
-                     }
-                 }
-             }
-
-             if decompressed_bundle_data.is_none() {
-                 if let Some(reader) = reader {
-                     println!("Bundle not found or decompression failed. Attempting direct GGPK file lookup for: {}", file_info.path);
-                     if let Ok(Some(rec)) = reader.read_file_by_path(&file_info.path) {
-                         if let Ok(data) = reader.get_data_slice(rec.data_offset, rec.data_length) {
-                             let start = file_info.file_offset as usize;
-                             let end = start + data.len();
-                             let mut fake_decompressed = vec![0u8; end];
-                             fake_decompressed[start..end].copy_from_slice(data);
-                             decompressed_bundle_data = Some(fake_decompressed);
-                             println!("Direct GGPK fallback succeeded for: {}", file_info.path);
-                         }
-                     }
-                 }
-             }
-
-             if let Some(decompressed_data) = decompressed_bundle_data {
+         // Loose files (Steam Art/, GGPK FMOD/*.bank, ...) are read straight
+         // from disk; bundled files come from the GGPK, Steam, then the CDN.
+         let sources = crate::vfs::BundleSources {
+             reader,
+             steam: self.steam_loader.as_ref(),
+             cdn: self.cdn_loader.as_ref(),
+         };
+         match sources.read_file(index, file_info) {
+             Ok(data) => {
                  self.failed_loads.remove(&hash);
-                 let start = file_info.file_offset as usize;
-                 let end = start + file_info.file_size as usize;
-                 
-                 if end <= decompressed_data.len() {
-                     let file_data = decompressed_data[start..end].to_vec();
-                     self.route_file_data(ctx, &file_info.path, hash, file_data);
-                 } else {
-                     let msg = format!("Decompressed bounds check failed for '{}'", file_info.path);
-                     println!("{}", msg);
-                     self.last_error = Some(msg);
-                     self.failed_loads.insert(hash);
+                 self.last_error = None;
+                 self.route_file_data(ctx, &file_info.path, hash, data);
+             }
+             Err(e) => {
+                 let mut msg = format!("Failed to load '{}': {}", file_info.path, e);
+                 if matches!(e, crate::error::Error::CdnNotFound { .. }) {
+                     msg.push_str("\nCheck the patch version in Settings.");
                  }
-             } else {
-                 let msg = format!("Failed to load or decompress data for '{}' (bundle not found and GGPK lookup failed)", file_info.path);
-                 println!("{}", msg);
+                 log::warn!("{}", msg);
                  self.last_error = Some(msg);
                  self.failed_loads.insert(hash);
              }
-          }
+         }
     }
 
     /// Routes raw file bytes into the appropriate viewer state based on the
@@ -2318,15 +2190,6 @@ fn display_name_from_path(path: &str) -> String {
         .to_string()
 }
 
-fn sanitize_filename(name: &str) -> String {
-    name.chars()
-        .map(|c| match c {
-            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
-            c => c,
-        })
-        .collect()
-}
-
 fn format_file_size(size: u64) -> String {
     const KB: f64 = 1024.0;
     const MB: f64 = KB * 1024.0;
@@ -2478,44 +2341,6 @@ fn launch_bink_player(path: &std::path::Path, _game_root: Option<&std::path::Pat
     open::that(path).map_err(|e| e.to_string())
 }
 
-/// Synchronously extracts one file from the bundle system without going through the cache.
-fn extract_bundle_file_sync(
-    file_info: &crate::bundles::index::FileInfo,
-    index: &crate::bundles::index::Index,
-    reader: Option<&GgpkReader>,
-    steam_loader: Option<&crate::bundles::steam::SteamBundleLoader>,
-) -> Option<Vec<u8>> {
-    if file_info.bundle_index == crate::bundles::index::GGPK_LOOSE_FILE_SENTINEL {
-        let r = reader?;
-        let rec = r.read_file_by_path(&file_info.path).ok().flatten()?;
-        return r.get_data_slice(rec.data_offset, rec.data_length).ok().map(|d| d.to_vec());
-    }
-
-    let bundle_info = index.bundles.get(file_info.bundle_index as usize)?;
-
-    let raw = if let Some(reader) = reader {
-        let candidates = [
-            format!("Bundles2/{}", bundle_info.name),
-            format!("Bundles2/{}.bundle.bin", bundle_info.name),
-        ];
-        candidates.iter().find_map(|c| {
-            reader.read_file_by_path(c).ok().flatten().and_then(|rec| {
-                reader.get_data_slice(rec.data_offset, rec.data_length).ok().map(|d| d.to_vec())
-            })
-        })
-    } else {
-        None
-    }
-    .or_else(|| steam_loader.and_then(|s| s.fetch_bundle(&bundle_info.name).ok()))?;
-
-    let mut cursor = std::io::Cursor::new(raw);
-    let header = crate::bundles::bundle::Bundle::read_header(&mut cursor).ok()?;
-    let data = header.decompress(&mut cursor).ok()?;
-    let start = file_info.file_offset as usize;
-    let end = start + file_info.file_size as usize;
-    if end <= data.len() { Some(data[start..end].to_vec()) } else { None }
-}
-
 fn render_parsed_content(ui: &mut egui::Ui, file_name: &str, parsed: &crate::parsers::ParsedContent) {
     let format = crate::parsers::FileFormat::from_extension(file_name);
 
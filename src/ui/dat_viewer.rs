@@ -1,10 +1,20 @@
 use crate::ggpk::reader::GgpkReader;
 use crate::dat::reader::DatReader;
-use crate::dat::schema::Schema;
+use crate::dat::schema::{Schema, Table};
 use eframe::egui;
 use serde_json;
 use lru::LruCache;
 use std::num::NonZeroUsize;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::Arc;
+use crate::dat::infer::Inference;
+use crate::dat::overlay::{self, ColumnEdit, SchemaOverlay};
+use crate::dat::relational::{Database, ReverseIndex};
+
+enum ReverseIndexStatus {
+    Progress(usize, usize),
+    Done(ReverseIndex),
+}
 
 pub struct DatViewer {
     pub schema: Option<Schema>,
@@ -13,8 +23,43 @@ pub struct DatViewer {
     pub request_update_schema: bool,
     pub error_msg: Option<String>,
     pub row_cache: LruCache<u32, Vec<crate::dat::reader::DatValue>>,
+
+    pub selected_row: Option<u32>,
+    /// Row to select and scroll to once the next table is shown.
+    pub pending_row: Option<u32>,
+    /// Set when the user asks for the reverse-reference index; the app
+    /// answers with [`DatViewer::build_reverse_index`].
+    pub request_reverse_index: bool,
+    /// (table, row) the user clicked in "Referenced by"; the app opens it.
+    pub navigate_to: Option<(String, u32)>,
+    reverse_index: Option<Arc<ReverseIndex>>,
+    reverse_rx: Option<Receiver<ReverseIndexStatus>>,
+    reverse_progress: Option<(usize, usize)>,
+    /// Column guesses for the loaded table, after "Guess Columns".
+    inference: Option<Inference>,
+    column_editor: Option<ColumnEditor>,
+    /// Set when a column edit or guessed table was saved to the schema
+    /// overlay; the app clears it after dropping data loaded with the old
+    /// schema.
+    pub schema_edited: bool,
+}
+
+/// Name, type and array flag of one column, edited in a small window and
+/// saved to the schema overlay.
+struct ColumnEditor {
+    table: String,
+    index: usize,
+    original: Option<String>,
+    name: String,
+    r#type: String,
+    array: bool,
+    error: Option<String>,
 }
 
+const COLUMN_TYPES: &[&str] = &[
+    "bool", "u8", "i16", "u16", "i32", "u32", "i64", "u64", "f32", "string", "foreignrow", "row", "enumrow",
+];
+
 impl Default for DatViewer {
     fn default() -> Self {
         Self {
@@ -24,6 +69,16 @@ impl Default for DatViewer {
             request_update_schema: false,
             error_msg: None,
             row_cache: LruCache::new(NonZeroUsize::new(5000).unwrap()),
+            selected_row: None,
+            pending_row: None,
+            request_reverse_index: false,
+            navigate_to: None,
+            reverse_index: None,
+            reverse_rx: None,
+            reverse_progress: None,
+            inference: None,
+            column_editor: None,
+            schema_edited: false,
         }
     }
 }
@@ -36,6 +91,98 @@ impl DatViewer {
     pub fn set_schema(&mut self, schema: Schema, date: String) {
         self.schema = Some(schema);
         self.schema_date = date;
+        self.clear_reverse_index();
+    }
+
+    /// Drops the reverse-reference index, e.g. after a different install
+    /// was opened.
+    pub fn clear_reverse_index(&mut self) {
+        self.reverse_index = None;
+        self.reverse_rx = None;
+        self.reverse_progress = None;
+    }
+
+    /// Scans every table in the schema from `vfs` on a background thread.
+    pub fn build_reverse_index(&mut self, vfs: Arc<crate::vfs::Vfs>) {
+        self.request_reverse_index = false;
+        let Some(schema) = self.schema.clone() else { return };
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.reverse_rx = Some(rx);
+        self.reverse_progress = None;
+        std::thread::spawn(move || {
+            let db = Database::from_vfs(vfs, Arc::new(schema));
+            let index = ReverseIndex::build(&db, |done, total| {
+                let _ = tx.send(ReverseIndexStatus::Progress(done, total));
+            });
+            for (what, reason) in &index.skipped {
+                log::debug!("Reverse index skipped {}: {}", what, reason);
+            }
+            let _ = tx.send(ReverseIndexStatus::Done(index));
+        });
+    }
+
+    fn poll_reverse_index(&mut self, ctx: &egui::Context) {
+        let Some(rx) = &self.reverse_rx else { return };
+        loop {
+            match rx.try_recv() {
+                Ok(ReverseIndexStatus::Progress(done, total)) => self.reverse_progress = Some((done, total)),
+                Ok(ReverseIndexStatus::Done(index)) => {
+                    self.reverse_index = Some(Arc::new(index));
+                    self.reverse_rx = None;
+                    return;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.reverse_rx = None;
+                    return;
+                }
+            }
+        }
+        ctx.request_repaint_after(std::time::Duration::from_millis(100));
+    }
+
+    fn show_referenced_by(&mut self, ui: &mut egui::Ui, table: &str) {
+        let Some(row) = self.selected_row else { return };
+        egui::CollapsingHeader::new(format!("Referenced by (row {})", row))
+            .id_salt("dat_referenced_by")
+            .default_open(true)
+            .show(ui, |ui| {
+                if let Some(index) = &self.reverse_index {
+                    let referrers = index.referrers(table, row);
+                    if referrers.is_empty() {
+                        ui.label("No rows reference this row.");
+                    }
+                    egui::ScrollArea::vertical().max_height(160.0).id_salt("dat_referrers").show(ui, |ui| {
+                        for r in referrers {
+                            if ui.link(format!("{} row {} ({})", r.table, r.row, r.column)).clicked() {
+                                self.navigate_to = Some((r.table.clone(), r.row));
+                            }
+                        }
+                    });
+                    if !index.skipped.is_empty() {
+                        ui.label(
+                            egui::RichText::new(format!("{} tables or columns could not be scanned", index.skipped.len()))
+                                .size(11.0)
+                                .weak(),
+                        );
+                    }
+                } else if self.reverse_rx.is_some() {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        match self.reverse_progress {
+                            Some((done, total)) => ui.label(format!("Scanning tables {}/{}", done, total)),
+                            None => ui.label("Scanning tables..."),
+                        };
+                    });
+                } else {
+                    ui.horizontal(|ui| {
+                        ui.label("Finding referrers reads every table once.");
+                        if ui.button("Build Index").clicked() {
+                            self.request_reverse_index = true;
+                        }
+                    });
+                }
+            });
     }
 
     pub fn load(&mut self, reader: &GgpkReader, offset: u64) {
@@ -56,6 +203,8 @@ impl DatViewer {
     pub fn load_from_bytes(&mut self, data: Vec<u8>, filename: &str) {
         self.error_msg = None;
         self.row_cache.clear();
+        self.selected_row = None;
+        self.inference = None;
         match DatReader::new(data, filename) {
             Ok(dat_reader) => {
                 println!("Successfully loaded DAT: {}", filename);
@@ -71,6 +220,7 @@ impl DatViewer {
     }
     
     pub fn show(&mut self, ui: &mut egui::Ui, is_poe2: bool) {
+         self.poll_reverse_index(ui.ctx());
          if let Some(err) = &self.error_msg {
              ui.colored_label(egui::Color32::from_rgb(239, 68, 68), err);
              // Show diagnostic info for failed loads
@@ -108,6 +258,26 @@ impl DatViewer {
              ui.label(format!("Rows: {}", reader.row_count));
          }
          
+         let scroll_to = self
+             .pending_row
+             .take()
+             .filter(|&r| self.reader.as_ref().is_some_and(|reader| r < reader.row_count));
+         if scroll_to.is_some() {
+             self.selected_row = scroll_to;
+         }
+         if let (Some(schema), Some(reader)) = (&self.schema, &self.reader) {
+             let stem = std::path::Path::new(&reader.filename)
+                 .file_stem()
+                 .map(|s| s.to_string_lossy().to_string())
+                 .unwrap_or_default();
+             if schema.tables.iter().any(|t| t.name.eq_ignore_ascii_case(&stem)) {
+                 self.show_referenced_by(ui, &stem);
+             }
+         }
+
+         let mut guessed = None;
+         let mut edit_column = None;
+         let mut save_table = None;
          if let Some(schema) = &self.schema {
              if let Some(reader) = &self.reader {
                  // Match table name (insensitive) and pick best valid_for
@@ -127,11 +297,28 @@ impl DatViewer {
                  ui.horizontal(|ui| {
                      ui.label(format!("Table: {} (ver: {})", table.name, table.valid_for.unwrap_or(0)));
                  });
+                 if let Err(e) = reader.check_schema(table) {
+                     ui.horizontal(|ui| {
+                         ui.colored_label(egui::Color32::from_rgb(245, 158, 11), e.to_string());
+                         if ui.button("Guess Columns").on_hover_text("Guess the columns the schema is missing from the data").clicked() {
+                             guessed = Some(crate::dat::infer::infer(reader, &table.name, Some(table)));
+                         }
+                     });
+                     if let Some(inference) = &self.inference {
+                         if show_inference(ui, inference, Some(table)) {
+                             save_table = Some(inference.schema_table(Some(table)));
+                         }
+                     }
+                 }
 
                  use egui_extras::{TableBuilder, Column};
                  
                  egui::ScrollArea::horizontal().show(ui, |ui| {
-                     TableBuilder::new(ui)
+                     let mut builder = TableBuilder::new(ui);
+                     if let Some(row) = scroll_to {
+                         builder = builder.scroll_to_row(row as usize, Some(egui::Align::Center));
+                     }
+                     builder
                          .striped(true)
                          .resizable(true)
                          .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
@@ -140,16 +327,38 @@ impl DatViewer {
                          .min_scrolled_height(0.0)
                          .header(20.0, |mut header| {
                              header.col(|ui| { ui.strong("Index"); });
-                             for col in &table.columns {
+                             for (j, col) in table.columns.iter().enumerate() {
                                  let name = col.name.as_deref().unwrap_or("?");
-                                 header.col(|ui| { ui.strong(name).on_hover_text(format!("Type: {}\nArray: {}", col.r#type, col.array)); });
+                                 header.col(|ui| {
+                                     ui.strong(name)
+                                         .on_hover_text(format!("Type: {}\nArray: {}\nRight-click to edit", col.r#type, col.array))
+                                         .context_menu(|ui| {
+                                             if ui.button("Edit Column...").clicked() {
+                                                 edit_column = Some(ColumnEditor {
+                                                     table: table.name.clone(),
+                                                     index: j,
+                                                     original: col.name.clone(),
+                                                     name: col.name.clone().unwrap_or_default(),
+                                                     r#type: col.r#type.clone(),
+                                                     array: col.array,
+                                                     error: None,
+                                                 });
+                                                 ui.close_menu();
+                                             }
+                                         });
+                                 });
                              }
                          })
                          .body(|body| {
                              if let Some(reader) = &self.reader {
                                  body.rows(20.0, reader.row_count as usize, |mut row| {
                                      let row_index = row.index();
-                                     row.col(|ui| { ui.label(row_index.to_string()); });
+                                     row.col(|ui| {
+                                         let selected = self.selected_row == Some(row_index as u32);
+                                         if ui.selectable_label(selected, row_index.to_string()).clicked() {
+                                             self.selected_row = Some(row_index as u32);
+                                         }
+                                     });
                                      
                                      // Check cache first
                                      let values = if let Some(cached) = self.row_cache.get(&(row_index as u32)) {
@@ -221,7 +430,15 @@ impl DatViewer {
                       ui.horizontal(|ui| {
                           ui.colored_label(egui::Color32::from_rgb(245, 158, 11), "⚠️ Table not defined in schema.");
                           ui.label("Displaying generic layout (8-byte columns).");
+                          if ui.button("Guess Columns").clicked() {
+                              guessed = Some(crate::dat::infer::infer(reader, &stem, None));
+                          }
                       });
+                      if let Some(inference) = &self.inference {
+                          if show_inference(ui, inference, None) {
+                              save_table = Some(inference.schema_table(None));
+                          }
+                      }
                       ui.add_space(4.0);
                       self.show_generic_view(ui, reader);
                   }
@@ -240,6 +457,23 @@ impl DatViewer {
                    self.show_generic_view(ui, reader);
                }
           }
+         if guessed.is_some() {
+             self.inference = guessed;
+         }
+         if edit_column.is_some() {
+             self.column_editor = edit_column;
+         }
+         if let Some(table) = save_table {
+             match serde_json::from_value(table).map_err(|e| e.to_string()).and_then(|table: Table| {
+                 let mut overlay = SchemaOverlay::load(&overlay::default_path()).map_err(|e| e.to_string())?;
+                 overlay.set_table(table.clone());
+                 self.save_overlay(overlay, SchemaOverlay { tables: vec![table], ..Default::default() })
+             }) {
+                 Ok(()) => self.inference = None,
+                 Err(e) => self.error_msg = Some(format!("Failed to save the schema overlay: {}", e)),
+             }
+         }
+         self.show_column_editor(ui.ctx());
          
          ui.separator();
          ui.horizontal(|ui| {
@@ -301,6 +535,87 @@ impl DatViewer {
         }
     }
 
+    /// Saves `overlay` and merges `change`, the edit just added to it, into
+    /// the loaded schema so the edit shows up without reloading. The loaded
+    /// schema already has the rest of the overlay merged in.
+    fn save_overlay(&mut self, overlay: SchemaOverlay, change: SchemaOverlay) -> Result<(), String> {
+        overlay.save(&overlay::default_path()).map_err(|e| e.to_string())?;
+        if let Some(schema) = &mut self.schema {
+            change.apply(schema);
+        }
+        self.row_cache.clear();
+        self.clear_reverse_index();
+        self.schema_edited = true;
+        Ok(())
+    }
+
+    fn show_column_editor(&mut self, ctx: &egui::Context) {
+        let Some(editor) = &mut self.column_editor else { return };
+        let mut open = true;
+        let mut save = false;
+        egui::Window::new(format!("Edit {} Column {}", editor.table, editor.index))
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("column_editor").num_columns(2).show(ui, |ui| {
+                    ui.label("Name");
+                    ui.text_edit_singleline(&mut editor.name);
+                    ui.end_row();
+                    ui.label("Type");
+                    egui::ComboBox::from_id_salt("column_editor_type")
+                        .selected_text(editor.r#type.as_str())
+                        .show_ui(ui, |ui| {
+                            for ty in COLUMN_TYPES {
+                                ui.selectable_value(&mut editor.r#type, ty.to_string(), *ty);
+                            }
+                        });
+                    ui.end_row();
+                    ui.label("Array");
+                    ui.checkbox(&mut editor.array, "");
+                    ui.end_row();
+                });
+                ui.label(
+                    egui::RichText::new(format!("Saved to {}, which is applied over every schema update", overlay::OVERLAY_FILE_NAME))
+                        .size(11.0)
+                        .weak(),
+                );
+                if let Some(e) = &editor.error {
+                    ui.colored_label(egui::Color32::from_rgb(239, 68, 68), e);
+                }
+                ui.horizontal(|ui| {
+                    save = ui.add_enabled(!editor.name.trim().is_empty(), egui::Button::new("Save")).clicked();
+                });
+            });
+        if !open {
+            self.column_editor = None;
+            return;
+        }
+        if !save {
+            return;
+        }
+        let edit = ColumnEdit {
+            table: editor.table.clone(),
+            index: editor.index,
+            original: editor.original.clone(),
+            name: Some(editor.name.trim().to_string()),
+            r#type: Some(editor.r#type.clone()),
+            array: Some(editor.array),
+        };
+        let saved = SchemaOverlay::load(&overlay::default_path()).map_err(|e| e.to_string()).and_then(|mut overlay| {
+            overlay.set_column(edit.clone());
+            self.save_overlay(overlay, SchemaOverlay { columns: vec![edit], ..Default::default() })
+        });
+        match saved {
+            Ok(()) => self.column_editor = None,
+            Err(e) => {
+                if let Some(editor) = &mut self.column_editor {
+                    editor.error = Some(e);
+                }
+            }
+        }
+    }
+
     pub fn show_generic_view(&self, ui: &mut egui::Ui, reader: &DatReader) {
         ui.label("Generic View (No Schema / Unknown Table)");
         if let Some(row_len) = reader.row_length {
@@ -383,3 +698,45 @@ impl DatViewer {
         None
     }
 }
+
+/// The guessed columns, with buttons to copy them as a schema entry or
+/// save it to the schema overlay. Returns true when Save was clicked.
+fn show_inference(ui: &mut egui::Ui, inference: &Inference, table: Option<&crate::dat::schema::Table>) -> bool {
+    let mut save = false;
+    egui::CollapsingHeader::new(format!("Guessed columns ({})", inference.columns.len()))
+        .default_open(true)
+        .show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "Kept {} schema columns; guessing from byte {} of {}.",
+                    inference.kept_columns,
+                    inference.guessed_from(),
+                    inference.row_length
+                ));
+                if ui.button("Copy Schema Entry").on_hover_text("schema.min.json table with the guesses appended").clicked() {
+                    let entry = serde_json::to_string_pretty(&inference.schema_table(table)).unwrap_or_default();
+                    ui.ctx().copy_text(entry);
+                }
+                if ui.button("Save to Overlay").on_hover_text("Use the guesses for this table until the schema is fixed upstream").clicked() {
+                    save = true;
+                }
+            });
+            egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                egui::Grid::new("inferred_columns").striped(true).show(ui, |ui| {
+                    ui.strong("Offset");
+                    ui.strong("Size");
+                    ui.strong("Type");
+                    ui.strong("Evidence");
+                    ui.end_row();
+                    for c in &inference.columns {
+                        ui.label(c.offset.to_string());
+                        ui.label(c.size.to_string());
+                        ui.monospace(if c.array { format!("[{}]", c.r#type) } else { c.r#type.clone() });
+                        ui.label(&c.evidence);
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+    save
+}
@@ -0,0 +1,308 @@
+use eframe::egui;
+use std::collections::BTreeMap;
+use std::sync::mpsc::{Receiver, TryRecvError};
+
+use crate::diff::{ChangeKind, FileChange, IndexDiff};
+use crate::ui::components::modal_section;
+use crate::vfs::Vfs;
+
+enum DiffStatus {
+    Progress(usize, usize),
+    Done(Result<IndexDiff, String>),
+}
+
+/// Directory level of the change tree; `files` index into `IndexDiff::changes`.
+#[derive(Default)]
+struct DiffNode {
+    dirs: BTreeMap<String, DiffNode>,
+    files: Vec<usize>,
+    added: usize,
+    removed: usize,
+    changed: usize,
+}
+
+impl DiffNode {
+    fn build(diff: &IndexDiff, filter: &str) -> Self {
+        let filter = filter.to_ascii_lowercase();
+        let mut root = DiffNode::default();
+        for (i, change) in diff.changes.iter().enumerate() {
+            let path = change.display_path();
+            if !filter.is_empty() && !path.to_ascii_lowercase().contains(&filter) {
+                continue;
+            }
+            let mut dirs: Vec<&str> = if change.path.is_empty() {
+                vec!["(unnamed)"]
+            } else {
+                change.path.split('/').collect()
+            };
+            dirs.pop();
+            let mut node = &mut root;
+            node.count(change.kind);
+            for dir in dirs {
+                node = node.dirs.entry(dir.to_string()).or_default();
+                node.count(change.kind);
+            }
+            node.files.push(i);
+        }
+        root
+    }
+
+    fn count(&mut self, kind: ChangeKind) {
+        match kind {
+            ChangeKind::Added => self.added += 1,
+            ChangeKind::Removed => self.removed += 1,
+            ChangeKind::Changed => self.changed += 1,
+        }
+    }
+}
+
+fn kind_color(ui: &egui::Ui, kind: ChangeKind) -> egui::Color32 {
+    let dark = ui.visuals().dark_mode;
+    match (kind, dark) {
+        (ChangeKind::Added, true) => egui::Color32::from_rgb(74, 222, 128),
+        (ChangeKind::Added, false) => egui::Color32::from_rgb(22, 130, 60),
+        (ChangeKind::Removed, true) => egui::Color32::from_rgb(248, 113, 113),
+        (ChangeKind::Removed, false) => egui::Color32::from_rgb(185, 28, 28),
+        (ChangeKind::Changed, true) => egui::Color32::from_rgb(250, 204, 21),
+        (ChangeKind::Changed, false) => egui::Color32::from_rgb(161, 98, 7),
+    }
+}
+
+fn size_text(change: &FileChange) -> String {
+    match (change.old_size, change.new_size) {
+        (Some(old), Some(new)) if old != new => format!("{} -> {} bytes", old, new),
+        (_, Some(size)) | (Some(size), None) => format!("{} bytes", size),
+        (None, None) => String::new(),
+    }
+}
+
+#[derive(Default)]
+pub struct DiffWindow {
+    open: bool,
+    /// Anything `ggpk-explorer diff` accepts: a GGPK, a Bundles2 or game
+    /// directory, an index `.cache` file or `cdn:<patch-version>`.
+    old_source: String,
+    size_only: bool,
+    /// Set when the user clicks Compare; the app then calls [`DiffWindow::start`]
+    /// with the currently open install.
+    pub confirmed: bool,
+    rx: Option<Receiver<DiffStatus>>,
+    progress: Option<(usize, usize)>,
+    result: Option<IndexDiff>,
+    error: Option<String>,
+    filter: String,
+    tree: Option<(String, DiffNode)>,
+}
+
+impl DiffWindow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+        self.confirmed = false;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.rx.is_some()
+    }
+
+    /// Diffs the entered source (old) against `new` on a background thread.
+    pub fn start(&mut self, new: Vfs, schema: Option<crate::dat::schema::Schema>) {
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.rx = Some(rx);
+        self.progress = None;
+        self.result = None;
+        self.error = None;
+        self.tree = None;
+
+        let spec = self.old_source.trim().to_string();
+        let size_only = self.size_only;
+        std::thread::spawn(move || {
+            let result = crate::cli::open_diff_source(&spec, schema.as_ref())
+                .map_err(|e| e.to_string())
+                .map(|(old, readable)| {
+                    // An index cache has no data to hash.
+                    if size_only || !readable {
+                        crate::diff::diff_sizes(old.index(), new.index())
+                    } else {
+                        crate::diff::diff(&old, &new, |done, total| {
+                            if done % 256 == 0 || done == total {
+                                let _ = tx.send(DiffStatus::Progress(done, total));
+                            }
+                        })
+                    }
+                });
+            let _ = tx.send(DiffStatus::Done(result));
+        });
+    }
+
+    fn poll(&mut self, ctx: &egui::Context) {
+        let Some(rx) = &self.rx else { return };
+        loop {
+            match rx.try_recv() {
+                Ok(DiffStatus::Progress(done, total)) => self.progress = Some((done, total)),
+                Ok(DiffStatus::Done(result)) => {
+                    match result {
+                        Ok(diff) => self.result = Some(diff),
+                        Err(e) => self.error = Some(e),
+                    }
+                    self.rx = None;
+                    return;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.error = Some("Diff thread disconnected (Panic?)".to_string());
+                    self.rx = None;
+                    return;
+                }
+            }
+        }
+        ctx.request_repaint_after(std::time::Duration::from_millis(100));
+    }
+
+    fn save_json(&mut self) {
+        let Some(diff) = &self.result else { return };
+        let Some(path) = rfd::FileDialog::new()
+            .set_file_name("diff.json")
+            .add_filter("JSON", &["json"])
+            .save_file()
+        else {
+            return;
+        };
+        let written = std::fs::File::create(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|f| serde_json::to_writer_pretty(std::io::BufWriter::new(f), diff).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            self.error = Some(format!("Failed to save {}: {}", path.display(), e));
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        self.poll(ctx);
+        let mut open = self.open;
+        if !open {
+            return;
+        }
+
+        egui::Window::new("Compare Versions")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(true)
+            .default_width(560.0)
+            .default_height(480.0)
+            .show(ctx, |ui| {
+                ui.spacing_mut().item_spacing.y = 5.0;
+
+                modal_section(ui, "OLD VERSION");
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.old_source)
+                            .hint_text("Content.ggpk, Bundles2, bundles2.cache or cdn:<version>")
+                            .desired_width(ui.available_width() - 190.0),
+                    );
+                    if ui.button("GGPK...").clicked() {
+                        if let Some(p) = rfd::FileDialog::new().add_filter("GGPK", &["ggpk"]).pick_file() {
+                            self.old_source = p.to_string_lossy().into_owned();
+                        }
+                    }
+                    if ui.button("Folder...").clicked() {
+                        if let Some(p) = rfd::FileDialog::new().pick_folder() {
+                            self.old_source = p.to_string_lossy().into_owned();
+                        }
+                    }
+                    if ui.button("Cache...").clicked() {
+                        if let Some(p) = rfd::FileDialog::new().add_filter("Index cache", &["cache"]).pick_file() {
+                            self.old_source = p.to_string_lossy().into_owned();
+                        }
+                    }
+                });
+                ui.label(egui::RichText::new("New version: the install that is currently open").size(11.0).weak());
+                ui.checkbox(&mut self.size_only, "Compare sizes only (fast; misses same-size edits)");
+
+                ui.horizontal(|ui| {
+                    let can_start = !self.is_running() && !self.old_source.trim().is_empty();
+                    if ui.add_enabled(can_start, egui::Button::new("Compare")).clicked() {
+                        self.confirmed = true;
+                    }
+                    if self.is_running() {
+                        ui.spinner();
+                        match self.progress {
+                            Some((done, total)) => ui.label(format!("Comparing contents {}/{}", done, total)),
+                            None => ui.label("Loading indexes..."),
+                        };
+                    }
+                    if self.result.is_some() {
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if ui.button("Save JSON...").clicked() {
+                                self.save_json();
+                            }
+                        });
+                    }
+                });
+
+                if let Some(e) = &self.error {
+                    ui.colored_label(kind_color(ui, ChangeKind::Removed), e);
+                }
+
+                let Some(diff) = &self.result else { return };
+                ui.separator();
+                modal_section(ui, "CHANGES");
+                ui.label(egui::RichText::new(diff.summary()).monospace().size(11.0));
+                if !diff.contents_compared {
+                    ui.label(egui::RichText::new("Compared by size only").size(11.0).weak());
+                }
+                if !diff.unreadable.is_empty() {
+                    ui.label(format!("{} files could not be read and were not compared", diff.unreadable.len()))
+                        .on_hover_text(diff.unreadable.iter().take(20).cloned().collect::<Vec<_>>().join("\n"));
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Filter:");
+                    ui.text_edit_singleline(&mut self.filter);
+                });
+
+                if self.tree.as_ref().is_none_or(|(f, _)| *f != self.filter) {
+                    self.tree = Some((self.filter.clone(), DiffNode::build(diff, &self.filter)));
+                }
+                let (_, root) = self.tree.as_ref().expect("tree was just built");
+                egui::ScrollArea::vertical().auto_shrink([false, false]).show(ui, |ui| {
+                    show_node(ui, diff, root, "");
+                });
+            });
+
+        self.open = open;
+    }
+}
+
+fn show_node(ui: &mut egui::Ui, diff: &IndexDiff, node: &DiffNode, id: &str) {
+    for (name, child) in &node.dirs {
+        let child_id = format!("{}/{}", id, name);
+        let label = format!("{}/  +{} -{} ~{}", name, child.added, child.removed, child.changed);
+        egui::CollapsingHeader::new(label)
+            .id_salt(&child_id)
+            .show(ui, |ui| show_node(ui, diff, child, &child_id));
+    }
+    for &i in &node.files {
+        let change = &diff.changes[i];
+        let name = match change.path.rsplit_once('/') {
+            Some((_, name)) => name.to_string(),
+            None => change.display_path(),
+        };
+        let marker = match change.kind {
+            ChangeKind::Added => '+',
+            ChangeKind::Removed => '-',
+            ChangeKind::Changed => '~',
+        };
+        let mut hover = size_text(change);
+        if let (Some(old), Some(new)) = (&change.old_sha256, &change.new_sha256) {
+            hover.push_str(&format!("\nold sha256 {}\nnew sha256 {}", old, new));
+        }
+        ui.label(
+            egui::RichText::new(format!("{} {}", marker, name))
+                .monospace()
+                .color(kind_color(ui, change.kind)),
+        )
+        .on_hover_text(hover);
+    }
+}
@@ -1,51 +1,7 @@
 use eframe::egui;
 use crate::ui::components::modal_section;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum TextureFormat {
-    OriginalDds,
-    WebP,
-    Png,
-}
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum AudioFormat {
-    Original,
-    Wav,
-}
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum DataFormat {
-    Original,
-    Json,
-}
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum PsgFormat {
-    Original,
-    Json,
-}
-
-#[derive(Clone)]
-pub struct ExportSettings {
-    pub texture_format: TextureFormat,
-    pub audio_format: AudioFormat,
-    pub data_format: DataFormat,
-    pub psg_format: PsgFormat,
-    pub recursive: bool,
-}
-
-impl Default for ExportSettings {
-    fn default() -> Self {
-        Self {
-            texture_format: TextureFormat::OriginalDds,
-            audio_format: AudioFormat::Original,
-            data_format: DataFormat::Original,
-            psg_format: PsgFormat::Original,
-            recursive: true,
-        }
-    }
-}
+pub use crate::export::{AudioFormat, DataFormat, ExportSettings, PsgFormat, TextureFormat};
 
 pub struct ExportWindow {
     open: bool,
@@ -93,7 +49,7 @@ impl ExportWindow {
         let mut should_close = false;
 
         let is_dds = self.target_name.ends_with(".dds");
-        let is_ogg = self.target_name.ends_with(".ogg");
+        let is_ogg = self.target_name.ends_with(".ogg") || self.target_name.ends_with(".bank");
         let is_dat = self.target_name.contains(".dat");
         let is_psg = self.target_name.ends_with(".psg");
         let show_all = self.is_folder;
@@ -144,6 +100,12 @@ impl ExportWindow {
                     ui.horizontal(|ui| {
                         ui.radio_value(&mut self.settings.data_format, DataFormat::Original, "Original");
                         ui.radio_value(&mut self.settings.data_format, DataFormat::Json, "JSON");
+                        ui.radio_value(&mut self.settings.data_format, DataFormat::Csv, "CSV")
+                            .on_hover_text("Array columns become one cell holding a JSON array");
+                        ui.radio_value(&mut self.settings.data_format, DataFormat::Arrow, "Arrow")
+                            .on_hover_text("Arrow IPC (Feather) files with typed columns, for pandas/polars");
+                        ui.radio_value(&mut self.settings.data_format, DataFormat::Sqlite, "SQLite")
+                            .on_hover_text("All tables in one tables.sqlite in the target folder");
                     });
                 }
 
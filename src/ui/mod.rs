@@ -5,6 +5,8 @@ mod dat_viewer;
 pub mod hex_viewer;
 pub mod settings_window;
 pub mod export_window;
+pub mod diff_window;
+pub mod query_window;
 pub mod json_viewer;
 pub mod syntax;
 pub mod chrome;
@@ -0,0 +1,214 @@
+use eframe::egui;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::Arc;
+
+use crate::dat::query::QueryResult;
+use crate::dat::relational::Database;
+use crate::dat::schema::Schema;
+use crate::ui::components::modal_section;
+use crate::vfs::Vfs;
+
+pub struct QueryWindow {
+    open: bool,
+    sql: String,
+    /// Set when the user clicks Run; the app then calls [`QueryWindow::start`]
+    /// with the currently open install.
+    pub confirmed: bool,
+    /// Kept between runs so tables are only loaded once per install.
+    db: Option<Arc<Database>>,
+    rx: Option<Receiver<Result<QueryResult, String>>>,
+    result: Option<QueryResult>,
+    error: Option<String>,
+}
+
+impl Default for QueryWindow {
+    fn default() -> Self {
+        Self {
+            open: false,
+            sql: "SELECT * FROM Mods LIMIT 100".to_string(),
+            confirmed: false,
+            db: None,
+            rx: None,
+            result: None,
+            error: None,
+        }
+    }
+}
+
+impl QueryWindow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+        self.confirmed = false;
+    }
+
+    /// Forgets loaded tables, e.g. after another install was opened.
+    pub fn reset(&mut self) {
+        self.db = None;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.rx.is_some()
+    }
+
+    /// Runs the entered query on a background thread.
+    pub fn start(&mut self, vfs: Vfs, schema: Schema) {
+        // A schema update also invalidates the loaded tables.
+        if self.db.as_ref().is_some_and(|db| db.schema().created_at != schema.created_at) {
+            self.db = None;
+        }
+        let db = self
+            .db
+            .get_or_insert_with(|| Arc::new(Database::from_vfs(Arc::new(vfs), Arc::new(schema))))
+            .clone();
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.rx = Some(rx);
+        self.error = None;
+
+        let sql = self.sql.clone();
+        std::thread::spawn(move || {
+            let _ = tx.send(crate::dat::query::run(&db, &sql).map_err(|e| e.to_string()));
+        });
+    }
+
+    fn poll(&mut self, ctx: &egui::Context) {
+        let Some(rx) = &self.rx else { return };
+        match rx.try_recv() {
+            Ok(result) => {
+                match result {
+                    Ok(result) => self.result = Some(result),
+                    Err(e) => self.error = Some(e),
+                }
+                self.rx = None;
+            }
+            Err(TryRecvError::Empty) => ctx.request_repaint_after(std::time::Duration::from_millis(100)),
+            Err(TryRecvError::Disconnected) => {
+                self.error = Some("Query thread disconnected (Panic?)".to_string());
+                self.rx = None;
+            }
+        }
+    }
+
+    fn save_json(&mut self) {
+        let Some(result) = &self.result else { return };
+        let Some(path) = rfd::FileDialog::new()
+            .set_file_name("query.json")
+            .add_filter("JSON", &["json"])
+            .save_file()
+        else {
+            return;
+        };
+        let written = std::fs::File::create(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|f| serde_json::to_writer_pretty(std::io::BufWriter::new(f), &result.to_json()).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            self.error = Some(format!("Failed to save {}: {}", path.display(), e));
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        self.poll(ctx);
+        let mut open = self.open;
+        if !open {
+            return;
+        }
+
+        egui::Window::new("Query Tables")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(true)
+            .default_width(720.0)
+            .default_height(520.0)
+            .show(ctx, |ui| {
+                ui.spacing_mut().item_spacing.y = 5.0;
+
+                modal_section(ui, "QUERY");
+                let response = ui.add(
+                    egui::TextEdit::multiline(&mut self.sql)
+                        .code_editor()
+                        .desired_rows(4)
+                        .desired_width(f32::INFINITY)
+                        .hint_text("SELECT Id, ModTypeKey.Name FROM Mods WHERE Domain = 'ITEM' LIMIT 100"),
+                );
+                let ctrl_enter = response.has_focus() && ui.input(|i| i.modifiers.command && i.key_pressed(egui::Key::Enter));
+                ui.label(
+                    egui::RichText::new(
+                        "Dotted columns follow foreign keys; JOIN <table> ON <foreign key column> adds a row per referenced row",
+                    )
+                    .size(11.0)
+                    .weak(),
+                );
+
+                ui.horizontal(|ui| {
+                    let can_run = !self.is_running() && !self.sql.trim().is_empty();
+                    if (ui.add_enabled(can_run, egui::Button::new("Run")).on_hover_text("Ctrl+Enter").clicked()
+                        || ctrl_enter)
+                        && can_run
+                    {
+                        self.confirmed = true;
+                    }
+                    if self.is_running() {
+                        ui.spinner();
+                        ui.label("Running...");
+                    } else if let Some(result) = &self.result {
+                        ui.label(format!("{} rows", result.rows.len()));
+                    }
+                    if self.result.is_some() {
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if ui.button("Save JSON...").clicked() {
+                                self.save_json();
+                            }
+                        });
+                    }
+                });
+
+                if let Some(e) = &self.error {
+                    let color = if ui.visuals().dark_mode {
+                        egui::Color32::from_rgb(248, 113, 113)
+                    } else {
+                        egui::Color32::from_rgb(185, 28, 28)
+                    };
+                    ui.label(egui::RichText::new(e).monospace().color(color));
+                }
+
+                let Some(result) = &self.result else { return };
+                ui.separator();
+                show_result(ui, result);
+            });
+
+        self.open = open;
+    }
+}
+
+fn show_result(ui: &mut egui::Ui, result: &QueryResult) {
+    use egui_extras::{Column, TableBuilder};
+
+    egui::ScrollArea::horizontal().show(ui, |ui| {
+        TableBuilder::new(ui)
+            .striped(true)
+            .resizable(true)
+            .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+            .columns(Column::initial(150.0).resizable(true).clip(true), result.columns.len())
+            .min_scrolled_height(0.0)
+            .header(20.0, |mut header| {
+                for name in &result.columns {
+                    header.col(|ui| {
+                        ui.strong(name);
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(20.0, result.rows.len(), |mut row| {
+                    for value in &result.rows[row.index()] {
+                        row.col(|ui| {
+                            let text = QueryResult::cell_text(value);
+                            ui.label(&text).on_hover_text(&text);
+                        });
+                    }
+                });
+            });
+    });
+}
@@ -0,0 +1,454 @@
+//! One read path over every place game files can live.
+//!
+//! A file is found through the bundle [`Index`], then its bytes come from
+//! either a loose record (GGPK FILE record or a file in the Steam install)
+//! or a slice of a decompressed bundle. Raw bundles are looked up in the
+//! GGPK first, then the Steam `Bundles2` directory, then the patch CDN.
+
+use std::borrow::Cow;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::bundles::bundle::{default_threads, Bundle};
+use crate::bundles::cdn::CdnBundleLoader;
+use crate::bundles::index::{fnv1a64, murmur_hash64a, FileInfo, Index, GGPK_LOOSE_FILE_SENTINEL};
+use crate::bundles::steam::{SteamBundleLoader, LOOSE_FILE_SENTINEL};
+use crate::dat::schema::Schema;
+use crate::error::{Error, Result};
+use crate::ggpk::reader::GgpkReader;
+
+/// Borrowed view of the places raw bundles and loose files are read from.
+/// Cheap to build wherever the individual loaders are already at hand.
+#[derive(Clone, Copy, Default)]
+pub struct BundleSources<'a> {
+    pub reader: Option<&'a GgpkReader>,
+    pub steam: Option<&'a SteamBundleLoader>,
+    pub cdn: Option<&'a CdnBundleLoader>,
+}
+
+impl<'a> BundleSources<'a> {
+    /// Returns the compressed bundle `name` (as stored in the index, with or
+    /// without `.bundle.bin`). Data read from the GGPK is borrowed from the
+    /// memory map.
+    pub fn fetch_raw_bundle(&self, name: &str) -> Result<Cow<'a, [u8]>> {
+        let file_name = if name.ends_with(".bundle.bin") {
+            name.to_string()
+        } else {
+            format!("{}.bundle.bin", name)
+        };
+
+        if let Some(reader) = self.reader {
+            for cand in [format!("Bundles2/{}", file_name), format!("Bundles2/{}", name)] {
+                if let Ok(Some(rec)) = reader.read_file_by_path(&cand) {
+                    return reader.get_data_slice(rec.data_offset, rec.data_length).map(Cow::Borrowed);
+                }
+            }
+        }
+        if let Some(steam) = self.steam {
+            if let Ok(data) = steam.fetch_bundle(name) {
+                return Ok(Cow::Owned(data));
+            }
+        }
+        if let Some(cdn) = self.cdn {
+            return cdn.fetch_bundle(&file_name).map(Cow::Owned);
+        }
+        Err(Error::BundleMissing(name.to_string()))
+    }
+
+    /// Fetches and fully decompresses the bundle `name`, one thread per core.
+    pub fn read_bundle(&self, name: &str) -> Result<Vec<u8>> {
+        let raw = self.fetch_raw_bundle(name)?;
+        let mut cursor = io::Cursor::new(&*raw);
+        let bundle = Bundle::read_header(&mut cursor)?;
+        bundle.decompress_parallel(&raw, default_threads())
+    }
+
+    /// Reads a loose file (one whose `bundle_index` is a sentinel) directly
+    /// from the GGPK or the Steam install. Returns `None` for bundled files.
+    pub fn read_loose(&self, file: &FileInfo) -> Option<Result<Vec<u8>>> {
+        match file.bundle_index {
+            GGPK_LOOSE_FILE_SENTINEL => Some(self.read_ggpk_record(&file.path)),
+            LOOSE_FILE_SENTINEL => Some(match self.steam {
+                Some(steam) => match steam.loose_file_path(&file.path) {
+                    Some(p) => std::fs::read(p).map_err(Error::from),
+                    None => Err(Error::NotFound(format!("loose file on disk: {}", file.path))),
+                },
+                None => Err(Error::NotFound(format!(
+                    "loose file {} (no Steam install loaded)",
+                    file.path
+                ))),
+            }),
+            _ => None,
+        }
+    }
+
+    fn read_ggpk_record(&self, path: &str) -> Result<Vec<u8>> {
+        let reader = self
+            .reader
+            .ok_or_else(|| Error::NotFound(format!("GGPK file {} (no GGPK loaded)", path)))?;
+        let rec = reader
+            .read_file_by_path(path)?
+            .ok_or_else(|| Error::NotFound(format!("GGPK file {}", path)))?;
+        Ok(reader.get_data_slice(rec.data_offset, rec.data_length)?.to_vec())
+    }
+
+    /// Reads one file's decompressed bytes.
+    pub fn read_file(&self, index: &Index, file: &FileInfo) -> Result<Vec<u8>> {
+        if let Some(loose) = self.read_loose(file) {
+            return loose;
+        }
+        let bundle = index.bundles.get(file.bundle_index as usize).ok_or_else(|| {
+            Error::InvalidData(format!("bundle index {} out of range", file.bundle_index))
+        })?;
+        let raw = match self.fetch_raw_bundle(&bundle.name) {
+            Ok(raw) => raw,
+            // Some older GGPKs still carry the file as a plain FILE record.
+            Err(e) => return self.read_ggpk_record(&file.path).map_err(|_| e),
+        };
+        // Only the blocks covering the file are decompressed.
+        let header = Bundle::read_header(io::Cursor::new(&*raw))?;
+        header.decompress_range(&raw, file.file_offset as u64, file.file_size as u64)
+    }
+}
+
+/// Cuts `file` out of its decompressed bundle.
+pub fn slice_file(bundle_data: &[u8], file: &FileInfo) -> Result<Vec<u8>> {
+    let start = file.file_offset as usize;
+    let end = start + file.file_size as usize;
+    bundle_data
+        .get(start..end)
+        .map(|s| s.to_vec())
+        .ok_or(Error::TruncatedRecord { what: "bundled file", offset: start as u64 })
+}
+
+/// Where a file's bytes live.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EntryKind {
+    Dir,
+    Bundle,
+    GgpkLoose,
+    SteamLoose,
+}
+
+impl EntryKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EntryKind::Dir => "dir",
+            EntryKind::Bundle => "bundle",
+            EntryKind::GgpkLoose => "ggpk-loose",
+            EntryKind::SteamLoose => "steam-loose",
+        }
+    }
+}
+
+/// A file or directory returned by [`Vfs::stat`], [`Vfs::list_dir`] and
+/// [`Vfs::walk`]. Directories get the total size of every file beneath them.
+#[derive(Debug, Clone)]
+pub struct VfsEntry<'a> {
+    pub path: String,
+    pub hash: Option<u64>,
+    pub size: u64,
+    pub kind: EntryKind,
+    pub bundle: Option<&'a str>,
+    pub offset: Option<u32>,
+}
+
+/// A bundle index plus the sources its files are read from.
+#[derive(Clone)]
+pub struct Vfs {
+    index: Arc<Index>,
+    reader: Option<Arc<GgpkReader>>,
+    steam: Option<SteamBundleLoader>,
+    cdn: Option<CdnBundleLoader>,
+}
+
+impl Vfs {
+    pub fn from_parts(
+        index: Arc<Index>,
+        reader: Option<Arc<GgpkReader>>,
+        steam: Option<SteamBundleLoader>,
+        cdn: Option<CdnBundleLoader>,
+    ) -> Self {
+        Self { index, reader, steam, cdn }
+    }
+
+    /// Opens a `Content.ggpk`, parses `Bundles2/_.index.bin` from it and
+    /// injects the loose FILE records (FMOD/, Media/, ...).
+    pub fn open_ggpk(path: impl AsRef<Path>) -> Result<Self> {
+        let reader = GgpkReader::open(path)?;
+        let rec = reader
+            .read_file_by_path("Bundles2/_.index.bin")?
+            .ok_or_else(|| Error::NotFound("Bundles2/_.index.bin in GGPK".to_string()))?;
+        let mut index = read_index_bundle(reader.get_data_slice(rec.data_offset, rec.data_length)?)?;
+        index.add_ggpk_loose_files(&reader);
+        Ok(Self::from_parts(Arc::new(index), Some(Arc::new(reader)), None, None))
+    }
+
+    /// Opens a Steam/standalone install from its `Bundles2` directory and
+    /// injects the loose files under the game root.
+    pub fn open_steam(bundles2_dir: impl Into<std::path::PathBuf>) -> Result<Self> {
+        let steam = SteamBundleLoader::new(bundles2_dir.into());
+        let mut index = read_index_bundle(&steam.load_index_bytes()?)?;
+        steam.add_loose_files_to_index(&mut index);
+        Ok(Self::from_parts(Arc::new(index), None, Some(steam), None))
+    }
+
+    /// Streams everything for `patch_version` from the patch CDN, caching
+    /// downloads under `cache_root`. Bundles are cached by name only, so use
+    /// a separate `cache_root` per version.
+    pub fn open_cdn(cache_root: &Path, patch_version: &str) -> Result<Self> {
+        let cdn = CdnBundleLoader::new(cache_root, Some(patch_version));
+        let index_bytes = cdn.fetch_bundle("_.index.bin")?;
+        let index = read_index_bundle(&index_bytes)?;
+        Ok(Self::from_parts(Arc::new(index), None, None, Some(cdn)))
+    }
+
+    /// Adds a CDN fallback for bundles missing from the local install.
+    pub fn with_cdn(mut self, cdn: CdnBundleLoader) -> Self {
+        self.cdn = Some(cdn);
+        self
+    }
+
+    /// Resolves path hashes the index has no name for by scanning dat files
+    /// that reference other files. Returns how many paths were added.
+    pub fn enrich_paths(&mut self, schema: &Schema) -> u32 {
+        if !self.index.files.values().any(|f| f.path.is_empty()) {
+            return 0;
+        }
+        let Self { index, reader, steam, cdn } = self;
+        let sources = BundleSources {
+            reader: reader.as_deref(),
+            steam: steam.as_ref(),
+            cdn: cdn.as_ref(),
+        };
+        crate::bundles::path_enrichment::enrich_paths_from_dat(Arc::make_mut(index), schema, sources)
+    }
+
+    pub fn index(&self) -> &Arc<Index> {
+        &self.index
+    }
+
+    pub fn reader(&self) -> Option<&Arc<GgpkReader>> {
+        self.reader.as_ref()
+    }
+
+    pub fn steam_loader(&self) -> Option<&SteamBundleLoader> {
+        self.steam.as_ref()
+    }
+
+    pub fn cdn_loader(&self) -> Option<&CdnBundleLoader> {
+        self.cdn.as_ref()
+    }
+
+    pub fn sources(&self) -> BundleSources<'_> {
+        BundleSources {
+            reader: self.reader.as_deref(),
+            steam: self.steam.as_ref(),
+            cdn: self.cdn.as_ref(),
+        }
+    }
+
+    /// Finds the index entry for a virtual path (case-insensitive).
+    pub fn lookup(&self, path: &str) -> Option<(u64, &FileInfo)> {
+        let path = normalize(path);
+        let lower = path.to_ascii_lowercase();
+        // Which hash the index uses depends on the game version; try the
+        // detected one, then both on the path as given and lowercased.
+        let candidates = [
+            self.index.hash_algorithm.hash_path(path),
+            murmur_hash64a(lower.as_bytes()),
+            fnv1a64(lower.as_bytes()),
+            murmur_hash64a(path.as_bytes()),
+            fnv1a64(path.as_bytes()),
+        ];
+        candidates.into_iter().find_map(|hash| {
+            self.index
+                .files
+                .get(&hash)
+                .filter(|f| f.path.is_empty() || f.path.eq_ignore_ascii_case(path))
+                .map(|f| (hash, f))
+        })
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.lookup(path).is_some()
+    }
+
+    pub fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let (_, file) = self
+            .lookup(path)
+            .ok_or_else(|| Error::NotFound(path.to_string()))?;
+        self.sources().read_file(&self.index, file)
+    }
+
+    pub fn read_hash(&self, hash: u64) -> Result<Vec<u8>> {
+        let file = self.index.files.get(&hash).ok_or(Error::HashNotInIndex(hash))?;
+        self.sources().read_file(&self.index, file)
+    }
+
+    /// Describes a file, or a directory if some indexed path lies below `path`.
+    pub fn stat(&self, path: &str) -> Option<VfsEntry<'_>> {
+        if let Some((hash, file)) = self.lookup(path) {
+            return Some(self.file_entry(hash, file));
+        }
+        let dir = normalize(path);
+        let entries = self.entries_below(dir, true);
+        if entries.is_empty() {
+            return None;
+        }
+        Some(VfsEntry {
+            path: dir.to_string(),
+            hash: None,
+            size: entries.iter().map(|e| e.size).sum(),
+            kind: EntryKind::Dir,
+            bundle: None,
+            offset: None,
+        })
+    }
+
+    /// Lists the files and subdirectories directly inside `dir` (an empty
+    /// string is the root), sorted by path. Files whose path hash was never
+    /// resolved cannot be placed in a directory and are skipped.
+    pub fn list_dir(&self, dir: &str) -> Vec<VfsEntry<'_>> {
+        self.entries_below(normalize(dir), false)
+    }
+
+    /// Lists every file below `dir`, sorted by path.
+    pub fn walk(&self, dir: &str) -> Vec<VfsEntry<'_>> {
+        self.entries_below(normalize(dir), true)
+    }
+
+    fn file_entry(&self, hash: u64, file: &FileInfo) -> VfsEntry<'_> {
+        let (kind, bundle, offset) = match file.bundle_index {
+            LOOSE_FILE_SENTINEL => (EntryKind::SteamLoose, None, None),
+            GGPK_LOOSE_FILE_SENTINEL => (EntryKind::GgpkLoose, None, None),
+            i => (
+                EntryKind::Bundle,
+                self.index.bundles.get(i as usize).map(|b| b.name.as_str()),
+                Some(file.file_offset),
+            ),
+        };
+        VfsEntry {
+            path: file.path.clone(),
+            hash: Some(hash),
+            size: file.file_size as u64,
+            kind,
+            bundle,
+            offset,
+        }
+    }
+
+    fn entries_below(&self, dir: &str, recursive: bool) -> Vec<VfsEntry<'_>> {
+        let prefix = if dir.is_empty() { String::new() } else { format!("{}/", dir) };
+
+        let mut entries = Vec::new();
+        let mut subdirs: std::collections::BTreeMap<String, u64> = std::collections::BTreeMap::new();
+
+        for (&hash, file) in &self.index.files {
+            let in_dir = file.path.get(..prefix.len()).is_some_and(|p| p.eq_ignore_ascii_case(&prefix));
+            if file.path.is_empty() || !in_dir {
+                continue;
+            }
+            if !recursive {
+                if let Some(slash) = file.path[prefix.len()..].find('/') {
+                    let sub = &file.path[..prefix.len() + slash];
+                    *subdirs.entry(sub.to_string()).or_default() += file.file_size as u64;
+                    continue;
+                }
+            }
+            entries.push(self.file_entry(hash, file));
+        }
+
+        entries.extend(subdirs.into_iter().map(|(path, size)| VfsEntry {
+            path,
+            hash: None,
+            size,
+            kind: EntryKind::Dir,
+            bundle: None,
+            offset: None,
+        }));
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        entries
+    }
+}
+
+fn normalize(path: &str) -> &str {
+    path.trim_matches(|c| c == '/' || c == '\\')
+}
+
+/// Decompresses `_.index.bin` (itself a bundle) and parses it.
+pub fn read_index_bundle(data: &[u8]) -> Result<Index> {
+    let mut cursor = io::Cursor::new(data);
+    let bundle = Bundle::read_header(&mut cursor)?;
+    let decompressed = bundle.decompress_from_slice(data)?;
+    Index::read(&decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundles::index::BundleInfo;
+
+    fn test_vfs() -> Vfs {
+        let mut index = Index {
+            bundles: vec![BundleInfo { name: "Data/Foo".to_string(), uncompressed_size: 100 }],
+            ..Index::default()
+        };
+        for (path, bundle_index, size) in [
+            ("Data/Mods.datc64", 0, 10),
+            ("Data/Balance/Stats.datc64", 0, 20),
+            ("Data/Balance/Tags.datc64", 0, 30),
+            ("FMOD/Music.bank", GGPK_LOOSE_FILE_SENTINEL, 40),
+        ] {
+            let hash = murmur_hash64a(path.to_ascii_lowercase().as_bytes());
+            index.files.insert(hash, FileInfo {
+                path_hash: hash,
+                bundle_index,
+                file_offset: 7,
+                file_size: size,
+                path: path.to_string(),
+            });
+        }
+        Vfs::from_parts(Arc::new(index), None, None, None)
+    }
+
+    #[test]
+    fn list_dir_groups_subdirectories_and_walk_lists_files() {
+        let vfs = test_vfs();
+
+        let ls = vfs.list_dir("data");
+        let paths: Vec<_> = ls.iter().map(|e| (e.path.as_str(), e.kind, e.size)).collect();
+        assert_eq!(paths, vec![
+            ("Data/Balance", EntryKind::Dir, 50),
+            ("Data/Mods.datc64", EntryKind::Bundle, 10),
+        ]);
+
+        let tree = vfs.walk("");
+        assert_eq!(tree.len(), 4);
+        assert!(tree.iter().all(|e| e.kind != EntryKind::Dir));
+        let bank = tree.iter().find(|e| e.path == "FMOD/Music.bank").unwrap();
+        assert_eq!(bank.kind, EntryKind::GgpkLoose);
+        assert_eq!(bank.bundle, None);
+    }
+
+    #[test]
+    fn lookup_is_case_insensitive_and_stat_sees_directories() {
+        let vfs = test_vfs();
+        assert!(vfs.exists("DATA/mods.DATC64"));
+        assert!(!vfs.exists("Data/Missing.datc64"));
+
+        let stat = vfs.stat("data/balance/").unwrap();
+        assert_eq!(stat.kind, EntryKind::Dir);
+        assert_eq!(stat.size, 50);
+        assert_eq!(vfs.stat("Data/Mods.datc64").unwrap().offset, Some(7));
+    }
+
+    #[test]
+    fn read_without_sources_reports_missing_bundle() {
+        let vfs = test_vfs();
+        let err = vfs.read("Data/Mods.datc64").unwrap_err();
+        assert!(matches!(err, Error::BundleMissing(ref name) if name == "Data/Foo"));
+        assert!(matches!(vfs.read_hash(42), Err(Error::HashNotInIndex(42))));
+    }
+}